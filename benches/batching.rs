@@ -0,0 +1,87 @@
+//! CPU side of building frames, no GPU needed. Run with `cargo bench --bench batching`
+
+#[macro_use]
+extern crate criterion;
+extern crate rmge;
+
+mod common;
+
+use common::noise;
+use criterion::{black_box, Criterion};
+use rmge::geometry::{Quad, Rect, Vec2};
+use rmge::graphics::{BlendMode, Frame, Sprite, TextureId, TexturedQuad};
+
+const QUADS: usize = 10_000;
+
+fn quads() -> Vec<TexturedQuad> {
+    (0..QUADS)
+        .map(|i| {
+            let rect = Rect {
+                x: noise(i) * 2.0 - 1.0,
+                y: noise(i + QUADS) * 2.0 - 1.0,
+                w: 0.05,
+                h: 0.05,
+            };
+            TexturedQuad::new(Quad::from(rect), 0)
+        })
+        .collect()
+}
+
+fn batching(c: &mut Criterion) {
+    let quads = quads();
+    let mut frame = Frame::new();
+    c.bench_function("draw_quads 10k, one batch", |b| {
+        b.iter(|| {
+            frame.clear();
+            frame.draw_quads(black_box(&quads));
+        })
+    });
+    c.bench_function("draw_quads 10k, one at a time", |b| {
+        b.iter(|| {
+            frame.clear();
+            for quad in &quads {
+                frame.draw_quads(black_box(std::slice::from_ref(quad)));
+            }
+        })
+    });
+    // every blend mode change starts a new batch, so this is the worst case for batching
+    c.bench_function("draw_quads 10k, alternating blend modes", |b| {
+        b.iter(|| {
+            frame.clear();
+            for (i, quad) in quads.iter().enumerate() {
+                frame.set_blend_mode(if i % 2 == 0 {
+                    BlendMode::Alpha
+                } else {
+                    BlendMode::Additive
+                });
+                frame.draw_quads(black_box(std::slice::from_ref(quad)));
+            }
+        })
+    });
+    let sprites: Vec<Sprite> = (0..QUADS)
+        .map(|i| {
+            Sprite::new(TextureId::NONE, Vec2::new(0.05, 0.05))
+                .with_position(Vec2::new(
+                    noise(i) * 2.0 - 1.0,
+                    noise(i + QUADS) * 2.0 - 1.0,
+                ))
+                .with_rotation(f64::from(noise(i + QUADS * 2)) * 360.0)
+        })
+        .collect();
+    c.bench_function("draw_sprites 10k", |b| {
+        b.iter(|| {
+            frame.clear();
+            frame.draw_sprites(black_box(&sprites));
+        })
+    });
+}
+
+fn texture_decoding(c: &mut Criterion) {
+    let png = include_bytes!("../examples/creature-smol.png");
+    c.bench_function("decode creature-smol.png", |b| {
+        b.iter(|| image::load_from_memory(black_box(png)).unwrap().to_rgba())
+    });
+}
+
+criterion_group!(benches, batching, texture_decoding);
+criterion_main!(benches);
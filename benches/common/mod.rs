@@ -0,0 +1,5 @@
+/// Cheap deterministic noise, so every run draws the same thing
+pub fn noise(i: usize) -> f32 {
+    let x = (i as u32).wrapping_mul(2_654_435_761) >> 8;
+    x as f32 / (1 << 24) as f32
+}
@@ -0,0 +1,89 @@
+//! Upload paths that need a GPU and a window, run with `cargo bench --bench gpu`. The frame
+//! benchmarks time `FrameStats::cpu_record_time` and `FrameStats::upload_time` instead of the whole
+//! draw call, so waiting on vsync and the GPU doesn't show up in the numbers
+
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate slog;
+extern crate rmge;
+
+mod common;
+
+use common::noise;
+use criterion::{black_box, Criterion};
+use gfx_hal::window::PresentMode::*;
+use rmge::geometry::{Quad, Rect};
+use rmge::graphics::{Frame, HalState, TexturedQuad};
+use std::time::Duration;
+use winit::{EventsLoop, Window};
+
+fn frame(quads: usize) -> Frame {
+    let quads: Vec<_> = (0..quads)
+        .map(|i| {
+            let rect = Rect {
+                x: noise(i) * 2.0 - 1.0,
+                y: noise(i + quads) * 2.0 - 1.0,
+                w: 0.05,
+                h: 0.05,
+            };
+            TexturedQuad::new(Quad::from(rect), 0)
+        })
+        .collect();
+    let mut frame = Frame::new();
+    frame.draw_quads(&quads);
+    frame
+}
+
+fn gpu(c: &mut Criterion) {
+    let events_loop = EventsLoop::new();
+    let window = Window::new(&events_loop).unwrap();
+    let mut hal_state = HalState::builder()
+        .name("rmge benchmarks")
+        // no vsync so the frames don't take forever
+        .vsync([Immediate, Mailbox, Relaxed, Fifo])
+        .build(&window, slog::Logger::root(slog::Discard, o!()))
+        .unwrap();
+    let png = include_bytes!("../examples/creature-smol.png");
+    let texture = hal_state.load_texture(png).unwrap();
+    // the frame is the same every time, so reusing would skip the upload entirely
+    hal_state.set_reuse_command_buffers(false);
+
+    for &quads in &[1_000, 10_000, 100_000] {
+        let scene = frame(quads);
+        c.bench_function(&format!("upload {} quads", quads), |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::from_secs(0);
+                for _ in 0..iters {
+                    total += hal_state.draw_frame(black_box(&scene)).unwrap().upload_time;
+                }
+                total
+            })
+        });
+        c.bench_function(&format!("upload and record {} quads", quads), |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::from_secs(0);
+                for _ in 0..iters {
+                    total += hal_state
+                        .draw_frame(black_box(&scene))
+                        .unwrap()
+                        .cpu_record_time;
+                }
+                total
+            })
+        });
+    }
+
+    // load_texture would eventually run out of slots, so this replaces the same one over and over
+    let image = image::load_from_memory(png).unwrap().to_rgba();
+    c.bench_function("update_texture creature-smol.png", |b| {
+        b.iter(|| {
+            hal_state
+                .update_texture(texture, black_box(image.clone()))
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, gpu);
+criterion_main!(benches);
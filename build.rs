@@ -0,0 +1,32 @@
+//! Compiles the built-in shaders ahead of time when the `precompiled-shaders` feature is on, so the
+//! library doesn't have to carry shaderc around at runtime
+
+#[cfg(feature = "precompiled-shaders")]
+fn main() {
+    use std::{env, fs, path::Path};
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut compiler = shaderc_build::Compiler::new().expect("shaderc not found!");
+    let shaders = [
+        ("vertex", shaderc_build::ShaderKind::Vertex),
+        ("fragment", shaderc_build::ShaderKind::Fragment),
+        ("crt_vertex", shaderc_build::ShaderKind::Vertex),
+        ("crt_fragment", shaderc_build::ShaderKind::Fragment),
+    ];
+    for &(name, kind) in shaders.iter() {
+        let path = format!("src/graphics/{}.glsl", name);
+        println!("cargo:rerun-if-changed={}", path);
+        let source = fs::read_to_string(&path).unwrap();
+        let artifact = compiler
+            .compile_into_spirv(&source, kind, &path, "main", None)
+            .unwrap_or_else(|e| panic!("couldn't compile {}: {}", path, e));
+        fs::write(
+            Path::new(&out_dir).join(format!("{}.spv", name)),
+            artifact.as_binary_u8(),
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(not(feature = "precompiled-shaders"))]
+fn main() {}
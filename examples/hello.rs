@@ -0,0 +1,59 @@
+//! The smallest thing that opens a window and draws, a sprite that follows the arrow keys
+
+extern crate rmge;
+
+use rmge::geometry::{Quad, Rect};
+use rmge::graphics::{Frame, TexturedQuad};
+use rmge::{Context, ContextBuilder, ContextHandler};
+use winit::VirtualKeyCode;
+
+struct Hello {
+    x: f32,
+    y: f32,
+}
+
+impl ContextHandler for Hello {
+    fn update(&mut self, ctx: &mut Context) {
+        let step = ctx.clock.delta().as_millis() as f32 / 1000.0;
+        let input = &ctx.input;
+        if input.is_key_down(VirtualKeyCode::Left) {
+            self.x -= step;
+        }
+        if input.is_key_down(VirtualKeyCode::Right) {
+            self.x += step;
+        }
+        if input.is_key_down(VirtualKeyCode::Up) {
+            self.y -= step;
+        }
+        if input.is_key_down(VirtualKeyCode::Down) {
+            self.y += step;
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) {
+        let mut frame = Frame::new();
+        frame.draw_quads(&[TexturedQuad::new(
+            Quad::from(Rect {
+                x: self.x - 0.1,
+                y: self.y - 0.1,
+                w: 0.2,
+                h: 0.2,
+            }),
+            0,
+        )]);
+        if let Err(e) = ctx.graphics.draw_frame(&frame) {
+            eprintln!("render error: {}", e);
+        }
+    }
+}
+
+fn main() {
+    let result = ContextBuilder::new("hello").run(|ctx| {
+        ctx.graphics
+            .load_texture(include_bytes!("creature-smol.png"))?;
+        Ok(Hello { x: 0.0, y: 0.0 })
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
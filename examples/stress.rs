@@ -0,0 +1,114 @@
+//! Resizes the window and renders as fast as it can, to shake out synchronization bugs. Run it with
+//! the validation layers on, it should get through every frame without errors or hanging
+
+extern crate winit;
+#[macro_use]
+extern crate slog;
+extern crate rmge;
+extern crate slog_async;
+extern crate slog_term;
+
+use slog::Drain;
+
+use gfx_hal::window::PresentMode::*;
+use rmge::geometry::{Quad, Rect};
+use rmge::graphics::{HalState, TexturedQuad};
+use std::time::Instant;
+use winit::{dpi::LogicalSize, Event, EventsLoop, Window, WindowEvent};
+
+const FRAMES: usize = 5000;
+/// How often the window gets resized, in frames
+const RESIZE_INTERVAL: usize = 7;
+const QUADS: usize = 2000;
+
+fn create_halstate(window: &Window, log: &slog::Logger) -> HalState {
+    let mut hal_state = HalState::builder()
+        .name("rmge stress test")
+        // mailbox with 3 images is where frame/image index mixups show up
+        .vsync([Mailbox, Immediate, Relaxed, Fifo])
+        .build(&window, log.new(o!()))
+        .unwrap();
+    hal_state
+        .load_texture(include_bytes!("creature-smol.png"))
+        .unwrap();
+    hal_state
+}
+
+/// Cheap deterministic noise, so every run draws the same thing
+fn noise(i: usize) -> f32 {
+    let x = (i as u32).wrapping_mul(2_654_435_761) >> 8;
+    x as f32 / (1 << 24) as f32
+}
+
+fn main() {
+    let decorator = slog_term::PlainDecorator::new(std::io::stdout());
+    let drain = slog_term::FullFormat::new(decorator).build().fuse();
+    let drain = slog_async::Async::new(drain).build().fuse();
+    let log = slog::Logger::root(drain, o!());
+
+    let mut events_loop = EventsLoop::new();
+    let window = Window::new(&events_loop).unwrap();
+    let mut hal_state = create_halstate(&window, &log);
+
+    let start = Instant::now();
+    let mut recreations = 0;
+    for frame in 0..FRAMES {
+        if frame % RESIZE_INTERVAL == 0 {
+            let w = 200.0 + 600.0 * f64::from(noise(frame));
+            let h = 200.0 + 400.0 * f64::from(noise(frame + 1));
+            window.set_inner_size(LogicalSize::new(w, h));
+        }
+
+        let mut resized = false;
+        let mut quit = false;
+        events_loop.poll_events(|event| match event {
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => resized = true,
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => quit = true,
+            _ => (),
+        });
+        if quit {
+            break;
+        }
+        if resized {
+            if let Err(e) = hal_state.recreate_swapchain(&window) {
+                error!(log, "couldn't recreate the swapchain"; "frame" => frame, "error" => e);
+            }
+            recreations += 1;
+        }
+
+        // mix in clear frames and reused command buffers so every path gets hit
+        hal_state.set_reuse_command_buffers(frame % 200 >= 100);
+        let result = if frame % 13 == 0 {
+            hal_state.draw_clear_frame([noise(frame), 0.2, 0.3, 1.0])
+        } else {
+            let t = (frame % 60) as f32 / 60.0;
+            let quads: Vec<TexturedQuad> = (0..QUADS)
+                .map(|i| {
+                    TexturedQuad::new(
+                        Quad::from(Rect {
+                            x: noise(i) * 2.0 - 1.0 + t * 0.1,
+                            y: noise(i + QUADS) * 2.0 - 1.0,
+                            w: 0.05,
+                            h: 0.05,
+                        }),
+                        0,
+                    )
+                })
+                .collect();
+            hal_state.draw_quad_frame(&quads)
+        };
+        if let Err(e) = result {
+            error!(log, "render error"; "frame" => frame, "render_error" => e);
+            hal_state = create_halstate(&window, &log);
+            recreations += 1;
+        }
+    }
+    info!(log, "stress test done"; "frames" => FRAMES, "recreations" => recreations,
+          "seconds" => start.elapsed().as_secs());
+}
@@ -0,0 +1,184 @@
+use crate::geometry::{Vec2, Vec3};
+use std::{f32::consts::PI, time::Duration};
+
+/// Maps linear progress from 0 to 1 onto a curve. Elastic and back overshoot past 0 and 1
+/// somewhere in the middle, but every curve starts at 0 and ends at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    /// `t` gets clamped to 0..=1 first
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => quad(t),
+            Easing::QuadOut => ease_out(quad, t),
+            Easing::QuadInOut => ease_in_out(quad, t),
+            Easing::CubicIn => cubic(t),
+            Easing::CubicOut => ease_out(cubic, t),
+            Easing::CubicInOut => ease_in_out(cubic, t),
+            Easing::ElasticIn => elastic(t),
+            Easing::ElasticOut => ease_out(elastic, t),
+            Easing::ElasticInOut => ease_in_out(elastic, t),
+            Easing::BackIn => back(t),
+            Easing::BackOut => ease_out(back, t),
+            Easing::BackInOut => ease_in_out(back, t),
+        }
+    }
+}
+
+// all the curves are written as the "in" version, out and in-out get built from them
+
+fn ease_out(f: fn(f32) -> f32, t: f32) -> f32 {
+    1.0 - f(1.0 - t)
+}
+
+fn ease_in_out(f: fn(f32) -> f32, t: f32) -> f32 {
+    if t < 0.5 {
+        f(2.0 * t) / 2.0
+    } else {
+        1.0 - f(2.0 - 2.0 * t) / 2.0
+    }
+}
+
+fn quad(t: f32) -> f32 {
+    t * t
+}
+
+fn cubic(t: f32) -> f32 {
+    t * t * t
+}
+
+fn elastic(t: f32) -> f32 {
+    // the formula doesn't quite hit 0 and 1 exactly on its own
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    let period = 2.0 * PI / 3.0;
+    -(2.0f32).powf(10.0 * t - 10.0) * ((10.0 * t - 10.75) * period).sin()
+}
+
+fn back(t: f32) -> f32 {
+    // how far it pulls back, this one gives the usual 10% overshoot
+    const S: f32 = 1.701_58;
+    t * t * ((S + 1.0) * t - S)
+}
+
+/// Things that can be tweened between
+pub trait Tweenable: Copy {
+    /// `t` isn't clamped, since elastic and back easing go past the ends
+    fn interpolate(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec2<f32> {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec3<f32> {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+/// Colors, per channel
+impl Tweenable for [f32; 4] {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        let mut out = from;
+        for (out, (from, to)) in out.iter_mut().zip(from.iter().zip(to.iter())) {
+            *out = f32::interpolate(*from, *to, t);
+        }
+        out
+    }
+}
+
+/// Goes from one value to another over some time. It doesn't look at the clock itself, `advance`
+/// it by the timestep in your update so it stays in step with everything else
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tween<T: Tweenable> {
+    pub from: T,
+    pub to: T,
+    pub duration: Duration,
+    pub easing: Easing,
+    elapsed: Duration,
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new(from: T, to: T, duration: Duration, easing: Easing) -> Self {
+        Tween {
+            from,
+            to,
+            duration,
+            easing,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    /// Moves the tween forward and returns the new value, it stays at `to` once it's done
+    pub fn advance(&mut self, dt: Duration) -> T {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.value()
+    }
+
+    pub fn value(&self) -> T {
+        T::interpolate(self.from, self.to, self.easing.apply(self.progress()))
+    }
+
+    /// Linear progress from 0 to 1, before easing
+    pub fn progress(&self) -> f32 {
+        if self.duration == Duration::from_secs(0) {
+            return 1.0;
+        }
+        (self.elapsed.as_secs_f64() / self.duration.as_secs_f64()) as f32
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Starts over from `from`
+    pub fn reset(&mut self) {
+        self.elapsed = Duration::from_secs(0);
+    }
+
+    /// Starts a new tween from wherever this one currently is, so interrupting an animation doesn't
+    /// make things jump
+    pub fn retarget(&mut self, to: T, duration: Duration) {
+        self.from = self.value();
+        self.to = to;
+        self.duration = duration;
+        self.elapsed = Duration::from_secs(0);
+    }
+}
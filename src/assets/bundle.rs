@@ -0,0 +1,393 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+const MAGIC: &[u8; 8] = b"RMGEPAK\0";
+const VERSION: u32 = 1;
+/// Magic, version, entry count and where the index starts
+const HEADER_SIZE: u64 = 8 + 4 + 4 + 8;
+/// Name length, offset, stored length, length and flags, without the name itself
+const MIN_ENTRY_SIZE: u64 = 2 + 8 + 8 + 8 + 1;
+const FLAG_DEFLATE: u8 = 1;
+/// Decompressing never reserves more than this up front, the index could be lying about `len`
+#[cfg(feature = "compression")]
+const MAX_PREALLOC: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    offset: u64,
+    stored_len: u64,
+    len: u64,
+    flags: u8,
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Paths inside a bundle always use forward slashes and never start with ./ or /, so the same name
+/// works on every platform
+pub(crate) fn normalize_name(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    let mut name = name.as_str();
+    loop {
+        if name.starts_with("./") {
+            name = &name[2..];
+        } else if name.starts_with('/') {
+            name = &name[1..];
+        } else {
+            return name.to_string();
+        }
+    }
+}
+
+enum Source {
+    File(Mutex<File>),
+    Memory(Cow<'static, [u8]>),
+}
+
+/// A pile of assets packed into one file by `BundleWriter`. Only the index gets read up front,
+/// entries get read (and decompressed) when they're asked for
+pub struct Bundle {
+    source: Source,
+    entries: HashMap<String, Entry>,
+}
+
+impl std::fmt::Debug for Bundle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Bundle")
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let entries = read_index(&mut file)?;
+        Ok(Bundle {
+            source: Source::File(Mutex::new(file)),
+            entries,
+        })
+    }
+
+    /// For a bundle that's already in memory, like one that got `include_bytes!`ed into the binary
+    pub fn from_bytes(data: impl Into<Cow<'static, [u8]>>) -> io::Result<Self> {
+        let data = data.into();
+        let entries = read_index(&mut io::Cursor::new(&data[..]))?;
+        Ok(Bundle {
+            source: Source::Memory(data),
+            entries,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(&normalize_name(name))
+    }
+
+    /// Every name in the bundle, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = *self
+            .entries
+            .get(&normalize_name(name))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not in the bundle"))?;
+        let mut stored = vec![0; entry.stored_len as usize];
+        match &self.source {
+            Source::File(file) => {
+                let mut file = file.lock().map_err(|_| invalid("bundle lock poisoned"))?;
+                file.seek(SeekFrom::Start(entry.offset))?;
+                file.read_exact(&mut stored)?;
+            }
+            Source::Memory(data) => {
+                // read_index made sure these fit in the data
+                let start = entry.offset as usize;
+                let end = start + entry.stored_len as usize;
+                stored.copy_from_slice(data.get(start..end).ok_or_else(|| invalid("truncated"))?);
+            }
+        }
+        if entry.flags & FLAG_DEFLATE != 0 {
+            decompress(&stored, entry.len as usize)
+        } else {
+            Ok(stored)
+        }
+    }
+}
+
+fn read_index<R: Read + Seek>(source: &mut R) -> io::Result<HashMap<String, Entry>> {
+    let mut magic = [0; 8];
+    source.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not an asset bundle"));
+    }
+    if read_u32(source)? != VERSION {
+        return Err(invalid("unsupported bundle version"));
+    }
+    let count = read_u32(source)?;
+    let index = read_u64(source)?;
+    // nothing in the header gets trusted until it's been checked against how big the bundle is
+    let file_len = source.seek(SeekFrom::End(0))?;
+    if index < HEADER_SIZE || index > file_len {
+        return Err(invalid("bundle index is out of bounds"));
+    }
+    let mut remaining = file_len - index;
+    if u64::from(count) > remaining / MIN_ENTRY_SIZE {
+        return Err(invalid("bundle index is truncated"));
+    }
+    source.seek(SeekFrom::Start(index))?;
+
+    let mut entries = HashMap::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut len = [0; 2];
+        source.read_exact(&mut len)?;
+        let name_len = u64::from(u16::from_le_bytes(len));
+        if name_len + MIN_ENTRY_SIZE > remaining {
+            return Err(invalid("bundle index is truncated"));
+        }
+        remaining -= name_len + MIN_ENTRY_SIZE;
+        let mut name = vec![0; name_len as usize];
+        source.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| invalid("entry name isn't utf-8"))?;
+        let entry = Entry {
+            offset: read_u64(source)?,
+            stored_len: read_u64(source)?,
+            len: read_u64(source)?,
+            flags: {
+                let mut flags = [0];
+                source.read_exact(&mut flags)?;
+                flags[0]
+            },
+        };
+        // entries all live between the header and the index
+        match entry.offset.checked_add(entry.stored_len) {
+            Some(end) if entry.offset >= HEADER_SIZE && end <= index => {}
+            _ => return Err(invalid("bundle entry is out of bounds")),
+        }
+        if entry.flags & FLAG_DEFLATE == 0 && entry.len != entry.stored_len {
+            return Err(invalid("bundle entry has the wrong length"));
+        }
+        entries.insert(name, entry);
+    }
+    Ok(entries)
+}
+
+fn read_u32<R: Read>(source: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    source.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(source: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    source.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(feature = "compression")]
+fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "compressing needs the compression feature",
+    ))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len.min(MAX_PREALLOC));
+    // one byte past len is enough to tell that the entry's bigger than it says
+    flate2::read::DeflateDecoder::new(data)
+        .take(len as u64 + 1)
+        .read_to_end(&mut out)?;
+    if out.len() != len {
+        return Err(invalid("bundle entry has the wrong length"));
+    }
+    Ok(out)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_data: &[u8], _len: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "this entry is compressed, reading it needs the compression feature",
+    ))
+}
+
+/// Writes a `Bundle`. The data goes out as it's added and the index gets written at the end by
+/// `finish`, so a bundle that never got finished can't be opened
+pub struct BundleWriter<W: Write + Seek> {
+    out: W,
+    entries: Vec<(String, Entry)>,
+    offset: u64,
+}
+
+impl<W: Write + Seek> BundleWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        // filled in for real by finish
+        out.write_all(&[0; HEADER_SIZE as usize])?;
+        Ok(BundleWriter {
+            out,
+            entries: Vec::new(),
+            offset: HEADER_SIZE,
+        })
+    }
+
+    /// Entries that get added twice keep the last data. Compressing only helps for formats that
+    /// aren't already compressed, so not for PNGs and OGGs
+    pub fn add(&mut self, name: &str, data: &[u8], compress_it: bool) -> io::Result<()> {
+        let name = normalize_name(name);
+        if name.len() > std::u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entry name too long",
+            ));
+        }
+        let compressed;
+        let (stored, flags) = if compress_it {
+            compressed = compress(data)?;
+            (&compressed[..], FLAG_DEFLATE)
+        } else {
+            (data, 0)
+        };
+        self.out.write_all(stored)?;
+        let entry = Entry {
+            offset: self.offset,
+            stored_len: stored.len() as u64,
+            len: data.len() as u64,
+            flags,
+        };
+        self.offset += stored.len() as u64;
+        self.entries.retain(|(n, _)| *n != name);
+        self.entries.push((name, entry));
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: &str, path: &Path, compress_it: bool) -> io::Result<()> {
+        self.add(name, &fs::read(path)?, compress_it)
+    }
+
+    /// Everything under `dir`, named by their path relative to it with `prefix` in front
+    pub fn add_dir(&mut self, prefix: &str, dir: &Path, compress_it: bool) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!(
+                "{}/{}",
+                prefix.trim_end_matches('/'),
+                entry.file_name().to_string_lossy()
+            );
+            if entry.file_type()?.is_dir() {
+                self.add_dir(&name, &entry.path(), compress_it)?;
+            } else {
+                self.add_file(&name, &entry.path(), compress_it)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the index and header, and hands back the output
+    pub fn finish(mut self) -> io::Result<W> {
+        for (name, entry) in &self.entries {
+            self.out.write_all(&(name.len() as u16).to_le_bytes())?;
+            self.out.write_all(name.as_bytes())?;
+            self.out.write_all(&entry.offset.to_le_bytes())?;
+            self.out.write_all(&entry.stored_len.to_le_bytes())?;
+            self.out.write_all(&entry.len.to_le_bytes())?;
+            self.out.write_all(&[entry.flags])?;
+        }
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(MAGIC)?;
+        self.out.write_all(&VERSION.to_le_bytes())?;
+        self.out
+            .write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        self.out.write_all(&self.offset.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle_bytes(compress_it: bool) -> Vec<u8> {
+        let mut writer = BundleWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        writer
+            .add("./textures\\note.png", &[1, 2, 3, 4], false)
+            .unwrap();
+        writer
+            .add("songs/chart.txt", &b"a".repeat(1000), compress_it)
+            .unwrap();
+        writer.add("empty", &[], false).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Where the index starts, from the header
+    fn index_of(data: &[u8]) -> usize {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&data[16..24]);
+        u64::from_le_bytes(bytes) as usize
+    }
+
+    #[test]
+    fn round_trip() {
+        let bundle = Bundle::from_bytes(bundle_bytes(false)).unwrap();
+        let mut names: Vec<&str> = bundle.names().collect();
+        names.sort();
+        assert_eq!(names, ["empty", "songs/chart.txt", "textures/note.png"]);
+        assert_eq!(bundle.read("textures/note.png").unwrap(), [1, 2, 3, 4]);
+        assert_eq!(bundle.read("/songs/chart.txt").unwrap(), b"a".repeat(1000));
+        assert!(bundle.read("empty").unwrap().is_empty());
+        assert!(bundle.read("missing").is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_round_trip() {
+        let data = bundle_bytes(true);
+        assert!(data.len() < 1000);
+        let bundle = Bundle::from_bytes(data).unwrap();
+        assert_eq!(bundle.read("songs/chart.txt").unwrap(), b"a".repeat(1000));
+    }
+
+    #[test]
+    fn truncated_bundles_are_rejected() {
+        let data = bundle_bytes(false);
+        for len in &[0, HEADER_SIZE as usize - 1, index_of(&data), data.len() - 1] {
+            assert!(
+                Bundle::from_bytes(data[..*len].to_vec()).is_err(),
+                "opened with {} of {} bytes",
+                len,
+                data.len()
+            );
+        }
+    }
+
+    #[test]
+    fn entries_overlapping_the_index_are_rejected() {
+        let mut data = bundle_bytes(false);
+        let index = index_of(&data);
+        // the first entry's offset comes right after its name
+        let name_len = u16::from_le_bytes([data[index], data[index + 1]]) as usize;
+        let offset = index + 2 + name_len;
+        data[offset..offset + 8].copy_from_slice(&(index as u64 - 1).to_le_bytes());
+        assert!(Bundle::from_bytes(data.clone()).is_err());
+
+        // and an index that starts inside the header
+        data[16..24].copy_from_slice(&(HEADER_SIZE - 1).to_le_bytes());
+        assert!(Bundle::from_bytes(data).is_err());
+    }
+}
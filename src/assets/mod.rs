@@ -0,0 +1,5 @@
+mod bundle;
+mod vfs;
+
+pub use bundle::{Bundle, BundleWriter};
+pub use vfs::{AssetSource, DirectorySource, EmbeddedSource, Vfs};
@@ -0,0 +1,181 @@
+use crate::assets::{bundle::normalize_name, Bundle};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such asset")
+}
+
+/// Somewhere assets can be read from. Paths use forward slashes and are relative to the source
+pub trait AssetSource: Send + Sync {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+    fn exists(&self, path: &str) -> bool;
+}
+
+/// Loose files in a directory on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectorySource(pub PathBuf);
+
+impl DirectorySource {
+    fn path(&self, path: &str) -> io::Result<PathBuf> {
+        let path = normalize_name(path);
+        // mounts shouldn't be able to reach outside of their directory, and joining an absolute
+        // path (like C:/ on Windows, which normalize_name leaves alone) replaces the directory
+        let escapes = Path::new(&path).components().any(|part| match part {
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => true,
+            Component::CurDir | Component::Normal(_) => false,
+        });
+        if escapes {
+            return Err(not_found());
+        }
+        Ok(self.0.join(path))
+    }
+}
+
+impl AssetSource for DirectorySource {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(path)?)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.path(path).map(|path| path.is_file()).unwrap_or(false)
+    }
+}
+
+impl AssetSource for Bundle {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        Bundle::read(self, path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.contains(path)
+    }
+}
+
+/// Files baked into the binary, filled with `include_bytes!`
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedSource(HashMap<String, &'static [u8]>);
+
+impl EmbeddedSource {
+    pub fn new() -> Self {
+        EmbeddedSource::default()
+    }
+
+    pub fn with(mut self, path: &str, data: &'static [u8]) -> Self {
+        self.0.insert(normalize_name(path), data);
+        self
+    }
+}
+
+impl AssetSource for EmbeddedSource {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.0
+            .get(&normalize_name(path))
+            .map(|data| data.to_vec())
+            .ok_or_else(not_found)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.0.contains_key(&normalize_name(path))
+    }
+}
+
+/// A whole directory baked in with `include_dir!`
+#[cfg(feature = "embed")]
+impl AssetSource for include_dir::Dir<'static> {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.get_file(normalize_name(path))
+            .map(|file| file.contents().to_vec())
+            .ok_or_else(not_found)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.get_file(normalize_name(path)).is_some()
+    }
+}
+
+#[derive(Clone)]
+struct Mount {
+    /// Normalized, empty for the root
+    point: String,
+    source: Arc<dyn AssetSource>,
+}
+
+/// Puts asset sources together into one tree. Every source gets mounted at a path, and a read goes
+/// to the newest mount that has the file, so a player's skin directory mounted over the defaults
+/// only has to contain what it changes. Clones share their sources
+#[derive(Clone, Default)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+impl fmt::Debug for Vfs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.mounts.iter().map(|mount| &mount.point))
+            .finish()
+    }
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Vfs::default()
+    }
+
+    /// `point` is where in the tree the source's root shows up, "" for the root of the tree.
+    /// Mounts made later shadow earlier ones
+    pub fn mount(&mut self, point: &str, source: impl AssetSource + 'static) -> &mut Self {
+        let point = normalize_name(point).trim_end_matches('/').to_string();
+        self.mounts.push(Mount {
+            point,
+            source: Arc::new(source),
+        });
+        self
+    }
+
+    pub fn with_mount(mut self, point: &str, source: impl AssetSource + 'static) -> Self {
+        self.mount(point, source);
+        self
+    }
+
+    /// Drops every source mounted at `point`
+    pub fn unmount(&mut self, point: &str) {
+        let point = normalize_name(point).trim_end_matches('/').to_string();
+        self.mounts.retain(|mount| mount.point != point);
+    }
+
+    /// The mounts covering `path` newest first, along with the path inside each of them
+    fn candidates<'a>(&'a self, path: &str) -> impl Iterator<Item = (&'a Mount, String)> + 'a {
+        let path = normalize_name(path);
+        self.mounts.iter().rev().filter_map(move |mount| {
+            if mount.point.is_empty() {
+                Some((mount, path.clone()))
+            } else if path.starts_with(&mount.point) && path[mount.point.len()..].starts_with('/') {
+                Some((mount, path[mount.point.len() + 1..].to_string()))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl AssetSource for Vfs {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        for (mount, inner) in self.candidates(path) {
+            match mount.source.read(&inner) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                result => return result,
+            }
+        }
+        Err(not_found())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.candidates(path)
+            .any(|(mount, inner)| mount.source.exists(&inner))
+    }
+}
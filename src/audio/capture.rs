@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+/// Loudness of a stretch of input, both linear from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Level {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl Level {
+    /// The RMS in decibels relative to full scale, -inf for silence
+    pub fn rms_db(&self) -> f32 {
+        20.0 * self.rms.log10()
+    }
+}
+
+/// Keeps the last few seconds of microphone input around for analysis. Whatever reads the device
+/// calls `push` from its callback, the game asks for levels and pitches during `update`. With the
+/// `audio-device` feature `AudioInput` does the reading
+#[derive(Debug, Clone)]
+pub struct CaptureBuffer {
+    sample_rate: u32,
+    capacity: usize,
+    /// Mono, oldest first
+    samples: VecDeque<f32>,
+    total: u64,
+}
+
+impl CaptureBuffer {
+    /// Holds `capacity` frames, older input gets dropped as new input comes in. It always holds at
+    /// least one
+    pub fn new(sample_rate: u32, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        CaptureBuffer {
+            sample_rate,
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            total: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Interleaved input with `channels` channels, mixed down to mono
+    pub fn push(&mut self, samples: &[f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        for frame in samples.chunks(channels) {
+            if self.samples.len() == self.capacity {
+                self.samples.pop_front();
+            }
+            self.samples
+                .push_back(frame.iter().sum::<f32>() / channels as f32);
+            self.total += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Frames pushed since the buffer was made, for lining input up with the audio clock
+    pub fn total_frames(&self) -> u64 {
+        self.total
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Copies the newest frames into the end of `out`, returns how many there were
+    pub fn latest(&self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.samples.len());
+        let skip = self.samples.len() - count;
+        let start = out.len() - count;
+        for (out, sample) in out[start..].iter_mut().zip(self.samples.iter().skip(skip)) {
+            *out = *sample;
+        }
+        count
+    }
+
+    /// Over the newest `window` frames, for meters and voice activation
+    pub fn level(&self, window: usize) -> Level {
+        let count = window.min(self.samples.len());
+        if count == 0 {
+            return Level::default();
+        }
+        let skip = self.samples.len() - count;
+        let (mut sum, mut peak) = (0.0, 0.0f32);
+        for &sample in self.samples.iter().skip(skip) {
+            sum += sample * sample;
+            peak = peak.max(sample.abs());
+        }
+        Level {
+            rms: (sum / count as f32).sqrt(),
+            peak,
+        }
+    }
+
+    /// The fundamental frequency of the newest input in Hz, None when it's too quiet or too noisy
+    /// to tell. Looks for periods between `min_hz` and `max_hz`, a window of `window` frames needs
+    /// to cover at least a couple of periods of `min_hz`. Also None unless 0 < `min_hz` < `max_hz`
+    pub fn pitch(&self, window: usize, min_hz: f32, max_hz: f32) -> Option<f32> {
+        // NaN fails these too
+        if !(min_hz > 0.0 && min_hz < max_hz && max_hz.is_finite()) {
+            return None;
+        }
+        let max_lag = (self.sample_rate as f32 / min_hz).ceil() as usize;
+        let min_lag = ((self.sample_rate as f32 / max_hz).floor() as usize).max(2);
+        let needed = window.checked_add(max_lag)?;
+        if max_lag <= min_lag || self.samples.len() < needed {
+            return None;
+        }
+        let mut input = vec![0.0; needed];
+        self.latest(&mut input);
+        detect_pitch(&input, window, min_lag, max_lag).map(|lag| self.sample_rate as f32 / lag)
+    }
+}
+
+/// How low the normalized difference has to dip to count as periodic, YIN's usual threshold
+const YIN_THRESHOLD: f32 = 0.15;
+
+/// The YIN pitch detector, returns the period in frames
+fn detect_pitch(input: &[f32], window: usize, min_lag: usize, max_lag: usize) -> Option<f32> {
+    // how different the signal is from itself shifted by the lag, normalized by the running mean so
+    // the shortest lag that dips far enough wins over its multiples
+    let mut normalized = vec![1.0; max_lag + 1];
+    let mut running = 0.0;
+    for lag in 1..=max_lag {
+        let difference: f32 = (0..window)
+            .map(|i| {
+                let d = input[i] - input[i + lag];
+                d * d
+            })
+            .sum();
+        running += difference;
+        normalized[lag] = if running > 0.0 {
+            difference * lag as f32 / running
+        } else {
+            1.0
+        };
+    }
+
+    let mut lag = min_lag;
+    while lag < max_lag {
+        if normalized[lag] < YIN_THRESHOLD {
+            // walk down to the bottom of the dip
+            while lag + 1 < max_lag && normalized[lag + 1] < normalized[lag] {
+                lag += 1;
+            }
+            // a parabola through the neighbours gets it below a whole frame
+            let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+            let curve = a - 2.0 * b + c;
+            let shift = if curve.abs() > std::f32::EPSILON {
+                0.5 * (a - c) / curve
+            } else {
+                0.0
+            };
+            return Some(lag as f32 + shift);
+        }
+        lag += 1;
+    }
+    None
+}
@@ -0,0 +1,265 @@
+use crate::audio::{CaptureBuffer, Mixer};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Device, InputCallbackInfo, OutputCallbackInfo, Sample, SampleFormat, StreamConfig, StreamError,
+};
+use slog::Logger;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Names of the input devices plugged in right now, for a microphone picker. Empty when there's no
+/// audio system to ask
+pub fn input_device_names() -> Vec<String> {
+    match cpal::default_host().input_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Names of the output devices plugged in right now, for a settings menu. Empty when there's no
+/// audio system to ask
+pub fn output_device_names() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// A microphone feeding a `CaptureBuffer`, input keeps coming in until this is dropped. The stream
+/// can't leave the thread that opened it on some platforms, share `capture` with other threads
+/// instead
+pub struct AudioInput {
+    stream: cpal::Stream,
+    capture: Arc<Mutex<CaptureBuffer>>,
+    name: String,
+}
+
+impl fmt::Debug for AudioInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AudioInput")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl AudioInput {
+    /// The system's default input device, keeping `seconds` of input around
+    pub fn open_default(seconds: f32, log: Logger) -> Result<Self, &'static str> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or("There's no input device!")?;
+        AudioInput::open_device(device, seconds, log)
+    }
+
+    /// One of the names from `input_device_names`
+    pub fn open(name: &str, seconds: f32, log: Logger) -> Result<Self, &'static str> {
+        let device = cpal::default_host()
+            .input_devices()
+            .map_err(|_| "Couldn't list the input devices!")?
+            .find(|device| device.name().ok().as_deref() == Some(name))
+            .ok_or("There's no input device with that name!")?;
+        AudioInput::open_device(device, seconds, log)
+    }
+
+    fn open_device(device: Device, seconds: f32, log: Logger) -> Result<Self, &'static str> {
+        let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+        let config = device
+            .default_input_config()
+            .map_err(|_| "Couldn't get the input device's format!")?;
+        let (sample_rate, channels) = (config.sample_rate().0, config.channels());
+        let capacity = (seconds.max(0.0) * sample_rate as f32) as usize;
+        let capture = Arc::new(Mutex::new(CaptureBuffer::new(sample_rate, capacity)));
+
+        let error_log = log.clone();
+        let on_error = move |e: StreamError| error!(error_log, "audio input failed"; "error" => %e);
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_input::<f32, _>(&device, &stream_config, &capture, on_error),
+            SampleFormat::I16 => build_input::<i16, _>(&device, &stream_config, &capture, on_error),
+            SampleFormat::U16 => build_input::<u16, _>(&device, &stream_config, &capture, on_error),
+        }
+        .map_err(|_| "Couldn't open the input stream!")?;
+        stream
+            .play()
+            .map_err(|_| "Couldn't start the input stream!")?;
+        info!(log, "opened audio input"; "device" => &name, "sample_rate" => sample_rate,
+              "channels" => channels);
+        Ok(AudioInput {
+            stream,
+            capture,
+            name,
+        })
+    }
+
+    /// Where the input ends up, lock it during `update` for levels and pitches
+    pub fn capture(&self) -> &Arc<Mutex<CaptureBuffer>> {
+        &self.capture
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Stops taking input without closing the device, for pausing
+    pub fn pause(&self) -> Result<(), &'static str> {
+        self.stream
+            .pause()
+            .map_err(|_| "Couldn't pause the input stream!")
+    }
+
+    pub fn resume(&self) -> Result<(), &'static str> {
+        self.stream
+            .play()
+            .map_err(|_| "Couldn't resume the input stream!")
+    }
+}
+
+/// Plays whatever a `Mixer` renders, the stream keeps pulling from it until this is dropped. The
+/// mixer runs at the device's sample rate. Like `AudioInput` the stream has to stay on the thread
+/// that opened it, share `mixer` with other threads instead
+pub struct AudioOutput {
+    stream: cpal::Stream,
+    mixer: Arc<Mutex<Mixer>>,
+    name: String,
+}
+
+impl fmt::Debug for AudioOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AudioOutput")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl AudioOutput {
+    pub fn open_default(log: Logger) -> Result<Self, &'static str> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("There's no output device!")?;
+        AudioOutput::open_device(device, log)
+    }
+
+    /// One of the names from `output_device_names`
+    pub fn open(name: &str, log: Logger) -> Result<Self, &'static str> {
+        let device = cpal::default_host()
+            .output_devices()
+            .map_err(|_| "Couldn't list the output devices!")?
+            .find(|device| device.name().ok().as_deref() == Some(name))
+            .ok_or("There's no output device with that name!")?;
+        AudioOutput::open_device(device, log)
+    }
+
+    fn open_device(device: Device, log: Logger) -> Result<Self, &'static str> {
+        let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+        let config = device
+            .default_output_config()
+            .map_err(|_| "Couldn't get the output device's format!")?;
+        let (sample_rate, channels) = (config.sample_rate().0, config.channels());
+        let mixer = Arc::new(Mutex::new(Mixer::new(sample_rate)));
+
+        let error_log = log.clone();
+        let on_error =
+            move |e: StreamError| error!(error_log, "audio output failed"; "error" => %e);
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_output::<f32, _>(&device, &stream_config, &mixer, on_error),
+            SampleFormat::I16 => build_output::<i16, _>(&device, &stream_config, &mixer, on_error),
+            SampleFormat::U16 => build_output::<u16, _>(&device, &stream_config, &mixer, on_error),
+        }
+        .map_err(|_| "Couldn't open the output stream!")?;
+        stream
+            .play()
+            .map_err(|_| "Couldn't start the output stream!")?;
+        info!(log, "opened audio output"; "device" => &name, "sample_rate" => sample_rate,
+              "channels" => channels);
+        Ok(AudioOutput {
+            stream,
+            mixer,
+            name,
+        })
+    }
+
+    /// What gets played, lock it to add buses and play sounds. The audio thread locks it for every
+    /// buffer, so don't hold on to the lock for long
+    pub fn mixer(&self) -> &Arc<Mutex<Mixer>> {
+        &self.mixer
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Stops pulling from the mixer, which stops its clock too
+    pub fn pause(&self) -> Result<(), &'static str> {
+        self.stream
+            .pause()
+            .map_err(|_| "Couldn't pause the output stream!")
+    }
+
+    pub fn resume(&self) -> Result<(), &'static str> {
+        self.stream
+            .play()
+            .map_err(|_| "Couldn't resume the output stream!")
+    }
+}
+
+fn build_input<T: Sample, E: FnMut(StreamError) + Send + 'static>(
+    device: &Device,
+    config: &StreamConfig,
+    capture: &Arc<Mutex<CaptureBuffer>>,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let capture = Arc::clone(capture);
+    let channels = config.channels;
+    // reused so the callback doesn't allocate once it's warmed up
+    let mut converted = Vec::new();
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &InputCallbackInfo| {
+            converted.clear();
+            converted.extend(data.iter().map(Sample::to_f32));
+            if let Ok(mut capture) = capture.lock() {
+                capture.push(&converted, channels);
+            }
+        },
+        on_error,
+    )
+}
+
+fn build_output<T: Sample, E: FnMut(StreamError) + Send + 'static>(
+    device: &Device,
+    config: &StreamConfig,
+    mixer: &Arc<Mutex<Mixer>>,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let mixer = Arc::clone(mixer);
+    let channels = usize::from(config.channels.max(1));
+    // reused so the callback doesn't allocate once it's warmed up
+    let mut stereo = Vec::new();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &OutputCallbackInfo| {
+            stereo.clear();
+            stereo.resize(data.len() / channels * 2, 0.0);
+            // a poisoned mixer plays silence instead of taking the audio thread down with it
+            if let Ok(mut mixer) = mixer.lock() {
+                mixer.render(&mut stereo);
+            }
+            // mono gets both sides, anything past stereo stays silent
+            for (frame, sample) in data.chunks_mut(channels).zip(stereo.chunks(2)) {
+                for (channel, out) in frame.iter_mut().enumerate() {
+                    let value = match (channels, channel) {
+                        (1, _) => (sample[0] + sample[1]) / 2.0,
+                        (_, 0) => sample[0],
+                        (_, 1) => sample[1],
+                        _ => 0.0,
+                    };
+                    *out = T::from(&value);
+                }
+            }
+        },
+        on_error,
+    )
+}
@@ -0,0 +1,466 @@
+mod capture;
+#[cfg(feature = "audio-device")]
+mod device;
+mod spectrum;
+
+pub use capture::{CaptureBuffer, Level};
+#[cfg(feature = "audio-device")]
+pub use device::{input_device_names, output_device_names, AudioInput, AudioOutput};
+pub use spectrum::Spectrum;
+
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+/// Decoded audio, stereo and interleaved. Cheap to clone, voices playing it share the samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    samples: Arc<Vec<f32>>,
+    sample_rate: u32,
+}
+
+impl Sound {
+    /// Mono gets copied to both channels, anything past the first two channels gets dropped
+    pub fn from_samples(samples: &[f32], channels: u16, sample_rate: u32) -> Self {
+        let stereo = match channels {
+            0 => Vec::new(),
+            1 => samples
+                .iter()
+                .flat_map(|&s| std::iter::repeat(s).take(2))
+                .collect(),
+            2 => samples.to_vec(),
+            n => samples
+                .chunks(n as usize)
+                .flat_map(|frame| frame.iter().take(2).cloned())
+                .collect(),
+        };
+        Sound {
+            samples: Arc::new(stereo),
+            sample_rate,
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Linearly interpolated, silence past the end
+    fn frame_at(&self, position: f64) -> [f32; 2] {
+        let index = position as usize;
+        let t = (position - index as f64) as f32;
+        let frame = |i: usize| match self.samples.get(i * 2..i * 2 + 2) {
+            Some(frame) => [frame[0], frame[1]],
+            None => [0.0; 2],
+        };
+        let (a, b) = (frame(index), frame(index + 1));
+        [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusId(usize);
+
+impl BusId {
+    /// Every other bus ends up in here, and this one goes to the output
+    pub const MASTER: BusId = BusId(0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VoiceId(u64);
+
+/// Processing applied to everything going through a bus, in the order they were added
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// One pole, for muffling the music behind a pause menu
+    LowPass { cutoff: f32 },
+    /// Changes the pitch without changing the speed, for rate mods that shouldn't sound like
+    /// chipmunks. Smears transients a bit since it crossfades between two delayed copies
+    PitchShift { semitones: f32 },
+}
+
+/// How a sound gets played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayParams {
+    pub volume: f32,
+    /// -1 is all the way left, 1 all the way right
+    pub pan: f32,
+    /// Playback speed, which also changes the pitch. Has to be positive
+    pub rate: f32,
+}
+
+impl Default for PlayParams {
+    fn default() -> Self {
+        PlayParams {
+            volume: 1.0,
+            pan: 0.0,
+            rate: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Voice {
+    id: VoiceId,
+    sound: Sound,
+    bus: BusId,
+    params: PlayParams,
+    /// Output frame it starts on
+    start: u64,
+    /// In the sound's frames
+    position: f64,
+}
+
+/// Length of the pitch shifter's delay line, in frames
+const PITCH_WINDOW: usize = 2048;
+
+#[derive(Debug, Clone)]
+enum EffectState {
+    LowPass([f32; 2]),
+    PitchShift {
+        buffer: Vec<[f32; 2]>,
+        write: usize,
+        phase: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Bus {
+    parent: BusId,
+    volume: f32,
+    muted: bool,
+    effects: Vec<(Effect, EffectState)>,
+    buffer: Vec<f32>,
+}
+
+/// Mixes sounds through a tree of buses into one stereo stream. It doesn't talk to an audio device
+/// by itself, whatever does calls `render` from its callback (behind a mutex, it's not `Sync`),
+/// like `AudioOutput` does with the `audio-device` feature. Frames rendered so far are the audio
+/// clock, so `play_at` lines sounds up to the exact sample no matter how big the device's buffers
+/// are
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    sample_rate: u32,
+    buses: Vec<Bus>,
+    voices: Vec<Voice>,
+    next_voice: u64,
+    /// Frames rendered so far
+    position: u64,
+    /// The newest output mixed down to mono, for visualizers
+    history: VecDeque<f32>,
+}
+
+/// How many frames of output `Mixer::recent_output` can go back
+pub const OUTPUT_HISTORY: usize = 8192;
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Mixer {
+            sample_rate,
+            buses: vec![Bus {
+                parent: BusId::MASTER,
+                volume: 1.0,
+                muted: false,
+                effects: Vec::new(),
+                buffer: Vec::new(),
+            }],
+            voices: Vec::new(),
+            next_voice: 0,
+            position: 0,
+            history: VecDeque::with_capacity(OUTPUT_HISTORY),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Buses only exist in the mixer they were added to
+    fn bus(&self, bus: BusId) -> Result<&Bus, &'static str> {
+        self.buses.get(bus.0).ok_or("That bus doesn't exist!")
+    }
+
+    fn bus_mut(&mut self, bus: BusId) -> Result<&mut Bus, &'static str> {
+        self.buses.get_mut(bus.0).ok_or("That bus doesn't exist!")
+    }
+
+    /// A bus that mixes into `parent`, like music and SFX buses into the master
+    pub fn add_bus(&mut self, parent: BusId) -> Result<BusId, &'static str> {
+        self.bus(parent)?;
+        self.buses.push(Bus {
+            parent,
+            volume: 1.0,
+            muted: false,
+            effects: Vec::new(),
+            buffer: Vec::new(),
+        });
+        Ok(BusId(self.buses.len() - 1))
+    }
+
+    pub fn set_volume(&mut self, bus: BusId, volume: f32) -> Result<(), &'static str> {
+        self.bus_mut(bus)?.volume = volume;
+        Ok(())
+    }
+
+    /// None for buses that aren't in this mixer
+    pub fn volume(&self, bus: BusId) -> Option<f32> {
+        self.bus(bus).ok().map(|bus| bus.volume)
+    }
+
+    pub fn set_muted(&mut self, bus: BusId, muted: bool) -> Result<(), &'static str> {
+        self.bus_mut(bus)?.muted = muted;
+        Ok(())
+    }
+
+    /// None for buses that aren't in this mixer
+    pub fn is_muted(&self, bus: BusId) -> Option<bool> {
+        self.bus(bus).ok().map(|bus| bus.muted)
+    }
+
+    pub fn add_effect(&mut self, bus: BusId, effect: Effect) -> Result<(), &'static str> {
+        let bus = self.bus_mut(bus)?;
+        let state = match effect {
+            Effect::LowPass { .. } => EffectState::LowPass([0.0; 2]),
+            Effect::PitchShift { .. } => EffectState::PitchShift {
+                buffer: vec![[0.0; 2]; PITCH_WINDOW],
+                write: 0,
+                phase: 0.0,
+            },
+        };
+        bus.effects.push((effect, state));
+        Ok(())
+    }
+
+    /// Changes the settings of the `index`th effect on `bus`, keeping its state so it doesn't click
+    pub fn set_effect(
+        &mut self,
+        bus: BusId,
+        index: usize,
+        effect: Effect,
+    ) -> Result<(), &'static str> {
+        if let Some((old, state)) = self.bus_mut(bus)?.effects.get_mut(index) {
+            match (effect, state) {
+                (Effect::LowPass { .. }, EffectState::LowPass(_))
+                | (Effect::PitchShift { .. }, EffectState::PitchShift { .. }) => *old = effect,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear_effects(&mut self, bus: BusId) -> Result<(), &'static str> {
+        self.bus_mut(bus)?.effects.clear();
+        Ok(())
+    }
+
+    /// Frames rendered so far, the audio clock
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// `position` in seconds
+    pub fn time(&self) -> f64 {
+        self.position as f64 / f64::from(self.sample_rate)
+    }
+
+    /// Starts with the next `render`
+    pub fn play(
+        &mut self,
+        sound: &Sound,
+        bus: BusId,
+        params: PlayParams,
+    ) -> Result<VoiceId, &'static str> {
+        let position = self.position;
+        self.play_at(sound, bus, params, position)
+    }
+
+    /// Starts on output frame `frame`, or right away if that's already gone by
+    pub fn play_at(
+        &mut self,
+        sound: &Sound,
+        bus: BusId,
+        params: PlayParams,
+        frame: u64,
+    ) -> Result<VoiceId, &'static str> {
+        self.bus(bus)?;
+        // NaN fails this too, and a voice that never moves forward would never finish
+        if !(params.rate > 0.0 && params.rate.is_finite()) {
+            return Err("Voices have to play at a positive rate!");
+        }
+        let id = VoiceId(self.next_voice);
+        self.next_voice += 1;
+        self.voices.push(Voice {
+            id,
+            sound: sound.clone(),
+            bus,
+            params,
+            start: frame,
+            position: 0.0,
+        });
+        Ok(id)
+    }
+
+    /// `play_at` in seconds of audio clock, for keysounds scheduled off a chart
+    pub fn play_at_time(
+        &mut self,
+        sound: &Sound,
+        bus: BusId,
+        params: PlayParams,
+        time: f64,
+    ) -> Result<VoiceId, &'static str> {
+        let frame = (time.max(0.0) * f64::from(self.sample_rate)).round() as u64;
+        self.play_at(sound, bus, params, frame)
+    }
+
+    pub fn stop(&mut self, voice: VoiceId) {
+        self.voices.retain(|v| v.id != voice);
+    }
+
+    pub fn is_playing(&self, voice: VoiceId) -> bool {
+        self.voices.iter().any(|v| v.id == voice)
+    }
+
+    /// Fills `out` with interleaved stereo and moves the clock forward by that many frames. An odd
+    /// sample left over at the end isn't a whole frame, it gets silence
+    pub fn render(&mut self, out: &mut [f32]) {
+        let frames = out.len() / 2;
+        let (out, leftover) = out.split_at_mut(frames * 2);
+        for sample in leftover {
+            *sample = 0.0;
+        }
+        for bus in &mut self.buses {
+            bus.buffer.clear();
+            bus.buffer.resize(frames * 2, 0.0);
+        }
+
+        let (position, sample_rate) = (self.position, self.sample_rate);
+        for voice in &mut self.voices {
+            let offset = voice.start.saturating_sub(position);
+            if offset >= frames as u64 {
+                continue;
+            }
+            let step = f64::from(voice.params.rate) * f64::from(voice.sound.sample_rate)
+                / f64::from(sample_rate);
+            let pan = voice.params.pan.max(-1.0).min(1.0);
+            let gains = [
+                voice.params.volume * (1.0 - pan).min(1.0),
+                voice.params.volume * (1.0 + pan).min(1.0),
+            ];
+            let end = voice.sound.frames() as f64;
+            let buffer = &mut self.buses[voice.bus.0].buffer;
+            for frame in buffer.chunks_mut(2).skip(offset as usize) {
+                if voice.position >= end {
+                    break;
+                }
+                let sample = voice.sound.frame_at(voice.position);
+                frame[0] += sample[0] * gains[0];
+                frame[1] += sample[1] * gains[1];
+                voice.position += step;
+            }
+        }
+        self.voices
+            .retain(|voice| voice.position < voice.sound.frames() as f64);
+        self.position += frames as u64;
+
+        // children always come after their parents, so going backwards mixes every bus before
+        // its parent needs it
+        for i in (0..self.buses.len()).rev() {
+            let mut buffer = std::mem::replace(&mut self.buses[i].buffer, Vec::new());
+            let bus = &mut self.buses[i];
+            for (effect, state) in &mut bus.effects {
+                apply_effect(*effect, state, &mut buffer, sample_rate);
+            }
+            let gain = if bus.muted { 0.0 } else { bus.volume };
+            let parent = bus.parent;
+            if i == 0 {
+                for (out, sample) in out.iter_mut().zip(&buffer) {
+                    *out = sample * gain;
+                }
+            } else {
+                let parent = &mut self.buses[parent.0].buffer;
+                for (out, sample) in parent.iter_mut().zip(&buffer) {
+                    *out += sample * gain;
+                }
+            }
+            self.buses[i].buffer = buffer;
+        }
+
+        for frame in out.chunks(2) {
+            if self.history.len() == OUTPUT_HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(frame.iter().sum::<f32>() / 2.0);
+        }
+    }
+
+    /// Copies the newest output, mixed down to mono, into the end of `out`. Returns how many
+    /// frames there were, at most `OUTPUT_HISTORY`
+    pub fn recent_output(&self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.history.len());
+        let skip = self.history.len() - count;
+        let start = out.len() - count;
+        for (out, sample) in out[start..].iter_mut().zip(self.history.iter().skip(skip)) {
+            *out = *sample;
+        }
+        count
+    }
+}
+
+fn apply_effect(effect: Effect, state: &mut EffectState, buffer: &mut [f32], sample_rate: u32) {
+    match (effect, state) {
+        (Effect::LowPass { cutoff }, EffectState::LowPass(last)) => {
+            let a = 1.0 - (-2.0 * PI * cutoff / sample_rate as f32).exp();
+            for frame in buffer.chunks_mut(2) {
+                for (sample, last) in frame.iter_mut().zip(last.iter_mut()) {
+                    *last += a * (*sample - *last);
+                    *sample = *last;
+                }
+            }
+        }
+        (
+            Effect::PitchShift { semitones },
+            EffectState::PitchShift {
+                buffer: delay,
+                write,
+                phase,
+            },
+        ) => {
+            // two read heads half a window apart, sliding through the delay line at the pitch
+            // ratio and crossfading so the jump when one wraps around is silent
+            let ratio = 2.0f32.powf(semitones / 12.0);
+            let window = PITCH_WINDOW as f32;
+            for frame in buffer.chunks_mut(2) {
+                delay[*write] = [frame[0], frame[1]];
+                *phase = wrap(*phase + (1.0 - ratio) / window, 1.0);
+                let mut out = [0.0; 2];
+                for &tap in &[*phase, (*phase + 0.5) % 1.0] {
+                    let gain = 1.0 - (2.0 * tap - 1.0).abs();
+                    let read = wrap(*write as f32 - tap * window, window);
+                    let index = read as usize % PITCH_WINDOW;
+                    let next = (index + 1) % PITCH_WINDOW;
+                    let t = read - read.floor();
+                    for (channel, out) in out.iter_mut().enumerate() {
+                        let a = delay[index][channel];
+                        let b = delay[next][channel];
+                        *out += (a + (b - a) * t) * gain;
+                    }
+                }
+                frame[0] = out[0];
+                frame[1] = out[1];
+                *write = (*write + 1) % PITCH_WINDOW;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// `x` modulo `m`, but never negative
+fn wrap(x: f32, m: f32) -> f32 {
+    let r = x % m;
+    if r < 0.0 {
+        r + m
+    } else {
+        r
+    }
+}
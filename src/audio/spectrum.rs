@@ -0,0 +1,154 @@
+use crate::audio::Mixer;
+use std::f32::consts::PI;
+
+/// Splits audio into frequency bands for visualizers. Each `update` takes a Hann windowed FFT of
+/// the newest output and sums it into bands spaced evenly in octaves, which is closer to how it
+/// sounds than spacing them evenly in Hz
+#[derive(Debug, Clone)]
+pub struct Spectrum {
+    size: usize,
+    sample_rate: u32,
+    /// 0 follows the audio exactly, closer to 1 falls off slower. Rises are never smoothed so
+    /// beats still hit right away
+    pub smoothing: f32,
+    /// FFT bin ranges
+    ranges: Vec<(usize, usize)>,
+    bands: Vec<f32>,
+    bins: Vec<f32>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl Spectrum {
+    /// `size` is the FFT length and gets rounded up to a power of two, bigger is finer in
+    /// frequency and coarser in time. The bands go from `min_hz` to `max_hz`
+    pub fn new(size: usize, bands: usize, sample_rate: u32, min_hz: f32, max_hz: f32) -> Self {
+        let size = size.max(2).next_power_of_two();
+        let bin_hz = sample_rate as f32 / size as f32;
+        let (min_hz, max_hz) = (min_hz.max(bin_hz), max_hz.min(sample_rate as f32 / 2.0));
+        let ratio = (max_hz / min_hz).max(1.0);
+        let bin = |hz: f32| ((hz / bin_hz).round() as usize).min(size / 2);
+        let ranges = (0..bands)
+            .map(|i| {
+                let low = min_hz * ratio.powf(i as f32 / bands as f32);
+                let high = min_hz * ratio.powf((i + 1) as f32 / bands as f32);
+                // low bands can be narrower than a bin, they get the one bin they start in
+                let start = bin(low).min(size / 2);
+                (start, bin(high).max(start + 1).min(size / 2 + 1))
+            })
+            .collect();
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        Spectrum {
+            size,
+            sample_rate,
+            smoothing: 0.6,
+            ranges,
+            bands: vec![0.0; bands],
+            bins: vec![0.0; size / 2 + 1],
+            window,
+            input: vec![0.0; size],
+            re: vec![0.0; size],
+            im: vec![0.0; size],
+        }
+    }
+
+    pub fn with_smoothing(self, smoothing: f32) -> Self {
+        Spectrum { smoothing, ..self }
+    }
+
+    /// Analyzes what the mixer played last, call it once a frame with the mixer locked
+    pub fn update(&mut self, mixer: &Mixer) {
+        let mut input = std::mem::replace(&mut self.input, Vec::new());
+        for sample in &mut input {
+            *sample = 0.0;
+        }
+        mixer.recent_output(&mut input);
+        self.analyze(&input);
+        self.input = input;
+    }
+
+    /// Analyzes the newest `size` samples of mono audio from anywhere else, like a `CaptureBuffer`
+    pub fn analyze(&mut self, samples: &[f32]) {
+        let skip = samples.len().saturating_sub(self.size);
+        for (i, re) in self.re.iter_mut().enumerate() {
+            *re = samples.get(skip + i).cloned().unwrap_or(0.0) * self.window[i];
+        }
+        for im in &mut self.im {
+            *im = 0.0;
+        }
+        fft(&mut self.re, &mut self.im);
+
+        // the Hann window halves the amplitude, so a full scale sine comes out at about 1
+        let scale = 4.0 / self.size as f32;
+        for (i, bin) in self.bins.iter_mut().enumerate() {
+            *bin = (self.re[i] * self.re[i] + self.im[i] * self.im[i]).sqrt() * scale;
+        }
+        for (band, &(start, end)) in self.bands.iter_mut().zip(&self.ranges) {
+            let peak = self.bins[start..end].iter().cloned().fold(0.0, f32::max);
+            *band = if peak > *band {
+                peak
+            } else {
+                *band * self.smoothing + peak * (1.0 - self.smoothing)
+            };
+        }
+    }
+
+    /// Per band magnitudes from low to high, around 1 for a full scale tone
+    pub fn bands(&self) -> &[f32] {
+        &self.bands
+    }
+
+    /// The raw FFT magnitudes from 0 Hz up to half the sample rate, unsmoothed
+    pub fn bins(&self) -> &[f32] {
+        &self.bins
+    }
+
+    /// The frequency in the middle of `bin`
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / self.size as f32
+    }
+}
+
+/// In place radix-2 FFT, the length has to be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    // bit reversed order first, so the butterflies can work on neighbours
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        let (w_re, w_im) = (angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let (mut t_re, mut t_im) = (1.0f32, 0.0f32);
+            for k in 0..len / 2 {
+                let (a, b) = (start + k, start + k + len / 2);
+                let b_re = re[b] * t_re - im[b] * t_im;
+                let b_im = re[b] * t_im + im[b] * t_re;
+                re[b] = re[a] - b_re;
+                im[b] = im[a] - b_im;
+                re[a] += b_re;
+                im[a] += b_im;
+                let next = t_re * w_re - t_im * w_im;
+                t_im = t_re * w_im + t_im * w_re;
+                t_re = next;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,728 @@
+#[cfg(feature = "discord")]
+use crate::discord::{DiscordPresence, Presence};
+#[cfg(feature = "settings")]
+use crate::settings::Settings;
+#[cfg(feature = "steam")]
+use crate::steam::Steam;
+use crate::{
+    assets::{AssetSource, Vfs},
+    crash::{self, CrashReporter, LogHistory},
+    event::{EventHandler, Key, MouseMove},
+    graphics::{
+        Animation, DrawableSize, HalState, HalStateBuilder, SamplerDesc, TextureError, TextureId,
+        Vsync,
+    },
+    jobs::{self, JobPool},
+    random::Rng,
+    run::{self, LoopSettings, RedrawHandle, WindowSettings},
+    timer::{self, Clock, Scheduler},
+    transition::{Scene, SceneChange, Transition},
+};
+use slog::{Drain, Logger};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::{LogicalPosition, LogicalSize},
+    MouseScrollDelta, VirtualKeyCode, Window,
+};
+
+/// Everything a game needs to get at while it runs, so a hello world is a `ContextBuilder` and an
+/// `update` and `draw`
+#[derive(Debug)]
+pub struct Context {
+    pub window: WindowState,
+    pub graphics: HalState,
+    pub input: InputState,
+    pub clock: Clock,
+    /// Advanced by the clock's delta at the start of every update, before the handler's. Callbacks
+    /// get the `Context`
+    pub scheduler: Scheduler<Context>,
+    /// Where `load_texture` and friends read from
+    pub assets: Vfs,
+    /// Seeded once per session, `reseed` it with the replay's seed before playing one back. Use
+    /// `Rng::derive` for effects so they don't change what gameplay rolls
+    pub rng: Rng,
+    /// For loading and crunching in the background, `JobPool::spawn_then` hands results back
+    /// before an update
+    pub jobs: JobPool,
+    pub(crate) scene_change: SceneChange,
+    #[cfg(feature = "discord")]
+    discord: Option<DiscordPresence>,
+    /// Only there when the `ContextBuilder` asked for it
+    #[cfg(feature = "steam")]
+    pub steam: Option<Steam>,
+    crash: Option<CrashReporter>,
+    redraw: RedrawHandle,
+    log: Logger,
+}
+
+impl Context {
+    pub fn logger(&self) -> &Logger {
+        &self.log
+    }
+
+    /// Another frame for a `Redraw::OnDemand` loop, see `redraw_handle` for other threads.
+    /// Finished jobs ask for one by themselves
+    pub fn request_redraw(&self) {
+        self.redraw.request_redraw();
+    }
+
+    pub fn redraw_handle(&self) -> RedrawHandle {
+        self.redraw.clone()
+    }
+
+    /// Reads `path` from `assets`, read errors get logged
+    pub fn read_asset(&self, path: &str) -> Result<Vec<u8>, &'static str> {
+        self.assets.read(path).map_err(|e| {
+            error!(self.log, "couldn't read asset"; "path" => path, "io_error" => %e);
+            "Couldn't read the asset!"
+        })
+    }
+
+    /// Reads `path` from `assets` and loads it as a texture. Errors get logged along with whatever
+    /// the decoder said, since the `&'static str` loses it
+    pub fn load_texture(&mut self, path: &str) -> Result<TextureId, &'static str> {
+        let data = self.read_asset(path)?;
+        let result = self.graphics.load_texture(&data);
+        result.map_err(|e| self.texture_error(path, e))
+    }
+
+    pub fn load_texture_with_sampler(
+        &mut self,
+        path: &str,
+        sampler: SamplerDesc,
+    ) -> Result<TextureId, &'static str> {
+        let data = self.read_asset(path)?;
+        let result = self.graphics.load_texture_with_sampler(&data, sampler);
+        result.map_err(|e| self.texture_error(path, e))
+    }
+
+    fn texture_error(&self, path: &str, e: TextureError) -> &'static str {
+        error!(self.log, "couldn't load texture"; "path" => path, "error" => %e);
+        e.into()
+    }
+
+    pub fn load_animated_texture(
+        &mut self,
+        path: &str,
+    ) -> Result<(TextureId, Animation), &'static str> {
+        let data = self.read_asset(path)?;
+        self.graphics.load_animated_texture(&data)
+    }
+
+    #[cfg(feature = "svg")]
+    pub fn load_svg(&mut self, path: &str, scale: f32) -> Result<TextureId, &'static str> {
+        let data = self.read_asset(path)?;
+        self.graphics.load_svg(&data, scale)
+    }
+
+    /// Shows what the player's doing in Discord, does nothing unless the `ContextBuilder` got a
+    /// `discord` client id
+    #[cfg(feature = "discord")]
+    pub fn set_presence(&mut self, presence: Presence) {
+        if let Some(discord) = &self.discord {
+            discord.set(presence);
+        }
+    }
+
+    #[cfg(feature = "discord")]
+    pub fn clear_presence(&mut self) {
+        if let Some(discord) = &self.discord {
+            discord.clear();
+        }
+    }
+
+    /// Switches to `scene` once the current update is over. Only does anything when the handler
+    /// is a `SceneRunner`
+    pub fn transition_to(&mut self, scene: Box<dyn Scene>, transition: Transition) {
+        self.scene_change.0 = Some((scene, transition));
+    }
+}
+
+/// The window and what's been heard about it through events
+pub struct WindowState {
+    window: Arc<Window>,
+    hidpi_factor: f64,
+    focused: bool,
+}
+
+impl std::fmt::Debug for WindowState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WindowState")
+            .field("hidpi_factor", &self.hidpi_factor)
+            .field("focused", &self.focused)
+            .finish()
+    }
+}
+
+impl WindowState {
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn hidpi_factor(&self) -> f64 {
+        self.hidpi_factor
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// None once the window is gone
+    pub fn inner_size(&self) -> Option<LogicalSize> {
+        self.window.get_inner_size()
+    }
+}
+
+/// Named actions and the keys that trigger them, so players can rebind controls without the game
+/// caring which key is which
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "settings",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct KeyBindings(HashMap<String, Vec<VirtualKeyCode>>);
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        KeyBindings::default()
+    }
+
+    /// Replaces whatever `action` was bound to before
+    pub fn bind(&mut self, action: &str, keys: &[VirtualKeyCode]) {
+        self.0.insert(action.to_string(), keys.to_vec());
+    }
+
+    pub fn with(mut self, action: &str, keys: &[VirtualKeyCode]) -> Self {
+        self.bind(action, keys);
+        self
+    }
+
+    /// Empty for actions that aren't bound
+    pub fn keys(&self, action: &str) -> &[VirtualKeyCode] {
+        self.0.get(action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Keeps the bindings in `other` that aren't in here, for filling in actions a saved config
+    /// doesn't know about yet
+    pub fn merge_defaults(&mut self, other: &KeyBindings) {
+        for (action, keys) in &other.0 {
+            self.0.entry(action.clone()).or_insert_with(|| keys.clone());
+        }
+    }
+}
+
+/// Keyboard and mouse state for polling during `update`, the pressed and released sets only cover
+/// what happened since the last update
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    held: HashSet<VirtualKeyCode>,
+    pressed: HashSet<VirtualKeyCode>,
+    released: HashSet<VirtualKeyCode>,
+    mouse_position: Option<LogicalPosition>,
+    /// Lines scrolled since the last update, pixel deltas from touchpads are counted as lines
+    /// of 20 pixels
+    wheel: (f32, f32),
+    text: String,
+    key_presses: Vec<Key>,
+    bindings: KeyBindings,
+    /// From `set_action`
+    actions_held: HashSet<String>,
+    actions_pressed: HashSet<String>,
+    actions_released: HashSet<String>,
+}
+
+impl InputState {
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    pub fn was_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn was_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.released.contains(&key)
+    }
+
+    /// None until the cursor has moved over the window
+    pub fn mouse_position(&self) -> Option<LogicalPosition> {
+        self.mouse_position
+    }
+
+    pub fn wheel(&self) -> (f32, f32) {
+        self.wheel
+    }
+
+    /// What got typed since the last update, without control characters
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Every key press since the last update in order, including key repeats, for text editing
+    /// and menus that scroll while a key is held
+    pub fn key_presses(&self) -> &[Key] {
+        &self.key_presses
+    }
+
+    pub fn bindings(&self) -> &KeyBindings {
+        &self.bindings
+    }
+
+    /// For rebinding from an options menu, save them with `Settings` to keep them
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    /// Any of the keys bound to `action` is down, or something else like a controller said it's
+    /// down through `set_action`
+    pub fn is_action_down(&self, action: &str) -> bool {
+        self.actions_held.contains(action)
+            || self
+                .bindings
+                .keys(action)
+                .iter()
+                .any(|&key| self.is_key_down(key))
+    }
+
+    pub fn was_action_pressed(&self, action: &str) -> bool {
+        self.actions_pressed.contains(action)
+            || self
+                .bindings
+                .keys(action)
+                .iter()
+                .any(|&key| self.was_key_pressed(key))
+    }
+
+    pub fn was_action_released(&self, action: &str) -> bool {
+        self.actions_released.contains(action)
+            || self
+                .bindings
+                .keys(action)
+                .iter()
+                .any(|&key| self.was_key_released(key))
+    }
+
+    /// For input that doesn't come in as keys, like Steam Input or a game's own controller code.
+    /// Call it whenever the state's known, the pressed and released edges get worked out here
+    pub fn set_action(&mut self, action: &str, down: bool) {
+        if down {
+            if !self.actions_held.contains(action) {
+                self.actions_held.insert(action.to_string());
+                self.actions_pressed.insert(action.to_string());
+            }
+        } else if self.actions_held.remove(action) {
+            self.actions_released.insert(action.to_string());
+        }
+    }
+
+    fn key_down(&mut self, key: &Key) {
+        self.key_presses.push(*key);
+        if let Some(code) = key.virtual_keycode {
+            // key repeat sends more presses without releases in between
+            if self.held.insert(code) {
+                self.pressed.insert(code);
+            }
+        }
+    }
+
+    fn key_up(&mut self, key: &Key) {
+        if let Some(code) = key.virtual_keycode {
+            self.held.remove(&code);
+            self.released.insert(code);
+        }
+    }
+
+    fn scroll(&mut self, delta: MouseScrollDelta) {
+        let (x, y) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x, y),
+            MouseScrollDelta::PixelDelta(position) => {
+                ((position.x / 20.0) as f32, (position.y / 20.0) as f32)
+            }
+        };
+        self.wheel.0 += x;
+        self.wheel.1 += y;
+    }
+
+    fn end_update(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.wheel = (0.0, 0.0);
+        self.text.clear();
+        self.key_presses.clear();
+        self.actions_pressed.clear();
+        self.actions_released.clear();
+    }
+
+    /// Releases come in for the window that has focus, so anything held when focus went away would
+    /// stay held forever
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+        self.actions_released.extend(self.actions_held.drain());
+    }
+}
+
+/// What `ContextBuilder::run` drives, the `Context` version of `EventHandler`. Input gets polled
+/// from `ctx.input` instead of coming in through callbacks
+pub trait ContextHandler {
+    fn update(&mut self, ctx: &mut Context);
+    fn draw(&mut self, ctx: &mut Context);
+    /// The swapchain has already been recreated for the new size, in physical pixels
+    fn on_resize(&mut self, _ctx: &mut Context, _width: u32, _height: u32) {}
+    /// Comes after `on_resize` with the size the swapchain actually ended up with, and once before
+    /// the first update. Use this one for cameras and UI scaling
+    fn on_drawable_resize(&mut self, _ctx: &mut Context, _size: DrawableSize) {}
+    /// See `EventHandler::fell_behind`, only happens with `Timestep::Fixed`
+    fn fell_behind(&mut self, _ctx: &mut Context, _dropped: Duration) {}
+    /// See `EventHandler::next_redraw`, only asked with `Redraw::OnDemand`
+    fn next_redraw(&mut self, _ctx: &mut Context) -> Option<Instant> {
+        None
+    }
+    fn quit(&mut self, _ctx: &mut Context) -> bool {
+        true
+    }
+}
+
+/// Sets up the window, graphics and logging for a `Context` and runs it
+#[derive(Debug, Clone)]
+pub struct ContextBuilder {
+    window: WindowSettings,
+    loop_settings: LoopSettings,
+    graphics: HalStateBuilder,
+    /// The player's saved vsync, it goes over `low_latency` so it has to wait until `build`
+    vsync: Option<Vsync>,
+    assets: Vfs,
+    bindings: KeyBindings,
+    seed: Option<u64>,
+    job_threads: usize,
+    #[cfg(feature = "discord")]
+    discord_client_id: Option<String>,
+    #[cfg(feature = "steam")]
+    steam: bool,
+    crash_dir: Option<PathBuf>,
+    log: Logger,
+}
+
+impl ContextBuilder {
+    /// Uses `title` for both the window and the driver's application name, logs go nowhere until
+    /// there's a `logger`
+    pub fn new(title: &str) -> Self {
+        ContextBuilder {
+            window: WindowSettings {
+                title: title.to_string(),
+                ..WindowSettings::default()
+            },
+            loop_settings: LoopSettings::default(),
+            graphics: HalStateBuilder::new().name(title),
+            vsync: None,
+            assets: Vfs::new(),
+            bindings: KeyBindings::default(),
+            seed: None,
+            job_threads: 2,
+            #[cfg(feature = "discord")]
+            discord_client_id: None,
+            #[cfg(feature = "steam")]
+            steam: false,
+            crash_dir: None,
+            log: Logger::root(slog::Discard, o!()),
+        }
+    }
+
+    pub fn window(mut self, window: WindowSettings) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// With `Timestep::Fixed`, `ctx.clock.delta()` is always the step. `Redraw::OnDemand` is for
+    /// tools and editors
+    pub fn loop_settings(mut self, loop_settings: LoopSettings) -> Self {
+        self.loop_settings = loop_settings;
+        self
+    }
+
+    /// `WindowSettings::performance_mode` turns on `HalStateBuilder::low_latency` on top of this,
+    /// and a vsync mode from `settings` goes over both
+    pub fn graphics(mut self, graphics: HalStateBuilder) -> Self {
+        self.graphics = graphics;
+        self
+    }
+
+    /// Nothing's mounted by default
+    pub fn assets(mut self, assets: Vfs) -> Self {
+        self.assets = assets;
+        self
+    }
+
+    pub fn key_bindings(mut self, bindings: KeyBindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Puts a player's saved settings over what's been set so far. Call it after `window`,
+    /// `graphics` and `key_bindings`, which would throw the settings away otherwise. Bindings the
+    /// settings don't have keep the game's defaults
+    #[cfg(feature = "settings")]
+    pub fn settings(mut self, settings: &Settings) -> Self {
+        if let Some((width, height)) = settings.resolution {
+            self.window.size = LogicalSize::new(width, height);
+        }
+        if let Some(fullscreen) = settings.fullscreen {
+            self.window.performance_mode = fullscreen;
+        }
+        if settings.vsync.is_some() {
+            self.vsync = settings.vsync;
+        }
+        let mut bindings = settings.key_bindings.clone();
+        bindings.merge_defaults(&self.bindings);
+        self.bindings = bindings;
+        self
+    }
+
+    /// For tests and replays, otherwise the seed comes from the clock. It gets logged either way
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Two by default, which leaves cores for the render and event threads
+    pub fn job_threads(mut self, job_threads: usize) -> Self {
+        self.job_threads = job_threads;
+        self
+    }
+
+    /// Turns on `Context::set_presence`, `client_id` is the application id from the Discord
+    /// developer portal
+    #[cfg(feature = "discord")]
+    pub fn discord(mut self, client_id: &str) -> Self {
+        self.discord_client_id = Some(client_id.to_string());
+        self
+    }
+
+    /// Starts Steamworks before the window opens, `run` fails if Steam isn't running
+    #[cfg(feature = "steam")]
+    pub fn steam(mut self) -> Self {
+        self.steam = true;
+        self
+    }
+
+    /// Writes a crash file to `dir` when anything panics, with the graphics device, swapchain
+    /// setup and the last log lines in it. See `crash::install`
+    pub fn crash_reports(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.crash_dir = Some(dir.into());
+        self
+    }
+
+    pub fn logger(mut self, log: Logger) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Opens the window, then `make_handler` gets the `Context` to load textures and such before
+    /// the first update. See `run::run` for how the threads are set up
+    pub fn run<H, F>(self, make_handler: F) -> Result<(), &'static str>
+    where
+        H: ContextHandler,
+        F: FnOnce(&mut Context) -> Result<H, &'static str>,
+    {
+        let ContextBuilder {
+            window,
+            loop_settings,
+            graphics,
+            vsync,
+            assets,
+            bindings,
+            seed,
+            job_threads,
+            #[cfg(feature = "discord")]
+            discord_client_id,
+            #[cfg(feature = "steam")]
+            steam,
+            crash_dir,
+            log,
+        } = self;
+        // before anything logs, so the crash file has all of it
+        let (log, crash) = match crash_dir {
+            Some(dir) => {
+                let history = LogHistory::new();
+                let log = Logger::root(slog::Duplicate::new(log, history.clone()).fuse(), o!());
+                (log, Some(crash::install(dir, history)))
+            }
+            None => (log, None),
+        };
+        let graphics = if window.performance_mode {
+            graphics.low_latency()
+        } else {
+            graphics
+        };
+        // the player picking a vsync mode wins over what performance mode would pick
+        let graphics = match vsync {
+            Some(vsync) => graphics.vsync(vsync.preferred()),
+            None => graphics,
+        };
+        // the overlay only hooks into graphics devices created after this
+        #[cfg(feature = "steam")]
+        let steam = if steam {
+            Some(Steam::init(log.new(o!("subsystem" => "steam")))?)
+        } else {
+            None
+        };
+        let rng = seed.map_or_else(Rng::from_time, Rng::new);
+        info!(log, "session seed"; "seed" => rng.seed());
+        let context_log = log.clone();
+        run::run_with(window, loop_settings, log, move |window, redraw| {
+            let mut ctx = Context {
+                window: WindowState {
+                    window: Arc::clone(window),
+                    hidpi_factor: window.get_hidpi_factor(),
+                    focused: true,
+                },
+                graphics: graphics.build(window, context_log.new(o!("subsystem" => "graphics")))?,
+                input: InputState {
+                    bindings,
+                    ..InputState::default()
+                },
+                clock: Clock::new(Instant::now()),
+                scheduler: Scheduler::new(),
+                assets,
+                rng,
+                jobs: JobPool::new(job_threads, context_log.new(o!("subsystem" => "jobs")))?,
+                scene_change: SceneChange::default(),
+                #[cfg(feature = "discord")]
+                discord: match discord_client_id {
+                    Some(id) => Some(DiscordPresence::new(
+                        &id,
+                        context_log.new(o!("subsystem" => "discord")),
+                    )?),
+                    None => None,
+                },
+                #[cfg(feature = "steam")]
+                steam,
+                crash,
+                redraw: redraw.clone(),
+                log: context_log,
+            };
+            ctx.jobs.set_redraw(redraw);
+            if let Some(crash) = &ctx.crash {
+                crash.set_device_info(ctx.graphics.device_info());
+            }
+            let mut handler = make_handler(&mut ctx)?;
+            let size = ctx.graphics.swapchain_info().drawable_size();
+            handler.on_drawable_resize(&mut ctx, size);
+            Ok(Running { ctx, handler })
+        })
+    }
+}
+
+/// Feeds the run loop's events into a `Context`
+struct Running<H> {
+    ctx: Context,
+    handler: H,
+}
+
+impl<H: ContextHandler> Running<H> {
+    /// Everything an update does after the clock moved on
+    fn run_update(&mut self) {
+        #[cfg(feature = "steam")]
+        {
+            let ctx = &mut self.ctx;
+            if let Some(steam) = &mut ctx.steam {
+                steam.update(&mut ctx.input);
+            }
+        }
+        jobs::run_finished(&mut self.ctx);
+        let delta = self.ctx.clock.delta();
+        timer::advance_context(&mut self.ctx, delta);
+        self.handler.update(&mut self.ctx);
+        self.ctx.input.end_update();
+    }
+}
+
+impl<H: ContextHandler> EventHandler for Running<H> {
+    fn draw(&mut self) {
+        self.handler.draw(&mut self.ctx);
+    }
+
+    fn update(&mut self) {
+        self.ctx.clock.tick(Instant::now());
+        self.run_update();
+    }
+
+    fn fixed_update(&mut self, step: Duration) {
+        self.ctx.clock.step(step);
+        self.run_update();
+    }
+
+    fn fell_behind(&mut self, _time: Instant, dropped: Duration) {
+        debug!(self.ctx.log, "fell behind, dropped some updates";
+               "dropped" => %format_args!("{:?}", dropped));
+        self.handler.fell_behind(&mut self.ctx, dropped);
+    }
+
+    fn next_redraw(&mut self) -> Option<Instant> {
+        self.handler.next_redraw(&mut self.ctx)
+    }
+
+    fn key_down(&mut self, _time: Instant, key: Key) {
+        self.ctx.input.key_down(&key);
+    }
+
+    fn key_up(&mut self, _time: Instant, key: Key) {
+        self.ctx.input.key_up(&key);
+    }
+
+    fn received_character(&mut self, _time: Instant, c: char) {
+        if !c.is_control() {
+            self.ctx.input.text.push(c);
+        }
+    }
+
+    fn mouse_move(&mut self, _time: Instant, motion: MouseMove) {
+        self.ctx.input.mouse_position = Some(motion.position);
+    }
+
+    fn mouse_wheel(&mut self, _time: Instant, scroll: MouseScrollDelta) {
+        self.ctx.input.scroll(scroll);
+    }
+
+    fn hidpi_factor_changed(&mut self, _time: Instant, factor: f64) {
+        self.ctx.window.hidpi_factor = factor;
+    }
+
+    fn on_resize(&mut self, _time: Instant, width: u32, height: u32) {
+        let ctx = &mut self.ctx;
+        debug!(ctx.log, "window resized"; "width" => width, "height" => height);
+        let size = match ctx.graphics.handle_resize(&ctx.window.window) {
+            Ok(size) => size,
+            Err(e) => {
+                error!(ctx.log, "couldn't recreate the swapchain"; "error" => e);
+                return;
+            }
+        };
+        if let Some(crash) = &ctx.crash {
+            crash.set_device_info(ctx.graphics.device_info());
+        }
+        self.handler.on_resize(&mut self.ctx, width, height);
+        self.handler.on_drawable_resize(&mut self.ctx, size);
+    }
+
+    fn window_focused(&mut self, _time: Instant, focused: bool) -> bool {
+        self.ctx.window.focused = focused;
+        if !focused {
+            self.ctx.input.release_all();
+        }
+        !focused
+    }
+
+    fn on_resume(&mut self, time: Instant) {
+        self.ctx.clock.resume(time);
+    }
+
+    fn quit(&mut self) -> bool {
+        self.handler.quit(&mut self.ctx)
+    }
+}
@@ -0,0 +1,213 @@
+use crate::graphics::DeviceInfo;
+use slog::{Drain, Level, Never, OwnedKVList, Record, KV};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    panic::{self, PanicInfo},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Log lines kept for a crash report
+const LOG_LINES: usize = 200;
+/// Backend warnings and validation messages kept for a crash report
+const VALIDATION_LINES: usize = 50;
+
+/// A slog drain that remembers the newest log lines, put it next to the real drain with
+/// `slog::Duplicate`. Warnings and errors from the gfx backends, which is where validation layer
+/// output ends up when the `log` crate gets routed into slog with slog-stdlog, get kept in a list
+/// of their own so a flood of other logging can't push them out
+#[derive(Debug, Clone)]
+pub struct LogHistory {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    validation: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl Default for LogHistory {
+    fn default() -> Self {
+        LogHistory {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(LOG_LINES))),
+            validation: Arc::new(Mutex::new(VecDeque::with_capacity(VALIDATION_LINES))),
+        }
+    }
+}
+
+impl LogHistory {
+    pub fn new() -> Self {
+        LogHistory::default()
+    }
+
+    /// Oldest first
+    pub fn lines(&self) -> Vec<String> {
+        lock_lines(&self.lines)
+    }
+
+    pub fn validation_messages(&self) -> Vec<String> {
+        lock_lines(&self.validation)
+    }
+}
+
+fn lock_lines(lines: &Mutex<VecDeque<String>>) -> Vec<String> {
+    // try_lock, this runs in the panic hook and the panic could have come from inside `push`
+    match lines.try_lock() {
+        Ok(lines) => lines.iter().cloned().collect(),
+        Err(_) => vec!["(the log was locked)".to_string()],
+    }
+}
+
+fn push(lines: &Mutex<VecDeque<String>>, capacity: usize, line: &str) {
+    if let Ok(mut lines) = lines.lock() {
+        if lines.len() == capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+}
+
+impl Drain for LogHistory {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        let mut line = format!(
+            "{} {} [{}]",
+            record.level().as_short_str(),
+            record.msg(),
+            record.module()
+        );
+        let mut serializer = LineSerializer(&mut line);
+        let _ = record.kv().serialize(record, &mut serializer);
+        let _ = values.serialize(record, &mut serializer);
+        if record.module().starts_with("gfx_backend") && record.level().is_at_least(Level::Warning)
+        {
+            push(&self.validation, VALIDATION_LINES, &line);
+        }
+        push(&self.lines, LOG_LINES, &line);
+        Ok(())
+    }
+}
+
+/// Appends key-value pairs to a log line
+struct LineSerializer<'a>(&'a mut String);
+
+impl<'a> slog::Serializer for LineSerializer<'a> {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        let _ = write!(self.0, ", {}: {}", key, val);
+        Ok(())
+    }
+}
+
+/// Writes a crash file when anything panics, see `install`. Clones share the device info
+#[derive(Debug, Clone)]
+pub struct CrashReporter {
+    dir: PathBuf,
+    history: LogHistory,
+    device: Arc<Mutex<Option<DeviceInfo>>>,
+}
+
+impl CrashReporter {
+    /// `Context` keeps this up to date on its own
+    pub fn set_device_info(&self, info: DeviceInfo) {
+        if let Ok(mut device) = self.device.lock() {
+            *device = Some(info);
+        }
+    }
+
+    fn report(&self, info: &PanicInfo) -> String {
+        let mut report = String::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let _ = writeln!(report, "rmge {} crash report", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(report, "time: {} (unix)", now);
+        let _ = writeln!(
+            report,
+            "thread: {}",
+            thread::current().name().unwrap_or("<unnamed>")
+        );
+        let _ = writeln!(report, "{}", info);
+
+        let _ = writeln!(report, "\n== device ==");
+        match self
+            .device
+            .try_lock()
+            .ok()
+            .and_then(|device| device.clone())
+        {
+            Some(device) => {
+                let _ = writeln!(report, "{:#?}", device);
+            }
+            None => {
+                let _ = writeln!(report, "graphics weren't up yet");
+            }
+        }
+
+        let _ = writeln!(report, "\n== backend warnings and validation ==");
+        for line in self.history.validation_messages() {
+            let _ = writeln!(report, "{}", line);
+        }
+        let _ = writeln!(report, "\n== last log lines ==");
+        for line in self.history.lines() {
+            let _ = writeln!(report, "{}", line);
+        }
+        report
+    }
+}
+
+/// Sets a panic hook that writes everything a "black screen on my machine" report needs to a
+/// crash-<time>-<pid>.txt in `dir`: the panic, the adapter, backend and swapchain setup, backend
+/// warnings and the last few hundred log lines from `history`. The hook that was there before
+/// still runs after it. `ContextBuilder::crash_reports` does all of this for a `Context`
+pub fn install(dir: impl Into<PathBuf>, history: LogHistory) -> CrashReporter {
+    let reporter = CrashReporter {
+        dir: dir.into(),
+        history,
+        device: Arc::new(Mutex::new(None)),
+    };
+    let hook_reporter = reporter.clone();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = hook_reporter.report(info);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let name = format!("crash-{}-{}", now, std::process::id());
+        match write_report(&hook_reporter.dir, &name, &report) {
+            Ok(path) => eprintln!("wrote a crash report to {}", path.display()),
+            Err(e) => eprintln!(
+                "couldn't write a crash report to {}: {}",
+                hook_reporter.dir.display(),
+                e
+            ),
+        }
+        previous(info);
+    }));
+    reporter
+}
+
+/// Never overwrites another report, threads panicking in the same second get a counter after the
+/// name
+fn write_report(dir: &Path, name: &str, report: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    for attempt in 0..100 {
+        let path = match attempt {
+            0 => dir.join(format!("{}.txt", name)),
+            n => dir.join(format!("{}-{}.txt", name, n)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => return file.write_all(report.as_bytes()).map(|()| path),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "too many crash reports with the same name",
+    ))
+}
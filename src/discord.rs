@@ -0,0 +1,318 @@
+use serde_json::{json, Value};
+use slog::Logger;
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long to wait before trying again when Discord isn't running
+const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+/// How long to wait before reconnecting after losing the connection, doubling every time it gets
+/// lost again soon after up to `MAX_RECONNECT_DELAY`
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(120);
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+
+/// What shows up under the player's name in Discord. Everything's optional, an empty one still
+/// shows the game as running
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Presence {
+    /// The first line, like the song's name
+    pub details: Option<String>,
+    /// The second line, like the mode or difficulty
+    pub state: Option<String>,
+    /// Discord counts up from this
+    pub start: Option<SystemTime>,
+    /// Discord counts down to this instead, when it's set
+    pub end: Option<SystemTime>,
+    /// Key of an art asset uploaded to the Discord application
+    pub large_image: Option<String>,
+    /// Tooltip on the large image
+    pub large_text: Option<String>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Presence::default()
+    }
+
+    pub fn with_details(self, details: &str) -> Self {
+        Presence {
+            details: Some(details.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_state(self, state: &str) -> Self {
+        Presence {
+            state: Some(state.to_string()),
+            ..self
+        }
+    }
+
+    /// Elapsed time counting up from `start`
+    pub fn with_start(self, start: SystemTime) -> Self {
+        Presence {
+            start: Some(start),
+            ..self
+        }
+    }
+
+    /// Time left counting down to `end`, for a song that's playing
+    pub fn with_end(self, end: SystemTime) -> Self {
+        Presence {
+            end: Some(end),
+            ..self
+        }
+    }
+
+    pub fn with_large_image(self, image: &str, text: &str) -> Self {
+        Presence {
+            large_image: Some(image.to_string()),
+            large_text: Some(text.to_string()),
+            ..self
+        }
+    }
+
+    fn to_activity(&self) -> Value {
+        let timestamp = |time: &SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0)
+        };
+        let mut activity = json!({});
+        if let Some(details) = &self.details {
+            activity["details"] = json!(details);
+        }
+        if let Some(state) = &self.state {
+            activity["state"] = json!(state);
+        }
+        let mut timestamps = json!({});
+        if let Some(start) = &self.start {
+            timestamps["start"] = json!(timestamp(start));
+        }
+        if let Some(end) = &self.end {
+            timestamps["end"] = json!(timestamp(end));
+        }
+        activity["timestamps"] = timestamps;
+        if let Some(image) = &self.large_image {
+            activity["assets"] = json!({
+                "large_image": image,
+                "large_text": self.large_text.clone().unwrap_or_default(),
+            });
+        }
+        activity
+    }
+}
+
+/// Talks to the Discord client running on the same machine over its local IPC socket. It all
+/// happens on a thread, so `set` returns right away, and if Discord isn't running it quietly keeps
+/// trying every 15 seconds with the newest presence. `Context::set_presence` uses one of these when
+/// the `ContextBuilder` got a `discord` client id
+pub struct DiscordPresence {
+    updates: Sender<Option<Presence>>,
+}
+
+impl std::fmt::Debug for DiscordPresence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DiscordPresence").finish()
+    }
+}
+
+impl DiscordPresence {
+    /// `client_id` is the application id from the Discord developer portal
+    pub fn new(client_id: &str, log: Logger) -> Result<Self, &'static str> {
+        let (updates, updates_rx) = mpsc::channel();
+        let client_id = client_id.to_string();
+        thread::Builder::new()
+            .name("rmge-discord".to_string())
+            .spawn(move || presence_thread(&client_id, &updates_rx, &log))
+            .map_err(|_| "Couldn't spawn the Discord thread!")?;
+        Ok(DiscordPresence { updates })
+    }
+
+    pub fn set(&self, presence: Presence) {
+        // the thread only stops once this is dropped
+        let _ = self.updates.send(Some(presence));
+    }
+
+    /// Back to nothing showing
+    pub fn clear(&self) {
+        let _ = self.updates.send(None);
+    }
+}
+
+fn presence_thread(client_id: &str, updates: &Receiver<Option<Presence>>, log: &Logger) {
+    let mut current = None;
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
+    loop {
+        let attempt = Instant::now();
+        let mut socket = match connect(client_id) {
+            Ok(socket) => {
+                info!(log, "connected to Discord");
+                socket
+            }
+            Err(e) => {
+                debug!(log, "couldn't connect to Discord"; "io_error" => %e);
+                // keep up with updates while waiting so the newest one goes out on connecting
+                match wait_for_retry(updates, attempt + RETRY_INTERVAL, &mut current) {
+                    Ok(()) => continue,
+                    Err(()) => return,
+                }
+            }
+        };
+        let mut nonce = 0u64;
+        let result = loop {
+            if let Err(e) = send_activity(&mut *socket, &current, &mut nonce) {
+                break Err(e);
+            }
+            match updates.recv() {
+                Ok(update) => current = update,
+                Err(_) => break Ok(()),
+            }
+        };
+        match result {
+            Ok(()) => {
+                let _ = write_frame(&mut socket, OP_CLOSE, &json!({}));
+                return;
+            }
+            Err(e) => error!(log, "lost the connection to Discord"; "io_error" => %e),
+        }
+        // a connection that stayed up for a while wasn't the problem, start over from the shortest
+        if attempt.elapsed() > MAX_RECONNECT_DELAY {
+            reconnect_delay = MIN_RECONNECT_DELAY;
+        }
+        match wait_for_retry(updates, Instant::now() + reconnect_delay, &mut current) {
+            Ok(()) => reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY),
+            Err(()) => return,
+        }
+    }
+}
+
+/// Keeps taking updates until `retry_at` so `current` is the newest one by then, Err when the
+/// sender's gone
+fn wait_for_retry(
+    updates: &Receiver<Option<Presence>>,
+    retry_at: Instant,
+    current: &mut Option<Presence>,
+) -> Result<(), ()> {
+    loop {
+        let now = Instant::now();
+        if now >= retry_at {
+            return Ok(());
+        }
+        match updates.recv_timeout(retry_at - now) {
+            Ok(update) => *current = update,
+            Err(RecvTimeoutError::Timeout) => return Ok(()),
+            Err(RecvTimeoutError::Disconnected) => return Err(()),
+        }
+    }
+}
+
+fn send_activity(
+    socket: &mut dyn Socket,
+    presence: &Option<Presence>,
+    nonce: &mut u64,
+) -> io::Result<()> {
+    *nonce += 1;
+    let activity = presence.as_ref().map(Presence::to_activity);
+    write_frame(
+        socket,
+        OP_FRAME,
+        &json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": activity },
+            "nonce": nonce.to_string(),
+        }),
+    )?;
+    // Discord answers every command, it has to be read so the socket doesn't fill up
+    read_frame(socket).map(|_| ())
+}
+
+trait Socket: Read + Write + Send {}
+impl<T: Read + Write + Send> Socket for T {}
+
+fn connect(client_id: &str) -> io::Result<Box<dyn Socket>> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "Discord isn't running");
+    // Discord takes the first free one, and there can be more than one client running
+    for i in 0..10 {
+        // one client refusing the handshake doesn't mean the next one will
+        match open_socket(i).and_then(|socket| handshake(socket, client_id)) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn handshake(mut socket: Box<dyn Socket>, client_id: &str) -> io::Result<Box<dyn Socket>> {
+    write_frame(
+        &mut socket,
+        OP_HANDSHAKE,
+        &json!({ "v": 1, "client_id": client_id }),
+    )?;
+    let (op, _) = read_frame(&mut socket)?;
+    if op != OP_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Discord refused the handshake",
+        ));
+    }
+    Ok(socket)
+}
+
+#[cfg(unix)]
+fn open_socket(i: u32) -> io::Result<Box<dyn Socket>> {
+    use std::{env, os::unix::net::UnixStream, path::PathBuf};
+    let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .filter_map(|var| env::var_os(var))
+        .map(PathBuf::from)
+        .next()
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    let stream = UnixStream::connect(dir.join(format!("discord-ipc-{}", i)))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(windows)]
+fn open_socket(i: u32) -> io::Result<Box<dyn Socket>> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!(r"\\?\pipe\discord-ipc-{}", i))?;
+    Ok(Box::new(pipe))
+}
+
+/// Every message is an opcode and a length, both little endian u32, then that much JSON
+fn write_frame<S: Write + ?Sized>(socket: &mut S, op: u32, payload: &Value) -> io::Result<()> {
+    let payload = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+    socket.write_all(&frame)?;
+    socket.flush()
+}
+
+fn read_frame<S: Read + ?Sized>(socket: &mut S) -> io::Result<(u32, Value)> {
+    let mut header = [0; 8];
+    socket.read_exact(&mut header)?;
+    let op = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let mut payload = vec![0; len as usize];
+    socket.read_exact(&mut payload)?;
+    if op == OP_CLOSE {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "Discord closed the connection",
+        ));
+    }
+    let payload = serde_json::from_slice(&payload)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((op, payload))
+}
@@ -1,32 +1,70 @@
-use std::time::Instant;
-use winit::{ButtonId, DeviceId, MouseScrollDelta, ScanCode, VirtualKeyCode};
+use std::time::{Duration, Instant};
+use winit::{
+    dpi::LogicalPosition, ButtonId, DeviceId, MouseScrollDelta, ScanCode, Touch, VirtualKeyCode,
+};
 
 pub trait EventHandler {
     fn draw(&mut self);
     fn update(&mut self);
+    /// Instead of `update` with `Timestep::Fixed`, `step` is always the same
+    fn fixed_update(&mut self, _step: Duration) {
+        self.update();
+    }
+    /// A fixed timestep loop got further behind than `FixedTimestep::max_lag` and skipped
+    /// `dropped` worth of updates to catch up, the game runs slower than real time until it stops
+    /// happening
+    fn fell_behind(&mut self, _time: Instant, _dropped: Duration) {}
+    /// With `Redraw::OnDemand`, asked after every draw for when the next frame should happen even
+    /// if nothing else asks for one. Animations return when their next frame is due, None sleeps
+    /// until an event or a `RedrawHandle::request_redraw`
+    fn next_redraw(&mut self) -> Option<Instant> {
+        None
+    }
     fn key_down(&mut self, _time: Instant, _key: Key) {}
     fn key_up(&mut self, _time: Instant, _key: Key) {}
+    /// Text typed into the window, after the keyboard layout and any input method had their say.
+    /// Comes on top of the key events, and includes control characters like backspace
+    fn received_character(&mut self, _time: Instant, _c: char) {}
     fn device_button_down(&mut self, _time: Instant, _button: DeviceButton) {}
     fn device_button_up(&mut self, _time: Instant, _button: DeviceButton) {}
     fn mouse_move(&mut self, _time: Instant, _motion: MouseMove) {}
     fn mouse_wheel(&mut self, _time: Instant, _scroll: MouseScrollDelta) {}
+    /// One finger went down, moved, lifted or got cancelled, `touch.id` tells the fingers apart
+    fn touch(&mut self, _time: Instant, _touch: Touch) {}
+    /// The window moved to a monitor with a different hidpi factor, `on_resize` comes right after
+    fn hidpi_factor_changed(&mut self, _time: Instant, _factor: f64) {}
+    /// The window's drawable size changed, in physical pixels. Resizes and hidpi changes that come
+    /// in together only show up once. The swapchain has to be recreated with
+    /// `HalState::handle_resize` before it's drawn to again, `Context` does that by itself
+    fn on_resize(&mut self, _time: Instant, _width: u32, _height: u32) {}
     /// This function is run whenever the user changes focus. The return value is whether to suspend the event loop while unfocused.
     /// Default is to suspend the eventloop
     fn window_focused(&mut self, _time: Instant, focused: bool) -> bool {
         !focused
     }
+    /// The run loop stopped updating and drawing because `window_focused` asked for it, or because
+    /// the app went to the background on a phone
+    fn on_suspend(&mut self, _time: Instant) {}
+    /// Focus came back after a suspend. Time kept passing in the meantime, so anything measuring
+    /// frame deltas should start over from `time`. On mobile the surface might be gone after coming
+    /// back from the background, so recreate the swapchain here
+    fn on_resume(&mut self, _time: Instant) {}
     fn quit(&mut self) -> bool {
         true
     }
 }
 
-pub struct MouseMove {/* no fields yet */}
+pub struct MouseMove {
+    /// Where the cursor is now, relative to the top left of the window
+    pub position: LogicalPosition,
+}
 
 pub struct DeviceButton {
     pub device: DeviceId,
     pub button: ButtonId,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Key {
     pub device: DeviceId,
     pub scancode: ScanCode,
@@ -34,6 +72,7 @@ pub struct Key {
     pub modifiers: KeyModifiers,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct KeyModifiers {
     pub shift: bool,
     pub ctrl: bool,
@@ -1,13 +1,18 @@
-pub use vek::geom::repr_simd::Rect;
-pub use vek::mat::repr_simd::column_major::mat2::Mat2;
-pub use vek::mat::repr_simd::column_major::mat3::Mat3;
+// Math types used all over the public API. These are vek's `repr_c` types rather than the
+// `repr_simd` ones, so they work on stable and have the same layout as plain arrays of their
+// elements, which is also what anything talking to the GPU wants
+pub use vek::geom::repr_c::Rect;
+pub use vek::mat::repr_c::column_major::mat2::Mat2;
+pub use vek::mat::repr_c::column_major::mat3::Mat3;
+pub use vek::vec::repr_c::vec2::Vec2;
+pub use vek::vec::repr_c::vec3::Vec3;
 pub use vek::vec::repr_c::vec4::Vec4 as CVec4;
-pub use vek::vec::repr_simd::vec2::Vec2;
-pub use vek::vec::repr_simd::vec3::Vec3;
 
-impl From<Rect<f32, f32>> for Quad {
+use num_traits::Float;
+
+impl<T: Float> From<Rect<T, T>> for Quad<T> {
     /// Yeah this should probably be used at some point, will remove if it never gets used when the project is becoming more stable
-    fn from(rect: Rect<f32, f32>) -> Quad {
+    fn from(rect: Rect<T, T>) -> Quad<T> {
         let Rect { x, y, w, h } = rect;
         Quad {
             top_left: Vec2 { x: x, y: y + h },
@@ -18,30 +23,32 @@ impl From<Rect<f32, f32>> for Quad {
     }
 }
 
-/// Quad of points
+/// Quad of points. The scalar defaults to f32 since that's what ends up on the GPU, use `Quad<f64>`
+/// for gameplay code that needs the precision and `cast` it at the end
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
-pub struct Quad {
-    pub top_left: Vec2<f32>,
-    pub bottom_left: Vec2<f32>,
-    pub bottom_right: Vec2<f32>,
-    pub top_right: Vec2<f32>,
+pub struct Quad<T = f32> {
+    pub top_left: Vec2<T>,
+    pub bottom_left: Vec2<T>,
+    pub bottom_right: Vec2<T>,
+    pub top_right: Vec2<T>,
 }
 
-impl Into<CVec4<Vec3<f32>>> for Quad {
-    fn into(self) -> CVec4<Vec3<f32>> {
+impl<T: Float> Into<CVec4<Vec3<T>>> for Quad<T> {
+    fn into(self) -> CVec4<Vec3<T>> {
         let Quad {
             top_left,
             bottom_left,
             bottom_right,
             top_right,
         } = self;
-        CVec4::new(top_left, bottom_left, bottom_right, top_right).map(Vec3::from_point_2d)
+        CVec4::new(top_left, bottom_left, bottom_right, top_right)
+            .map(|p| Vec3::new(p.x, p.y, T::one()))
     }
 }
 
-impl From<CVec4<Vec3<f32>>> for Quad {
-    fn from(v: CVec4<Vec3<f32>>) -> Quad {
+impl<T: Float> From<CVec4<Vec3<T>>> for Quad<T> {
+    fn from(v: CVec4<Vec3<T>>) -> Quad<T> {
         let [top_left, bottom_left, bottom_right, top_right] = v.map(conv_homogeneous).into_array();
         Quad {
             top_left,
@@ -52,36 +59,222 @@ impl From<CVec4<Vec3<f32>>> for Quad {
     }
 }
 
-fn conv_homogeneous(v: Vec3<f32>) -> Vec2<f32> {
-    Vec2::new(v.x, v.y) / v.z
+impl From<Quad<f32>> for Quad<f64> {
+    fn from(quad: Quad<f32>) -> Quad<f64> {
+        quad.cast()
+    }
+}
+
+fn conv_homogeneous<T: Float>(v: Vec3<T>) -> Vec2<T> {
+    Vec2::new(v.x / v.z, v.y / v.z)
+}
+
+/// Applies a 2D homogeneous transform to a point, written out so it works for any float
+pub fn transform_point<T: Float>(m: Mat3<T>, p: Vec2<T>) -> Vec2<T> {
+    let cols = m.cols;
+    let x = cols.x.x * p.x + cols.y.x * p.y + cols.z.x;
+    let y = cols.x.y * p.x + cols.y.y * p.y + cols.z.y;
+    let w = cols.x.z * p.x + cols.y.z * p.y + cols.z.z;
+    Vec2::new(x / w, y / w)
+}
+
+/// Rotation by `degrees` counterclockwise around `center`
+pub fn rotation_around<T: Float>(center: Vec2<T>, degrees: f64) -> Mat3<T> {
+    let radians = degrees / 360.0 * (std::f64::consts::PI * 2.0);
+    let (sin, cos) = (cast(radians.sin()), cast(radians.cos()));
+    let (zero, one) = (T::zero(), T::one());
+    Mat3::new(
+        cos,
+        -sin,
+        center.x - cos * center.x + sin * center.y,
+        sin,
+        cos,
+        center.y - sin * center.x - cos * center.y,
+        zero,
+        zero,
+        one,
+    )
+}
+
+/// Both f32 and f64 can hold any value of the other, just less precisely
+fn cast<T: Float, U: Float>(value: T) -> U {
+    num_traits::cast(value).unwrap()
+}
+
+impl<T: Float> Quad<T> {
+    pub fn transform(self, rhs: Mat3<T>) -> Quad<T> {
+        self.map(|p| transform_point(rhs, p))
+    }
+
+    /// Runs `f` on all four points
+    pub fn map<F: FnMut(Vec2<T>) -> Vec2<T>>(self, mut f: F) -> Quad<T> {
+        Quad {
+            top_left: f(self.top_left),
+            bottom_left: f(self.bottom_left),
+            bottom_right: f(self.bottom_right),
+            top_right: f(self.top_right),
+        }
+    }
+
+    /// Converts to another float type, `Quad::<f64>::cast::<f32>` is how f64 gameplay quads end up
+    /// in a `TexturedQuad`
+    pub fn cast<U: Float>(self) -> Quad<U> {
+        let point = |p: Vec2<T>| Vec2::new(cast(p.x), cast(p.y));
+        Quad {
+            top_left: point(self.top_left),
+            bottom_left: point(self.bottom_left),
+            bottom_right: point(self.bottom_right),
+            top_right: point(self.top_right),
+        }
+    }
+
+    pub fn center(&self) -> Vec2<T> {
+        let four: T = cast(4.0);
+        let points = self.points();
+        Vec2::new(
+            (points[0].x + points[1].x + points[2].x + points[3].x) / four,
+            (points[0].y + points[1].y + points[2].y + points[3].y) / four,
+        )
+    }
+
+    pub fn rotate_around_center_matrix(&self, degrees: f64) -> Mat3<T> {
+        rotation_around(self.center(), degrees)
+    }
+
+    pub fn rotate_180_around_center(self) -> Quad<T> {
+        let center = self.center();
+        self.map(|p| Vec2::new(center.x + center.x - p.x, center.y + center.y - p.y))
+    }
+
+    pub fn invert_y(self) -> Quad<T> {
+        self.map(|p| Vec2::new(p.x, -p.y))
+    }
+}
+
+impl<T: Float> Quad<T> {
+    fn points(&self) -> [Vec2<T>; 4] {
+        [
+            self.top_left,
+            self.bottom_left,
+            self.bottom_right,
+            self.top_right,
+        ]
+    }
+
+    /// Smallest axis aligned rect containing all four points, y is the bottom edge like in
+    /// `Quad::from(Rect)`
+    pub fn bounding_rect(&self) -> Rect<T, T> {
+        let points = self.points();
+        let (mut min, mut max) = (points[0], points[0]);
+        for p in &points[1..] {
+            min = Vec2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vec2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        Rect {
+            x: min.x,
+            y: min.y,
+            w: max.x - min.x,
+            h: max.y - min.y,
+        }
+    }
+
+    /// Separating axis test, assumes both quads are convex (which anything made from a Rect and
+    /// transforms is). Quads that only touch at an edge count as intersecting
+    pub fn intersects(&self, other: &Quad<T>) -> bool {
+        let (a, b) = (self.points(), other.points());
+        !separated_on_edges(&a, &a, &b) && !separated_on_edges(&b, &a, &b)
+    }
+
+    pub fn intersects_rect(&self, rect: &Rect<T, T>) -> bool {
+        // cheap AABB rejection first, most culling queries end here
+        rects_overlap(&self.bounding_rect(), rect) && self.intersects(&Quad::from(*rect))
+    }
+}
+
+/// Overlap test for two axis aligned rects, edges touching counts as overlap
+pub fn rects_overlap<T: Float>(a: &Rect<T, T>, b: &Rect<T, T>) -> bool {
+    a.x <= b.x + b.w && b.x <= a.x + a.w && a.y <= b.y + b.h && b.y <= a.y + a.h
+}
+
+fn separated_on_edges<T: Float>(
+    edges_of: &[Vec2<T>; 4],
+    a: &[Vec2<T>; 4],
+    b: &[Vec2<T>; 4],
+) -> bool {
+    (0..4).any(|i| {
+        let (from, to) = (edges_of[i], edges_of[(i + 1) % 4]);
+        let axis = Vec2::new(from.y - to.y, to.x - from.x);
+        let (a_min, a_max) = project(a, axis);
+        let (b_min, b_max) = project(b, axis);
+        a_max < b_min || b_max < a_min
+    })
+}
+
+fn project<T: Float>(points: &[Vec2<T>; 4], axis: Vec2<T>) -> (T, T) {
+    points
+        .iter()
+        .fold((T::max_value(), T::min_value()), |(min, max), p| {
+            let d = p.x * axis.x + p.y * axis.y;
+            (min.min(d), max.max(d))
+        })
 }
 
-impl Quad {
-    pub fn transform(self, rhs: Mat3<f32>) -> Quad {
-        Quad::from(<Self as Into<CVec4<Vec3<f32>>>>::into(self).map(|v| rhs * v))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Rect<f32, f32> {
+        Rect { x, y, w, h }
+    }
+
+    fn quad(x: f32, y: f32, w: f32, h: f32) -> Quad {
+        Quad::from(rect(x, y, w, h))
+    }
+
+    #[test]
+    fn bounding_rect_of_a_rotated_quad() {
+        let rotated = quad(-1.0, -1.0, 2.0, 2.0).transform(rotation_around(Vec2::zero(), 45.0));
+        let bounds = rotated.bounding_rect();
+        let half_diagonal = 2f32.sqrt();
+        for (got, want) in [bounds.x, bounds.y, bounds.w, bounds.h].iter().zip(&[
+            -half_diagonal,
+            -half_diagonal,
+            2.0 * half_diagonal,
+            2.0 * half_diagonal,
+        ]) {
+            assert!((got - want).abs() < 1e-5, "{} isn't {}", got, want);
+        }
     }
 
-    pub fn rotate_around_center_matrix(&self, degrees: f64) -> Mat3<f32> {
-        let center_point = ((self.top_left + self.bottom_right) / 2.0
-            + (self.bottom_left + self.top_right) / 2.0)
-            / 2.0;
-        let t_1: Mat3<f32> = Mat3::identity().translated_2d(center_point);
-        let t_2: Mat3<f32> = Mat3::identity().translated_2d(-center_point);
-        let r = Mat2::rotation_z((degrees / 360.0 * (std::f64::consts::PI * 2.0)) as f32);
-        t_1 * Mat3::from(r) * t_2
+    #[test]
+    fn rects_overlap_and_touch() {
+        let a = rect(0.0, 0.0, 2.0, 2.0);
+        assert!(rects_overlap(&a, &rect(1.0, 1.0, 2.0, 2.0)));
+        assert!(rects_overlap(&a, &rect(0.5, 0.5, 1.0, 1.0)));
+        // sharing an edge or a corner counts
+        assert!(rects_overlap(&a, &rect(2.0, 0.0, 1.0, 1.0)));
+        assert!(rects_overlap(&a, &rect(2.0, 2.0, 1.0, 1.0)));
+        assert!(!rects_overlap(&a, &rect(2.1, 0.0, 1.0, 1.0)));
+        assert!(!rects_overlap(&a, &rect(0.0, -1.5, 1.0, 1.0)));
     }
 
-    pub fn rotate_180_around_center(self) -> Quad {
-        let center_point = ((self.top_left + self.bottom_right) / 2.0
-            + (self.bottom_left + self.top_right) / 2.0)
-            / 2.0;
-        let t_1: Mat3<f32> = Mat3::identity().translated_2d(center_point);
-        let t_2: Mat3<f32> = Mat3::identity().translated_2d(-center_point);
-        let r = Mat3::with_diagonal(Vec3::new(-1.0, -1.0, 1.0));
-        self.transform(t_1 * r * t_2)
+    #[test]
+    fn quads_overlap_and_touch() {
+        let a = quad(0.0, 0.0, 2.0, 2.0);
+        assert!(a.intersects(&quad(1.0, 1.0, 2.0, 2.0)));
+        assert!(a.intersects(&quad(0.5, 0.5, 1.0, 1.0)));
+        assert!(a.intersects(&quad(2.0, 0.0, 1.0, 2.0)));
+        assert!(a.intersects(&quad(2.0, 2.0, 1.0, 1.0)));
+        assert!(!a.intersects(&quad(2.1, 0.0, 1.0, 2.0)));
     }
 
-    pub fn invert_y(self) -> Quad {
-        self.transform(Mat3::with_diagonal(Vec3::new(1.0, -1.0, 1.0)))
+    #[test]
+    fn rotated_quad_inside_the_bounding_rect_corner_doesnt_intersect() {
+        // a diamond's bounding rect overlaps the square, the diamond itself doesn't
+        let diamond = quad(-1.0, -1.0, 2.0, 2.0).transform(rotation_around(Vec2::zero(), 45.0));
+        let corner = rect(1.0, 1.0, 1.0, 1.0);
+        assert!(rects_overlap(&diamond.bounding_rect(), &corner));
+        assert!(!diamond.intersects_rect(&corner));
+        assert!(diamond.intersects_rect(&rect(0.5, 0.5, 1.0, 1.0)));
     }
 }
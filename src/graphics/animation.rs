@@ -0,0 +1,119 @@
+use crate::graphics::{
+    decode::{decode_image, image_error_str},
+    pixel_uv,
+};
+use image::{gif, AnimationDecoder, ImageFormat, RgbaImage};
+use std::time::Duration;
+
+/// Frames of an animated texture, in order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationFrame {
+    /// Where the frame is in the sprite sheet, normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    /// How long the frame stays up
+    pub delay: Duration,
+}
+
+impl Animation {
+    /// How long it takes to play through once
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.delay).sum()
+    }
+
+    /// The frame showing `time` after the animation started, looping animations wrap around and the
+    /// others stay on the last frame
+    pub fn frame_at(&self, time: Duration, looping: bool) -> Option<&AnimationFrame> {
+        let total = self.duration();
+        let mut time = time;
+        if looping && total > Duration::from_secs(0) {
+            let total_nanos = total.as_secs() * 1_000_000_000 + u64::from(total.subsec_nanos());
+            let nanos = time.as_secs() * 1_000_000_000 + u64::from(time.subsec_nanos());
+            time = Duration::from_nanos(nanos % total_nanos);
+        }
+        let mut start = Duration::from_secs(0);
+        for frame in &self.frames {
+            start += frame.delay;
+            if time < start {
+                return Some(frame);
+            }
+        }
+        self.frames.last()
+    }
+}
+
+/// Decodes an animated image into a sprite sheet with every frame in a grid, plus where each frame
+/// ended up. Only GIF is animated, image 0.21 can't decode APNG so those (and every other format)
+/// come out as a single frame
+pub fn decode_animation(data: &[u8]) -> Result<(RgbaImage, Animation), &'static str> {
+    let format = image::guess_format(data).map_err(|e| image_error_str(&e))?;
+    let frames: Vec<(RgbaImage, (u32, u32), Duration)> = if format == ImageFormat::GIF {
+        gif::Decoder::new(data)
+            .map_err(|e| image_error_str(&e))?
+            .into_frames()
+            .collect_frames()
+            .map_err(|e| image_error_str(&e))?
+            .into_iter()
+            .map(|frame| {
+                let delay = frame.delay();
+                let millis = u64::from(*delay.numer()) / u64::from(*delay.denom()).max(1);
+                let (left, top) = (frame.left(), frame.top());
+                (
+                    frame.into_buffer(),
+                    (left, top),
+                    Duration::from_millis(millis),
+                )
+            })
+            .collect()
+    } else {
+        let image = decode_image(data).map_err(|e| image_error_str(&e))?;
+        vec![(image, (0, 0), Duration::from_secs(0))]
+    };
+    if frames.is_empty() {
+        return Err("The image has no frames!");
+    }
+
+    // frames can be smaller than the whole thing and offset into it
+    let cell_width = frames
+        .iter()
+        .map(|(image, (left, _), _)| left + image.width())
+        .max()
+        .unwrap_or(0);
+    let cell_height = frames
+        .iter()
+        .map(|(image, (_, top), _)| top + image.height())
+        .max()
+        .unwrap_or(0);
+    // as square as possible, so big animations don't run into the max texture size as quickly
+    let columns = (frames.len() as f64).sqrt().ceil() as u32;
+    let rows = (frames.len() as u32 + columns - 1) / columns;
+    let mut sheet = RgbaImage::new(cell_width * columns, cell_height * rows);
+
+    let mut animation = Animation {
+        frames: Vec::with_capacity(frames.len()),
+    };
+    for (i, (image, (left, top), delay)) in frames.into_iter().enumerate() {
+        let x = (i as u32 % columns) * cell_width;
+        let y = (i as u32 / columns) * cell_height;
+        for (px, py, pixel) in image.enumerate_pixels() {
+            sheet.put_pixel(x + left + px, y + top + py, *pixel);
+        }
+        animation.frames.push(AnimationFrame {
+            uv_rect: pixel_uv(
+                sheet.dimensions(),
+                [
+                    x as f32,
+                    y as f32,
+                    (x + cell_width) as f32,
+                    (y + cell_height) as f32,
+                ],
+            ),
+            delay,
+        });
+    }
+    Ok((sheet, animation))
+}
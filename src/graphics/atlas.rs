@@ -0,0 +1,321 @@
+use crate::graphics::{loadedimage::TextureId, pixel_uv, vertex::Vertex, HalState};
+use image::RgbaImage;
+use std::{collections::HashMap, hash::Hash};
+
+/// Packs lots of small images (glyphs, icons, ...) into one texture at runtime, so they can all be
+/// drawn in one batch and only take up one slot in the texture array
+#[derive(Debug, Clone)]
+pub struct Packer<K: Hash + Eq> {
+    packer: ShelfPacker,
+    image: RgbaImage,
+    /// Along with the size of the space each one got, which smaller images can reuse
+    regions: HashMap<K, (AtlasRegion, (u32, u32))>,
+    texture: Option<TextureId>,
+    dirty: bool,
+}
+
+/// Where an image ended up in a `Packer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    /// Normalized to the whole atlas, so it can go straight into `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    /// Top left corner in pixels
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<K: Hash + Eq> Packer<K> {
+    pub fn new(width: u32, height: u32) -> Self {
+        Packer {
+            packer: ShelfPacker::new(width, height),
+            image: RgbaImage::new(width, height),
+            regions: HashMap::new(),
+            texture: None,
+            dirty: false,
+        }
+    }
+
+    /// Packs `image` under `key`. Replacing an image with one that fits in its old space reuses it,
+    /// a bigger one gets new space and the old space stays taken until a `repack`. Nothing reaches
+    /// the GPU until the next `upload`
+    pub fn insert(&mut self, key: K, image: &RgbaImage) -> Result<AtlasRegion, &'static str> {
+        let (w, h) = image.dimensions();
+        let (x, y, slot) = match self.regions.get(&key) {
+            Some(&(region, slot)) if w <= slot.0 && h <= slot.1 => (region.x, region.y, slot),
+            _ => {
+                let (x, y) = self
+                    .packer
+                    .pack(w + PADDING * 2, h + PADDING * 2)
+                    .ok_or("The atlas is full!")?;
+                (x + PADDING, y + PADDING, (w, h))
+            }
+        };
+        blit_padded(&mut self.image, image, x, y);
+        let region = AtlasRegion {
+            uv_rect: pixel_uv(
+                self.image.dimensions(),
+                [x as f32, y as f32, (x + w) as f32, (y + h) as f32],
+            ),
+            x,
+            y,
+            width: w,
+            height: h,
+        };
+        self.regions.insert(key, (region, slot));
+        self.dirty = true;
+        Ok(region)
+    }
+
+    /// Packs everything again from scratch, getting back the space that replaced images left
+    /// behind. Regions move around, so anything holding on to one has to `get` it again
+    pub fn repack(&mut self) -> Result<(), &'static str> {
+        let (width, height) = self.image.dimensions();
+        let atlas = &self.image;
+        let mut images: Vec<_> = self
+            .regions
+            .drain()
+            .map(|(key, (region, _))| {
+                let image = RgbaImage::from_fn(region.width, region.height, |x, y| {
+                    *atlas.get_pixel(region.x + x, region.y + y)
+                });
+                (key, image)
+            })
+            .collect();
+        // tallest first packs shelves the tightest
+        images.sort_by_key(|(_, image)| std::cmp::Reverse(image.height()));
+        self.packer = ShelfPacker::new(width, height);
+        self.image = RgbaImage::new(width, height);
+        for (key, image) in images {
+            self.insert(key, &image)?;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &K) -> Option<AtlasRegion> {
+        self.regions.get(key).map(|&(region, _)| region)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.regions.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// The CPU side copy of the whole atlas
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// The texture from the last `upload`, draw the regions with this
+    pub fn texture(&self) -> Option<TextureId> {
+        self.texture
+    }
+
+    /// Loads the atlas as a texture the first time, and re-uploads it when things were inserted
+    /// since
+    pub fn upload(&mut self, hal_state: &mut HalState) -> Result<TextureId, &'static str> {
+        match self.texture {
+            Some(texture) if !self.dirty => Ok(texture),
+            Some(texture) => {
+                hal_state.update_texture(texture, self.image.clone())?;
+                self.dirty = false;
+                Ok(texture)
+            }
+            None => {
+                let texture = hal_state.load_image(self.image.clone())?;
+                self.texture = Some(texture);
+                self.dirty = false;
+                Ok(texture)
+            }
+        }
+    }
+}
+
+/// Online shelf packer, rectangles go left to right on shelves as tall as the first thing put on
+/// them
+#[derive(Debug, Clone)]
+pub(crate) struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    used_width: u32,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        ShelfPacker {
+            width,
+            height,
+            shelves: Vec::new(),
+        }
+    }
+
+    /// Top left corner of where a `w` by `h` rectangle fits, if it fits anywhere
+    pub fn pack(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        if w > self.width || h > self.height {
+            return None;
+        }
+        // the shelf wasting the least height wins
+        let width = self.width;
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| shelf.height >= h && width - shelf.used_width >= w)
+            .min_by_key(|shelf| shelf.height - h);
+        if let Some(shelf) = best {
+            let x = shelf.used_width;
+            shelf.used_width += w;
+            return Some((x, shelf.y));
+        }
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        if self.height - y < h {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height: h,
+            used_width: w,
+        });
+        Some((0, y))
+    }
+}
+
+/// Empty space around every image so linear filtering doesn't pull in the neighbours
+const PADDING: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub(crate) struct AtlasPage {
+    pub image: RgbaImage,
+    packer: ShelfPacker,
+}
+
+/// Where a texture ended up, `page` is the index into the texture array
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AtlasEntry {
+    pub page: u32,
+    pub offset: [f32; 2],
+    pub size: (u32, u32),
+}
+
+/// Fallback for when the texture array is too small to give every texture its own slot, textures
+/// get packed into a few big pages instead and TextureIds index into `entries`
+#[derive(Debug, Clone)]
+pub(crate) struct TextureAtlas {
+    page_size: u32,
+    pub pages: Vec<AtlasPage>,
+    pub entries: Vec<AtlasEntry>,
+}
+
+impl TextureAtlas {
+    pub fn new(page_size: u32) -> Self {
+        TextureAtlas {
+            page_size,
+            pages: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Packs `image` into a page and returns the index of the page it went into. Images too big to
+    /// share a page get one to themselves
+    pub fn insert(&mut self, image: &RgbaImage) -> usize {
+        let (w, h) = (image.width() + PADDING * 2, image.height() + PADDING * 2);
+        let spot = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, page)| page.packer.pack(w, h).map(|spot| (i, spot)))
+            .next();
+        let (page, (x, y)) = match spot {
+            Some(spot) => spot,
+            None => {
+                let mut packer = ShelfPacker::new(self.page_size.max(w), self.page_size.max(h));
+                let spot = packer.pack(w, h).expect("fresh page is big enough");
+                self.pages.push(AtlasPage {
+                    image: RgbaImage::new(packer.width, packer.height),
+                    packer,
+                });
+                (self.pages.len() - 1, spot)
+            }
+        };
+        blit_padded(&mut self.pages[page].image, image, x + PADDING, y + PADDING);
+        self.entries.push(AtlasEntry {
+            page: page as u32,
+            offset: [(x + PADDING) as f32, (y + PADDING) as f32],
+            size: image.dimensions(),
+        });
+        page
+    }
+
+    /// Overwrites the texture at `index` in place and returns its page, the size can't change
+    pub fn replace(&mut self, index: usize, image: &RgbaImage) -> Result<usize, &'static str> {
+        let entry = self.entries.get(index).ok_or("No texture with that id!")?;
+        if entry.size != image.dimensions() {
+            return Err("Atlas textures can't change size!");
+        }
+        let page = entry.page as usize;
+        let [x, y] = entry.offset;
+        blit_padded(&mut self.pages[page].image, image, x as u32, y as u32);
+        Ok(page)
+    }
+
+    /// Points the vertex at its texture's page, with the uv_rect moved to where the texture is in
+    /// it
+    pub fn resolve(&self, vertex: Vertex) -> Vertex {
+        match self.entries.get(vertex.tex_num as usize) {
+            Some(entry) => {
+                let [x, y] = entry.offset;
+                let (w, h) = (entry.size.0 as f32, entry.size.1 as f32);
+                let [x0, y0, x1, y1] = vertex.uv_rect;
+                let page_size = self.pages[entry.page as usize].image.dimensions();
+                Vertex {
+                    uv_rect: pixel_uv(page_size, [x + x0 * w, y + y0 * h, x + x1 * w, y + y1 * h]),
+                    tex_num: entry.page,
+                    ..vertex
+                }
+            }
+            // TextureId::NONE, or garbage that would've been out of bounds anyway
+            None => vertex,
+        }
+    }
+}
+
+/// Copies `src` to (x, y) and smears its edge pixels out into the padding
+fn blit_padded(dst: &mut RgbaImage, src: &RgbaImage, x: u32, y: u32) {
+    let (w, h) = src.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+    for sy in 0..h {
+        for sx in 0..w {
+            dst.put_pixel(x + sx, y + sy, *src.get_pixel(sx, sy));
+        }
+    }
+    for p in 1..=PADDING {
+        for sx in 0..w {
+            dst.put_pixel(x + sx, y - p, *src.get_pixel(sx, 0));
+            dst.put_pixel(x + sx, y + h - 1 + p, *src.get_pixel(sx, h - 1));
+        }
+        for sy in 0..h {
+            dst.put_pixel(x - p, y + sy, *src.get_pixel(0, sy));
+            dst.put_pixel(x + w - 1 + p, y + sy, *src.get_pixel(w - 1, sy));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::geometry::{Quad, Vec2};
+use crate::graphics::{
+    frame::{Frame, Space},
+    loadedimage::TextureId,
+    TexturedQuad,
+};
+use std::time::Duration;
+
+/// A texture tiled across the whole screen that scrolls forever, by moving the UVs instead of the
+/// quad. The texture needs a sampler with `WrapMode::Tile` or `WrapMode::Mirror`, see
+/// `HalState::set_texture_wrap`, and can't be in the texture atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollingBackground {
+    pub texture: TextureId,
+    /// How many times the texture repeats across the screen
+    pub repeat: Vec2<f32>,
+    /// In textures per second, so (0.5, 0) takes 2 seconds to scroll one tile to the left
+    pub velocity: Vec2<f32>,
+    pub color: [f32; 4],
+    offset: Vec2<f32>,
+}
+
+impl ScrollingBackground {
+    pub fn new(texture: TextureId, repeat: Vec2<f32>) -> Self {
+        ScrollingBackground {
+            texture,
+            repeat,
+            velocity: Vec2::zero(),
+            color: [1.0; 4],
+            offset: Vec2::zero(),
+        }
+    }
+
+    pub fn with_velocity(self, velocity: Vec2<f32>) -> Self {
+        ScrollingBackground { velocity, ..self }
+    }
+
+    pub fn with_color(self, color: [f32; 4]) -> Self {
+        ScrollingBackground { color, ..self }
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        let offset = self.offset + self.velocity * delta.as_secs_f32();
+        // a whole tile further looks the same, and keeping it small keeps the UVs precise. Mirrored
+        // textures only repeat every other tile
+        self.offset = offset.map(|x| x.rem_euclid(2.0));
+    }
+
+    /// How far it's scrolled, in textures
+    pub fn offset(&self) -> Vec2<f32> {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: Vec2<f32>) {
+        self.offset = offset.map(|x| x.rem_euclid(2.0));
+    }
+
+    /// Covers all of clip space, so it has to be drawn in `Space::Screen`. The uv_rect goes past
+    /// 1 whenever it repeats, so draw it with `Frame::draw_tiled_quads`
+    pub fn quad(&self) -> TexturedQuad {
+        let quad = Quad {
+            top_left: Vec2::new(-1.0, -1.0),
+            bottom_left: Vec2::new(-1.0, 1.0),
+            bottom_right: Vec2::new(1.0, 1.0),
+            top_right: Vec2::new(1.0, -1.0),
+        };
+        let start = self.offset;
+        let end = self.offset + self.repeat;
+        TexturedQuad {
+            uv_rect: [start.x, start.y, end.x, end.y],
+            ..TexturedQuad::new(quad, self.texture.index())
+        }
+        .with_color(self.color)
+    }
+
+    /// Draws the quad in screen space, whatever space the frame was in before gets restored after
+    pub fn draw(&self, frame: &mut Frame) {
+        let space = frame.space();
+        frame
+            .set_space(Space::Screen)
+            .draw_tiled_quads(&[self.quad()])
+            .set_space(space);
+    }
+}
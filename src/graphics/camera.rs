@@ -0,0 +1,101 @@
+use crate::geometry::{Mat2, Mat3, Quad, Rect, Vec2, Vec3};
+
+/// Simple 2D camera, applied to quads on the CPU before they get written into the vertex buffer.
+/// The default camera is the identity, so world space is the same as clip space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2D {
+    /// World position that ends up in the middle of the screen
+    pub position: Vec2<f32>,
+    /// 2.0 means everything is drawn twice as big
+    pub zoom: f32,
+    /// In degrees, same as `Quad::rotate_around_center_matrix`
+    pub rotation: f64,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Camera2D {
+            position: Vec2::zero(),
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Camera2D {
+    /// The part of clip space that actually ends up on screen
+    pub const CLIP_RECT: Rect<f32, f32> = Rect {
+        x: -1.0,
+        y: -1.0,
+        w: 2.0,
+        h: 2.0,
+    };
+
+    /// Matrix taking world coordinates into clip space
+    pub fn view_matrix(&self) -> Mat3<f32> {
+        let translate: Mat3<f32> = Mat3::identity().translated_2d(-self.position);
+        let rotate = Mat3::from(Mat2::rotation_z(
+            (-self.rotation / 360.0 * (std::f64::consts::PI * 2.0)) as f32,
+        ));
+        let scale = Mat3::with_diagonal(Vec3::new(self.zoom, self.zoom, 1.0));
+        scale * rotate * translate
+    }
+
+    /// Inverse of `view_matrix`, useful for turning mouse positions into world positions
+    pub fn inverse_view_matrix(&self) -> Mat3<f32> {
+        let translate: Mat3<f32> = Mat3::identity().translated_2d(self.position);
+        let rotate = Mat3::from(Mat2::rotation_z(
+            (self.rotation / 360.0 * (std::f64::consts::PI * 2.0)) as f32,
+        ));
+        let scale = Mat3::with_diagonal(Vec3::new(1.0 / self.zoom, 1.0 / self.zoom, 1.0));
+        translate * rotate * scale
+    }
+
+    /// World space AABB of everything the camera can see
+    pub fn visible_rect(&self) -> Rect<f32, f32> {
+        Quad::from(Self::CLIP_RECT)
+            .transform(self.inverse_view_matrix())
+            .bounding_rect()
+    }
+
+    /// Whether a world space quad ends up at least partially on screen
+    pub fn is_visible(&self, quad: &Quad) -> bool {
+        quad.transform(self.view_matrix())
+            .intersects_rect(&Self::CLIP_RECT)
+    }
+}
+
+/// Makes everything drawn on it scroll slower or faster than the camera, set it with
+/// `Frame::set_parallax_layer`. Rotation and zoom still apply as usual
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParallaxLayer {
+    /// How far the layer moves per unit of camera movement. 1.0 is normal world space, 0.0 stays
+    /// stuck to the screen, in between is a background and above 1.0 is a foreground
+    pub scroll_factor: Vec2<f32>,
+}
+
+impl Default for ParallaxLayer {
+    fn default() -> Self {
+        ParallaxLayer::new(1.0)
+    }
+}
+
+impl ParallaxLayer {
+    pub fn new(scroll_factor: f32) -> Self {
+        ParallaxLayer {
+            scroll_factor: Vec2::new(scroll_factor, scroll_factor),
+        }
+    }
+
+    /// The camera as seen from this layer
+    pub fn camera(&self, camera: &Camera2D) -> Camera2D {
+        Camera2D {
+            position: camera.position * self.scroll_factor,
+            ..*camera
+        }
+    }
+
+    pub fn view_matrix(&self, camera: &Camera2D) -> Mat3<f32> {
+        self.camera(camera).view_matrix()
+    }
+}
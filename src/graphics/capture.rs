@@ -0,0 +1,240 @@
+use crate::graphics::gpu_buffer::BufferBundle;
+use gfx_hal::{
+    adapter::Adapter,
+    buffer::{Access as BufferAccess, Usage as BufferUsage},
+    command::{BufferImageCopy, CommandBuffer, MultiShot, Primary},
+    device::Device,
+    format::{Aspects, Format},
+    image::{Access, Extent, Layout, Offset, SubresourceLayers, SubresourceRange},
+    memory::{Barrier, Dependencies},
+    pso::PipelineStage,
+    window::Extent2D,
+    Backend, Graphics,
+};
+use image::RgbaImage;
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+/// One frame handed to the capture callback, see `HalState::start_capture`
+#[derive(Debug)]
+pub struct CapturedFrame<'a> {
+    /// What got presented, already turned into RGBA. It gets reused for the next frame, so copy it
+    /// out if it has to stick around
+    pub image: &'a RgbaImage,
+    /// When the frame was submitted, counting from when the capture started
+    pub timestamp: Duration,
+    /// Counts up from 0, frames that got skipped because of a resize leave a gap
+    pub number: u64,
+}
+
+/// Copies every presented frame into a staging buffer, there's one per frame in flight so they work
+/// like a ring buffer. A frame gets read back once its fence comes around again, so the copy never
+/// stalls anything that wasn't already waiting on that frame
+pub(crate) struct FrameCapture<B: Backend, D: Device<B>> {
+    callback: Box<dyn FnMut(CapturedFrame)>,
+    staging: Vec<BufferBundle<B, D>>,
+    /// Timestamp and number of what's waiting in each staging buffer
+    pending: Vec<Option<(Duration, u64)>>,
+    started: Instant,
+    next_number: u64,
+    extent: Extent2D,
+    /// The swapchain is usually BGRA, which has to be swizzled on the way out
+    bgra: bool,
+    image: RgbaImage,
+}
+
+impl<B: Backend, D: Device<B>> FrameCapture<B, D> {
+    pub fn new(
+        adapter: &Adapter<B>,
+        device: &D,
+        frames_in_flight: usize,
+        extent: Extent2D,
+        format: Format,
+        callback: Box<dyn FnMut(CapturedFrame)>,
+    ) -> Result<Self, &'static str> {
+        let bgra = match format {
+            Format::Rgba8Srgb | Format::Rgba8Unorm => false,
+            Format::Bgra8Srgb | Format::Bgra8Unorm => true,
+            _ => return Err("Can't capture frames in this swapchain format!"),
+        };
+        let mut capture = FrameCapture {
+            callback,
+            staging: Vec::with_capacity(frames_in_flight),
+            pending: vec![None; frames_in_flight],
+            started: Instant::now(),
+            next_number: 0,
+            extent,
+            bgra,
+            image: RgbaImage::new(extent.width, extent.height),
+        };
+        if let Err(e) = capture.create_staging(adapter, device, frames_in_flight) {
+            unsafe { capture.destroy(device) };
+            return Err(e);
+        }
+        Ok(capture)
+    }
+
+    fn create_staging(
+        &mut self,
+        adapter: &Adapter<B>,
+        device: &D,
+        frames_in_flight: usize,
+    ) -> Result<(), &'static str> {
+        let size = (self.extent.width * self.extent.height) as usize * mem::size_of::<u32>();
+        for _ in 0..frames_in_flight {
+            self.staging.push(BufferBundle::new(
+                adapter,
+                device,
+                size,
+                BufferUsage::TRANSFER_DST,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Copies `image` (the swapchain image that was just rendered to) into this frame's staging
+    /// buffer. It has to be recorded after the last render pass, while the image is in Present layout
+    pub unsafe fn record(
+        &self,
+        buffer: &mut CommandBuffer<B, Graphics, MultiShot, Primary>,
+        frame_index: usize,
+        image: &B::Image,
+    ) {
+        let range = SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..1,
+        };
+        buffer.pipeline_barrier(
+            PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::COLOR_ATTACHMENT_WRITE, Layout::Present)
+                    ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                target: image,
+                families: None,
+                range: range.clone(),
+            }],
+        );
+        buffer.copy_image_to_buffer(
+            image,
+            Layout::TransferSrcOptimal,
+            &self.staging[frame_index].buffer,
+            &[BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: self.extent.width,
+                buffer_height: self.extent.height,
+                image_layers: SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: Offset { x: 0, y: 0, z: 0 },
+                image_extent: Extent {
+                    width: self.extent.width,
+                    height: self.extent.height,
+                    depth: 1,
+                },
+            }],
+        );
+        buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                    ..(Access::empty(), Layout::Present),
+                target: image,
+                families: None,
+                range,
+            }],
+        );
+        // the host reads the buffer once the fence is signaled
+        buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::HOST,
+            Dependencies::empty(),
+            &[Barrier::AllBuffers(
+                BufferAccess::TRANSFER_WRITE..BufferAccess::HOST_READ,
+            )],
+        );
+    }
+
+    /// Call right before submitting a command buffer that has the copy recorded in it
+    pub fn submitted(&mut self, frame_index: usize) {
+        self.pending[frame_index] = Some((self.started.elapsed(), self.next_number));
+        self.next_number += 1;
+    }
+
+    /// Hands the frame waiting in this staging buffer to the callback, if there is one. Only call
+    /// this once the frame's fence has been waited on
+    pub unsafe fn collect(&mut self, device: &D, frame_index: usize) -> Result<(), &'static str> {
+        let (timestamp, number) = match self.pending[frame_index].take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let staging = &self.staging[frame_index];
+        let mapping = device
+            .map_memory(&staging.memory, 0..staging.requirements.size)
+            .map_err(|_| "Couldn't map the captured frame!")?;
+        // the GPU wrote this, so non-coherent memory could still have stale data cached
+        if let Err(e) = staging.invalidate(device, 0..staging.requirements.size) {
+            device.unmap_memory(&staging.memory);
+            return Err(e);
+        }
+        let len = self.image.len();
+        self.image
+            .copy_from_slice(std::slice::from_raw_parts(mapping, len));
+        device.unmap_memory(&staging.memory);
+        if self.bgra {
+            for pixel in self.image.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        (self.callback)(CapturedFrame {
+            image: &self.image,
+            timestamp,
+            number,
+        });
+        Ok(())
+    }
+
+    /// Hands over everything still pending, only call this once the device is idle
+    pub unsafe fn flush(&mut self, device: &D) -> Result<(), &'static str> {
+        // oldest first, which is the one with the lowest number
+        let mut order: Vec<usize> = (0..self.pending.len())
+            .filter(|&i| self.pending[i].is_some())
+            .collect();
+        order.sort_by_key(|&i| self.pending[i].map(|(_, number)| number));
+        for frame_index in order {
+            self.collect(device, frame_index)?;
+        }
+        Ok(())
+    }
+
+    /// New staging buffers for a new swapchain size, anything pending gets thrown away so `flush`
+    /// first to keep it
+    pub unsafe fn resize(
+        &mut self,
+        adapter: &Adapter<B>,
+        device: &D,
+        extent: Extent2D,
+    ) -> Result<(), &'static str> {
+        let frames_in_flight = self.pending.len();
+        for staging in self.staging.drain(..) {
+            staging.manually_drop(device);
+        }
+        for pending in &mut self.pending {
+            *pending = None;
+        }
+        self.extent = extent;
+        self.image = RgbaImage::new(extent.width, extent.height);
+        self.create_staging(adapter, device, frames_in_flight)
+    }
+
+    pub unsafe fn destroy(self, device: &D) {
+        for staging in self.staging {
+            staging.manually_drop(device);
+        }
+    }
+}
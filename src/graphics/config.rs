@@ -0,0 +1,168 @@
+use crate::graphics::{DebugDraw, HalState, SamplingConfig};
+use crate::run::UnixBackend;
+use gfx_hal::{
+    adapter::{Adapter, DeviceType},
+    window::PresentMode,
+    Backend,
+};
+use slog::Logger;
+
+/// Which GPU to use when there's more than one that can draw to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdapterPreference {
+    /// Whatever the driver lists first
+    First,
+    /// Dedicated GPUs over integrated ones
+    HighPerformance,
+    /// Integrated GPUs over dedicated ones, for laptops running on battery
+    LowPower,
+}
+
+impl Default for AdapterPreference {
+    fn default() -> Self {
+        AdapterPreference::First
+    }
+}
+
+impl AdapterPreference {
+    /// Falls back to the first adapter if none of them are the preferred type
+    pub(crate) fn pick<B: Backend>(self, adapters: Vec<Adapter<B>>) -> Option<Adapter<B>> {
+        let preferred = match self {
+            AdapterPreference::First => None,
+            AdapterPreference::HighPerformance => Some(DeviceType::DiscreteGpu),
+            AdapterPreference::LowPower => Some(DeviceType::IntegratedGpu),
+        };
+        let index = preferred
+            .and_then(|device_type| {
+                adapters
+                    .iter()
+                    .position(|adapter| adapter.info.device_type == device_type)
+            })
+            .unwrap_or(0);
+        adapters.into_iter().nth(index)
+    }
+}
+
+/// Everything `HalState` gets created with. Most of it can't change afterwards, the exceptions being
+/// `debug_draw` and `premultiply_alpha` which are only starting values
+#[derive(Debug, Clone)]
+pub struct GraphicsConfig {
+    /// Goes to the driver as the application name
+    pub name: String,
+    /// How many quads fit in the vertex buffers before they have to grow
+    pub num_quads: usize,
+    /// Present modes in order of preference, the first one the surface supports gets used
+    pub vsync: [PresentMode; 4],
+    pub sampling: SamplingConfig,
+    pub adapter: AdapterPreference,
+    /// Textures per descriptor set, None picks the most the platform can bind. Anything past that
+    /// gets clamped to it. Fewer than 32 turns the texture atlas on
+    pub max_textures: Option<usize>,
+    /// None keeps one frame in flight per swapchain image
+    pub max_frames_in_flight: Option<usize>,
+    pub debug_draw: DebugDraw,
+    /// Starting value for `HalState::set_premultiply_alpha`
+    pub premultiply_alpha: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        GraphicsConfig {
+            name: "rmge".to_string(),
+            num_quads: 512,
+            vsync: [
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+                PresentMode::Relaxed,
+                PresentMode::Immediate,
+            ],
+            sampling: SamplingConfig::default(),
+            adapter: AdapterPreference::default(),
+            max_textures: None,
+            max_frames_in_flight: None,
+            debug_draw: DebugDraw::default(),
+            premultiply_alpha: false,
+        }
+    }
+}
+
+/// Builds a `GraphicsConfig` and the `HalState` from it, anything that isn't set keeps the
+/// `GraphicsConfig::default` value
+#[derive(Debug, Clone, Default)]
+pub struct HalStateBuilder {
+    config: GraphicsConfig,
+}
+
+impl HalStateBuilder {
+    pub fn new() -> Self {
+        HalStateBuilder::default()
+    }
+
+    pub fn from_config(config: GraphicsConfig) -> Self {
+        HalStateBuilder { config }
+    }
+
+    pub fn config(&self) -> &GraphicsConfig {
+        &self.config
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.config.name = name.to_string();
+        self
+    }
+
+    pub fn num_quads(mut self, num_quads: usize) -> Self {
+        self.config.num_quads = num_quads;
+        self
+    }
+
+    pub fn vsync(mut self, vsync: [PresentMode; 4]) -> Self {
+        self.config.vsync = vsync;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.config.sampling = sampling;
+        self
+    }
+
+    pub fn adapter(mut self, adapter: AdapterPreference) -> Self {
+        self.config.adapter = adapter;
+        self
+    }
+
+    pub fn max_textures(mut self, max_textures: usize) -> Self {
+        self.config.max_textures = Some(max_textures);
+        self
+    }
+
+    pub fn max_frames_in_flight(mut self, max_frames_in_flight: usize) -> Self {
+        self.config.max_frames_in_flight = Some(max_frames_in_flight);
+        self
+    }
+
+    /// For latency critical stuff, goes with `WindowSettings::performance_mode`. Prefers the
+    /// present modes that don't wait for vblank and only keeps one frame in flight, so the CPU
+    /// waits for the GPU before recording the next frame instead of queueing up frames of input
+    /// lag. Costs some throughput since the CPU and GPU take turns
+    pub fn low_latency(mut self) -> Self {
+        // tearing is fine as long as the frame gets out sooner, which is what X11 wants anyway
+        self.config.vsync = UnixBackend::X11.present_modes();
+        self.config.max_frames_in_flight = Some(1);
+        self
+    }
+
+    pub fn debug_draw(mut self, debug_draw: DebugDraw) -> Self {
+        self.config.debug_draw = debug_draw;
+        self
+    }
+
+    pub fn premultiply_alpha(mut self, premultiply_alpha: bool) -> Self {
+        self.config.premultiply_alpha = premultiply_alpha;
+        self
+    }
+
+    pub fn build(self, window: &winit::Window, logger: Logger) -> Result<HalState, &'static str> {
+        HalState::with_config(window, self.config, logger)
+    }
+}
@@ -0,0 +1,50 @@
+#version 450
+// the push constants are a CrtEffect, see postprocess.rs. Everything here happens in linear space
+
+layout (set = 0, binding = 0) uniform texture2D screen;
+layout (set = 0, binding = 1) uniform sampler samp;
+
+layout (push_constant) uniform CrtEffect {
+  float curvature;
+  float scanlines;
+  float chromatic_aberration;
+  float height;
+  // set when the swapchain isn't sRGB, the screen texture always is
+  uint srgb_conversion;
+} crt;
+
+layout (location = 0) in vec2 uv;
+layout (location = 0) out vec4 color;
+
+// same as in fragment.glsl
+vec3 linear_to_srgb(vec3 c)
+{
+  vec3 higher = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+  vec3 lower = c * 12.92;
+  return mix(higher, lower, lessThan(c, vec3(0.0031308)));
+}
+
+void main()
+{
+  // barrel distortion, the corners get pulled in the most
+  vec2 centered = uv * 2.0 - 1.0;
+  centered *= 1.0 + crt.curvature * 0.25 * dot(centered.yx, centered.yx);
+  vec2 curved = centered * 0.5 + 0.5;
+  if (any(lessThan(curved, vec2(0.0))) || any(greaterThan(curved, vec2(1.0)))) {
+    color = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+
+  // red and blue get pushed apart towards the edges
+  vec2 shift = (curved - 0.5) * crt.chromatic_aberration * 0.02;
+  float r = texture(sampler2D(screen, samp), curved + shift).r;
+  vec4 center = texture(sampler2D(screen, samp), curved);
+  float b = texture(sampler2D(screen, samp), curved - shift).b;
+
+  // darkens every other row of pixels
+  float scanline = 0.5 + 0.5 * cos(curved.y * crt.height * 3.14159265);
+  color = vec4(vec3(r, center.g, b) * (1.0 - crt.scanlines * scanline), center.a);
+  if (crt.srgb_conversion != 0) {
+    color.rgb = linear_to_srgb(color.rgb);
+  }
+}
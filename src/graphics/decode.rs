@@ -0,0 +1,118 @@
+use image::{png::PNGDecoder, ColorType, ImageDecoder, ImageError, ImageFormat, RgbaImage};
+use std::fmt;
+
+/// Why a texture couldn't be loaded, decode errors keep what the image crate had to say about it
+#[derive(Debug)]
+pub enum TextureError {
+    Decode(ImageError),
+    Upload(&'static str),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Decode(e) => write!(f, "invalid image: {}", e),
+            TextureError::Upload(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<ImageError> for TextureError {
+    fn from(e: ImageError) -> Self {
+        TextureError::Decode(e)
+    }
+}
+
+impl From<&'static str> for TextureError {
+    fn from(e: &'static str) -> Self {
+        TextureError::Upload(e)
+    }
+}
+
+/// So `?` keeps working in code that only deals in `&'static str`, only the kind of decode error
+/// survives
+impl From<TextureError> for &'static str {
+    fn from(e: TextureError) -> Self {
+        match e {
+            TextureError::Decode(e) => image_error_str(&e),
+            TextureError::Upload(e) => e,
+        }
+    }
+}
+
+/// The closest fixed message to an `ImageError`, whatever it was carrying gets dropped
+pub(crate) fn image_error_str(e: &ImageError) -> &'static str {
+    match e {
+        ImageError::FormatError(_) => "The image data is malformed!",
+        ImageError::DimensionError => "The image is too small or too large!",
+        ImageError::UnsupportedError(_) => "The image format isn't supported!",
+        ImageError::UnsupportedColor(_) => "The image's color type isn't supported!",
+        ImageError::NotEnoughData | ImageError::ImageEnd => "The image data ends too early!",
+        ImageError::IoError(_) => "Couldn't read the image data!",
+        ImageError::InsufficientMemory => "Not enough memory to decode the image!",
+    }
+}
+
+/// Decodes any format the image crate knows into 8-bit RGBA. Grayscale gets copied into every
+/// channel, paletted PNGs get expanded by the decoder, and 16-bit PNGs (which image 0.21 refuses to
+/// turn into a `DynamicImage`) get rounded down to 8 bits here
+pub fn decode_image(data: &[u8]) -> Result<RgbaImage, ImageError> {
+    if image::guess_format(data)? == ImageFormat::PNG {
+        let decoder = PNGDecoder::new(data)?;
+        if let Some(channels) = channels_16(decoder.colortype()) {
+            return decode_png_16(decoder, channels);
+        }
+    }
+    Ok(image::load_from_memory(data)?.to_rgba())
+}
+
+fn channels_16(color: ColorType) -> Option<usize> {
+    match color {
+        ColorType::Gray(16) => Some(1),
+        ColorType::GrayA(16) => Some(2),
+        ColorType::RGB(16) => Some(3),
+        ColorType::RGBA(16) => Some(4),
+        _ => None,
+    }
+}
+
+fn decode_png_16(decoder: PNGDecoder<&[u8]>, channels: usize) -> Result<RgbaImage, ImageError> {
+    let (width, height) = decoder.dimensions();
+    if width > u64::from(u32::max_value()) || height > u64::from(u32::max_value()) {
+        return Err(ImageError::DimensionError);
+    }
+    let data = decoder.read_image()?;
+    // PNG samples are big endian
+    let to_8 = |sample: &[u8]| {
+        let value = u32::from(u16::from_be_bytes([sample[0], sample[1]]));
+        ((value * 255 + 32_895) >> 16) as u8
+    };
+    let mut pixels = Vec::with_capacity(data.len() / channels / 2 * 4);
+    for pixel in data.chunks_exact(channels * 2) {
+        let mut samples = [0; 4];
+        for (sample, bytes) in samples.iter_mut().zip(pixel.chunks_exact(2)) {
+            *sample = to_8(bytes);
+        }
+        let rgba = match channels {
+            1 => [samples[0], samples[0], samples[0], 255],
+            2 => [samples[0], samples[0], samples[0], samples[1]],
+            3 => [samples[0], samples[1], samples[2], 255],
+            _ => [samples[0], samples[1], samples[2], samples[3]],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+    RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or(ImageError::NotEnoughData)
+}
+
+/// Multiplies the color of every pixel by its alpha, for textures drawn with
+/// `BlendMode::PremultipliedAlpha`
+pub fn premultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel.data[..3] {
+            *channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+        }
+    }
+}
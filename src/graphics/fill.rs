@@ -0,0 +1,206 @@
+use crate::geometry::{Quad, Rect, Vec2};
+use crate::graphics::{loadedimage::TextureId, vertex::Vertex, TexturedQuad};
+
+/// Which way a `ProgressBar` fills up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FillDirection {
+    LeftToRight,
+    RightToLeft,
+    BottomToTop,
+    TopToBottom,
+}
+
+/// A texture cut off at `fill`, for health bars and song progress. The quad and its uv_rect get
+/// cut instead of masking in the shader, so the texture doesn't stretch and any material works
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressBar {
+    /// The whole bar when it's full, x and y are the bottom left like in `Quad::from(Rect)`
+    pub rect: Rect<f32, f32>,
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+    /// 0 to 1, anything outside gets clamped
+    pub fill: f32,
+    pub direction: FillDirection,
+}
+
+impl ProgressBar {
+    pub fn new(rect: Rect<f32, f32>, texture: TextureId) -> Self {
+        ProgressBar {
+            rect,
+            texture,
+            uv_rect: TexturedQuad::FULL_UV,
+            color: [1.0; 4],
+            fill: 1.0,
+            direction: FillDirection::LeftToRight,
+        }
+    }
+
+    pub fn with_uv_rect(self, uv_rect: [f32; 4]) -> Self {
+        ProgressBar { uv_rect, ..self }
+    }
+
+    pub fn with_color(self, color: [f32; 4]) -> Self {
+        ProgressBar { color, ..self }
+    }
+
+    pub fn with_fill(self, fill: f32) -> Self {
+        ProgressBar { fill, ..self }
+    }
+
+    pub fn with_direction(self, direction: FillDirection) -> Self {
+        ProgressBar { direction, ..self }
+    }
+
+    /// None when it's empty
+    pub fn textured_quad(&self) -> Option<TexturedQuad> {
+        let fill = self.fill.max(0.0).min(1.0);
+        if fill <= 0.0 {
+            return None;
+        }
+        let Rect { x, y, w, h } = self.rect;
+        let [u0, v0, u1, v1] = self.uv_rect;
+        // v0 is the top of the texture, which is the top of the rect
+        let (rect, uv_rect) = match self.direction {
+            FillDirection::LeftToRight => (
+                Rect::new(x, y, w * fill, h),
+                [u0, v0, u0 + (u1 - u0) * fill, v1],
+            ),
+            FillDirection::RightToLeft => (
+                Rect::new(x + w * (1.0 - fill), y, w * fill, h),
+                [u1 - (u1 - u0) * fill, v0, u1, v1],
+            ),
+            FillDirection::BottomToTop => (
+                Rect::new(x, y, w, h * fill),
+                [u0, v1 - (v1 - v0) * fill, u1, v1],
+            ),
+            FillDirection::TopToBottom => (
+                Rect::new(x, y + h * (1.0 - fill), w, h * fill),
+                [u0, v0, u1, v0 + (v1 - v0) * fill],
+            ),
+        };
+        Some(TexturedQuad {
+            uv_rect,
+            color: self.color,
+            ..TexturedQuad::new(Quad::from(rect), self.texture.index())
+        })
+    }
+}
+
+/// Clock angles of the square's corners, clockwise from the top
+const CORNERS: [f32; 4] = [45.0, 135.0, 225.0, 315.0];
+
+/// A square texture swept out like a clock hand up to `fill`, for cooldowns and loading spinners.
+/// Like `ProgressBar` it's cut in the geometry, a fan of at most 6 triangles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadialFill {
+    pub center: Vec2<f32>,
+    /// Half the square's size
+    pub radius: f32,
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+    /// 0 to 1, anything outside gets clamped
+    pub fill: f32,
+    /// Where the sweep starts, in degrees clockwise from straight up
+    pub start: f32,
+    pub clockwise: bool,
+}
+
+impl RadialFill {
+    /// Starts at the top and goes clockwise
+    pub fn new(center: Vec2<f32>, radius: f32, texture: TextureId) -> Self {
+        RadialFill {
+            center,
+            radius,
+            texture,
+            uv_rect: TexturedQuad::FULL_UV,
+            color: [1.0; 4],
+            fill: 1.0,
+            start: 0.0,
+            clockwise: true,
+        }
+    }
+
+    pub fn with_uv_rect(self, uv_rect: [f32; 4]) -> Self {
+        RadialFill { uv_rect, ..self }
+    }
+
+    pub fn with_color(self, color: [f32; 4]) -> Self {
+        RadialFill { color, ..self }
+    }
+
+    pub fn with_fill(self, fill: f32) -> Self {
+        RadialFill { fill, ..self }
+    }
+
+    /// `start` in degrees clockwise from straight up
+    pub fn with_start(self, start: f32, clockwise: bool) -> Self {
+        RadialFill {
+            start,
+            clockwise,
+            ..self
+        }
+    }
+
+    /// Adds the fan to `vertices` and `indices`, nothing when it's empty
+    pub fn build(&self, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>) {
+        let sweep = self.fill.max(0.0).min(1.0) * 360.0;
+        if sweep <= 0.0 {
+            return;
+        }
+        // how far along the sweep each point is, the square's edges are straight so the corners
+        // in between are all it takes
+        let mut steps = vec![0.0];
+        let mut corners: Vec<f32> = CORNERS
+            .iter()
+            .map(|&corner| {
+                let along = if self.clockwise {
+                    corner - self.start
+                } else {
+                    self.start - corner
+                };
+                (along % 360.0 + 360.0) % 360.0
+            })
+            .filter(|&along| along > 0.0 && along < sweep)
+            .collect();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        steps.extend(corners);
+        steps.push(sweep);
+
+        let first = vertices.len() as u16;
+        vertices.push(self.vertex(self.center));
+        for along in steps {
+            let angle = if self.clockwise {
+                self.start + along
+            } else {
+                self.start - along
+            }
+            .to_radians();
+            // clockwise from up, with y pointing up
+            let direction = Vec2::new(angle.sin(), angle.cos());
+            // out to the edge of the square rather than a circle
+            let scale = self.radius / direction.x.abs().max(direction.y.abs());
+            vertices.push(self.vertex(self.center + direction * scale));
+        }
+        let points = vertices.len() as u16 - first;
+        for i in 1..points - 1 {
+            indices.extend_from_slice(&[first, first + i, first + i + 1]);
+        }
+    }
+
+    fn vertex(&self, p: Vec2<f32>) -> Vertex {
+        let size = (self.radius * 2.0).max(core::f32::EPSILON);
+        let left = self.center.x - self.radius;
+        let top = self.center.y + self.radius;
+        Vertex {
+            xy: [p.x, p.y],
+            uv: [(p.x - left) / size, (top - p.y) / size],
+            uv_rect: self.uv_rect,
+            tex_num: self.texture.index(),
+            color: self.color,
+        }
+    }
+}
@@ -1,14 +1,124 @@
 #version 450
+// the specialization constants are filled in by HalState::create_pipeline
+layout (constant_id = 0) const uint TEXTURE_COUNT = 64;
+layout (constant_id = 1) const bool SRGB_CONVERSION = false;
+layout (constant_id = 2) const bool PREMULTIPLY_ALPHA = false;
+// DebugDraw::overdraw, every fragment adds the same step so the color counts the layers
+layout (constant_id = 3) const bool OVERDRAW = false;
+const vec4 OVERDRAW_STEP = vec4(0.1, 0.05, 0.025, 0.0);
+// Material::mode, the second texture (or the shadow blur) is in the push constants
+layout (constant_id = 4) const uint MATERIAL = 0u;
+const uint MATERIAL_PLAIN = 0u;
+const uint MATERIAL_MASK = 1u;
+const uint MATERIAL_MULTIPLY = 2u;
+const uint MATERIAL_TINT_MASK = 3u;
+const uint MATERIAL_DISTANCE_FIELD = 4u;
+const uint MATERIAL_SHADOW = 5u;
+const uint MATERIAL_PALETTE = 6u;
 
-layout(set = 0, binding = 0) uniform texture2D tex[64];
-layout(set = 0, binding = 1) uniform sampler samp;
+layout(set = 0, binding = 0) uniform texture2D tex[TEXTURE_COUNT];
+// MAX_SAMPLERS in mod.rs
+layout(set = 0, binding = 1) uniform sampler samp[16];
+// which of samp each texture uses, a byte per texture packed into uvec4s because of std140
+layout(set = 0, binding = 2) uniform SamplerTable {
+  uvec4 sampler_table[1024];
+};
 
+// after the custom pipelines' view matrix, MATERIAL_PUSH_CONSTANT in mod.rs
+layout (push_constant) uniform MaterialConstants {
+  layout (offset = 48) uint material_texture;
+};
+
+const uint NO_TEXTURE = 0xFFFFFFFFu;
+
+layout (location = 0) in vec4 frag_color;
 layout (location = 1) in vec2 frag_uv;
 layout (location = 0) out vec4 color;
 layout (location = 3) flat in uint tex_num;
 
+uint sampler_index(uint texture_index)
+{
+  uint word = sampler_table[texture_index / 16u][(texture_index / 4u) % 4u];
+  return (word >> ((texture_index % 4u) * 8u)) & 0xFFu;
+}
+
+vec4 sample_texture(uint texture_index)
+{
+  return texture(sampler2D(tex[texture_index], samp[sampler_index(texture_index)]), frag_uv);
+}
+
+// a 5x5 tent filter spread over material_texture texels, which is the blur for shadows
+float shadow_alpha(float alpha)
+{
+  if (material_texture == 0u || tex_num == NO_TEXTURE) {
+    return alpha;
+  }
+  uint s = sampler_index(tex_num);
+  vec2 spread = float(material_texture) * 0.5 / vec2(textureSize(sampler2D(tex[tex_num], samp[s]), 0));
+  float sum = 0.0;
+  float total = 0.0;
+  for (int x = -2; x <= 2; x++) {
+    for (int y = -2; y <= 2; y++) {
+      float weight = (3.0 - abs(float(x))) * (3.0 - abs(float(y)));
+      sum += weight * texture(sampler2D(tex[tex_num], samp[s]), frag_uv + vec2(x, y) * spread).a;
+      total += weight;
+    }
+  }
+  return sum / total;
+}
+
+vec3 linear_to_srgb(vec3 c)
+{
+  vec3 higher = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+  vec3 lower = c * 12.92;
+  return mix(higher, lower, lessThan(c, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 c)
+{
+  vec3 higher = pow((c + 0.055) / 1.055, vec3(2.4));
+  vec3 lower = c / 12.92;
+  return mix(higher, lower, lessThan(c, vec3(0.04045)));
+}
+
 void main()
 {
-  vec4 tex_color = texture(sampler2D(tex[tex_num], samp), frag_uv);
-  color = tex_color; 
+  vec4 tex_color = tex_num == NO_TEXTURE ? vec4(1.0) : sample_texture(tex_num);
+  if (MATERIAL == MATERIAL_PLAIN) {
+    color = tex_color * frag_color;
+  } else if (MATERIAL == MATERIAL_DISTANCE_FIELD) {
+    // the edge is at 0.5, smoothed over about a pixel on screen whatever the scale
+    float width = max(fwidth(tex_color.a) * 0.5, 0.0001);
+    color = vec4(frag_color.rgb, frag_color.a * smoothstep(0.5 - width, 0.5 + width, tex_color.a));
+  } else if (MATERIAL == MATERIAL_SHADOW) {
+    color = vec4(frag_color.rgb, frag_color.a * shadow_alpha(tex_color.a));
+  } else if (MATERIAL == MATERIAL_PALETTE) {
+    // the red channel is the entry, through the middle of its texel so filtering can't blend two.
+    // Index textures and palettes are UNORM so the entry comes through exactly, which leaves
+    // decoding the palette's color to the shader
+    uint s = sampler_index(material_texture);
+    float entries = float(textureSize(sampler2D(tex[material_texture], samp[s]), 0).x);
+    float entry = floor(tex_color.r * 255.0 + 0.5);
+    vec4 swapped = texture(sampler2D(tex[material_texture], samp[s]), vec2((entry + 0.5) / entries, 0.5));
+    color = vec4(srgb_to_linear(swapped.rgb), swapped.a * tex_color.a) * frag_color;
+  } else {
+    vec4 second = sample_texture(material_texture);
+    if (MATERIAL == MATERIAL_MASK) {
+      color = tex_color * frag_color;
+      color.a *= second.r;
+    } else if (MATERIAL == MATERIAL_MULTIPLY) {
+      color = tex_color * frag_color * second;
+    } else if (MATERIAL == MATERIAL_TINT_MASK) {
+      color = tex_color * mix(vec4(1.0), frag_color, second.r);
+    }
+  }
+  if (SRGB_CONVERSION) {
+    color.rgb = linear_to_srgb(color.rgb);
+  }
+  if (PREMULTIPLY_ALPHA) {
+    color.rgb *= color.a;
+  }
+  if (OVERDRAW) {
+    color = OVERDRAW_STEP;
+  }
 }
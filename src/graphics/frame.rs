@@ -0,0 +1,616 @@
+use crate::geometry::{Mat3, Rect};
+use crate::graphics::{
+    camera::{Camera2D, ParallaxLayer},
+    fill::{ProgressBar, RadialFill},
+    indirect::IndirectMeshId,
+    loadedimage::TextureId,
+    material::Material,
+    rounded::RoundedQuad,
+    shadow::DropShadow,
+    shake::{ShakeOffset, ShakeParams},
+    sprite::Sprite,
+    vertex::{Vertex, VertexLayout, WithParams},
+    BlendMode, CustomPipelineId, TexturedQuad,
+};
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+    ops::Range,
+    slice,
+};
+
+/// Everything that should be drawn in one frame, in order. Build one of these up and hand it to
+/// `HalState::draw_frame`, it can be cleared and reused between frames to keep the allocations around
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub(crate) quads: Vec<TexturedQuad>,
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) indices: Vec<u16>,
+    /// Vertices for custom pipelines, as raw bytes since every pipeline has its own format
+    pub(crate) custom_vertices: Vec<u8>,
+    pub(crate) batches: Vec<Batch>,
+    state: BatchState,
+    /// None skips the clear and draws over whatever's in the image
+    clear_color: Option<[f32; 4]>,
+    /// Handed to `HalState::apply_shake` when the frame gets drawn
+    pub(crate) shakes: Vec<ShakeParams>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame {
+            quads: Vec::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            custom_vertices: Vec::new(),
+            batches: Vec::new(),
+            state: BatchState::default(),
+            clear_color: Some(Frame::DEFAULT_CLEAR_COLOR),
+            shakes: Vec::new(),
+        }
+    }
+}
+
+/// Everything that applies to a whole batch, changing any of it between draws starts a new batch
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct BatchState {
+    pub blend_mode: BlendMode,
+    pub parallax_layer: ParallaxLayer,
+    /// None uses the HalState's camera
+    pub camera: Option<Camera2D>,
+    /// None is the whole render area
+    pub viewport: Option<Rect<f32, f32>>,
+    pub space: Space,
+    pub material: Material,
+    /// None is the screen, otherwise a texture from `HalState::create_render_target`
+    pub target: Option<TextureId>,
+}
+
+impl BatchState {
+    /// What everything in the batch gets multiplied by, `camera` is the one the batch falls back to
+    pub fn view_matrix(&self, camera: &Camera2D, shake: &ShakeOffset) -> Mat3<f32> {
+        match self.space {
+            Space::World => {
+                let camera = shake.apply(&self.camera.unwrap_or(*camera));
+                self.parallax_layer.view_matrix(&camera)
+            }
+            Space::Screen => Mat3::identity(),
+        }
+    }
+}
+
+/// What the coordinates of a batch are relative to, see `Frame::set_space`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Space {
+    /// Goes through the camera, parallax and screen shake
+    World,
+    /// Straight clip space, (-1, -1) to (1, 1) covers the viewport no matter where the camera is.
+    /// For HUDs and other UI
+    Screen,
+}
+
+impl Default for Space {
+    fn default() -> Self {
+        Space::World
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Batch {
+    pub state: BatchState,
+    pub kind: BatchKind,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum BatchKind {
+    /// Range into `Frame::quads`
+    Quads(Range<usize>),
+    /// Ranges into `Frame::vertices` and `Frame::indices`, the indices are relative to the start of
+    /// the mesh's vertices
+    Mesh {
+        vertices: Range<usize>,
+        indices: Range<usize>,
+    },
+    /// `vertices` is a byte range into `Frame::custom_vertices`, the indices work like a mesh's
+    Custom {
+        pipeline: CustomPipelineId,
+        vertices: Range<usize>,
+        indices: Range<usize>,
+    },
+    /// Everything about it is already on the GPU
+    Indirect {
+        pipeline: CustomPipelineId,
+        mesh: IndirectMeshId,
+    },
+}
+
+/// What a batch turns into once the quads are culled and everything is in the vertex buffer
+#[derive(Debug, Clone)]
+pub(crate) struct DrawCall {
+    pub state: BatchState,
+    pub kind: DrawKind,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum DrawKind {
+    Quads {
+        first_vertex: usize,
+        num_quads: usize,
+    },
+    Mesh {
+        indices: Range<u32>,
+        base_vertex: i32,
+    },
+    Custom {
+        pipeline: CustomPipelineId,
+        /// Where the vertices start in the vertex buffer, in bytes
+        vertex_offset: u64,
+        indices: Range<u32>,
+        /// The batch's view matrix, pushed as constants since the vertices can't be transformed on
+        /// the CPU
+        view: [f32; 12],
+    },
+    Indirect {
+        pipeline: CustomPipelineId,
+        mesh: IndirectMeshId,
+        view: [f32; 12],
+    },
+}
+
+impl Frame {
+    pub const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.1, 0.2, 0.3, 1.0];
+
+    pub fn new() -> Self {
+        Frame::default()
+    }
+
+    /// Empties the frame but keeps the allocations, the batch state and clear color go back to the
+    /// defaults
+    pub fn clear(&mut self) {
+        self.quads.clear();
+        self.vertices.clear();
+        self.indices.clear();
+        self.custom_vertices.clear();
+        self.batches.clear();
+        self.state = BatchState::default();
+        self.clear_color = Some(Frame::DEFAULT_CLEAR_COLOR);
+        self.shakes.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// Hashes everything that ends up in the buffers and the draw calls, floats go in by their bits
+    pub(crate) fn hash_contents<H: Hasher>(&self, state: &mut H) {
+        for textured_quad in &self.quads {
+            let quad = &textured_quad.quad;
+            for point in &[
+                quad.top_left,
+                quad.bottom_left,
+                quad.bottom_right,
+                quad.top_right,
+            ] {
+                hash_floats(&[point.x, point.y], state);
+            }
+            hash_floats(&textured_quad.uv_rect, state);
+            textured_quad.tex_num.hash(state);
+            hash_floats(&textured_quad.color, state);
+            textured_quad.flip_x.hash(state);
+            textured_quad.flip_y.hash(state);
+            textured_quad.rotation.hash(state);
+        }
+        for vertex in &self.vertices {
+            hash_floats(&vertex.xy, state);
+            hash_floats(&vertex.uv, state);
+            hash_floats(&vertex.uv_rect, state);
+            hash_floats(&vertex.color, state);
+            vertex.tex_num.hash(state);
+        }
+        self.indices.hash(state);
+        self.custom_vertices.hash(state);
+        self.clear_color.is_some().hash(state);
+        if let Some(clear_color) = self.clear_color {
+            hash_floats(&clear_color, state);
+        }
+        for batch in &self.batches {
+            batch.state.blend_mode.hash(state);
+            let scroll_factor = batch.state.parallax_layer.scroll_factor;
+            hash_floats(&[scroll_factor.x, scroll_factor.y], state);
+            if let Some(camera) = batch.state.camera {
+                hash_floats(&[camera.position.x, camera.position.y, camera.zoom], state);
+                camera.rotation.to_bits().hash(state);
+            }
+            if let Some(viewport) = batch.state.viewport {
+                hash_floats(&[viewport.x, viewport.y, viewport.w, viewport.h], state);
+            }
+            batch.state.space.hash(state);
+            batch.state.material.hash(state);
+            batch.state.target.hash(state);
+            match &batch.kind {
+                BatchKind::Quads(range) => range.hash(state),
+                BatchKind::Mesh { vertices, indices } => {
+                    vertices.hash(state);
+                    indices.hash(state);
+                }
+                BatchKind::Custom {
+                    pipeline,
+                    vertices,
+                    indices,
+                } => {
+                    pipeline.hash(state);
+                    vertices.hash(state);
+                    indices.hash(state);
+                }
+                BatchKind::Indirect { pipeline, mesh } => {
+                    pipeline.hash(state);
+                    mesh.hash(state);
+                }
+            }
+        }
+    }
+
+    /// What the frame gets cleared to before anything is drawn. None skips clearing, which saves a
+    /// bit of bandwidth when the whole screen gets drawn over anyway, but anything that isn't
+    /// covered ends up as garbage
+    pub fn set_clear_color(&mut self, clear_color: Option<[f32; 4]>) -> &mut Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    pub fn clear_color(&self) -> Option<[f32; 4]> {
+        self.clear_color
+    }
+
+    /// Starts a shake once this frame gets drawn, same as `HalState::apply_shake` but for code
+    /// that only gets to see the `Frame`. Drawing the frame again without clearing it starts
+    /// another one
+    pub fn apply_shake(&mut self, params: ShakeParams) -> &mut Self {
+        self.shakes.push(params);
+        self
+    }
+
+    /// Blend mode used for everything drawn after this call
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) -> &mut Self {
+        self.state.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.state.blend_mode
+    }
+
+    /// Parallax layer for everything drawn after this call
+    pub fn set_parallax_layer(&mut self, layer: ParallaxLayer) -> &mut Self {
+        self.state.parallax_layer = layer;
+        self
+    }
+
+    pub fn parallax_layer(&self) -> ParallaxLayer {
+        self.state.parallax_layer
+    }
+
+    /// Draws everything after this call into part of the screen, for split screen. The rect is a
+    /// fraction of the render area with (0, 0) in the top left, so (0.5, 0, 0.5, 1) is the right half.
+    /// Clip space covers just the viewport, and anything outside of it gets cut off
+    pub fn set_viewport(&mut self, viewport: Option<Rect<f32, f32>>) -> &mut Self {
+        self.state.viewport = viewport;
+        self
+    }
+
+    pub fn viewport(&self) -> Option<Rect<f32, f32>> {
+        self.state.viewport
+    }
+
+    /// Camera for everything drawn after this call, instead of the HalState's camera. Mostly useful
+    /// together with `set_viewport`
+    pub fn set_camera(&mut self, camera: Option<Camera2D>) -> &mut Self {
+        self.state.camera = camera;
+        self
+    }
+
+    pub fn camera(&self) -> Option<Camera2D> {
+        self.state.camera
+    }
+
+    /// Coordinate space for everything drawn after this call, switch to `Space::Screen` for UI that
+    /// shouldn't move with the camera
+    pub fn set_space(&mut self, space: Space) -> &mut Self {
+        self.state.space = space;
+        self
+    }
+
+    pub fn space(&self) -> Space {
+        self.state.space
+    }
+
+    /// Material for the quads and meshes drawn after this call
+    pub fn set_material(&mut self, material: Material) -> &mut Self {
+        self.state.material = material;
+        self
+    }
+
+    pub fn material(&self) -> Material {
+        self.state.material
+    }
+
+    /// Draws everything after this call into a texture from `HalState::create_render_target`
+    /// instead of the screen, None goes back to the screen. The rest of the batch state goes back
+    /// to the defaults, so whatever draws next starts out like it got a cleared frame. Targets get
+    /// cleared to the clear color (transparent if it's None) and drawn before the screen, in the
+    /// order they were first used
+    pub fn set_target(&mut self, target: Option<TextureId>) -> &mut Self {
+        self.state = BatchState {
+            target,
+            ..BatchState::default()
+        };
+        self
+    }
+
+    pub fn target(&self) -> Option<TextureId> {
+        self.state.target
+    }
+
+    pub fn draw_quads(&mut self, textured_quads: &[TexturedQuad]) -> &mut Self {
+        self.push_quads(textured_quads.iter().cloned())
+    }
+
+    /// Same as `draw_quads`, except the uv_rects can go past 0..1 to repeat the texture. Only for
+    /// textures with a `WrapMode::Tile` or `WrapMode::Mirror` sampler outside the atlas, anything
+    /// else gets its edge pixels smeared out or bits of its neighbours in the atlas
+    pub fn draw_tiled_quads(&mut self, textured_quads: &[TexturedQuad]) -> &mut Self {
+        self.push_tiled_quads(textured_quads.iter().cloned())
+    }
+
+    /// Draws sprites in order, they batch together with quads
+    pub fn draw_sprites(&mut self, sprites: &[Sprite]) -> &mut Self {
+        self.push_quads(sprites.iter().map(Sprite::textured_quad))
+    }
+
+    /// Draws the shadows first and then the quads over them, in two batches no matter how many
+    /// quads there are
+    pub fn draw_quads_with_shadow(
+        &mut self,
+        textured_quads: &[TexturedQuad],
+        shadow: &DropShadow,
+    ) -> &mut Self {
+        self.push_shadows(
+            textured_quads.iter().map(|quad| shadow.shadow_quad(quad)),
+            shadow,
+        );
+        self.draw_quads(textured_quads)
+    }
+
+    pub fn draw_sprites_with_shadow(
+        &mut self,
+        sprites: &[Sprite],
+        shadow: &DropShadow,
+    ) -> &mut Self {
+        self.push_shadows(
+            sprites
+                .iter()
+                .map(|sprite| shadow.shadow_quad(&sprite.textured_quad())),
+            shadow,
+        );
+        self.draw_sprites(sprites)
+    }
+
+    /// Keeps whatever material was set for the quads themselves
+    fn push_shadows<I: IntoIterator<Item = TexturedQuad>>(
+        &mut self,
+        shadows: I,
+        shadow: &DropShadow,
+    ) {
+        let material = self.state.material;
+        self.state.material = Material::Shadow { blur: shadow.blur };
+        self.push_quads(shadows);
+        self.state.material = material;
+    }
+
+    fn push_quads<I: IntoIterator<Item = TexturedQuad>>(&mut self, textured_quads: I) -> &mut Self {
+        let start = self.quads.len();
+        self.push_tiled_quads(textured_quads);
+        debug_assert!(
+            self.quads[start..]
+                .iter()
+                .all(|quad| quad.uv_rect.iter().all(|&uv| uv >= 0.0 && uv <= 1.0)),
+            "uv_rect is normalized now, use TexturedQuad::with_pixel_uv for pixel coordinates"
+        );
+        self
+    }
+
+    fn push_tiled_quads<I: IntoIterator<Item = TexturedQuad>>(
+        &mut self,
+        textured_quads: I,
+    ) -> &mut Self {
+        let start = self.quads.len();
+        self.quads.extend(textured_quads);
+        let end = self.quads.len();
+        // consecutive quad batches with the same state get merged so they can share draw calls
+        let merged = match self.batches.last_mut() {
+            Some(Batch {
+                state,
+                kind: BatchKind::Quads(range),
+            }) if *state == self.state && range.end == start => {
+                range.end = end;
+                true
+            }
+            _ => false,
+        };
+        if !merged {
+            self.batches.push(Batch {
+                state: self.state,
+                kind: BatchKind::Quads(start..end),
+            });
+        }
+        self
+    }
+
+    /// Draws rounded quads as meshes, consecutive ones with the same texture share a batch
+    pub fn draw_rounded_quads(&mut self, quads: &[RoundedQuad]) -> &mut Self {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (i, quad) in quads.iter().enumerate() {
+            quad.build(&mut vertices, &mut indices);
+            // leaves room for the next one under the u16 index limit
+            let flush = match quads.get(i + 1) {
+                Some(next) => {
+                    next.texture != quad.texture
+                        || vertices.len() + 4 * (next.segments as usize + 1) + 1
+                            > core::u16::MAX as usize
+                }
+                None => true,
+            };
+            if flush {
+                self.draw_mesh(&vertices, &indices, quad.texture);
+                vertices.clear();
+                indices.clear();
+            }
+        }
+        self
+    }
+
+    /// Empty ones get skipped, they batch together with quads
+    pub fn draw_progress_bars(&mut self, bars: &[ProgressBar]) -> &mut Self {
+        self.push_quads(bars.iter().filter_map(ProgressBar::textured_quad))
+    }
+
+    /// Draws radial fills as meshes, consecutive ones with the same texture share a batch
+    pub fn draw_radial_fills(&mut self, fills: &[RadialFill]) -> &mut Self {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (i, fill) in fills.iter().enumerate() {
+            fill.build(&mut vertices, &mut indices);
+            // every fill is at most 7 vertices
+            let flush = match fills.get(i + 1) {
+                Some(next) => {
+                    next.texture != fill.texture || vertices.len() + 7 > core::u16::MAX as usize
+                }
+                None => true,
+            };
+            if flush && !indices.is_empty() {
+                self.draw_mesh(&vertices, &indices, fill.texture);
+                vertices.clear();
+                indices.clear();
+            }
+        }
+        self
+    }
+
+    /// Draws an arbitrary triangle list, `indices` index into `vertices`. The `tex_num` of every vertex
+    /// gets replaced with `texture`
+    pub fn draw_mesh(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u16],
+        texture: TextureId,
+    ) -> &mut Self {
+        debug_assert!(
+            indices.iter().all(|&i| (i as usize) < vertices.len()),
+            "mesh index out of bounds"
+        );
+        let vertex_start = self.vertices.len();
+        let index_start = self.indices.len();
+        self.vertices.extend(vertices.iter().map(|vertex| Vertex {
+            tex_num: texture.index(),
+            ..*vertex
+        }));
+        self.indices.extend_from_slice(indices);
+        self.batches.push(Batch {
+            state: self.state,
+            kind: BatchKind::Mesh {
+                vertices: vertex_start..self.vertices.len(),
+                indices: index_start..self.indices.len(),
+            },
+        });
+        self
+    }
+
+    /// Draws a triangle list with a pipeline made by `HalState::create_custom_pipeline`, `V` has to
+    /// match the format the pipeline was made with. The vertices go to the GPU as they are, the
+    /// camera only gets applied if the vertex shader reads the view matrix from the push constants
+    /// (a `mat3` at offset 0)
+    pub fn draw_custom<V: VertexLayout>(
+        &mut self,
+        pipeline: CustomPipelineId,
+        vertices: &[V],
+        indices: &[u16],
+    ) -> &mut Self {
+        debug_assert_eq!(
+            V::format().stride() as usize,
+            mem::size_of::<V>(),
+            "vertex format doesn't match the vertex type"
+        );
+        debug_assert!(
+            indices.iter().all(|&i| (i as usize) < vertices.len()),
+            "custom index out of bounds"
+        );
+        // VertexLayout promises there's no padding, so every byte is initialized
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * mem::size_of::<V>(),
+            )
+        };
+        // keeps every attribute aligned no matter what got drawn before
+        let vertex_start = (self.custom_vertices.len() + 15) & !15;
+        let index_start = self.indices.len();
+        self.custom_vertices.resize(vertex_start, 0);
+        self.custom_vertices.extend_from_slice(bytes);
+        self.indices.extend_from_slice(indices);
+        self.batches.push(Batch {
+            state: self.state,
+            kind: BatchKind::Custom {
+                pipeline,
+                vertices: vertex_start..self.custom_vertices.len(),
+                indices: index_start..self.indices.len(),
+            },
+        });
+        self
+    }
+
+    /// `draw_custom` for a pipeline made with `WithParams<V>`'s format. `vertices` gets split into
+    /// `params.len()` equal runs, 4 each for quads, and every vertex of a run gets that run's params
+    pub fn draw_custom_with_params<V: VertexLayout>(
+        &mut self,
+        pipeline: CustomPipelineId,
+        vertices: &[V],
+        indices: &[u16],
+        params: &[[f32; 4]],
+    ) -> &mut Self {
+        if params.is_empty() {
+            return self;
+        }
+        debug_assert_eq!(
+            vertices.len() % params.len(),
+            0,
+            "vertices don't split evenly between the params"
+        );
+        let run = vertices.len() / params.len();
+        let vertices: Vec<WithParams<V>> = vertices
+            .iter()
+            .enumerate()
+            .map(|(i, &vertex)| WithParams {
+                vertex,
+                params: params[(i / run.max(1)).min(params.len() - 1)],
+            })
+            .collect();
+        self.draw_custom(pipeline, &vertices, indices)
+    }
+
+    /// Runs the draw commands of a mesh made by `HalState::create_indirect_mesh` with a custom
+    /// pipeline, nothing gets copied so this costs the same no matter how big the mesh is
+    pub fn draw_indirect(&mut self, pipeline: CustomPipelineId, mesh: IndirectMeshId) -> &mut Self {
+        self.batches.push(Batch {
+            state: self.state,
+            kind: BatchKind::Indirect { pipeline, mesh },
+        });
+        self
+    }
+}
+
+pub(crate) fn hash_floats<H: Hasher>(floats: &[f32], state: &mut H) {
+    for f in floats {
+        f.to_bits().hash(state);
+    }
+}
@@ -0,0 +1,262 @@
+use image::{Rgba, RgbaImage};
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+/// Set this to write the rendered images as the new golden images instead of comparing against them
+pub const UPDATE_GOLDEN_VAR: &str = "RMGE_UPDATE_GOLDEN";
+
+/// Compares rendered images against PNGs checked into the repo. Render the scene with a
+/// `SoftwareRasterizer` (or grab it with `HalState::start_capture`) and hand it to `check`
+#[derive(Debug, Clone)]
+pub struct GoldenImage {
+    path: PathBuf,
+    /// How far apart a channel can be before the pixel counts as different
+    pub channel_tolerance: u8,
+    /// Fraction of the pixels that can be different before the check fails
+    pub max_mismatched: f32,
+}
+
+/// Why `GoldenImage::check` failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoldenMismatch {
+    /// There's no golden image yet, run again with `UPDATE_GOLDEN_VAR` set to make one
+    Missing(PathBuf),
+    /// The golden image couldn't be read or the new one couldn't be written
+    Io(PathBuf),
+    Size {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// Too many pixels were off, the diff image has them in red over a faded copy of the golden
+    Pixels {
+        mismatched: usize,
+        total: usize,
+        diff: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenMismatch::Missing(path) => write!(
+                f,
+                "no golden image at {}, set {} to create it",
+                path.display(),
+                UPDATE_GOLDEN_VAR
+            ),
+            GoldenMismatch::Io(path) => write!(f, "couldn't read or write {}", path.display()),
+            GoldenMismatch::Size { expected, actual } => write!(
+                f,
+                "expected a {}x{} image, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            GoldenMismatch::Pixels {
+                mismatched,
+                total,
+                diff,
+            } => write!(
+                f,
+                "{} of {} pixels differ, see {}",
+                mismatched,
+                total,
+                diff.display()
+            ),
+        }
+    }
+}
+
+impl GoldenImage {
+    /// Exact matches only, loosen it with the fields for anything that went through a GPU
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        GoldenImage {
+            path: path.as_ref().to_path_buf(),
+            channel_tolerance: 0,
+            max_mismatched: 0.0,
+        }
+    }
+
+    pub fn with_tolerance(self, channel_tolerance: u8, max_mismatched: f32) -> Self {
+        GoldenImage {
+            channel_tolerance,
+            max_mismatched,
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the diff goes when the check fails, next to the golden image
+    pub fn diff_path(&self) -> PathBuf {
+        self.path.with_extension("diff.png")
+    }
+
+    /// Where the rendered image goes when the check fails, so it can be looked at or copied over
+    pub fn actual_path(&self) -> PathBuf {
+        self.path.with_extension("actual.png")
+    }
+
+    pub fn check(&self, image: &RgbaImage) -> Result<(), GoldenMismatch> {
+        if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            return image
+                .save(&self.path)
+                .map_err(|_| GoldenMismatch::Io(self.path.clone()));
+        }
+        if !self.path.exists() {
+            return Err(GoldenMismatch::Missing(self.path.clone()));
+        }
+        let golden = image::open(&self.path)
+            .map_err(|_| GoldenMismatch::Io(self.path.clone()))?
+            .to_rgba();
+        if golden.dimensions() != image.dimensions() {
+            return Err(GoldenMismatch::Size {
+                expected: golden.dimensions(),
+                actual: image.dimensions(),
+            });
+        }
+        let (width, height) = golden.dimensions();
+        let mut diff = RgbaImage::new(width, height);
+        let mut mismatched = 0;
+        for (x, y, expected) in golden.enumerate_pixels() {
+            let actual = image.get_pixel(x, y);
+            let differs = expected.data.iter().zip(&actual.data).any(|(&e, &a)| {
+                (i16::from(e) - i16::from(a)).abs() > i16::from(self.channel_tolerance)
+            });
+            let faded = |c: u8| 128 + c / 2;
+            diff.put_pixel(
+                x,
+                y,
+                if differs {
+                    mismatched += 1;
+                    Rgba {
+                        data: [255, 0, 0, 255],
+                    }
+                } else {
+                    Rgba {
+                        data: [
+                            faded(expected[0]),
+                            faded(expected[1]),
+                            faded(expected[2]),
+                            255,
+                        ],
+                    }
+                },
+            );
+        }
+        let total = (width * height) as usize;
+        if mismatched as f32 <= self.max_mismatched * total as f32 {
+            return Ok(());
+        }
+        let diff_path = self.diff_path();
+        diff.save(&diff_path)
+            .map_err(|_| GoldenMismatch::Io(diff_path.clone()))?;
+        image
+            .save(self.actual_path())
+            .map_err(|_| GoldenMismatch::Io(self.actual_path()))?;
+        Err(GoldenMismatch::Pixels {
+            mismatched,
+            total,
+            diff: diff_path,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "software-renderer"))]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::{Quad, Rect},
+        graphics::{BlendMode, Frame, SoftwareRasterizer, Space, TextureId, TexturedQuad},
+    };
+
+    fn golden(name: &str) -> GoldenImage {
+        GoldenImage::new(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/golden")
+                .join(name),
+        )
+    }
+
+    fn screen_quad(rect: Rect<f32, f32>, texture: TextureId) -> TexturedQuad {
+        TexturedQuad::new(Quad::from(rect), texture.index())
+    }
+
+    /// A 2x2 checker over the whole screen with a faint white quad over the bottom half
+    fn checker_fade() -> RgbaImage {
+        let texels = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 0, 255],
+        ];
+        let mut rasterizer = SoftwareRasterizer::new();
+        let checker = rasterizer.add_texture(RgbaImage::from_fn(2, 2, |x, y| Rgba {
+            data: texels[(y * 2 + x) as usize],
+        }));
+        let mut frame = Frame::new();
+        frame
+            .set_clear_color(Some([0.0, 0.0, 0.0, 1.0]))
+            .set_space(Space::Screen)
+            .set_blend_mode(BlendMode::Alpha)
+            .draw_quads(&[
+                screen_quad(
+                    Rect {
+                        x: -1.0,
+                        y: -1.0,
+                        w: 2.0,
+                        h: 2.0,
+                    },
+                    checker,
+                ),
+                screen_quad(
+                    Rect {
+                        x: -1.0,
+                        y: 0.0,
+                        w: 2.0,
+                        h: 1.0,
+                    },
+                    TextureId::NONE,
+                )
+                .with_color([1.0, 1.0, 1.0, 0.25]),
+            ]);
+        rasterizer.render(&frame, 8, 8)
+    }
+
+    #[test]
+    fn matches_checked_in_golden() {
+        if let Err(mismatch) = golden("checker_fade.png").check(&checker_fade()) {
+            panic!("{}", mismatch);
+        }
+    }
+
+    #[test]
+    fn reports_size_mismatch() {
+        // updating would overwrite the golden with the wrong image
+        if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            return;
+        }
+        let image = RgbaImage::new(4, 4);
+        assert_eq!(
+            golden("checker_fade.png").check(&image),
+            Err(GoldenMismatch::Size {
+                expected: (8, 8),
+                actual: (4, 4),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_missing_golden() {
+        if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            return;
+        }
+        let golden = golden("does_not_exist.png");
+        assert_eq!(
+            golden.check(&RgbaImage::new(1, 1)),
+            Err(GoldenMismatch::Missing(golden.path().to_path_buf()))
+        );
+    }
+}
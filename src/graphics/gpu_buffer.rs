@@ -1,4 +1,4 @@
-use std::{marker::PhantomData, mem::ManuallyDrop};
+use std::{marker::PhantomData, mem, mem::ManuallyDrop, ops::Range, slice};
 
 use gfx_hal::{
     adapter::{Adapter, MemoryTypeId, PhysicalDevice},
@@ -42,6 +42,8 @@ pub struct BufferBundle<B: Backend, D: Device<B>> {
     pub buffer: ManuallyDrop<B::Buffer>,
     pub requirements: Requirements,
     pub memory: ManuallyDrop<B::Memory>,
+    /// Writes through a mapping of non-coherent memory only show up on the GPU after a flush
+    pub coherent: bool,
     pub phantom: PhantomData<D>,
 }
 
@@ -57,18 +59,23 @@ impl<B: Backend, D: Device<B>> BufferBundle<B, D> {
                 .create_buffer(size as u64, usage)
                 .map_err(|_| "Couldn't create a buffer!")?;
             let requirements = device.get_buffer_requirements(&buffer);
-            let memory_type_id = adapter
-                .physical_device
-                .memory_properties()
-                .memory_types
-                .iter()
-                .enumerate()
-                .find(|&(id, memory_type)| {
-                    requirements.type_mask & (1 << id) != 0
-                        && memory_type.properties.contains(Properties::CPU_VISIBLE)
-                })
-                .map(|(id, _)| MemoryTypeId(id))
-                .ok_or("Couldn't find a memory type to support the vertex buffer")?;
+            let memory_types = adapter.physical_device.memory_properties().memory_types;
+            let find_memory_type = |properties: Properties| {
+                memory_types
+                    .iter()
+                    .enumerate()
+                    .find(|&(id, memory_type)| {
+                        requirements.type_mask & (1 << id) != 0
+                            && memory_type.properties.contains(properties)
+                    })
+                    .map(|(id, memory_type)| (MemoryTypeId(id), memory_type.properties))
+            };
+            // coherent memory saves the flushes, but some devices only have non-coherent host
+            // visible memory for some buffer types
+            let (memory_type_id, properties) =
+                find_memory_type(Properties::CPU_VISIBLE | Properties::COHERENT)
+                    .or_else(|| find_memory_type(Properties::CPU_VISIBLE))
+                    .ok_or("Couldn't find a memory type to support the vertex buffer")?;
             let memory = device
                 .allocate_memory(memory_type_id, requirements.size)
                 .map_err(|_| "Couldn't allocate buffer memory!")?;
@@ -79,14 +86,140 @@ impl<B: Backend, D: Device<B>> BufferBundle<B, D> {
                 buffer: ManuallyDrop::new(buffer),
                 requirements,
                 memory: ManuallyDrop::new(memory),
+                coherent: properties.contains(Properties::COHERENT),
                 phantom: PhantomData,
             })
         }
     }
 
+    /// Copies `data` to the start of the buffer and flushes it if the memory isn't coherent. The
+    /// GPU can't be using the buffer while this happens
+    pub unsafe fn write<T: Copy>(&self, device: &D, data: &[T]) -> Result<(), &'static str> {
+        let size = mem::size_of::<T>() * data.len();
+        if size as u64 > self.requirements.size {
+            return Err("The data doesn't fit in the buffer!");
+        }
+        let mapping = device
+            .map_memory(&self.memory, 0..self.requirements.size)
+            .map_err(|_| "Couldn't map the buffer!")?;
+        std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, mapping, size);
+        // the whole allocation is always a valid range to flush, parts of it have to be aligned
+        let flushed = if self.coherent {
+            Ok(())
+        } else {
+            device
+                .flush_mapped_memory_ranges(Some((&*self.memory, 0..self.requirements.size)))
+                .map_err(|_| "Couldn't flush the buffer!")
+        };
+        device.unmap_memory(&self.memory);
+        flushed
+    }
+
+    /// Makes what the GPU wrote visible through a mapping, does nothing on coherent memory. `range`
+    /// needs to be the whole buffer or aligned to `non_coherent_atom_size`
+    pub unsafe fn invalidate(&self, device: &D, range: Range<u64>) -> Result<(), &'static str> {
+        if self.coherent {
+            return Ok(());
+        }
+        device
+            .invalidate_mapped_memory_ranges(Some((&*self.memory, range)))
+            .map_err(|_| "Couldn't invalidate the buffer!")
+    }
+
     pub unsafe fn manually_drop(&self, device: &D) {
         use core::ptr::read;
         device.destroy_buffer(ManuallyDrop::into_inner(read(&self.buffer)));
         device.free_memory(ManuallyDrop::into_inner(read(&self.memory)));
     }
 }
+
+/// A buffer that stays mapped for its whole life, split into one region per frame in flight so
+/// writing the next frame never touches what the GPU is still reading. Saves mapping and unmapping
+/// every frame, which adds up with a lot of quads
+pub struct StreamBuffer<B: Backend, D: Device<B>> {
+    bundle: BufferBundle<B, D>,
+    mapping: *mut u8,
+    region_size: usize,
+    regions: usize,
+    /// Flushes have to start and end on multiples of this
+    atom_size: usize,
+}
+
+impl<B: Backend, D: Device<B>> StreamBuffer<B, D> {
+    pub fn new(
+        adapter: &Adapter<B>,
+        device: &D,
+        region_size: usize,
+        regions: usize,
+        usage: BufferUsage,
+    ) -> Result<Self, &'static str> {
+        let atom_size = adapter
+            .physical_device
+            .limits()
+            .non_coherent_atom_size
+            .max(mem::align_of::<u32>());
+        // every region starts on an atom so flushing one never spills into the next
+        let region_size = align_up(region_size.max(1), atom_size);
+        let bundle = BufferBundle::new(adapter, device, region_size * regions, usage)?;
+        let mapping =
+            match unsafe { device.map_memory(&bundle.memory, 0..bundle.requirements.size) } {
+                Ok(mapping) => mapping,
+                Err(_) => {
+                    unsafe { bundle.manually_drop(device) };
+                    return Err("Couldn't map the stream buffer!");
+                }
+            };
+        Ok(StreamBuffer {
+            bundle,
+            mapping,
+            region_size,
+            regions,
+            atom_size,
+        })
+    }
+
+    pub fn buffer(&self) -> &B::Buffer {
+        &self.bundle.buffer
+    }
+
+    /// Where `region` starts in the buffer, for binding it
+    pub fn region_offset(&self, region: usize) -> u64 {
+        (region * self.region_size) as u64
+    }
+
+    /// The GPU can't be reading from `region` while this is around, waiting on the frame's fence
+    /// takes care of that. `T` can't have any padding
+    pub unsafe fn region_mut<T: Copy>(&mut self, region: usize) -> &mut [T] {
+        debug_assert!(region < self.regions);
+        let start = self.mapping.add(region * self.region_size);
+        debug_assert_eq!(start as usize % mem::align_of::<T>(), 0);
+        slice::from_raw_parts_mut(start as *mut T, self.region_size / mem::size_of::<T>())
+    }
+
+    /// Makes the bytes in `range` of `region` visible to the GPU, does nothing on coherent memory
+    pub unsafe fn flush(
+        &self,
+        device: &D,
+        region: usize,
+        range: Range<usize>,
+    ) -> Result<(), &'static str> {
+        if self.bundle.coherent || range.start >= range.end {
+            return Ok(());
+        }
+        let base = region * self.region_size;
+        let start = (base + range.start) / self.atom_size * self.atom_size;
+        let end = align_up(base + range.end, self.atom_size).min(base + self.region_size);
+        device
+            .flush_mapped_memory_ranges(Some((&*self.bundle.memory, start as u64..end as u64)))
+            .map_err(|_| "Couldn't flush the stream buffer!")
+    }
+
+    pub unsafe fn manually_drop(&self, device: &D) {
+        device.unmap_memory(&self.bundle.memory);
+        self.bundle.manually_drop(device);
+    }
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
@@ -0,0 +1,243 @@
+use gfx_hal::format::Format;
+
+/// A render target in a `FrameGraph`, only valid for the graph it came from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TargetId(usize);
+
+/// What a transient target needs to look like, targets with the same description can share memory
+/// when their lifetimes don't overlap
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TargetDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+}
+
+/// How a pass uses a target, writes are what order passes against each other
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    ColorWrite,
+    ShaderRead,
+    TransferRead,
+    TransferWrite,
+}
+
+impl Access {
+    fn is_write(self) -> bool {
+        match self {
+            Access::ColorWrite | Access::TransferWrite => true,
+            Access::ShaderRead | Access::TransferRead => false,
+        }
+    }
+}
+
+struct TargetNode {
+    /// None for imported targets like the swapchain image, those never get aliased and anything
+    /// written to them counts as output
+    desc: Option<TargetDesc>,
+}
+
+struct PassNode<P> {
+    pass: P,
+    uses: Vec<(TargetId, Access)>,
+}
+
+/// Passes say which targets they read and write, and `compile` works out the order they run in,
+/// which ones can be skipped because nothing uses what they write and which transient targets can
+/// share an image. Barriers are still up to the passes themselves. Writers of the same target run
+/// in the order they were added, everything reading it runs after the last of them
+pub struct FrameGraph<P> {
+    targets: Vec<TargetNode>,
+    passes: Vec<PassNode<P>>,
+}
+
+impl<P> Default for FrameGraph<P> {
+    fn default() -> Self {
+        FrameGraph {
+            targets: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+}
+
+impl<P> FrameGraph<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A target that only exists for this frame
+    pub fn create_target(&mut self, desc: TargetDesc) -> TargetId {
+        self.targets.push(TargetNode { desc: Some(desc) });
+        TargetId(self.targets.len() - 1)
+    }
+
+    /// Something that lives outside the graph, like the swapchain image or a buffer being read back
+    pub fn import_target(&mut self) -> TargetId {
+        self.targets.push(TargetNode { desc: None });
+        TargetId(self.targets.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: P, uses: &[(TargetId, Access)]) {
+        self.passes.push(PassNode {
+            pass,
+            uses: uses.to_vec(),
+        });
+    }
+
+    pub fn compile(self) -> Result<CompiledGraph<P>, &'static str> {
+        let FrameGraph { targets, passes } = self;
+        if passes
+            .iter()
+            .flat_map(|pass| &pass.uses)
+            .any(|&(target, _)| target.0 >= targets.len())
+        {
+            return Err("A pass uses a target from another frame graph!");
+        }
+        let writes = |pass: &PassNode<P>, target: TargetId| {
+            pass.uses
+                .iter()
+                .any(|&(t, access)| t == target && access.is_write())
+        };
+
+        // dependencies[i] is every pass that has to run before pass i
+        let mut dependencies = vec![Vec::new(); passes.len()];
+        for target in (0..targets.len()).map(TargetId) {
+            let writers: Vec<usize> = (0..passes.len())
+                .filter(|&i| writes(&passes[i], target))
+                .collect();
+            for pair in writers.windows(2) {
+                dependencies[pair[1]].push(pair[0]);
+            }
+            let last_writer = match writers.last() {
+                Some(&last) => last,
+                None if targets[target.0].desc.is_none() => continue,
+                None => {
+                    let read = passes
+                        .iter()
+                        .any(|pass| pass.uses.iter().any(|&(t, _)| t == target));
+                    if read {
+                        return Err("A transient target gets read but never written!");
+                    }
+                    continue;
+                }
+            };
+            for (i, pass) in passes.iter().enumerate() {
+                if !writes(pass, target) && pass.uses.iter().any(|&(t, _)| t == target) {
+                    dependencies[i].push(last_writer);
+                }
+            }
+        }
+
+        // keep whatever writes to an imported target, and then whatever those depend on
+        let mut needed: Vec<bool> = passes
+            .iter()
+            .map(|pass| {
+                pass.uses
+                    .iter()
+                    .any(|&(target, access)| access.is_write() && targets[target.0].desc.is_none())
+            })
+            .collect();
+        let mut stack: Vec<usize> = (0..passes.len()).filter(|&i| needed[i]).collect();
+        while let Some(i) = stack.pop() {
+            for &dependency in &dependencies[i] {
+                if !needed[dependency] {
+                    needed[dependency] = true;
+                    stack.push(dependency);
+                }
+            }
+        }
+
+        // topological sort, going by the order passes were added whenever there's a choice
+        let mut order = Vec::with_capacity(passes.len());
+        let mut placed = vec![false; passes.len()];
+        let num_needed = needed.iter().filter(|&&needed| needed).count();
+        while order.len() < num_needed {
+            let next = (0..passes.len())
+                .find(|&i| needed[i] && !placed[i] && dependencies[i].iter().all(|&d| placed[d]));
+            match next {
+                Some(i) => {
+                    placed[i] = true;
+                    order.push(i);
+                }
+                None => return Err("The frame graph has a cycle!"),
+            }
+        }
+
+        // which positions in the order each transient target is used between
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; targets.len()];
+        for (position, &i) in order.iter().enumerate() {
+            for &(target, _) in &passes[i].uses {
+                let lifetime = &mut lifetimes[target.0];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+            }
+        }
+        // handing out images in order of first use, each one goes to the first image with the
+        // same description that's free by then
+        let mut transient: Vec<(usize, TargetDesc, (usize, usize))> = targets
+            .iter()
+            .enumerate()
+            .filter_map(|(target, node)| match (node.desc, lifetimes[target]) {
+                (Some(desc), Some(lifetime)) => Some((target, desc, lifetime)),
+                _ => None,
+            })
+            .collect();
+        transient.sort_by_key(|&(_, _, (first, _))| first);
+        let mut physical_targets: Vec<(TargetDesc, usize)> = Vec::new();
+        let mut physical = vec![None; targets.len()];
+        for (target, desc, (first, last)) in transient {
+            let free = physical_targets
+                .iter()
+                .position(|&(slot_desc, free_after)| slot_desc == desc && free_after < first);
+            physical[target] = Some(match free {
+                Some(slot) => {
+                    physical_targets[slot].1 = last;
+                    slot
+                }
+                None => {
+                    physical_targets.push((desc, last));
+                    physical_targets.len() - 1
+                }
+            });
+        }
+
+        let mut passes: Vec<Option<P>> = passes.into_iter().map(|node| Some(node.pass)).collect();
+        let compiled = order.iter().map(|&i| passes[i].take().unwrap()).collect();
+        Ok(CompiledGraph {
+            passes: compiled,
+            physical,
+            physical_targets: physical_targets.into_iter().map(|(desc, _)| desc).collect(),
+        })
+    }
+}
+
+/// The passes that are needed, in the order they run
+#[derive(Debug, Clone)]
+pub struct CompiledGraph<P> {
+    passes: Vec<P>,
+    physical: Vec<Option<usize>>,
+    physical_targets: Vec<TargetDesc>,
+}
+
+impl<P> CompiledGraph<P> {
+    pub fn passes(&self) -> &[P] {
+        &self.passes
+    }
+
+    pub fn into_passes(self) -> Vec<P> {
+        self.passes
+    }
+
+    /// The images that actually have to be created, transient targets get mapped onto these
+    pub fn physical_targets(&self) -> &[TargetDesc] {
+        &self.physical_targets
+    }
+
+    /// Which of `physical_targets` a transient target ended up in, None for imported targets and
+    /// ones only used by passes that got culled
+    pub fn physical_target(&self, target: TargetId) -> Option<usize> {
+        self.physical.get(target.0).and_then(|&slot| slot)
+    }
+}
@@ -0,0 +1,350 @@
+use crate::geometry::Vec2;
+use crate::graphics::{
+    frame::Frame,
+    loadedimage::TextureId,
+    sprite::{Anchor, Sprite},
+    TexturedQuad,
+};
+use crate::timing::BeatClock;
+
+/// Which way the notes move, towards the receptors. `Up` is towards +y in world space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl ScrollDirection {
+    fn vector(self) -> Vec2<f32> {
+        match self {
+            ScrollDirection::Up => Vec2::new(0.0, 1.0),
+            ScrollDirection::Down => Vec2::new(0.0, -1.0),
+            ScrollDirection::Left => Vec2::new(-1.0, 0.0),
+            ScrollDirection::Right => Vec2::new(1.0, 0.0),
+        }
+    }
+
+    /// Notes are drawn upright when the highway scrolls down, this turns them to face the way
+    /// they're going otherwise
+    fn rotation(self) -> f64 {
+        match self {
+            ScrollDirection::Down => 0.0,
+            ScrollDirection::Right => 90.0,
+            ScrollDirection::Up => 180.0,
+            ScrollDirection::Left => 270.0,
+        }
+    }
+}
+
+/// How song time turns into distance along the highway
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScrollSpeed {
+    /// World units per second, notes are spaced the same no matter the tempo
+    Constant(f32),
+    /// World units per beat, so notes bunch up in slow parts, spread out in fast ones and stand
+    /// still during stops
+    Beats {
+        clock: BeatClock,
+        units_per_beat: f32,
+    },
+}
+
+impl ScrollSpeed {
+    /// How far `time` is from `now` along the highway, positive for notes that haven't reached the
+    /// receptors yet
+    fn distance(&self, time: f64, now: f64) -> f32 {
+        match self {
+            ScrollSpeed::Constant(speed) => (time - now) as f32 * speed,
+            ScrollSpeed::Beats {
+                clock,
+                units_per_beat,
+            } => (clock.beat_at(time) - clock.beat_at(now)) as f32 * units_per_beat,
+        }
+    }
+}
+
+/// One note in a chart. `length` is 0 for taps and how long it's held for holds, both in seconds
+/// of song time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub time: f64,
+    pub lane: u32,
+    pub length: f64,
+}
+
+impl Note {
+    pub fn tap(time: f64, lane: u32) -> Self {
+        Note {
+            time,
+            lane,
+            length: 0.0,
+        }
+    }
+
+    pub fn hold(time: f64, lane: u32, length: f64) -> Self {
+        Note { time, lane, length }
+    }
+
+    pub fn end(&self) -> f64 {
+        self.time + self.length
+    }
+}
+
+/// A texture and the part of it to use, for the pieces of a `NoteHighway`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HighwaySkin {
+    pub receptor: (TextureId, [f32; 4]),
+    pub note: (TextureId, [f32; 4]),
+    /// Stretched from the head of a hold to its end
+    pub hold_body: (TextureId, [f32; 4]),
+}
+
+impl HighwaySkin {
+    /// The whole of each texture
+    pub fn new(receptor: TextureId, note: TextureId, hold_body: TextureId) -> Self {
+        HighwaySkin {
+            receptor: (receptor, TexturedQuad::FULL_UV),
+            note: (note, TexturedQuad::FULL_UV),
+            hold_body: (hold_body, TexturedQuad::FULL_UV),
+        }
+    }
+}
+
+/// The scrolling lanes of a rhythm game: a row of receptors and notes coming at them from a chart.
+/// Everything goes out as sprites with `Frame::draw_sprites`, so a whole screen of notes is one
+/// batch as long as the skin shares a texture or array set
+#[derive(Debug, Clone)]
+pub struct NoteHighway {
+    pub lanes: u32,
+    /// Also how wide notes and receptors are across the highway
+    pub lane_width: f32,
+    /// How long notes and receptors are along the highway
+    pub note_length: f32,
+    /// Where the middle of the row of receptors is
+    pub position: Vec2<f32>,
+    pub direction: ScrollDirection,
+    pub speed: ScrollSpeed,
+    /// How far before the receptors notes show up
+    pub visible_length: f32,
+    /// How far past the receptors notes keep going before they're dropped, for missed notes to
+    /// scroll off
+    pub overshoot: f32,
+    pub skin: HighwaySkin,
+    /// Per lane, wraps around if there are fewer colors than lanes
+    pub lane_colors: Vec<[f32; 4]>,
+    /// Multiplied with a receptor's lane color while its lane is pressed
+    pub pressed_tint: [f32; 4],
+    notes: Vec<Note>,
+    hit: Vec<bool>,
+    pressed: Vec<bool>,
+    /// Notes before this one are past the overshoot, so drawing doesn't walk the whole chart
+    first: usize,
+    last_time: f64,
+    sprites: Vec<Sprite>,
+    heads: Vec<Sprite>,
+}
+
+impl NoteHighway {
+    pub fn new(lanes: u32, lane_width: f32, skin: HighwaySkin) -> Self {
+        NoteHighway {
+            lanes,
+            lane_width,
+            note_length: lane_width,
+            position: Vec2::zero(),
+            direction: ScrollDirection::Down,
+            speed: ScrollSpeed::Constant(lane_width * 8.0),
+            visible_length: lane_width * 8.0,
+            overshoot: lane_width,
+            skin,
+            lane_colors: vec![[1.0; 4]],
+            pressed_tint: [0.6, 0.6, 0.6, 1.0],
+            notes: Vec::new(),
+            hit: Vec::new(),
+            pressed: vec![false; lanes as usize],
+            first: 0,
+            last_time: std::f64::NEG_INFINITY,
+            sprites: Vec::new(),
+            heads: Vec::new(),
+        }
+    }
+
+    pub fn with_position(self, position: Vec2<f32>) -> Self {
+        NoteHighway { position, ..self }
+    }
+
+    pub fn with_direction(self, direction: ScrollDirection) -> Self {
+        NoteHighway { direction, ..self }
+    }
+
+    pub fn with_speed(self, speed: ScrollSpeed) -> Self {
+        NoteHighway { speed, ..self }
+    }
+
+    pub fn with_visible_length(self, visible_length: f32) -> Self {
+        NoteHighway {
+            visible_length,
+            ..self
+        }
+    }
+
+    pub fn with_lane_colors(self, lane_colors: Vec<[f32; 4]>) -> Self {
+        NoteHighway {
+            lane_colors,
+            ..self
+        }
+    }
+
+    /// Replaces the chart, notes with a lane past `lanes` are dropped
+    pub fn set_notes(&mut self, notes: &[Note]) {
+        let lanes = self.lanes;
+        self.notes = notes.iter().cloned().filter(|n| n.lane < lanes).collect();
+        self.notes.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.hit = vec![false; self.notes.len()];
+        self.first = 0;
+    }
+
+    /// Sorted by time, the indices are the ones `set_hit` takes
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Hit notes stop being drawn, holds keep their body until the end but lose the head
+    pub fn set_hit(&mut self, index: usize, hit: bool) {
+        if let Some(h) = self.hit.get_mut(index) {
+            *h = hit;
+        }
+    }
+
+    pub fn is_hit(&self, index: usize) -> bool {
+        self.hit.get(index).cloned().unwrap_or(false)
+    }
+
+    /// Brings back every hit note, for restarting the chart
+    pub fn reset(&mut self) {
+        for h in &mut self.hit {
+            *h = false;
+        }
+        self.first = 0;
+    }
+
+    pub fn set_pressed(&mut self, lane: u32, pressed: bool) {
+        if let Some(p) = self.pressed.get_mut(lane as usize) {
+            *p = pressed;
+        }
+    }
+
+    /// The center of `lane`'s receptor
+    pub fn receptor_position(&self, lane: u32) -> Vec2<f32> {
+        let dir = self.direction.vector();
+        let across = Vec2::new(dir.y.abs(), dir.x.abs());
+        let offset = (lane as f32 + 0.5 - self.lanes as f32 / 2.0) * self.lane_width;
+        self.position + across * offset
+    }
+
+    fn lane_color(&self, lane: u32) -> [f32; 4] {
+        if self.lane_colors.is_empty() {
+            [1.0; 4]
+        } else {
+            self.lane_colors[lane as usize % self.lane_colors.len()]
+        }
+    }
+
+    /// Builds the sprites for `song_time`: receptors first, then hold bodies, then heads so they
+    /// end up on top. Only valid until the next call
+    pub fn sprites(&mut self, song_time: f64) -> &[Sprite] {
+        // seeking backwards brings notes back that were already skipped
+        if song_time < self.last_time {
+            self.first = 0;
+        }
+        self.last_time = song_time;
+        while self.first < self.notes.len() {
+            let note = &self.notes[self.first];
+            if self.speed.distance(note.end(), song_time) < -self.overshoot {
+                self.first += 1;
+            } else {
+                break;
+            }
+        }
+
+        let dir = self.direction.vector();
+        let rotation = self.direction.rotation();
+        let size = Vec2::new(self.lane_width, self.note_length);
+        let mut sprites = std::mem::replace(&mut self.sprites, Vec::new());
+        let mut heads = std::mem::replace(&mut self.heads, Vec::new());
+        sprites.clear();
+        heads.clear();
+
+        for lane in 0..self.lanes {
+            let mut tint = self.lane_color(lane);
+            if self.pressed.get(lane as usize).cloned().unwrap_or(false) {
+                for (t, p) in tint.iter_mut().zip(&self.pressed_tint) {
+                    *t *= p;
+                }
+            }
+            let (texture, uv_rect) = self.skin.receptor;
+            sprites.push(
+                Sprite::new(texture, size)
+                    .with_uv_rect(uv_rect)
+                    .with_position(self.receptor_position(lane))
+                    .with_rotation(rotation)
+                    .with_tint(tint),
+            );
+        }
+
+        for (i, note) in self.notes.iter().enumerate().skip(self.first) {
+            let head = self.speed.distance(note.time, song_time);
+            // sorted by time, so everything after this is too far away as well
+            if head > self.visible_length {
+                break;
+            }
+            let tail = self.speed.distance(note.end(), song_time);
+            if tail < -self.overshoot {
+                continue;
+            }
+            let receptor = self.receptor_position(note.lane);
+            let color = self.lane_color(note.lane);
+            // a hit hold's body only goes from the receptors to the end
+            let head = if self.hit[i] && note.length > 0.0 {
+                head.max(0.0)
+            } else {
+                head
+            };
+            if tail > head {
+                let (texture, uv_rect) = self.skin.hold_body;
+                let body = Sprite::new(texture, Vec2::new(self.lane_width, tail - head))
+                    .with_uv_rect(uv_rect)
+                    .with_pivot(Anchor::Custom(Vec2::new(0.5, 0.0)))
+                    .with_position(receptor - dir * head)
+                    .with_rotation(rotation)
+                    .with_tint(color);
+                sprites.push(body);
+            }
+            if !self.hit[i] {
+                let (texture, uv_rect) = self.skin.note;
+                heads.push(
+                    Sprite::new(texture, size)
+                        .with_uv_rect(uv_rect)
+                        .with_position(receptor - dir * head)
+                        .with_rotation(rotation)
+                        .with_tint(color),
+                );
+            }
+        }
+
+        sprites.extend_from_slice(&heads);
+        self.sprites = sprites;
+        self.heads = heads;
+        &self.sprites
+    }
+
+    /// Draws the highway at `song_time` in whatever space the frame is in
+    pub fn draw(&mut self, frame: &mut Frame, song_time: f64) {
+        frame.draw_sprites(self.sprites(song_time));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::graphics::{gpu_buffer::BufferBundle, vertex::VertexLayout};
+use gfx_hal::{adapter::Adapter, buffer::Usage as BufferUsage, device::Device, Backend};
+use std::{mem, slice};
+
+/// One indexed draw in an indirect buffer, laid out the way the GPU reads it
+/// (`VkDrawIndexedIndirectCommand`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct IndirectDraw {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    /// Added to every index before it's looked up in the vertex buffer
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+impl IndirectDraw {
+    /// Draws `indices` once, with no vertex offset
+    pub fn new(indices: std::ops::Range<u32>) -> Self {
+        IndirectDraw {
+            index_count: indices.end - indices.start,
+            instance_count: 1,
+            first_index: indices.start,
+            vertex_offset: 0,
+            first_instance: 0,
+        }
+    }
+}
+
+/// Handle to geometry made with `HalState::create_indirect_mesh`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IndirectMeshId(pub(crate) usize);
+
+/// Vertices, indices and draw commands that stay on the GPU between frames, so drawing them every
+/// frame costs one indirect draw per command and nothing else
+pub(crate) struct IndirectMesh<B: Backend, D: Device<B>> {
+    pub vertices: BufferBundle<B, D>,
+    pub indices: BufferBundle<B, D>,
+    pub commands: BufferBundle<B, D>,
+    pub num_commands: u32,
+    /// Commands that can fit without making a new buffer
+    command_capacity: usize,
+}
+
+impl<B: Backend, D: Device<B>> IndirectMesh<B, D> {
+    pub fn new<V: VertexLayout>(
+        adapter: &Adapter<B>,
+        device: &D,
+        vertices: &[V],
+        indices: &[u16],
+        commands: &[IndirectDraw],
+    ) -> Result<Self, &'static str> {
+        debug_assert_eq!(
+            V::format().stride() as usize,
+            mem::size_of::<V>(),
+            "vertex format doesn't match the vertex type"
+        );
+        // VertexLayout promises there's no padding, so every byte is initialized
+        let vertex_bytes = unsafe {
+            slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * mem::size_of::<V>(),
+            )
+        };
+        let vertex_buffer = upload(adapter, device, BufferUsage::VERTEX, vertex_bytes)?;
+        let index_buffer = match upload(adapter, device, BufferUsage::INDEX, indices) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                unsafe { vertex_buffer.manually_drop(device) };
+                return Err(e);
+            }
+        };
+        let command_buffer = BufferBundle::new(
+            adapter,
+            device,
+            command_buffer_size(commands.len()),
+            BufferUsage::INDIRECT,
+        )
+        .map_err(|e| {
+            unsafe {
+                vertex_buffer.manually_drop(device);
+                index_buffer.manually_drop(device);
+            }
+            e
+        })?;
+        let mut mesh = IndirectMesh {
+            vertices: vertex_buffer,
+            indices: index_buffer,
+            commands: command_buffer,
+            num_commands: 0,
+            command_capacity: commands.len().max(1),
+        };
+        if let Err(e) = unsafe { mesh.write_commands(adapter, device, commands) } {
+            unsafe { mesh.destroy(device) };
+            return Err(e);
+        }
+        Ok(mesh)
+    }
+
+    /// Replaces the draw commands, the GPU can't be using the buffer while this happens
+    pub unsafe fn write_commands(
+        &mut self,
+        adapter: &Adapter<B>,
+        device: &D,
+        commands: &[IndirectDraw],
+    ) -> Result<(), &'static str> {
+        if commands.len() > self.command_capacity {
+            let buffer = BufferBundle::new(
+                adapter,
+                device,
+                command_buffer_size(commands.len()),
+                BufferUsage::INDIRECT,
+            )?;
+            mem::replace(&mut self.commands, buffer).manually_drop(device);
+            self.command_capacity = commands.len();
+        }
+        if !commands.is_empty() {
+            self.commands.write(device, commands)?;
+        }
+        self.num_commands = commands.len() as u32;
+        Ok(())
+    }
+
+    pub unsafe fn destroy(self, device: &D) {
+        self.vertices.manually_drop(device);
+        self.indices.manually_drop(device);
+        self.commands.manually_drop(device);
+    }
+}
+
+fn command_buffer_size(num_commands: usize) -> usize {
+    num_commands.max(1) * mem::size_of::<IndirectDraw>()
+}
+
+/// A buffer with `data` in it, zero sized buffers aren't allowed so empty data still gets a bit
+fn upload<B: Backend, D: Device<B>, T: Copy>(
+    adapter: &Adapter<B>,
+    device: &D,
+    usage: BufferUsage,
+    data: &[T],
+) -> Result<BufferBundle<B, D>, &'static str> {
+    let size = (data.len() * mem::size_of::<T>()).max(4);
+    let buffer = BufferBundle::new(adapter, device, size, usage)?;
+    if !data.is_empty() {
+        if let Err(e) = unsafe { buffer.write(device, data) } {
+            unsafe { buffer.manually_drop(device) };
+            return Err(e);
+        }
+    }
+    Ok(buffer)
+}
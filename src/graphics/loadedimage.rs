@@ -1,40 +1,73 @@
-use crate::graphics::gpu_buffer::BufferBundle;
+use crate::graphics::{gpu_buffer::BufferBundle, SamplerDesc};
 
 use gfx_hal::{
     adapter::{Adapter, MemoryTypeId, PhysicalDevice},
     buffer::Usage as BufferUsage,
+    command::{CommandBuffer, OneShot},
     device::Device,
     format::{Aspects, Format},
-    image::{Layout, SubresourceRange},
+    image::{Layout, SubresourceRange, Usage},
     memory::{Properties, Requirements},
     pool::CommandPool,
     pso::PipelineStage,
     queue::{
-        capability::{Capability, Supports, Transfer},
-        CommandQueue,
+        capability::{Capability, Graphics, Supports, Transfer},
+        family::{QueueFamilyId, QueueGroup},
+        CommandQueue, Submission,
     },
     Backend,
 };
 use std::{
+    iter,
     marker::PhantomData,
     mem::{self, ManuallyDrop},
 };
 
+/// Handle to a texture loaded with `HalState::load_texture`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(pub(crate) u32);
+
+impl TextureId {
+    /// Draws without sampling any texture, only the vertex colors get used
+    pub const NONE: TextureId = TextureId(core::u32::MAX);
+
+    /// The index the shaders know this texture by, this is what goes in `TexturedQuad::tex_num`
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
 pub struct TexturePool<B: Backend, D: Device<B>> {
     pub textures: Vec<LoadedImage<B, D>>,
     pub descriptor_size: usize,
+    /// Sets per descriptor pool, not in total
     pub pool_size: usize,
-    pub descriptor_pool: ManuallyDrop<B::DescriptorPool>,
-    pub descriptor_sets: Vec<B::DescriptorSet>, // we have one sampler per descriptor set
-    pub samplers: Vec<ManuallyDrop<B::Sampler>>,
+    pub descriptor_pools: Vec<B::DescriptorPool>,
+    pub descriptor_sets: Vec<B::DescriptorSet>,
+    /// Every set has all of these bound, so a texture's sampler doesn't depend on its set
+    pub samplers: Vec<(SamplerDesc, ManuallyDrop<B::Sampler>)>,
+    /// Which of `samplers` each texture uses
+    pub texture_samplers: Vec<u8>,
+    /// Slots of unloaded textures, they hold a 1x1 placeholder until an upload reuses them
+    pub free_slots: Vec<usize>,
+    /// `texture_samplers` for each set, where the shaders look them up
+    pub sampler_tables: Vec<BufferBundle<B, D>>,
     pub descriptor_set_layouts: Vec<B::DescriptorSetLayout>,
 }
 
+/// A queue from a transfer-only family, texture uploads go through this when the device has one
+pub struct TransferQueue<B: Backend> {
+    pub queue_group: QueueGroup<B, Transfer>,
+    pub command_pool: ManuallyDrop<CommandPool<B, Transfer>>,
+}
+
 pub struct LoadedImage<B: Backend, D: Device<B>> {
     pub image: ManuallyDrop<B::Image>,
     pub requirements: Requirements,
     pub memory: ManuallyDrop<B::Memory>,
     pub image_view: ManuallyDrop<B::ImageView>,
+    /// Rgba8Srgb for colors, Rgba8Unorm for data like palette indices
+    pub format: Format,
     pub phantom: PhantomData<D>,
 }
 
@@ -45,134 +78,57 @@ impl<B: Backend, D: Device<B>> LoadedImage<B, D> {
         command_pool: &mut CommandPool<B, C>,
         command_queue: &mut CommandQueue<B, C>,
         img: image::RgbaImage,
+        format: Format,
+    ) -> Result<Self, &'static str> {
+        Self::with_usage(
+            adapter,
+            device,
+            command_pool,
+            command_queue,
+            img,
+            format,
+            Usage::TRANSFER_DST | Usage::SAMPLED,
+        )
+    }
+
+    /// A transparent image that can also be drawn into, for `HalState::create_render_target`
+    pub fn new_render_target<C: Capability + Supports<Transfer>>(
+        adapter: &Adapter<B>,
+        device: &D,
+        command_pool: &mut CommandPool<B, C>,
+        command_queue: &mut CommandQueue<B, C>,
+        width: u32,
+        height: u32,
+        format: Format,
+    ) -> Result<Self, &'static str> {
+        Self::with_usage(
+            adapter,
+            device,
+            command_pool,
+            command_queue,
+            image::RgbaImage::new(width, height),
+            format,
+            Usage::TRANSFER_DST | Usage::SAMPLED | Usage::COLOR_ATTACHMENT,
+        )
+    }
+
+    fn with_usage<C: Capability + Supports<Transfer>>(
+        adapter: &Adapter<B>,
+        device: &D,
+        command_pool: &mut CommandPool<B, C>,
+        command_queue: &mut CommandQueue<B, C>,
+        img: image::RgbaImage,
+        format: Format,
+        usage: Usage,
     ) -> Result<Self, &'static str> {
         unsafe {
-            let pixel_size = mem::size_of::<image::Rgba<u8>>();
-            let row_size = pixel_size * (img.width() as usize);
-            let limits = adapter.physical_device.limits();
-            let row_alignment_mask = limits.min_buffer_copy_pitch_alignment as u32 - 1;
-            let row_pitch = ((row_size as u32 + row_alignment_mask) & !row_alignment_mask) as usize;
-            debug_assert!(row_pitch as usize >= row_size);
-
-            // 1. make a staging buffer with enough memory for the image, and a
-            //    transfer_src usage
-            let required_bytes = row_pitch * img.height() as usize;
-            let staging_bundle =
-                BufferBundle::new(&adapter, device, required_bytes, BufferUsage::TRANSFER_SRC)?;
-
-            // 2. use mapping writer to put the image data into that buffer
-            let mut writer = device
-                .acquire_mapping_writer::<u8>(
-                    &staging_bundle.memory,
-                    0..staging_bundle.requirements.size,
-                )
-                .map_err(|_| "Failed to acquire a mapping writer to the staging buffer!")?;
-            for y in 0..img.height() as usize {
-                let row = &(*img)[y * row_size..(y + 1) * row_size];
-                let dest_base = y * row_pitch;
-                writer[dest_base..dest_base + row.len()].copy_from_slice(row);
-            }
-            device
-                .release_mapping_writer(writer)
-                .map_err(|_| "Couldn't release the mapping writer to the staging buffer!")?;
-
-            // 3. Make an image with transfer_dst and SAMPLED usage
-            let mut the_image = device
-                .create_image(
-                    gfx_hal::image::Kind::D2(img.width(), img.height(), 1, 1),
-                    1,
-                    Format::Rgba8Srgb,
-                    gfx_hal::image::Tiling::Optimal,
-                    gfx_hal::image::Usage::TRANSFER_DST | gfx_hal::image::Usage::SAMPLED,
-                    gfx_hal::image::ViewCapabilities::empty(),
-                )
-                .map_err(|_| "Couldn't create the image!")?;
-
-            // 4. allocate memory for the image and bind it
-            let requirements = device.get_image_requirements(&the_image);
-            let memory_type_id = adapter
-                .physical_device
-                .memory_properties()
-                .memory_types
-                .iter()
-                .enumerate()
-                .find(|&(id, memory_type)| {
-                    // BIG NOTE: THIS IS DEVICE LOCAL NOT CPU VISIBLE
-                    requirements.type_mask & (1 << id) != 0
-                        && memory_type.properties.contains(Properties::DEVICE_LOCAL)
-                })
-                .map(|(id, _)| MemoryTypeId(id))
-                .ok_or("Couldn't find memory type to support the image!")?;
-            let memory = device
-                .allocate_memory(memory_type_id, requirements.size)
-                .map_err(|_| "Couldn't allocate image memory!")?;
-            device
-                .bind_image_memory(&memory, 0, &mut the_image)
-                .map_err(|_| "Couldn't bind the image memory!")?;
-
-            // 5. create image view and sampler
-            let image_view = device
-                .create_image_view(
-                    &the_image,
-                    gfx_hal::image::ViewKind::D2,
-                    Format::Rgba8Srgb,
-                    gfx_hal::format::Swizzle::NO,
-                    SubresourceRange {
-                        aspects: Aspects::COLOR,
-                        levels: 0..1,
-                        layers: 0..1,
-                    },
-                )
-                .map_err(|_| "Couldn't create the image view!")?;
+            let upload = PendingUpload::new(adapter, device, &img, format, usage)?;
 
             // 6. create a CommandBuffer
-            let mut cmd_buffer = command_pool.acquire_command_buffer::<gfx_hal::command::OneShot>();
+            let mut cmd_buffer = command_pool.acquire_command_buffer::<OneShot>();
             cmd_buffer.begin();
 
-            // 7. Use a pipeline barrier to transition the image from empty/undefined
-            //    to TRANSFER_WRITE/TransferDstOptimal
-            let image_barrier = gfx_hal::memory::Barrier::Image {
-                states: (gfx_hal::image::Access::empty(), Layout::Undefined)
-                    ..(
-                        gfx_hal::image::Access::TRANSFER_WRITE,
-                        Layout::TransferDstOptimal,
-                    ),
-                target: &the_image,
-                families: None,
-                range: SubresourceRange {
-                    aspects: Aspects::COLOR,
-                    levels: 0..1,
-                    layers: 0..1,
-                },
-            };
-            cmd_buffer.pipeline_barrier(
-                PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
-                gfx_hal::memory::Dependencies::empty(),
-                &[image_barrier],
-            );
-
-            // 8. perform copy from staging buffer to image
-            cmd_buffer.copy_buffer_to_image(
-                &staging_bundle.buffer,
-                &the_image,
-                Layout::TransferDstOptimal,
-                &[gfx_hal::command::BufferImageCopy {
-                    buffer_offset: 0,
-                    buffer_width: (row_pitch / pixel_size) as u32,
-                    buffer_height: img.height(),
-                    image_layers: gfx_hal::image::SubresourceLayers {
-                        aspects: Aspects::COLOR,
-                        level: 0,
-                        layers: 0..1,
-                    },
-                    image_offset: gfx_hal::image::Offset { x: 0, y: 0, z: 0 },
-                    image_extent: gfx_hal::image::Extent {
-                        width: img.width(),
-                        height: img.height(),
-                        depth: 1,
-                    },
-                }],
-            );
+            upload.record_copy(&mut cmd_buffer);
 
             // 9. use pipeline barrier to transition the image to SHADER_READ access/
             //    ShaderReadOnlyOptimal layout
@@ -185,7 +141,7 @@ impl<B: Backend, D: Device<B>> LoadedImage<B, D> {
                         gfx_hal::image::Access::SHADER_READ,
                         Layout::ShaderReadOnlyOptimal,
                     ),
-                target: &the_image,
+                target: &upload.image,
                 families: None,
                 range: SubresourceRange {
                     aspects: Aspects::COLOR,
@@ -209,18 +165,115 @@ impl<B: Backend, D: Device<B>> LoadedImage<B, D> {
                 .wait_for_fence(&upload_fence, core::u64::MAX)
                 .map_err(|_| "Couldn't wait for the fence!")?;
             device.destroy_fence(upload_fence);
-
-            // 11. Destroy the staging bundle and one shot buffer now that we're done
-            staging_bundle.manually_drop(device);
             command_pool.free(Some(cmd_buffer));
 
-            Ok(LoadedImage {
-                image: ManuallyDrop::new(the_image),
-                requirements,
-                memory: ManuallyDrop::new(memory),
-                image_view: ManuallyDrop::new(image_view),
-                phantom: PhantomData,
-            })
+            Ok(upload.finish(device))
+        }
+    }
+
+    /// Same as `new`, but the copy happens on the dedicated transfer queue so it doesn't have to queue up
+    /// behind rendering. The image then gets handed over to the graphics queue family, with a semaphore
+    /// making the graphics side wait for the copy
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_transfer_queue(
+        adapter: &Adapter<B>,
+        device: &D,
+        transfer: &mut TransferQueue<B>,
+        command_pool: &mut CommandPool<B, Graphics>,
+        command_queue: &mut CommandQueue<B, Graphics>,
+        graphics_family: QueueFamilyId,
+        img: image::RgbaImage,
+        format: Format,
+    ) -> Result<Self, &'static str> {
+        unsafe {
+            let upload = PendingUpload::new(
+                adapter,
+                device,
+                &img,
+                format,
+                Usage::TRANSFER_DST | Usage::SAMPLED,
+            )?;
+            let families = Some(transfer.queue_group.family()..graphics_family);
+            let range = SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0..1,
+                layers: 0..1,
+            };
+
+            let mut transfer_buffer = transfer.command_pool.acquire_command_buffer::<OneShot>();
+            transfer_buffer.begin();
+            upload.record_copy(&mut transfer_buffer);
+            // release half of the ownership transfer
+            transfer_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+                gfx_hal::memory::Dependencies::empty(),
+                &[gfx_hal::memory::Barrier::Image {
+                    states: (
+                        gfx_hal::image::Access::TRANSFER_WRITE,
+                        Layout::TransferDstOptimal,
+                    )
+                        ..(
+                            gfx_hal::image::Access::empty(),
+                            Layout::ShaderReadOnlyOptimal,
+                        ),
+                    target: &upload.image,
+                    families: families.clone(),
+                    range: range.clone(),
+                }],
+            );
+            transfer_buffer.finish();
+
+            let mut graphics_buffer = command_pool.acquire_command_buffer::<OneShot>();
+            graphics_buffer.begin();
+            // acquire half, has to match the release exactly
+            graphics_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE..PipelineStage::FRAGMENT_SHADER,
+                gfx_hal::memory::Dependencies::empty(),
+                &[gfx_hal::memory::Barrier::Image {
+                    states: (gfx_hal::image::Access::empty(), Layout::TransferDstOptimal)
+                        ..(
+                            gfx_hal::image::Access::SHADER_READ,
+                            Layout::ShaderReadOnlyOptimal,
+                        ),
+                    target: &upload.image,
+                    families,
+                    range,
+                }],
+            );
+            graphics_buffer.finish();
+
+            let copied = device
+                .create_semaphore()
+                .map_err(|_| "Couldn't create an upload semaphore!")?;
+            let upload_fence = device
+                .create_fence(false)
+                .map_err(|_| "Couldn't create an upload fence!")?;
+            transfer.queue_group.queues[0].submit(
+                Submission {
+                    command_buffers: Some(&transfer_buffer),
+                    wait_semaphores: iter::empty::<(&B::Semaphore, PipelineStage)>(),
+                    signal_semaphores: Some(&copied),
+                },
+                None,
+            );
+            command_queue.submit(
+                Submission {
+                    command_buffers: Some(&graphics_buffer),
+                    wait_semaphores: Some((&copied, PipelineStage::TOP_OF_PIPE)),
+                    signal_semaphores: iter::empty::<&B::Semaphore>(),
+                },
+                Some(&upload_fence),
+            );
+            // the graphics submission waits on the transfer one, so this covers both
+            device
+                .wait_for_fence(&upload_fence, core::u64::MAX)
+                .map_err(|_| "Couldn't wait for the fence!")?;
+            device.destroy_fence(upload_fence);
+            device.destroy_semaphore(copied);
+            transfer.command_pool.free(Some(transfer_buffer));
+            command_pool.free(Some(graphics_buffer));
+
+            Ok(upload.finish(device))
         }
     }
 
@@ -231,3 +284,179 @@ impl<B: Backend, D: Device<B>> LoadedImage<B, D> {
         device.free_memory(ManuallyDrop::into_inner(read(&self.memory)));
     }
 }
+
+/// Staging buffer with the pixels already written into it, and the image they're going to end up in
+struct PendingUpload<B: Backend, D: Device<B>> {
+    staging: BufferBundle<B, D>,
+    image: B::Image,
+    requirements: Requirements,
+    memory: B::Memory,
+    image_view: B::ImageView,
+    format: Format,
+    row_pitch: usize,
+    width: u32,
+    height: u32,
+}
+
+impl<B: Backend, D: Device<B>> PendingUpload<B, D> {
+    unsafe fn new(
+        adapter: &Adapter<B>,
+        device: &D,
+        img: &image::RgbaImage,
+        format: Format,
+        usage: Usage,
+    ) -> Result<Self, &'static str> {
+        let pixel_size = mem::size_of::<image::Rgba<u8>>();
+        let row_size = pixel_size * (img.width() as usize);
+        let limits = adapter.physical_device.limits();
+        let row_alignment_mask = limits.min_buffer_copy_pitch_alignment as u32 - 1;
+        let row_pitch = ((row_size as u32 + row_alignment_mask) & !row_alignment_mask) as usize;
+        debug_assert!(row_pitch as usize >= row_size);
+
+        // 1. make a staging buffer with enough memory for the image, and a
+        //    transfer_src usage
+        let required_bytes = row_pitch * img.height() as usize;
+        let staging_bundle =
+            BufferBundle::new(&adapter, device, required_bytes, BufferUsage::TRANSFER_SRC)?;
+
+        // 2. use mapping writer to put the image data into that buffer
+        let mut writer = device
+            .acquire_mapping_writer::<u8>(
+                &staging_bundle.memory,
+                0..staging_bundle.requirements.size,
+            )
+            .map_err(|_| "Failed to acquire a mapping writer to the staging buffer!")?;
+        for y in 0..img.height() as usize {
+            let row = &(*img)[y * row_size..(y + 1) * row_size];
+            let dest_base = y * row_pitch;
+            writer[dest_base..dest_base + row.len()].copy_from_slice(row);
+        }
+        device
+            .release_mapping_writer(writer)
+            .map_err(|_| "Couldn't release the mapping writer to the staging buffer!")?;
+
+        // 3. Make an image with at least transfer_dst and SAMPLED usage
+        let mut the_image = device
+            .create_image(
+                gfx_hal::image::Kind::D2(img.width(), img.height(), 1, 1),
+                1,
+                format,
+                gfx_hal::image::Tiling::Optimal,
+                usage,
+                gfx_hal::image::ViewCapabilities::empty(),
+            )
+            .map_err(|_| "Couldn't create the image!")?;
+
+        // 4. allocate memory for the image and bind it
+        let requirements = device.get_image_requirements(&the_image);
+        let memory_type_id = adapter
+            .physical_device
+            .memory_properties()
+            .memory_types
+            .iter()
+            .enumerate()
+            .find(|&(id, memory_type)| {
+                // BIG NOTE: THIS IS DEVICE LOCAL NOT CPU VISIBLE
+                requirements.type_mask & (1 << id) != 0
+                    && memory_type.properties.contains(Properties::DEVICE_LOCAL)
+            })
+            .map(|(id, _)| MemoryTypeId(id))
+            .ok_or("Couldn't find memory type to support the image!")?;
+        let memory = device
+            .allocate_memory(memory_type_id, requirements.size)
+            .map_err(|_| "Couldn't allocate image memory!")?;
+        device
+            .bind_image_memory(&memory, 0, &mut the_image)
+            .map_err(|_| "Couldn't bind the image memory!")?;
+
+        // 5. create image view and sampler
+        let image_view = device
+            .create_image_view(
+                &the_image,
+                gfx_hal::image::ViewKind::D2,
+                format,
+                gfx_hal::format::Swizzle::NO,
+                SubresourceRange {
+                    aspects: Aspects::COLOR,
+                    levels: 0..1,
+                    layers: 0..1,
+                },
+            )
+            .map_err(|_| "Couldn't create the image view!")?;
+
+        Ok(PendingUpload {
+            staging: staging_bundle,
+            image: the_image,
+            requirements,
+            memory,
+            image_view,
+            format,
+            row_pitch,
+            width: img.width(),
+            height: img.height(),
+        })
+    }
+
+    unsafe fn record_copy<C: Capability + Supports<Transfer>>(
+        &self,
+        cmd_buffer: &mut CommandBuffer<B, C, OneShot>,
+    ) {
+        // 7. Use a pipeline barrier to transition the image from empty/undefined
+        //    to TRANSFER_WRITE/TransferDstOptimal
+        let image_barrier = gfx_hal::memory::Barrier::Image {
+            states: (gfx_hal::image::Access::empty(), Layout::Undefined)
+                ..(
+                    gfx_hal::image::Access::TRANSFER_WRITE,
+                    Layout::TransferDstOptimal,
+                ),
+            target: &self.image,
+            families: None,
+            range: SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0..1,
+                layers: 0..1,
+            },
+        };
+        cmd_buffer.pipeline_barrier(
+            PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+            gfx_hal::memory::Dependencies::empty(),
+            &[image_barrier],
+        );
+
+        // 8. perform copy from staging buffer to image
+        cmd_buffer.copy_buffer_to_image(
+            &self.staging.buffer,
+            &self.image,
+            Layout::TransferDstOptimal,
+            &[gfx_hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: (self.row_pitch / mem::size_of::<image::Rgba<u8>>()) as u32,
+                buffer_height: self.height,
+                image_layers: gfx_hal::image::SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: gfx_hal::image::Offset { x: 0, y: 0, z: 0 },
+                image_extent: gfx_hal::image::Extent {
+                    width: self.width,
+                    height: self.height,
+                    depth: 1,
+                },
+            }],
+        );
+    }
+
+    /// 11. Destroy the staging bundle now that the copy is done
+    unsafe fn finish(self, device: &D) -> LoadedImage<B, D> {
+        self.staging.manually_drop(device);
+        LoadedImage {
+            image: ManuallyDrop::new(self.image),
+            requirements: self.requirements,
+            memory: ManuallyDrop::new(self.memory),
+            image_view: ManuallyDrop::new(self.image_view),
+            format: self.format,
+            phantom: PhantomData,
+        }
+    }
+}
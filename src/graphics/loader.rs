@@ -0,0 +1,154 @@
+use crate::graphics::{decode::decode_image, loadedimage::TextureId, TextureError};
+use image::RgbaImage;
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+type LoadResult = Result<TextureId, &'static str>;
+
+/// Where the result of a queued load ends up once the render thread gets to it
+#[derive(Default)]
+struct Slot {
+    result: Mutex<Option<LoadResult>>,
+    done: Condvar,
+}
+
+impl Slot {
+    /// The result is just a Copy value that's either there or not, so a panic while holding the
+    /// lock can't leave it half written
+    fn lock(&self) -> MutexGuard<Option<LoadResult>> {
+        self.result.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Only the first result counts
+    fn set(&self, result: LoadResult) {
+        let mut slot = self.lock();
+        if slot.is_none() {
+            *slot = Some(result);
+        }
+        self.done.notify_all();
+    }
+}
+
+pub(crate) struct QueuedTexture {
+    image: RgbaImage,
+    slot: Arc<Slot>,
+}
+
+impl QueuedTexture {
+    /// Uploads the image with `load` and hands the result to whoever's waiting on it
+    pub fn finish<F: FnOnce(RgbaImage) -> LoadResult>(mut self, load: F) {
+        let image = mem::replace(&mut self.image, RgbaImage::new(0, 0));
+        self.slot.set(load(image));
+    }
+}
+
+impl Drop for QueuedTexture {
+    /// Textures still queued when the `HalState` goes away never get uploaded, this lets whoever's
+    /// waiting on them know instead of leaving them blocked
+    fn drop(&mut self) {
+        self.slot.set(Err(
+            "The HalState was dropped before the texture got uploaded!",
+        ));
+    }
+}
+
+/// Only `HalState` holds on to the queue itself, so dropping it drops everything still queued
+pub(crate) type TextureQueue = Arc<Mutex<VecDeque<QueuedTexture>>>;
+
+/// Nothing in the queue can be left half done by a panic, so a poisoned lock is still fine to use
+pub(crate) fn lock_queue(
+    queue: &Mutex<VecDeque<QueuedTexture>>,
+) -> MutexGuard<VecDeque<QueuedTexture>> {
+    queue.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Loads textures from other threads. Decoding happens on whichever thread calls `load`, the
+/// decoded images then wait in a queue until `HalState` uploads them at the start of the next
+/// frame, since only the render thread gets to touch the GPU. Get one with
+/// `HalState::texture_loader`, clones all feed the same queue
+#[derive(Clone)]
+pub struct TextureLoader {
+    queue: Weak<Mutex<VecDeque<QueuedTexture>>>,
+}
+
+impl std::fmt::Debug for TextureLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TextureLoader")
+            .field("queued", &self.queued())
+            .finish()
+    }
+}
+
+impl TextureLoader {
+    pub(crate) fn new(queue: &TextureQueue) -> Self {
+        TextureLoader {
+            queue: Arc::downgrade(queue),
+        }
+    }
+
+    /// Decodes `data` right here, so call it from a worker thread. Fails once the `HalState` is
+    /// gone
+    pub fn load(&self, data: &[u8]) -> Result<PendingTexture, TextureError> {
+        let image = decode_image(data)?;
+        Ok(self.load_image(image)?)
+    }
+
+    pub fn load_image(&self, image: RgbaImage) -> Result<PendingTexture, &'static str> {
+        let queue = self
+            .queue
+            .upgrade()
+            .ok_or("The HalState this loader belongs to was dropped!")?;
+        let slot = Arc::new(Slot::default());
+        lock_queue(&queue).push_back(QueuedTexture {
+            image,
+            slot: Arc::clone(&slot),
+        });
+        Ok(PendingTexture { slot })
+    }
+
+    /// Images waiting for the render thread
+    pub fn queued(&self) -> usize {
+        self.queue
+            .upgrade()
+            .map_or(0, |queue| lock_queue(&queue).len())
+    }
+}
+
+/// A texture that's been handed to a `TextureLoader` but might not be on the GPU yet
+pub struct PendingTexture {
+    slot: Arc<Slot>,
+}
+
+impl std::fmt::Debug for PendingTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PendingTexture")
+            .field("result", &self.try_get())
+            .finish()
+    }
+}
+
+impl PendingTexture {
+    /// None until the render thread has uploaded it
+    pub fn try_get(&self) -> Option<LoadResult> {
+        *self.slot.lock()
+    }
+
+    /// Blocks until the upload is done, or fails if the `HalState` gets dropped first. This needs
+    /// the render thread to keep drawing frames, so calling it from the render thread hangs forever
+    pub fn wait(&self) -> LoadResult {
+        let mut result = self.slot.lock();
+        loop {
+            if let Some(result) = *result {
+                return result;
+            }
+            result = self
+                .slot
+                .done
+                .wait(result)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
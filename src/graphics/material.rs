@@ -0,0 +1,70 @@
+use crate::graphics::loadedimage::TextureId;
+
+/// A second texture combined with the texture of every quad and mesh in a batch, or another way of
+/// reading their own, set with `Frame::set_material`. Second textures get sampled at the same UVs,
+/// so they have to line up with the textures they go with, and they don't work with the texture
+/// atlas. Custom pipelines don't know about materials
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Material {
+    /// Just the quad's own texture
+    Plain,
+    /// Multiplies the alpha by the mask's red channel, for reveals and wipes
+    Mask(TextureId),
+    /// Multiplies the color by the overlay's
+    Multiply(TextureId),
+    /// The vertex color only tints where the mask is white, everything else keeps the texture's
+    /// own color. For colorable skins with a tint mask on top of the base sprite
+    TintMask(TextureId),
+    /// The quad's own texture is a signed distance field, like `distance_field` makes, and gets
+    /// drawn as a sharp edge at any scale or rotation in the vertex color. Needs a linear sampler
+    DistanceField,
+    /// Only the texture's alpha in the vertex color, softened by `blur` texels, for drop shadows.
+    /// The blur stays inside the quad, so textures need that much transparent border around them,
+    /// and it reaches into neighbouring glyphs or atlas entries otherwise. See `DropShadow`
+    Shadow { blur: u16 },
+    /// The quad's own texture is grayscale, and its red channel picks a color out of the palette
+    /// (0 is the first entry, 1/255 the second and so on) that gets multiplied with the vertex
+    /// color. Swapping the palette recolors noteskins and characters without more textures. The
+    /// quad's texture has to come from `HalState::load_index_texture` and the palette from
+    /// `HalState::load_palette`, both on a nearest sampler
+    Palette(TextureId),
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::Plain
+    }
+}
+
+impl Material {
+    /// The MATERIAL specialization constant in fragment.glsl
+    pub(crate) fn mode(self) -> u32 {
+        match self {
+            Material::Plain => 0,
+            Material::Mask(_) => 1,
+            Material::Multiply(_) => 2,
+            Material::TintMask(_) => 3,
+            Material::DistanceField => 4,
+            Material::Shadow { .. } => 5,
+            Material::Palette(_) => 6,
+        }
+    }
+
+    pub fn texture(self) -> Option<TextureId> {
+        match self {
+            Material::Plain | Material::DistanceField | Material::Shadow { .. } => None,
+            Material::Mask(texture)
+            | Material::Multiply(texture)
+            | Material::TintMask(texture)
+            | Material::Palette(texture) => Some(texture),
+        }
+    }
+
+    /// What goes in the material push constant, the second texture or the blur
+    pub(crate) fn push_constant(self) -> Option<u32> {
+        match self {
+            Material::Shadow { blur } => Some(u32::from(blur)),
+            _ => self.texture().map(TextureId::index),
+        }
+    }
+}
@@ -5,61 +5,302 @@ use gfx_backend_metal as back;
 #[cfg(feature = "vulkan")]
 use gfx_backend_vulkan as back;
 
+#[cfg(feature = "dx12")]
+const BACKEND_NAME: &str = "dx12";
+#[cfg(feature = "metal")]
+const BACKEND_NAME: &str = "metal";
+#[cfg(feature = "vulkan")]
+const BACKEND_NAME: &str = "vulkan";
+
+/// The formats `HalState::capabilities` checks for sampling support
+const REPORTED_FORMATS: [Format; 11] = [
+    Format::Rgba8Srgb,
+    Format::Bgra8Srgb,
+    Format::Rgba8Unorm,
+    Format::R8Unorm,
+    Format::Rgba16Sfloat,
+    Format::Rgba32Sfloat,
+    Format::Bc1RgbaSrgb,
+    Format::Bc3Srgb,
+    Format::Bc7Srgb,
+    Format::Etc2R8g8b8a8Srgb,
+    Format::Astc4x4Srgb,
+];
+
+/// Textures get decoded to linear when they're sampled
+const COLOR_FORMAT: Format = Format::Rgba8Srgb;
+/// For textures the shaders have to read back exactly, like palette indices
+const DATA_FORMAT: Format = Format::Rgba8Unorm;
+
 macro_rules! debug_repr {
     ($e:expr) => {
         format_args!("{:?}", $e)
     };
 }
 
+mod animation;
+pub mod atlas;
+mod background;
+mod camera;
+mod capture;
+mod config;
+mod decode;
+mod fill;
+mod frame;
+#[cfg(feature = "test-support")]
+mod golden;
 mod gpu_buffer;
+mod graph;
+mod highway;
+mod indirect;
 mod loadedimage;
+mod loader;
+mod material;
+mod postprocess;
+mod quality;
+mod rounded;
+mod sampler;
+mod sdf;
+mod shadow;
+mod shake;
+#[cfg(feature = "software-renderer")]
+mod software;
+mod sprite;
+mod stats;
+#[cfg(feature = "svg")]
+mod svg;
+mod target;
+mod text;
+mod trail;
 mod vertex;
 
-use crate::geometry::Quad;
+pub use animation::{decode_animation, Animation, AnimationFrame};
+pub use background::ScrollingBackground;
+pub use camera::{Camera2D, ParallaxLayer};
+pub use capture::CapturedFrame;
+pub use config::{AdapterPreference, GraphicsConfig, HalStateBuilder};
+pub use decode::{decode_image, premultiply_alpha, TextureError};
+pub use fill::{FillDirection, ProgressBar, RadialFill};
+pub use frame::{Frame, Space};
+#[cfg(feature = "test-support")]
+pub use golden::{GoldenImage, GoldenMismatch, UPDATE_GOLDEN_VAR};
+pub use graph::{Access, CompiledGraph, FrameGraph, TargetDesc, TargetId};
+pub use highway::{HighwaySkin, Note, NoteHighway, ScrollDirection, ScrollSpeed};
+pub use indirect::{IndirectDraw, IndirectMeshId};
+pub use loadedimage::TextureId;
+pub use loader::{PendingTexture, TextureLoader};
+pub use material::Material;
+pub use postprocess::CrtEffect;
+pub use quality::{QualityChange, QualityGovernor};
+pub use rounded::RoundedQuad;
+pub use sampler::SamplerDesc;
+pub use sdf::distance_field;
+pub use shadow::DropShadow;
+pub use shake::{ScreenShake, ShakeOffset, ShakeParams};
+#[cfg(feature = "software-renderer")]
+pub use software::SoftwareRasterizer;
+pub use sprite::{Anchor, Sprite};
+pub use stats::{Capabilities, DeviceInfo, DrawableSize, FrameStats, SwapchainInfo};
+#[cfg(feature = "svg")]
+pub use svg::rasterize_svg;
+pub use text::{
+    Align, BitmapFont, Font, Glyph, LineMetrics, PositionedGlyph, TextBox, TextLayout, TextSpan,
+    VerticalAlign,
+};
+pub use trail::{LineJoin, TrailRenderer};
+pub use vertex::{Vertex, VertexAttribute, VertexFormat, VertexLayout, WithParams};
+
+use crate::geometry::{self, Mat3, Quad};
+use crate::profile;
 use arrayvec::ArrayVec;
+use atlas::TextureAtlas;
+use capture::FrameCapture;
 use core::{
     mem::{self, ManuallyDrop},
     ops::Deref,
 };
+use frame::{BatchKind, DrawCall, DrawKind};
 use gfx_hal::{
     adapter::{Adapter, PhysicalDevice},
     buffer::{IndexBufferView, Usage as BufferUsage},
     command::{ClearColor, ClearValue, CommandBuffer, MultiShot, Primary},
     device::Device,
-    format::{Aspects, ChannelType, Format, Swizzle},
-    image::{Extent, Filter, Layout, SubresourceRange, Usage, ViewKind},
+    format::{Aspects, ChannelType, Format, ImageFeature, Swizzle},
+    image::{Extent, Filter, Layout, SubresourceRange, Usage, ViewKind, WrapMode},
     pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, Subpass, SubpassDesc},
     pool::{CommandPool, CommandPoolCreateFlags},
     pso::{
-        AttributeDesc, BakedStates, BasePipeline, BlendDesc, BlendState, ColorBlendDesc, ColorMask,
-        DepthStencilDesc, DepthTest, DescriptorSetLayoutBinding, ElemStride, EntryPoint, Face,
-        FrontFace, GraphicsPipelineDesc, GraphicsShaderSet, InputAssemblerDesc, LogicOp,
+        AttributeDesc, BakedStates, BasePipeline, BlendDesc, BlendOp, BlendState, ColorBlendDesc,
+        ColorMask, DepthStencilDesc, DepthTest, DescriptorSetLayoutBinding, EntryPoint, Face,
+        Factor, FrontFace, GraphicsPipelineDesc, GraphicsShaderSet, InputAssemblerDesc,
         Multisampling, PipelineCreationFlags, PipelineStage, PolygonMode, Rasterizer, Rect,
-        ShaderStageFlags, Specialization, StencilTest, VertexBufferDesc, Viewport,
+        ShaderStageFlags, Specialization, SpecializationConstant, StencilTest, VertexBufferDesc,
+        Viewport,
     },
     queue::{family::QueueGroup, Submission},
     window::{Backbuffer, Extent2D, FrameSync, PresentMode, Swapchain, SwapchainConfig},
-    Backend, DescriptorPool, Gpu, Graphics, IndexType, Instance, Primitive, QueueFamily, Surface,
+    Backend, DescriptorPool, Gpu, Graphics, IndexType, Instance, Primitive, QueueFamily, QueueType,
+    Surface, Transfer,
 };
-use gpu_buffer::BufferBundle;
-use loadedimage::{LoadedImage, TexturePool};
+use gpu_buffer::{BufferBundle, StreamBuffer};
+use indirect::IndirectMesh;
+use loadedimage::{LoadedImage, TexturePool, TransferQueue};
+use loader::{lock_queue, TextureQueue};
+use postprocess::{PostProcess, OFFSCREEN_FORMAT};
 use slog::Logger;
-use vertex::Vertex;
+use stats::FrameTimer;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+use target::RenderTargets;
+use winit::dpi::{LogicalPosition, PhysicalPosition, PhysicalSize};
 
-const MAX_QUADS: usize = 4096;
 const QUAD_SIZE: usize = mem::size_of::<Vertex>() * 4;
+/// u16 indices can only address this many quads, past that the index buffer gets promoted to u32
+const MAX_U16_QUADS: usize = (core::u16::MAX as usize + 1) / 4;
+/// Size of the pages the atlas fallback packs textures into, if the device can do textures this big
+const ATLAS_PAGE_SIZE: usize = 2048;
+/// Texture arrays smaller than this switch to the atlas fallback on their own
+const ATLAS_FALLBACK_THRESHOLD: usize = 32;
+/// Different samplers every texture set has bound, 16 is the least any of the backends allow per stage
+pub const MAX_SAMPLERS: usize = 16;
+/// Bytes in the table saying which sampler each texture uses, one per texture. Has to match the
+/// SamplerTable block in fragment.glsl
+const SAMPLER_TABLE_SIZE: usize = 16 * 1024;
+/// Where the material texture (or shadow blur) goes in the push constants, in words. Right after
+/// the view matrix
+const MATERIAL_PUSH_CONSTANT: u32 = 12;
+
+#[cfg(not(any(feature = "precompiled-shaders", feature = "runtime-shaders")))]
+compile_error!("either the precompiled-shaders or the runtime-shaders feature has to be enabled");
+
+#[cfg(feature = "precompiled-shaders")]
+const VERTEX_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/vertex.spv"));
+#[cfg(feature = "precompiled-shaders")]
+const FRAGMENT_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/fragment.spv"));
+#[cfg(feature = "precompiled-shaders")]
+const CRT_VERTEX_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/crt_vertex.spv"));
+#[cfg(feature = "precompiled-shaders")]
+const CRT_FRAGMENT_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/crt_fragment.spv"));
+#[cfg(not(feature = "precompiled-shaders"))]
 const VERTEX_SOURCE: &str = include_str!("vertex.glsl");
+#[cfg(not(feature = "precompiled-shaders"))]
 const FRAGMENT_SOURCE: &str = include_str!("fragment.glsl");
+#[cfg(not(feature = "precompiled-shaders"))]
+const CRT_VERTEX_SOURCE: &str = include_str!("crt_vertex.glsl");
+#[cfg(not(feature = "precompiled-shaders"))]
+const CRT_FRAGMENT_SOURCE: &str = include_str!("crt_fragment.glsl");
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TexturedQuad {
     pub quad: Quad,
+    /// Part of the texture to draw as [x0, y0, x1, y1], normalized so [0, 0, 1, 1] is the whole
+    /// texture no matter its size. `with_pixel_uv` converts from pixels
     pub uv_rect: [f32; 4],
     pub tex_num: u32,
+    /// Multiplied with the texture color
+    pub color: [f32; 4],
+    /// Mirrors the texture left to right
+    pub flip_x: bool,
+    /// Mirrors the texture top to bottom
+    pub flip_y: bool,
+    /// Turns the texture inside the quad, after flipping
+    pub rotation: UvRotation,
+}
+
+/// Quarter turns of a texture inside its quad, counterclockwise
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UvRotation {
+    None,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Default for UvRotation {
+    fn default() -> Self {
+        UvRotation::None
+    }
+}
+
+impl UvRotation {
+    fn quarter_turns(self) -> usize {
+        match self {
+            UvRotation::None => 0,
+            UvRotation::Deg90 => 1,
+            UvRotation::Deg180 => 2,
+            UvRotation::Deg270 => 3,
+        }
+    }
 }
 
 impl TexturedQuad {
+    /// uv_rect covering the whole texture
+    pub const FULL_UV: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+    /// The whole texture on `quad`, not tinted, flipped or turned
+    pub fn new(quad: Quad, tex_num: u32) -> Self {
+        TexturedQuad {
+            quad,
+            uv_rect: Self::FULL_UV,
+            tex_num,
+            color: [1.0; 4],
+            flip_x: false,
+            flip_y: false,
+            rotation: UvRotation::None,
+        }
+    }
+
+    /// Sets `uv_rect` from a rect in pixels, for a texture that's `texture_size` big
+    pub fn with_pixel_uv(self, texture_size: (u32, u32), pixel_rect: [f32; 4]) -> Self {
+        TexturedQuad {
+            uv_rect: pixel_uv(texture_size, pixel_rect),
+            ..self
+        }
+    }
+
+    pub fn with_color(self, color: [f32; 4]) -> Self {
+        TexturedQuad { color, ..self }
+    }
+
+    pub fn flipped(self, flip_x: bool, flip_y: bool) -> Self {
+        TexturedQuad {
+            flip_x,
+            flip_y,
+            ..self
+        }
+    }
+
+    pub fn rotated(self, rotation: UvRotation) -> Self {
+        TexturedQuad { rotation, ..self }
+    }
+
+    /// Where each corner of the quad samples the uv_rect, in the same order as `to_vertices`
+    fn corner_uvs(self) -> [[f32; 2]; 4] {
+        let flip = |[u, v]: [f32; 2]| {
+            [
+                if self.flip_x { 1.0 - u } else { u },
+                if self.flip_y { 1.0 - v } else { v },
+            ]
+        };
+        // counterclockwise from the top left, so turning the texture is just shifting the corners
+        let corners = [
+            flip([0.0, 1.0]),
+            flip([0.0, 0.0]),
+            flip([1.0, 0.0]),
+            flip([1.0, 1.0]),
+        ];
+        let turns = self.rotation.quarter_turns();
+        let mut uvs = corners;
+        for (i, uv) in uvs.iter_mut().enumerate() {
+            *uv = corners[(i + 4 - turns) % 4];
+        }
+        uvs
+    }
+
     pub fn to_vertices(self) -> [Vertex; 4] {
         let uv_rect = self.uv_rect;
         let Quad {
@@ -69,36 +310,50 @@ impl TexturedQuad {
             top_right,
         } = self.quad;
         let tex_num = self.tex_num;
+        let color = self.color;
+        let [top_left_uv, bottom_left_uv, bottom_right_uv, top_right_uv] = self.corner_uvs();
         [
             Vertex {
                 xy: [top_left.x, top_left.y],
-                uv: [0.0, 1.0],
+                uv: top_left_uv,
                 uv_rect,
                 tex_num,
+                color,
             },
             Vertex {
                 xy: [bottom_left.x, bottom_left.y],
-                uv: [0.0, 0.0],
+                uv: bottom_left_uv,
                 uv_rect,
                 tex_num,
+                color,
             },
             Vertex {
                 xy: [bottom_right.x, bottom_right.y],
-                uv: [1.0, 0.0],
+                uv: bottom_right_uv,
                 uv_rect,
                 tex_num,
+                color,
             },
             Vertex {
                 xy: [top_right.x, top_right.y],
-                uv: [1.0, 1.0],
+                uv: top_right_uv,
                 uv_rect,
                 tex_num,
+                color,
             },
         ]
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Turns a [x0, y0, x1, y1] rect in pixels into the normalized one `TexturedQuad::uv_rect` wants
+pub fn pixel_uv(texture_size: (u32, u32), pixel_rect: [f32; 4]) -> [f32; 4] {
+    let (w, h) = (texture_size.0.max(1) as f32, texture_size.1.max(1) as f32);
+    let [x0, y0, x1, y1] = pixel_rect;
+    [x0 / w, y0 / h, x1 / w, y1 / h]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "settings", derive(serde::Serialize, serde::Deserialize))]
 pub enum Vsync {
     TripleBuffered,
     DoubleBuffered,
@@ -117,6 +372,182 @@ impl Into<PresentMode> for Vsync {
     }
 }
 
+impl Vsync {
+    /// For `HalStateBuilder::vsync`, this one first and the others after it in the default order
+    pub fn preferred(self) -> [PresentMode; 4] {
+        let first: PresentMode = self.into();
+        let mut modes = [first; 4];
+        let defaults = GraphicsConfig::default().vsync;
+        for (slot, &mode) in modes[1..]
+            .iter_mut()
+            .zip(defaults.iter().filter(|&&mode| mode != first))
+        {
+            *slot = mode;
+        }
+        modes
+    }
+}
+
+/// How a batch gets combined with what's already on screen
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Alpha,
+    /// Adds on top of what's there, for glows and such
+    Additive,
+    Multiply,
+    /// Alpha blending for textures where the color is already multiplied by alpha
+    PremultipliedAlpha,
+    /// Overwrites whatever's there, alpha included
+    None,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Alpha
+    }
+}
+
+impl Into<BlendState> for BlendMode {
+    fn into(self) -> BlendState {
+        match self {
+            BlendMode::Alpha => BlendState::ALPHA,
+            BlendMode::Additive => BlendState::On {
+                color: BlendOp::Add {
+                    src: Factor::SrcAlpha,
+                    dst: Factor::One,
+                },
+                alpha: BlendOp::Add {
+                    src: Factor::One,
+                    dst: Factor::One,
+                },
+            },
+            BlendMode::Multiply => BlendState::On {
+                color: BlendOp::Add {
+                    src: Factor::DstColor,
+                    dst: Factor::Zero,
+                },
+                alpha: BlendOp::Add {
+                    src: Factor::DstAlpha,
+                    dst: Factor::Zero,
+                },
+            },
+            BlendMode::PremultipliedAlpha => BlendState::On {
+                color: BlendOp::Add {
+                    src: Factor::One,
+                    dst: Factor::OneMinusSrcAlpha,
+                },
+                alpha: BlendOp::Add {
+                    src: Factor::One,
+                    dst: Factor::OneMinusSrcAlpha,
+                },
+            },
+            BlendMode::None => BlendState::Off,
+        }
+    }
+}
+
+/// Debugging aids that can be switched on and off at runtime with `HalState::set_debug_draw`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DebugDraw {
+    /// Draws every triangle as an outline, this needs the device to support non-fill polygon modes
+    pub wireframe: bool,
+    /// Logs every batch with its quad indices and texture ids. There's no text rendering to draw
+    /// these on screen yet, so they go to the logger instead
+    pub log_batches: bool,
+    /// Colors every pixel by how many times it got drawn to, going from black through red and
+    /// yellow to white at around 40 layers. Custom pipelines only get the additive blending, their
+    /// shaders don't know about it
+    pub overdraw: bool,
+}
+
+/// Overdraw mode always starts from black, the fragment shader adds OVERDRAW_STEP per layer
+const OVERDRAW_CLEAR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Shader behaviour that gets baked into the pipelines through specialization constants, changing it
+/// at runtime just means switching (or creating) pipelines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShaderConfig {
+    /// Does the linear to sRGB conversion in the fragment shader, for swapchain formats that aren't
+    /// sRGB. `HalState` turns it on by itself when it had to pick one of those, see
+    /// `HalState::srgb_surface`
+    pub srgb_conversion: bool,
+    /// Multiplies the output color by its alpha, so straight alpha textures can be drawn with
+    /// `BlendMode::PremultipliedAlpha`
+    pub premultiply_alpha: bool,
+}
+
+/// Has to match the constant_ids in the shaders, the data is laid out by `ShaderConfig::specialization_data`
+const SPECIALIZATION_CONSTANTS: [SpecializationConstant; 5] = [
+    // TEXTURE_COUNT
+    SpecializationConstant { id: 0, range: 0..4 },
+    // SRGB_CONVERSION
+    SpecializationConstant { id: 1, range: 4..8 },
+    // PREMULTIPLY_ALPHA
+    SpecializationConstant {
+        id: 2,
+        range: 8..12,
+    },
+    // OVERDRAW
+    SpecializationConstant {
+        id: 3,
+        range: 12..16,
+    },
+    // MATERIAL
+    SpecializationConstant {
+        id: 4,
+        range: 16..20,
+    },
+];
+
+impl ShaderConfig {
+    /// Bools are 32 bits wide in SPIR-V
+    fn specialization_data(self, texture_count: u32, overdraw: bool, material: u32) -> [u8; 20] {
+        let mut data = [0; 20];
+        data[0..4].copy_from_slice(&texture_count.to_ne_bytes());
+        data[4..8].copy_from_slice(&(self.srgb_conversion as u32).to_ne_bytes());
+        data[8..12].copy_from_slice(&(self.premultiply_alpha as u32).to_ne_bytes());
+        data[12..16].copy_from_slice(&(overdraw as u32).to_ne_bytes());
+        data[16..20].copy_from_slice(&material.to_ne_bytes());
+        data
+    }
+}
+
+/// Everything that gets baked into a pipeline, one pipeline gets created per key that's actually used
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    blend_mode: BlendMode,
+    wireframe: bool,
+    /// Overrides the blend mode with plain adding, see `DebugDraw::overdraw`
+    overdraw: bool,
+    shader_config: ShaderConfig,
+    /// `Material::mode`, always 0 for custom pipelines since they ignore it
+    material: u32,
+    /// None is the built in shaders
+    custom: Option<CustomPipelineId>,
+    /// Made for the post processing target instead of the swapchain, their formats differ
+    offscreen: bool,
+}
+
+/// The passes `draw_frame` records, in whatever order the `FrameGraph` comes up with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EnginePass {
+    Scene,
+    PostProcess,
+    Capture,
+}
+
+/// Handle to a pipeline made with `HalState::create_custom_pipeline`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomPipelineId(usize);
+
+/// Shaders and vertex format of a custom pipeline, the actual pipelines get created per `PipelineKey`
+/// like the built in ones
+struct CustomPipeline {
+    vertex_format: VertexFormat,
+    vertex_shader_module: <back::Backend as Backend>::ShaderModule,
+    fragment_shader_module: <back::Backend as Backend>::ShaderModule,
+}
+
 #[derive(Debug, Clone)]
 pub struct SamplingConfig {
     pub multisampling: Option<u8>, // number of samples
@@ -134,15 +565,63 @@ impl Default for SamplingConfig {
 
 pub struct HalState {
     sampling_config: SamplingConfig,
+    camera: Camera2D,
+    shake: ScreenShake,
+    /// When the shake was last advanced, None while nothing's shaking
+    last_shake_update: Option<Instant>,
+    debug_draw: DebugDraw,
+    /// Whether `load_image` premultiplies textures before uploading them
+    premultiply_alpha: bool,
+    shader_config: ShaderConfig,
+    atlas: Option<TextureAtlas>,
+    /// Images from `TextureLoader`s waiting to be uploaded
+    texture_queue: TextureQueue,
+    reuse_command_buffers: bool,
+    /// Hash of the frame each frame in flight last recorded and the swapchain image it was recorded
+    /// for, None if it has to be recorded again
+    recorded_frames: Vec<Option<(u64, usize)>>,
+    culling: bool,
+    culled_quads: usize,
     num_quads: usize,
-    vertices: BufferBundle<back::Backend, back::Device>,
+    /// A region per frame in flight
+    vertices: StreamBuffer<back::Backend, back::Device>,
+    /// Never changes after being written, so all the frames share it
     indexes: BufferBundle<back::Backend, back::Device>,
+    index_type: IndexType,
+    /// A region per frame in flight
+    mesh_indexes: StreamBuffer<back::Backend, back::Device>,
+    mesh_index_capacity: usize,
     texture_pool: TexturePool<back::Backend, back::Device>,
     logger: Logger,
     pipeline_layout: ManuallyDrop<<back::Backend as Backend>::PipelineLayout>,
-    graphics_pipeline: ManuallyDrop<<back::Backend as Backend>::GraphicsPipeline>,
+    pipelines: HashMap<PipelineKey, <back::Backend as Backend>::GraphicsPipeline>,
+    vertex_shader_module: ManuallyDrop<<back::Backend as Backend>::ShaderModule>,
+    fragment_shader_module: ManuallyDrop<<back::Backend as Backend>::ShaderModule>,
+    /// Indexed by `CustomPipelineId`, they're never destroyed before the HalState is
+    custom_pipelines: Vec<CustomPipeline>,
+    /// Indexed by `IndirectMeshId`, same deal as the custom pipelines
+    indirect_meshes: Vec<IndirectMesh<back::Backend, back::Device>>,
+    /// Which set of per frame resources (sync primitives, command buffer, vertex buffers) the next frame
+    /// uses, this has nothing to do with the swapchain image index
     current_frame: usize,
     frames_in_flight: usize,
+    preferred_vsync: [PresentMode; 4],
+    present_mode: PresentMode,
+    hidpi_factor: f64,
+    /// The render pass is made for this, so recreating the swapchain can't change it
+    format: Format,
+    crt_effect: Option<CrtEffect>,
+    /// Only around while there's a post processing effect
+    post_process: Option<PostProcess<back::Backend, back::Device>>,
+    /// Only around while frames are being captured
+    capture: Option<FrameCapture<back::Backend, back::Device>>,
+    /// Made when the first render target is
+    render_targets: Option<RenderTargets<back::Backend, back::Device>>,
+    frame_timer: FrameTimer,
+    frame_stats: FrameStats,
+    /// Draw call and quad counts of what's recorded in each frame's command buffer, for when it
+    /// gets reused
+    recorded_counts: Vec<(usize, usize)>,
     in_flight_fences: Vec<<back::Backend as Backend>::Fence>,
     render_finished_semaphores: Vec<<back::Backend as Backend>::Semaphore>,
     image_available_semaphores: Vec<<back::Backend as Backend>::Semaphore>,
@@ -150,13 +629,23 @@ pub struct HalState {
     command_pool: ManuallyDrop<CommandPool<back::Backend, Graphics>>,
     framebuffers: Vec<<back::Backend as Backend>::Framebuffer>,
     image_views: Vec<(<back::Backend as Backend>::ImageView)>,
+    /// Owned by the swapchain, these are only here to copy out of when capturing
+    swapchain_images: Vec<<back::Backend as Backend>::Image>,
+    /// Whether each swapchain image has been drawn to since it was made, `load_render_pass` needs
+    /// one that's been presented before
+    drawn_images: Vec<bool>,
     render_pass: ManuallyDrop<<back::Backend as Backend>::RenderPass>,
+    /// Same as `render_pass` but it keeps what's in the image, for frames that don't clear. Load ops
+    /// don't matter for compatibility so it shares the framebuffers and pipelines
+    load_render_pass: ManuallyDrop<<back::Backend as Backend>::RenderPass>,
     render_area: Rect,
     queue_group: QueueGroup<back::Backend, Graphics>,
-    swapchain: ManuallyDrop<<back::Backend as Backend>::Swapchain>,
+    transfer_queue: Option<TransferQueue<back::Backend>>,
+    /// Only None if recreating it failed
+    swapchain: Option<<back::Backend as Backend>::Swapchain>,
     device: ManuallyDrop<back::Device>,
     _adapter: Adapter<back::Backend>,
-    _surface: <back::Backend as Backend>::Surface,
+    surface: <back::Backend as Backend>::Surface,
     _instance: ManuallyDrop<back::Instance>,
 }
 
@@ -167,35 +656,78 @@ impl std::fmt::Debug for HalState {
 }
 
 impl HalState {
+    pub fn builder() -> HalStateBuilder {
+        HalStateBuilder::new()
+    }
+
+    #[deprecated(note = "use HalState::builder or HalState::with_config")]
     pub fn new(
         window: &winit::Window,
         name: &str,
         num_quads: usize,
         preferred_vsync: [PresentMode; 4],
-        mut sampling_config: SamplingConfig,
+        sampling_config: SamplingConfig,
+        logger: slog::Logger,
+    ) -> Result<Self, &'static str> {
+        let config = GraphicsConfig {
+            name: name.to_string(),
+            num_quads,
+            vsync: preferred_vsync,
+            sampling: sampling_config,
+            ..GraphicsConfig::default()
+        };
+        Self::with_config(window, config, logger)
+    }
+
+    pub fn with_config(
+        window: &winit::Window,
+        config: GraphicsConfig,
         logger: slog::Logger,
     ) -> Result<Self, &'static str> {
-        let instance = back::Instance::create(name, 1);
+        let GraphicsConfig {
+            name,
+            num_quads,
+            vsync: preferred_vsync,
+            sampling: mut sampling_config,
+            adapter: adapter_preference,
+            max_textures,
+            max_frames_in_flight,
+            debug_draw,
+            premultiply_alpha: premultiply_textures,
+        } = config;
+        let instance = back::Instance::create(&name, 1);
         let mut surface = instance.create_surface(window);
-        let adapter = instance
+        let adapters = instance
             .enumerate_adapters()
             .into_iter()
-            .find(|a| {
+            .filter(|a| {
                 a.queue_families
                     .iter()
                     .any(|qf| qf.supports_graphics() && surface.supports_queue_family(qf))
             })
+            .collect();
+        let adapter = adapter_preference
+            .pick(adapters)
             .ok_or("Couldn't find a graphical Adapter!")?;
-        let (mut device, queue_group) = {
+        info!(logger, "picked adapter"; "adapter" => debug_repr!(adapter.info),
+              "preference" => debug_repr!(adapter_preference));
+        let (device, queue_group, transfer_queue_group) = {
             let queue_family = adapter
                 .queue_families
                 .iter()
                 .find(|qf| qf.supports_graphics() && surface.supports_queue_family(qf))
                 .ok_or("Couldn't find QueueFamily with graphics!")?;
+            // a transfer only family usually means a DMA engine that can copy while rendering goes on
+            let transfer_family = adapter
+                .queue_families
+                .iter()
+                .find(|qf| qf.queue_type() == QueueType::Transfer && qf.id() != queue_family.id());
+            let mut families = vec![(queue_family, &[1.0; 1][..])];
+            families.extend(transfer_family.map(|qf| (qf, &[1.0; 1][..])));
             let Gpu { device, mut queues } = unsafe {
                 adapter
                     .physical_device
-                    .open(&[(&queue_family, &[1.0; 1])])
+                    .open(&families)
                     .map_err(|_| "Couldn't open the PhysicalDevice!")?
             };
             let queue_group = queues
@@ -206,90 +738,31 @@ impl HalState {
             } else {
                 Err("The QueueGroup didn't have any CommandQueues available!")
             }?;
-            (device, queue_group)
+            let transfer_queue_group = transfer_family
+                .and_then(|qf| queues.take::<Transfer>(qf.id()))
+                .filter(|group| !group.queues.is_empty());
+            info!(logger, "opened device"; "dedicated_transfer_queue" => transfer_queue_group.is_some());
+            (device, queue_group, transfer_queue_group)
         };
 
-        let (swapchain, extent, backbuffer, format, frames_in_flight) = {
-            let (caps, preferred_formats, present_modes, composite_alphas) =
-                surface.compatibility(&adapter.physical_device);
-            info!(&logger, "surface compatibility";
-                  kv!("caps" => debug_repr!(caps),
-                      "preferred_formats" => debug_repr!(preferred_formats),
-                      "present_modes" => debug_repr!(present_modes),
-                      "composite_alphas" => debug_repr!(composite_alphas)));
-            //
-            let present_mode = {
-                preferred_vsync
-                    .iter()
-                    .cloned()
-                    .find(|pm| present_modes.contains(pm))
-                    .ok_or("No PresentMode values specified!")?
-            };
-            let composite_alpha = {
-                use gfx_hal::window::CompositeAlpha::*;
-                [Opaque, Inherit, PreMultiplied, PostMultiplied]
-                    .iter()
-                    .cloned()
-                    .find(|ca| composite_alphas.contains(ca))
-                    .ok_or("No CompositeAlpha values specified!")?
-            };
-            let format = match preferred_formats {
-                None => Format::Rgba8Srgb,
-                Some(formats) => match formats
-                    .iter()
-                    .find(|format| format.base_format().1 == ChannelType::Srgb)
-                    .cloned()
-                {
-                    Some(srgb_format) => srgb_format,
-                    None => formats
-                        .get(0)
-                        .cloned()
-                        .ok_or("Preferred format list was empty!")?,
-                },
-            };
-            // This really just grabs the extent as reported, but does some extra math since metal might report 4096x4096 because reasons
-            let extent = {
-                let window_client_area = window
-                    .get_inner_size()
-                    .ok_or("Window doesn't exist!")?
-                    .to_physical(window.get_hidpi_factor());
-                Extent2D {
-                    width: caps.extents.end.width.min(window_client_area.width as u32),
-                    height: caps
-                        .extents
-                        .end
-                        .height
-                        .min(window_client_area.height as u32),
-                }
-            };
-            let image_count = if present_mode == PresentMode::Mailbox {
-                (caps.image_count.end - 1).min(3)
-            } else {
-                (caps.image_count.end - 1).min(2)
-            };
-            let image_layers = 1;
-            let image_usage = if caps.usage.contains(Usage::COLOR_ATTACHMENT) {
-                Usage::COLOR_ATTACHMENT
-            } else {
-                Err("The surface isn't capable of supporting color!")?
-            };
-            let swapchain_config = SwapchainConfig {
-                present_mode,
-                composite_alpha,
-                format,
-                extent,
-                image_count,
-                image_layers,
-                image_usage,
-            };
-            info!(logger, "created a swapchain config"; "swapchain_config" => format!("{:#?}", swapchain_config));
-            let (swapchain, backbuffer) = unsafe {
-                device
-                    .create_swapchain(&mut surface, swapchain_config, None)
-                    .map_err(|_| "Failed to create the swapchain!")?
-            };
-            (swapchain, extent, backbuffer, format, image_count as usize)
+        let hidpi_factor = window.get_hidpi_factor();
+        let (swapchain, extent, backbuffer, format, image_count, present_mode) = create_swapchain(
+            &adapter,
+            &device,
+            &mut surface,
+            window,
+            &preferred_vsync,
+            None,
+            &mut None,
+            &logger,
+        )?;
+        // the per frame resources don't care which swapchain image they end up drawing to, so
+        // there can be fewer of them than images
+        let frames_in_flight = match max_frames_in_flight {
+            Some(max) => (image_count as usize).min(max.max(1)),
+            None => image_count as usize,
         };
+        info!(logger, "frames in flight"; "frames_in_flight" => frames_in_flight);
 
         let max_samples = {
             let samples = adapter
@@ -352,124 +825,93 @@ impl HalState {
                 in_flight_fences,
             )
         };
-        let render_pass = {
-            let color_attachment = Attachment {
-                format: Some(format),
-                samples: sampling_config.multisampling.unwrap_or(1),
-                ops: AttachmentOps {
-                    load: AttachmentLoadOp::Clear,
-                    store: AttachmentStoreOp::Store,
-                },
-                stencil_ops: AttachmentOps::DONT_CARE,
-                layouts: Layout::Undefined..Layout::Present,
-            };
-            let subpass = SubpassDesc {
-                colors: &[(0, Layout::ColorAttachmentOptimal)],
-                depth_stencil: None,
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-            unsafe {
-                device
-                    .create_render_pass(&[color_attachment], &[subpass], &[])
-                    .map_err(|_| "Couldn't create a render pass!")?
-            }
-        };
-        let image_views: Vec<_> = match backbuffer {
-            Backbuffer::Images(images) => images
-                .into_iter()
-                .map(|image| unsafe {
-                    device
-                        .create_image_view(
-                            &image,
-                            ViewKind::D2,
-                            format,
-                            Swizzle::NO,
-                            SubresourceRange {
-                                aspects: Aspects::COLOR,
-                                levels: 0..1,
-                                layers: 0..1,
-                            },
-                        )
-                        .map_err(|_| "Couldn't create the image view for the image!")
-                })
-                .collect::<Result<Vec<_>, &str>>()?,
-            Backbuffer::Framebuffer(_) => unimplemented!("Can't handle framebuffer backbuffer!"),
-        };
-        let framebuffers: Vec<<back::Backend as Backend>::Framebuffer> = {
-            image_views
-                .iter()
-                .map(|image_view| unsafe {
-                    device
-                        .create_framebuffer(
-                            &render_pass,
-                            vec![image_view],
-                            Extent {
-                                width: extent.width as u32,
-                                height: extent.height as u32,
-                                depth: 1,
-                            },
-                        )
-                        .map_err(|_| "Failed to create a framebuffer!")
-                })
-                .collect::<Result<Vec<_>, &str>>()?
-        };
+        let samples = sampling_config.multisampling.unwrap_or(1);
+        let render_pass =
+            unsafe { create_render_pass(&device, format, samples, AttachmentLoadOp::Clear)? };
+        let load_render_pass =
+            unsafe { create_render_pass(&device, format, samples, AttachmentLoadOp::Load)? };
+        let (swapchain_images, image_views, framebuffers) =
+            unsafe { create_framebuffers(&device, &render_pass, backbuffer, format, extent)? };
         let mut command_pool = unsafe {
             device
                 .create_command_pool_typed(&queue_group, CommandPoolCreateFlags::RESET_INDIVIDUAL)
                 .map_err(|_| "Couldn't create the raw command pool!")?
         };
 
-        let command_buffers: Vec<_> = framebuffers
-            .iter()
+        let command_buffers: Vec<_> = (0..frames_in_flight)
             .map(|_| command_pool.acquire_command_buffer())
             .collect();
+        let transfer_queue = match transfer_queue_group {
+            Some(queue_group) => Some(TransferQueue {
+                command_pool: ManuallyDrop::new(unsafe {
+                    device
+                        .create_command_pool_typed(&queue_group, CommandPoolCreateFlags::TRANSIENT)
+                        .map_err(|_| "Couldn't create the transfer command pool!")?
+                }),
+                queue_group,
+            }),
+            None => None,
+        };
 
-        const DESCRIPTOR_SET_IMAGE_COUNT: usize = 64;
-
-        let (descriptor_set_layouts, pipeline_layout, graphics_pipeline) = Self::create_pipeline(
-            &mut device,
-            extent,
-            &render_pass,
-            DESCRIPTOR_SET_IMAGE_COUNT,
-            sampling_config.multisampling,
-            &logger,
-        )?;
+        let descriptor_set_image_count = max_textures_per_set(&adapter, max_textures, &logger);
 
+        // the same colors come out whether or not the surface does the conversion itself
+        let shader_config = ShaderConfig {
+            srgb_conversion: !is_srgb(format),
+            ..ShaderConfig::default()
+        };
+        let (vertex_shader_module, fragment_shader_module) =
+            Self::compile_shaders(&device, &logger)?;
+        let (descriptor_set_layouts, pipeline_layout) =
+            Self::create_pipeline_layout(&device, descriptor_set_image_count)?;
+        let default_key = PipelineKey {
+            blend_mode: BlendMode::default(),
+            wireframe: false,
+            overdraw: false,
+            shader_config,
+            material: 0,
+            custom: None,
+            offscreen: false,
+        };
+        let mut pipelines = HashMap::new();
+        pipelines.insert(
+            default_key,
+            Self::create_pipeline(
+                &device,
+                &render_pass,
+                &pipeline_layout,
+                &vertex_shader_module,
+                &fragment_shader_module,
+                &Vertex::format(),
+                sampling_config.multisampling,
+                descriptor_set_image_count as u32,
+                default_key,
+            )?,
+        );
+
+        // this is only how many sets go in one pool, another pool gets created when it runs out
         const DESCRIPTOR_SET_COUNT: usize = 16;
         // 2. you create a descriptor pool, and when making that descriptor pool
         //    you specify how many sets you want to be able to allocate from the
         //    pool, as well as the maximum number of each kind of descriptor you
         //    want to be able to allocate from that pool, total, for all sets.
-        let descriptor_pool = ManuallyDrop::new(unsafe {
-            device
-                .create_descriptor_pool(
-                    DESCRIPTOR_SET_COUNT, // sets
-                    &[
-                        gfx_hal::pso::DescriptorRangeDesc {
-                            ty: gfx_hal::pso::DescriptorType::SampledImage,
-                            count: DESCRIPTOR_SET_COUNT * DESCRIPTOR_SET_IMAGE_COUNT,
-                        },
-                        gfx_hal::pso::DescriptorRangeDesc {
-                            ty: gfx_hal::pso::DescriptorType::Sampler,
-                            count: 1,
-                        },
-                    ],
-                )
-                .map_err(|_| "Couldn't create a descriptor pool!")?
-        });
+        let descriptor_pool = unsafe {
+            create_descriptor_pool(&device, DESCRIPTOR_SET_COUNT, descriptor_set_image_count)?
+        };
         // 3. you allocate said descriptor set from the pool you made earlier
         let descriptor_sets: Vec<<back::Backend as Backend>::DescriptorSet> =
             Vec::with_capacity(DESCRIPTOR_SET_COUNT);
 
         let texture_pool = TexturePool {
-            textures: Vec::with_capacity(DESCRIPTOR_SET_IMAGE_COUNT),
-            descriptor_pool,
+            textures: Vec::with_capacity(descriptor_set_image_count),
+            descriptor_pools: vec![descriptor_pool],
             descriptor_sets,
             descriptor_set_layouts,
-            samplers: Vec::with_capacity(DESCRIPTOR_SET_COUNT),
-            descriptor_size: DESCRIPTOR_SET_IMAGE_COUNT,
+            samplers: Vec::with_capacity(MAX_SAMPLERS),
+            texture_samplers: Vec::with_capacity(descriptor_set_image_count),
+            free_slots: Vec::new(),
+            sampler_tables: Vec::with_capacity(DESCRIPTOR_SET_COUNT),
+            descriptor_size: descriptor_set_image_count,
             pool_size: DESCRIPTOR_SET_COUNT,
         };
 
@@ -482,52 +924,65 @@ impl HalState {
         // 6. You actually bind the descriptor set in the command buffer before
         //    the draw call using bind_graphics_descriptor_sets
 
-        let vertices = BufferBundle::new(
+        let vertices = StreamBuffer::new(
             &adapter,
             &device,
             QUAD_SIZE * num_quads,
+            frames_in_flight,
             BufferUsage::VERTEX,
         )?;
-        const U16_QUAD_INDICES: usize = mem::size_of::<u16>() * 2 * 3;
+        let index_type = quad_index_type(num_quads);
         let indexes = BufferBundle::new(
             &adapter,
             &device,
-            U16_QUAD_INDICES * num_quads,
+            quad_index_size(index_type) * num_quads,
+            BufferUsage::INDEX,
+        )?;
+        unsafe { write_quad_indices(&device, &indexes, num_quads, index_type)? };
+        let mesh_index_capacity = 6 * num_quads;
+        let mesh_indexes = StreamBuffer::new(
+            &adapter,
+            &device,
+            mem::size_of::<u16>() * mesh_index_capacity,
+            frames_in_flight,
             BufferUsage::INDEX,
         )?;
 
-        unsafe {
-            let mut data_target = device
-                .acquire_mapping_writer(&indexes.memory, 0..indexes.requirements.size)
-                .map_err(|_| "Failed to require an index buffer mapping writer!")?;
-            const INDEX_DATA: &[u16] = &[0, 1, 2, 2, 3, 0];
-            for i in 0..num_quads {
-                let stride: usize = 6;
-                let vertex_stride = 4;
-                let index_data: &[u16] = &[
-                    i as u16 * vertex_stride + INDEX_DATA[0],
-                    i as u16 * vertex_stride + INDEX_DATA[1],
-                    i as u16 * vertex_stride + INDEX_DATA[2],
-                    i as u16 * vertex_stride + INDEX_DATA[3],
-                    i as u16 * vertex_stride + INDEX_DATA[4],
-                    i as u16 * vertex_stride + INDEX_DATA[5],
-                ];
-                data_target[stride * i..stride * (i + 1)].copy_from_slice(&index_data);
-            }
-            device
-                .release_mapping_writer(data_target)
-                .map_err(|_| "Couldn't release the index buffer mapping writer!")?;
-        }
-
-        Ok(HalState {
+        let mut hal_state = HalState {
             sampling_config,
+            camera: Camera2D::default(),
+            shake: ScreenShake::new(),
+            last_shake_update: None,
+            debug_draw,
+            premultiply_alpha: premultiply_textures,
+            shader_config,
+            atlas: None,
+            texture_queue: TextureQueue::default(),
+            reuse_command_buffers: false,
+            recorded_frames: vec![None; frames_in_flight],
+            culling: false,
+            culled_quads: 0,
             num_quads,
             vertices,
             indexes,
+            index_type,
+            mesh_indexes,
+            mesh_index_capacity,
             texture_pool,
             logger,
             current_frame: 0,
             frames_in_flight,
+            preferred_vsync,
+            present_mode,
+            hidpi_factor,
+            format,
+            crt_effect: None,
+            post_process: None,
+            capture: None,
+            render_targets: None,
+            frame_timer: FrameTimer::default(),
+            frame_stats: FrameStats::default(),
+            recorded_counts: vec![(0, 0); frames_in_flight],
             in_flight_fences,
             render_finished_semaphores,
             image_available_semaphores,
@@ -535,173 +990,1151 @@ impl HalState {
             command_pool: ManuallyDrop::new(command_pool),
             framebuffers,
             image_views,
+            drawn_images: vec![false; swapchain_images.len()],
+            swapchain_images,
             render_pass: ManuallyDrop::new(render_pass),
+            load_render_pass: ManuallyDrop::new(load_render_pass),
             render_area: extent.to_extent().rect(),
             queue_group,
-            swapchain: ManuallyDrop::new(swapchain),
+            transfer_queue,
+            swapchain: Some(swapchain),
             device: ManuallyDrop::new(device),
             pipeline_layout: ManuallyDrop::new(pipeline_layout),
-            graphics_pipeline: ManuallyDrop::new(graphics_pipeline),
+            pipelines,
+            vertex_shader_module: ManuallyDrop::new(vertex_shader_module),
+            fragment_shader_module: ManuallyDrop::new(fragment_shader_module),
+            custom_pipelines: Vec::new(),
+            indirect_meshes: Vec::new(),
             _adapter: adapter,
-            _surface: surface,
+            surface,
             _instance: ManuallyDrop::new(instance),
-        })
+        };
+        if descriptor_set_image_count < ATLAS_FALLBACK_THRESHOLD {
+            hal_state.enable_texture_atlas()?;
+        }
+        Ok(hal_state)
     }
 
-    // TODO: Check all this to be correct
-    pub fn load_texture(&mut self, texture: &[u8]) -> Result<(), &'static str> {
-        let descriptor_set = {
-            if self.texture_pool.textures.len() == 0 {
-                let new_descriptor = unsafe {
-                    self.texture_pool
-                        .descriptor_pool
-                        .allocate_set(&self.texture_pool.descriptor_set_layouts[0])
-                        .map_err(|_| "Couldn't make a descriptor set!")?
-                };
-                let samplerinfo = gfx_hal::image::SamplerInfo::new(
-                    self.sampling_config.filter_type.unwrap_or(Filter::Nearest),
-                    gfx_hal::image::WrapMode::Tile,
-                );
+    /// Decodes with `decode_image`, so 16-bit, grayscale and paletted images all work
+    pub fn load_texture(&mut self, texture: &[u8]) -> Result<TextureId, TextureError> {
+        let sampler = self.default_sampler();
+        self.load_texture_with_sampler(texture, sampler)
+    }
 
-                let sampler = unsafe {
-                    match self.device.create_sampler(samplerinfo) {
-                        Ok(sampler) => sampler,
-                        Err(_) => {
-                            self.texture_pool
-                                .descriptor_pool
-                                .free_sets(Some(new_descriptor));
-                            return Err("Couldn't create the sampler!");
-                        }
-                    }
-                };
-                self.texture_pool.descriptor_sets.push(new_descriptor);
-                self.texture_pool.samplers.push(ManuallyDrop::new(sampler));
-                let descriptor_set = &self.texture_pool.descriptor_sets.last().unwrap();
-                let sampler = &self.texture_pool.samplers.last().unwrap();
+    pub fn load_texture_with_sampler(
+        &mut self,
+        texture: &[u8],
+        sampler: SamplerDesc,
+    ) -> Result<TextureId, TextureError> {
+        let image = decode_image(texture)?;
+        Ok(self.load_image_with_sampler(image, sampler)?)
+    }
 
-                unsafe {
-                    self.device
-                        .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
-                            // doing it this way to ensure that the descriptor set will be somewhere to get deallocated,
-                            // even if this function early returns
-                            set: *descriptor_set,
-                            binding: 1,
-                            array_offset: 0,
-                            descriptors: Some(gfx_hal::pso::Descriptor::Sampler(
-                                (*sampler).deref(),
-                            )),
-                        }));
-                }
-                &self.texture_pool.descriptor_sets[0] // this can never fail because we just pushed a new descriptor set
-            } else if self.texture_pool.textures.len()
-                == self.texture_pool.descriptor_sets.len() * self.texture_pool.descriptor_size
-            {
-                // this is when all current descriptor sets are full, so we allocate a new one
-                let new_descriptor = unsafe {
-                    self.texture_pool
-                        .descriptor_pool
-                        .allocate_set(&self.texture_pool.descriptor_set_layouts[0])
-                        .map_err(|_| "Couldn't make a descriptor set!")?
-                };
+    /// Loads an animated GIF as one sprite sheet texture, other formats come out as a single frame
+    pub fn load_animated_texture(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(TextureId, Animation), &'static str> {
+        let (sheet, animation) = decode_animation(data)?;
+        Ok((self.load_image(sheet)?, animation))
+    }
 
-                let mut samplerinfo = gfx_hal::image::SamplerInfo::new(
-                    self.sampling_config.filter_type.unwrap_or(Filter::Nearest),
-                    gfx_hal::image::WrapMode::Tile,
-                );
-                samplerinfo.anisotropic = gfx_hal::image::Anisotropic::On(8);
-
-                let sampler = unsafe {
-                    match self.device.create_sampler(samplerinfo) {
-                        Ok(sampler) => sampler,
-                        Err(_) => {
-                            self.texture_pool
-                                .descriptor_pool
-                                .free_sets(Some(new_descriptor));
-                            return Err("Couldn't create the sampler!");
-                        }
-                    }
-                };
-                self.texture_pool.descriptor_sets.push(new_descriptor);
-                self.texture_pool.samplers.push(ManuallyDrop::new(sampler));
-                let descriptor_set = self.texture_pool.descriptor_sets.last().unwrap();
-                let sampler = self.texture_pool.samplers.last().unwrap();
-                unsafe {
-                    self.device
-                        .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
-                            // doing it this way to ensure that the descriptor set will be somewhere to get deallocated,
-                            // even if this function early returns
-                            set: descriptor_set,
-                            binding: 1,
-                            array_offset: 0,
-                            descriptors: Some(gfx_hal::pso::Descriptor::Sampler(sampler.deref())),
-                        }));
-                }
+    /// Rasterizes an SVG at `scale` times its own size and loads the result as a texture
+    #[cfg(feature = "svg")]
+    pub fn load_svg(&mut self, data: &[u8], scale: f32) -> Result<TextureId, &'static str> {
+        self.load_image(rasterize_svg(data, scale)?)
+    }
 
-                descriptor_set // this can't fail because we just pushed a new descriptor set
-            } else {
-                self.texture_pool.descriptor_sets.last().unwrap() // this shouldn't be able to fail, I hope
-            }
-        };
+    /// Same as `load_texture` for images that are already decoded, or were made at runtime
+    pub fn load_image(&mut self, image: image::RgbaImage) -> Result<TextureId, &'static str> {
+        let sampler = self.default_sampler();
+        self.load_image_with_sampler(image, sampler)
+    }
 
-        let num_descriptor_sets = self.texture_pool.descriptor_sets.len();
-        let num_textures = self.texture_pool.textures.len();
+    /// The sampler gets ignored with the texture atlas on, everything in there uses the default one
+    pub fn load_image_with_sampler(
+        &mut self,
+        mut image: image::RgbaImage,
+        sampler: SamplerDesc,
+    ) -> Result<TextureId, &'static str> {
+        if self.premultiply_alpha {
+            premultiply_alpha(&mut image);
+        }
+        if self.atlas.is_some() {
+            self.load_atlas_texture(image)
+        } else {
+            self.upload_texture(image, sampler, COLOR_FORMAT)
+        }
+    }
 
-        let texture = LoadedImage::new(
-            &self._adapter,
-            self.device.deref(),
-            &mut self.command_pool,
-            &mut self.queue_group.queues[0],
-            image::load_from_memory(texture)
-                .map_err(|_| "invalid image!")?
-                .to_rgba(),
-        )?;
+    /// What `load_texture` and `load_image` use, `SamplingConfig::filter_type` with the old
+    /// defaults for everything else
+    pub fn default_sampler(&self) -> SamplerDesc {
+        SamplerDesc {
+            filter: self.sampling_config.filter_type.unwrap_or(Filter::Nearest),
+            ..SamplerDesc::default()
+        }
+    }
 
-        info!(self.logger, "writing to descriptor set...";
-              "array_offset" => num_textures % num_descriptor_sets,
-              "num_textures" => num_textures, "num_descriptor_sets" => num_descriptor_sets);
+    /// Switches a loaded texture to another sampler. This waits for the GPU to go idle like
+    /// `update_texture`
+    pub fn set_texture_sampler(
+        &mut self,
+        texture: TextureId,
+        sampler: SamplerDesc,
+    ) -> Result<(), &'static str> {
+        if self.atlas.is_some() {
+            return Err("Textures in the atlas all use the default sampler!");
+        }
+        let index = texture.index() as usize;
+        if index >= self.texture_pool.textures.len() {
+            return Err("No texture with that id!");
+        }
+        let sampler_index = self.sampler_index(sampler)?;
+        // frames in flight might still be reading the table
+        self.device
+            .wait_idle()
+            .map_err(|_| "Couldn't wait for the device to idle!")?;
+        self.texture_pool.texture_samplers[index] = sampler_index as u8;
+        self.write_sampler_table(index / self.texture_pool.descriptor_size)
+    }
 
-        unsafe {
-            // Some used here since we're only writing one thing, and Some implements IntoIterator, which is what write_descriptor_sets uses anyway
-            self.device
-                .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
-                    set: descriptor_set,
-                    binding: 0,
-                    // logic here is that hopefully the modulo will basically find which descriptor set we're supposed to write to
-                    // it probably works because they're all the same size
-                    array_offset: num_textures
-                        % (num_descriptor_sets * self.texture_pool.descriptor_size),
-                    descriptors: Some(gfx_hal::pso::Descriptor::Image(
-                        texture.image_view.deref(),
-                        Layout::Undefined,
-                    )),
-                }))
-        };
+    /// None for textures that don't exist or are in the atlas
+    pub fn texture_sampler(&self, texture: TextureId) -> Option<SamplerDesc> {
+        if self.atlas.is_some() {
+            return None;
+        }
+        let index = *self
+            .texture_pool
+            .texture_samplers
+            .get(texture.index() as usize)?;
+        Some(self.texture_pool.samplers[index as usize].0)
+    }
 
-        self.texture_pool.textures.push(texture);
+    /// Keeps the rest of the texture's sampler, `WrapMode::Tile` or `WrapMode::Mirror` is what a
+    /// `ScrollingBackground` needs
+    pub fn set_texture_wrap(
+        &mut self,
+        texture: TextureId,
+        wrap: WrapMode,
+    ) -> Result<(), &'static str> {
+        let sampler = self
+            .texture_sampler(texture)
+            .ok_or("No texture with that id outside the atlas!")?;
+        self.set_texture_sampler(texture, sampler.with_wrap(wrap))
+    }
 
-        info!(self.logger, "loaded texture"; "num_textures" => self.texture_pool.textures.len(),
-              "num_descriptor_sets" => self.texture_pool.descriptor_sets.len());
+    /// A handle for loading textures from other threads, they get uploaded at the start of the next
+    /// frame (or the next `finish_texture_loads`)
+    pub fn texture_loader(&self) -> TextureLoader {
+        TextureLoader::new(&self.texture_queue)
+    }
+
+    /// Uploads everything the `TextureLoader`s have queued up, returns how many there were. Drawing a
+    /// frame does this first thing, so it only needs calling to get textures in without drawing
+    pub fn finish_texture_loads(&mut self) -> usize {
+        let queued: Vec<_> = lock_queue(&self.texture_queue).drain(..).collect();
+        let count = queued.len();
+        for texture in queued {
+            texture.finish(|image| self.load_image(image));
+        }
+        if count > 0 {
+            debug!(self.logger, "finished queued texture loads"; "count" => count);
+        }
+        count
+    }
+
+    /// Replaces the contents of a loaded texture. This waits for the GPU to go idle, so it's not
+    /// something to do every frame. Index textures and palettes stay exactly what's in `image`
+    pub fn update_texture(
+        &mut self,
+        texture: TextureId,
+        mut image: image::RgbaImage,
+    ) -> Result<(), &'static str> {
+        let index = texture.index() as usize;
+        if let Some(render_targets) = self.render_targets.as_ref() {
+            if render_targets.framebuffer(index).is_some() {
+                return Err("Render targets get drawn into, not updated!");
+            }
+        }
+        let format = match self.texture_pool.textures.get(index) {
+            Some(texture) if self.atlas.is_none() => texture.format,
+            _ => COLOR_FORMAT,
+        };
+        if self.premultiply_alpha && format == COLOR_FORMAT {
+            premultiply_alpha(&mut image);
+        }
+        match self.atlas.as_mut() {
+            Some(atlas) => {
+                let page = atlas.replace(index, &image)?;
+                let page_image = atlas.pages[page].image.clone();
+                self.replace_texture(page, page_image, COLOR_FORMAT)
+            }
+            None if self.texture_pool.free_slots.contains(&index) => {
+                Err("That texture was unloaded!")
+            }
+            None if index < self.texture_pool.textures.len() => {
+                self.replace_texture(index, image, format)
+            }
+            None => Err("No texture with that id!"),
+        }
+    }
 
+    /// Frees a texture's memory, and its id goes to the next texture that gets loaded. Drawing with
+    /// it after this shows whatever got loaded next or nothing at all. Textures in the atlas can't
+    /// be unloaded since their space in the page can't be reused
+    pub fn unload_texture(&mut self, texture: TextureId) -> Result<(), &'static str> {
+        if self.atlas.is_some() {
+            return Err("Textures in the atlas can't be unloaded!");
+        }
+        let index = texture.index() as usize;
+        if index >= self.texture_pool.textures.len() {
+            return Err("No texture with that id!");
+        }
+        if self.texture_pool.free_slots.contains(&index) {
+            return Err("That texture was already unloaded!");
+        }
+        if let Some(render_targets) = self.render_targets.as_mut() {
+            let _ = self.device.wait_idle();
+            unsafe { render_targets.remove(&self.device, index) };
+        }
+        // the descriptor has to keep pointing at a real image
+        self.replace_texture(index, image::RgbaImage::new(1, 1), COLOR_FORMAT)?;
+        self.texture_pool.free_slots.push(index);
         Ok(())
     }
 
-    pub fn extend_quad_alloc(&mut self, new_max: usize) -> Result<(), &'static str> {
-        if new_max as u64 > self.vertices.requirements.size / QUAD_SIZE as u64 {
-            info!(&self.logger, "extending quad vertex/index buffer size"; "new_size" => new_max);
+    /// Uploads up to 256 colors as a palette for `Material::Palette`. It gets the pixel art
+    /// sampler, since blending between entries would make colors that aren't in the palette
+    pub fn load_palette(&mut self, colors: &[[u8; 4]]) -> Result<TextureId, &'static str> {
+        let image = palette_image(colors)?;
+        self.load_data_texture(image)
+    }
 
-            unsafe {
-                let new_vertices = BufferBundle::new(
-                    &self._adapter,
-                    &*self.device,
-                    QUAD_SIZE * new_max,
+    /// Loads the texture a quad with `Material::Palette` gets drawn with, its red channel is the
+    /// palette entry. It's uploaded as it is, without sRGB decoding or premultiplying, and gets
+    /// the pixel art sampler. Keep it on a nearest sampler, filtering blends neighbouring indices
+    /// into ones that pick a different color
+    pub fn load_index_texture(
+        &mut self,
+        image: image::RgbaImage,
+    ) -> Result<TextureId, &'static str> {
+        self.load_data_texture(image)
+    }
+
+    /// Index textures and palettes, the atlas would premultiply them and decode them as sRGB
+    fn load_data_texture(&mut self, image: image::RgbaImage) -> Result<TextureId, &'static str> {
+        if self.atlas.is_some() {
+            return Err("Palettes don't work with the texture atlas!");
+        }
+        self.upload_texture(image, SamplerDesc::PIXEL_ART, DATA_FORMAT)
+    }
+
+    /// Swaps a palette's colors, waits for the GPU like `update_texture`. It doesn't have to have
+    /// the same number of colors as before
+    pub fn update_palette(
+        &mut self,
+        palette: TextureId,
+        colors: &[[u8; 4]],
+    ) -> Result<(), &'static str> {
+        let image = palette_image(colors)?;
+        self.update_texture(palette, image)
+    }
+
+    /// Turns on the atlas fallback, which packs textures into a few big pages so the number of
+    /// textures isn't limited by `max_textures`. This gets turned on by itself when the texture array
+    /// is small, and can only be done before any textures are loaded
+    pub fn enable_texture_atlas(&mut self) -> Result<(), &'static str> {
+        if self.atlas.is_some() {
+            return Ok(());
+        }
+        if !self.texture_pool.textures.is_empty() {
+            return Err("Can't switch to the texture atlas after textures were loaded!");
+        }
+        let page_size =
+            ATLAS_PAGE_SIZE.min(self._adapter.physical_device.limits().max_texture_size);
+        info!(self.logger, "using the texture atlas fallback"; "page_size" => page_size);
+        self.atlas = Some(TextureAtlas::new(page_size as u32));
+        Ok(())
+    }
+
+    pub fn uses_texture_atlas(&self) -> bool {
+        self.atlas.is_some()
+    }
+
+    fn load_atlas_texture(&mut self, image: image::RgbaImage) -> Result<TextureId, &'static str> {
+        let atlas = self.atlas.as_mut().unwrap();
+        let page = atlas.insert(&image);
+        let id = TextureId(atlas.entries.len() as u32 - 1);
+        let page_image = atlas.pages[page].image.clone();
+        if page < self.texture_pool.textures.len() {
+            self.replace_texture(page, page_image, COLOR_FORMAT)?;
+        } else {
+            let sampler = self.default_sampler();
+            self.upload_texture(page_image, sampler, COLOR_FORMAT)?;
+        }
+        Ok(id)
+    }
+
+    /// Makes a transparent texture the size of the drawable area, that frames can draw into with
+    /// `Frame::set_target` and then draw with like any other texture. It gets resized along with
+    /// the swapchain, which loses what's in it. Doesn't work with multisampling or the atlas
+    pub fn create_render_target(&mut self) -> Result<TextureId, &'static str> {
+        if self.atlas.is_some() {
+            return Err("Render targets don't work with the texture atlas!");
+        }
+        if self
+            .sampling_config
+            .multisampling
+            .map_or(false, |samples| samples > 1)
+        {
+            return Err("Render targets don't work with multisampling!");
+        }
+        if self.render_targets.is_none() {
+            self.render_targets = Some(unsafe { RenderTargets::new(&*self.device)? });
+        }
+        // takes a slot like any other texture, then swaps in the real image
+        let sampler = self.default_sampler();
+        let texture =
+            self.upload_texture(image::RgbaImage::new(1, 1), sampler, OFFSCREEN_FORMAT)?;
+        if let Err(e) = self.resize_render_target(texture.index() as usize) {
+            let _ = self.unload_texture(texture);
+            return Err(e);
+        }
+        Ok(texture)
+    }
+
+    /// Makes the render target in slot `index` match the render area again
+    fn resize_render_target(&mut self, index: usize) -> Result<(), &'static str> {
+        let (width, height) = (self.render_area.w as u32, self.render_area.h as u32);
+        let texture = LoadedImage::new_render_target(
+            &self._adapter,
+            self.device.deref(),
+            &mut self.command_pool,
+            &mut self.queue_group.queues[0],
+            width,
+            height,
+            OFFSCREEN_FORMAT,
+        )?;
+        self.device
+            .wait_idle()
+            .map_err(|_| "Couldn't wait for the device to idle!")?;
+        let render_targets = self
+            .render_targets
+            .as_mut()
+            .ok_or("There are no render targets!")?;
+        let extent = Extent {
+            width,
+            height,
+            depth: 1,
+        };
+        // this replaces the framebuffer of the image that swap_texture is about to free
+        if let Err(e) = unsafe { render_targets.insert(&self.device, index, &texture, extent) } {
+            unsafe { texture.manually_drop(self.device.deref()) };
+            return Err(e);
+        }
+        self.swap_texture(index, texture)
+    }
+
+    /// Uploads through the transfer queue if there is one
+    fn create_image(
+        &mut self,
+        image: image::RgbaImage,
+        format: Format,
+    ) -> Result<LoadedImage<back::Backend, back::Device>, &'static str> {
+        let graphics_family = self.queue_group.family();
+        match self.transfer_queue.as_mut() {
+            Some(transfer_queue) => LoadedImage::new_with_transfer_queue(
+                &self._adapter,
+                self.device.deref(),
+                transfer_queue,
+                &mut self.command_pool,
+                &mut self.queue_group.queues[0],
+                graphics_family,
+                image,
+                format,
+            ),
+            None => LoadedImage::new(
+                &self._adapter,
+                self.device.deref(),
+                &mut self.command_pool,
+                &mut self.queue_group.queues[0],
+                image,
+                format,
+            ),
+        }
+    }
+
+    /// Re-uploads the texture in slot `index` of the texture array
+    fn replace_texture(
+        &mut self,
+        index: usize,
+        image: image::RgbaImage,
+        format: Format,
+    ) -> Result<(), &'static str> {
+        let texture = self.create_image(image, format)?;
+        self.swap_texture(index, texture)
+    }
+
+    /// Puts `texture` in slot `index` and frees whatever was there
+    fn swap_texture(
+        &mut self,
+        index: usize,
+        texture: LoadedImage<back::Backend, back::Device>,
+    ) -> Result<(), &'static str> {
+        // the old one might still be in use by a frame in flight
+        self.device
+            .wait_idle()
+            .map_err(|_| "Couldn't wait for the device to idle!")?;
+        let descriptor_size = self.texture_pool.descriptor_size;
+        unsafe {
+            self.device
+                .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
+                    set: &self.texture_pool.descriptor_sets[index / descriptor_size],
+                    binding: 0,
+                    array_offset: index % descriptor_size,
+                    descriptors: Some(gfx_hal::pso::Descriptor::Image(
+                        texture.image_view.deref(),
+                        Layout::Undefined,
+                    )),
+                }));
+        }
+        let old = mem::replace(&mut self.texture_pool.textures[index], texture);
+        unsafe { old.manually_drop(self.device.deref()) };
+        // writing to a bound descriptor set invalidates the command buffers using it
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    fn upload_texture(
+        &mut self,
+        image: image::RgbaImage,
+        sampler: SamplerDesc,
+        format: Format,
+    ) -> Result<TextureId, &'static str> {
+        let sampler_index = self.sampler_index(sampler)?;
+        if let Some(index) = self.texture_pool.free_slots.pop() {
+            if let Err(e) = self.replace_texture(index, image, format) {
+                self.texture_pool.free_slots.push(index);
+                return Err(e);
+            }
+            self.texture_pool.texture_samplers[index] = sampler_index as u8;
+            self.write_sampler_table(index / self.texture_pool.descriptor_size)?;
+            return Ok(TextureId(index as u32));
+        }
+        // all current descriptor sets being full includes there not being any yet
+        if self.texture_pool.textures.len()
+            == self.texture_pool.descriptor_sets.len() * self.texture_pool.descriptor_size
+        {
+            self.add_texture_set()?;
+        }
+
+        let num_descriptor_sets = self.texture_pool.descriptor_sets.len();
+        let num_textures = self.texture_pool.textures.len();
+
+        let texture = self.create_image(image, format)?;
+
+        info!(self.logger, "writing to descriptor set...";
+              "array_offset" => num_textures % self.texture_pool.descriptor_size,
+              "num_textures" => num_textures, "num_descriptor_sets" => num_descriptor_sets);
+
+        let set_index = num_textures / self.texture_pool.descriptor_size;
+        unsafe {
+            // Some used here since we're only writing one thing, and Some implements IntoIterator, which is what write_descriptor_sets uses anyway
+            self.device
+                .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
+                    set: &self.texture_pool.descriptor_sets[set_index],
+                    binding: 0,
+                    // every set holds descriptor_size textures, and this one was picked above
+                    array_offset: num_textures % self.texture_pool.descriptor_size,
+                    descriptors: Some(gfx_hal::pso::Descriptor::Image(
+                        texture.image_view.deref(),
+                        Layout::Undefined,
+                    )),
+                }))
+        };
+
+        self.texture_pool.textures.push(texture);
+        self.texture_pool.texture_samplers.push(sampler_index as u8);
+        self.write_sampler_table(set_index)?;
+        self.invalidate_command_buffers();
+
+        info!(self.logger, "loaded texture"; "num_textures" => self.texture_pool.textures.len(),
+              "num_descriptor_sets" => self.texture_pool.descriptor_sets.len());
+
+        Ok(TextureId(num_textures as u32))
+    }
+
+    /// Allocates another texture set with its sampler table, and binds every sampler there is to it
+    fn add_texture_set(&mut self) -> Result<(), &'static str> {
+        let descriptor_set = self.allocate_texture_set()?;
+        let sampler_table = match BufferBundle::new(
+            &self._adapter,
+            self.device.deref(),
+            SAMPLER_TABLE_SIZE,
+            BufferUsage::UNIFORM,
+        ) {
+            Ok(sampler_table) => sampler_table,
+            Err(e) => {
+                // the set always comes out of the newest pool
+                if let Some(pool) = self.texture_pool.descriptor_pools.last_mut() {
+                    unsafe { pool.free_sets(Some(descriptor_set)) };
+                }
+                return Err(e);
+            }
+        };
+        unsafe {
+            self.device
+                .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
+                    set: &descriptor_set,
+                    binding: 2,
+                    array_offset: 0,
+                    descriptors: Some(gfx_hal::pso::Descriptor::Buffer(
+                        &*sampler_table.buffer,
+                        None..None,
+                    )),
+                }));
+            if !self.texture_pool.samplers.is_empty() {
+                self.device
+                    .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
+                        set: &descriptor_set,
+                        binding: 1,
+                        array_offset: 0,
+                        descriptors: self
+                            .texture_pool
+                            .samplers
+                            .iter()
+                            .map(|(_, sampler)| gfx_hal::pso::Descriptor::Sampler(sampler.deref())),
+                    }));
+            }
+        }
+        self.texture_pool.descriptor_sets.push(descriptor_set);
+        self.texture_pool.sampler_tables.push(sampler_table);
+        Ok(())
+    }
+
+    /// Where `desc` is in the samplers every set has bound, creating it if it's new
+    fn sampler_index(&mut self, desc: SamplerDesc) -> Result<usize, &'static str> {
+        if let Some(index) = self
+            .texture_pool
+            .samplers
+            .iter()
+            .position(|&(existing, _)| existing == desc)
+        {
+            return Ok(index);
+        }
+        if self.texture_pool.samplers.len() == MAX_SAMPLERS {
+            return Err("There are already MAX_SAMPLERS different samplers!");
+        }
+        let anisotropy_supported = self
+            ._adapter
+            .physical_device
+            .features()
+            .contains(gfx_hal::Features::SAMPLER_ANISOTROPY);
+        let sampler = unsafe {
+            self.device
+                .create_sampler(desc.info(anisotropy_supported))
+                .map_err(|_| "Couldn't create the sampler!")?
+        };
+        let index = self.texture_pool.samplers.len();
+        for descriptor_set in &self.texture_pool.descriptor_sets {
+            unsafe {
+                self.device
+                    .write_descriptor_sets(Some(gfx_hal::pso::DescriptorSetWrite {
+                        set: descriptor_set,
+                        binding: 1,
+                        array_offset: index,
+                        descriptors: Some(gfx_hal::pso::Descriptor::Sampler(&sampler)),
+                    }));
+            }
+        }
+        debug!(self.logger, "created sampler"; "index" => index, "sampler" => debug_repr!(desc));
+        self.texture_pool
+            .samplers
+            .push((desc, ManuallyDrop::new(sampler)));
+        Ok(index)
+    }
+
+    /// Copies which sampler each texture in set `set_index` uses over to the GPU
+    fn write_sampler_table(&mut self, set_index: usize) -> Result<(), &'static str> {
+        let descriptor_size = self.texture_pool.descriptor_size;
+        let samplers = &self.texture_pool.texture_samplers;
+        let start = set_index * descriptor_size;
+        let end = samplers.len().min(start + descriptor_size);
+        unsafe {
+            self.texture_pool.sampler_tables[set_index]
+                .write(self.device.deref(), &samplers[start..end])
+        }
+    }
+
+    /// Allocates a set for `load_texture`, chaining on a new descriptor pool once the current ones are full
+    fn allocate_texture_set(
+        &mut self,
+    ) -> Result<<back::Backend as Backend>::DescriptorSet, &'static str> {
+        let layout = &self.texture_pool.descriptor_set_layouts[0];
+        if let Some(pool) = self.texture_pool.descriptor_pools.last_mut() {
+            if let Ok(set) = unsafe { pool.allocate_set(layout) } {
+                return Ok(set);
+            }
+        }
+        info!(self.logger, "descriptor pool is full, creating another one";
+              "num_pools" => self.texture_pool.descriptor_pools.len());
+        let mut pool = unsafe {
+            create_descriptor_pool(
+                &self.device,
+                self.texture_pool.pool_size,
+                self.texture_pool.descriptor_size,
+            )?
+        };
+        let set = unsafe { pool.allocate_set(layout) };
+        self.texture_pool.descriptor_pools.push(pool);
+        set.map_err(|_| "Couldn't make a descriptor set!")
+    }
+
+    /// How many textures can be drawn with, textures loaded past this go into descriptor sets that don't
+    /// get bound yet. Depends on the backend, see `max_textures_per_set`
+    pub fn max_textures(&self) -> usize {
+        self.texture_pool.descriptor_size
+    }
+
+    pub fn camera(&self) -> &Camera2D {
+        &self.camera
+    }
+
+    pub fn set_camera(&mut self, camera: Camera2D) {
+        self.camera = camera;
+    }
+
+    /// Turns the CRT effect on or off, or changes its settings. It doesn't work together with
+    /// multisampling
+    pub fn set_crt_effect(&mut self, effect: Option<CrtEffect>) -> Result<(), &'static str> {
+        if effect.is_some() && self.post_process.is_none() {
+            if self
+                .sampling_config
+                .multisampling
+                .map_or(false, |samples| samples > 1)
+            {
+                return Err("The CRT effect doesn't work with multisampling!");
+            }
+            let (vertex_spirv, fragment_spirv) = crt_spirv(&self.logger)?;
+            let extent = Extent2D {
+                width: self.render_area.w as u32,
+                height: self.render_area.h as u32,
+            };
+            unsafe {
+                let mut post_process = PostProcess::<back::Backend, back::Device>::new(
+                    &*self.device,
+                    self.format,
+                    &vertex_spirv,
+                    &fragment_spirv,
+                )?;
+                if let Err(e) =
+                    post_process.resize(&self._adapter, &self.device, extent, &self.image_views)
+                {
+                    post_process.destroy(&self.device);
+                    return Err(e);
+                }
+                self.post_process = Some(post_process);
+            }
+        } else if effect.is_none() {
+            if let Some(post_process) = self.post_process.take() {
+                let _ = self.device.wait_idle();
+                unsafe { post_process.destroy(&self.device) };
+            }
+        }
+        self.crt_effect = effect;
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    pub fn crt_effect(&self) -> Option<CrtEffect> {
+        self.crt_effect
+    }
+
+    /// Makes a pipeline out of your own SPIR-V shaders that takes vertices laid out like
+    /// `vertex_format`, draw with it through `Frame::draw_custom`. The shaders get the same
+    /// descriptor set and specialization constants as the built in ones, plus the batch's view
+    /// matrix as a `mat3` push constant in the vertex stage
+    pub fn create_custom_pipeline(
+        &mut self,
+        vertex_spirv: &[u8],
+        fragment_spirv: &[u8],
+        vertex_format: VertexFormat,
+    ) -> Result<CustomPipelineId, &'static str> {
+        let (vertex_shader_module, fragment_shader_module) = unsafe {
+            let vertex_shader_module = self
+                .device
+                .create_shader_module(vertex_spirv)
+                .map_err(|_| "Couldn't make the custom vertex module!")?;
+            match self.device.create_shader_module(fragment_spirv) {
+                Ok(module) => (vertex_shader_module, module),
+                Err(_) => {
+                    self.device.destroy_shader_module(vertex_shader_module);
+                    return Err("Couldn't make the custom fragment module!");
+                }
+            }
+        };
+        let id = CustomPipelineId(self.custom_pipelines.len());
+        self.custom_pipelines.push(CustomPipeline {
+            vertex_format,
+            vertex_shader_module,
+            fragment_shader_module,
+        });
+        // make the default one right away so broken shaders fail here instead of in draw_frame
+        let key = PipelineKey {
+            blend_mode: BlendMode::default(),
+            wireframe: false,
+            overdraw: false,
+            shader_config: self.scene_shader_config(),
+            material: 0,
+            custom: Some(id),
+            offscreen: self.post_process.is_some(),
+        };
+        if let Err(e) = self.ensure_pipeline(key) {
+            if let Some(custom) = self.custom_pipelines.pop() {
+                unsafe {
+                    self.device
+                        .destroy_shader_module(custom.vertex_shader_module);
+                    self.device
+                        .destroy_shader_module(custom.fragment_shader_module);
+                }
+            }
+            return Err(e);
+        }
+        info!(self.logger, "created a custom pipeline"; "id" => id.0,
+              "vertex_format" => debug_repr!(self.custom_pipelines[id.0].vertex_format));
+        Ok(id)
+    }
+
+    /// Uploads geometry that stays on the GPU, drawn with `Frame::draw_indirect` by running
+    /// `commands` out of a GPU buffer. Meant for big static stuff like tilemaps where writing the
+    /// vertices every frame would be a waste. Like `Frame::draw_custom` the vertices aren't touched
+    /// on the CPU, so it needs a custom pipeline that takes `V` and applies the view matrix itself
+    pub fn create_indirect_mesh<V: VertexLayout>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u16],
+        commands: &[IndirectDraw],
+    ) -> Result<IndirectMeshId, &'static str> {
+        let mesh = IndirectMesh::new(&self._adapter, &*self.device, vertices, indices, commands)?;
+        let id = IndirectMeshId(self.indirect_meshes.len());
+        self.indirect_meshes.push(mesh);
+        info!(self.logger, "created an indirect mesh"; "id" => id.0,
+              "vertices" => vertices.len(), "indices" => indices.len(), "commands" => commands.len());
+        Ok(id)
+    }
+
+    /// Replaces the draw commands of an indirect mesh, the geometry stays the same. This waits for
+    /// the GPU to go idle, so it's for the occasional change and not for every frame
+    pub fn update_indirect_commands(
+        &mut self,
+        mesh: IndirectMeshId,
+        commands: &[IndirectDraw],
+    ) -> Result<(), &'static str> {
+        if mesh.0 >= self.indirect_meshes.len() {
+            return Err("That indirect mesh doesn't exist!");
+        }
+        self.device
+            .wait_idle()
+            .map_err(|_| "Couldn't wait for the device to idle!")?;
+        unsafe {
+            self.indirect_meshes[mesh.0].write_commands(&self._adapter, &*self.device, commands)?;
+        }
+        // the number of draws is baked into the command buffers, and the buffer might be a new one
+        self.invalidate_command_buffers();
+        Ok(())
+    }
+
+    /// Starts copying every presented frame back to the CPU and handing it to `callback`, for
+    /// recording video. Frames come out a few frames late (however many are in flight) so rendering
+    /// doesn't have to wait on the copies. Replaces the callback if a capture is already running
+    pub fn start_capture<F: FnMut(CapturedFrame) + 'static>(
+        &mut self,
+        callback: F,
+    ) -> Result<(), &'static str> {
+        let (caps, _, _, _) = self.surface.compatibility(&self._adapter.physical_device);
+        if !caps.usage.contains(Usage::TRANSFER_SRC) {
+            return Err("The swapchain images can't be copied from!");
+        }
+        self.stop_capture()?;
+        let extent = Extent2D {
+            width: self.render_area.w as u32,
+            height: self.render_area.h as u32,
+        };
+        let capture = FrameCapture::new(
+            &self._adapter,
+            &*self.device,
+            self.frames_in_flight,
+            extent,
+            self.format,
+            Box::new(callback),
+        )?;
+        self.capture = Some(capture);
+        self.invalidate_command_buffers();
+        info!(self.logger, "started capturing frames"; "width" => extent.width, "height" => extent.height);
+        Ok(())
+    }
+
+    /// Stops capturing, the frames still in flight get handed to the callback before this returns
+    pub fn stop_capture(&mut self) -> Result<(), &'static str> {
+        if let Some(mut capture) = self.capture.take() {
+            let _ = self.device.wait_idle();
+            let result = unsafe { capture.flush(&self.device) };
+            unsafe { capture.destroy(&self.device) };
+            self.invalidate_command_buffers();
+            info!(self.logger, "stopped capturing frames");
+            result?;
+        }
+        Ok(())
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Shakes the camera, and any cameras set on a `Frame`, for a while. Runs on wall clock time
+    /// from the next `draw_frame` on. `Frame::apply_shake` does the same from inside a frame
+    pub fn apply_shake(&mut self, params: ShakeParams) {
+        self.shake.add(params);
+    }
+
+    pub fn stop_shake(&mut self) {
+        self.shake.clear();
+        self.last_shake_update = None;
+    }
+
+    pub fn screen_shake(&self) -> &ScreenShake {
+        &self.shake
+    }
+
+    /// When enabled, quads that end up entirely off screen after the camera transform are skipped
+    /// before they're written into the vertex buffer
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling;
+    }
+
+    pub fn culling(&self) -> bool {
+        self.culling
+    }
+
+    pub fn debug_draw(&self) -> DebugDraw {
+        self.debug_draw
+    }
+
+    pub fn set_debug_draw(&mut self, debug_draw: DebugDraw) {
+        self.debug_draw = debug_draw;
+    }
+
+    pub fn premultiply_alpha(&self) -> bool {
+        self.premultiply_alpha
+    }
+
+    /// Multiplies the color of textures by their alpha when they get loaded or updated, for drawing
+    /// them with `BlendMode::PremultipliedAlpha`. Unlike `ShaderConfig::premultiply_alpha` this
+    /// happens before filtering, so transparent texels don't bleed their color into the edges. Only
+    /// affects textures loaded after this, and shouldn't be combined with the shader version
+    pub fn set_premultiply_alpha(&mut self, premultiply_alpha: bool) {
+        self.premultiply_alpha = premultiply_alpha;
+    }
+
+    /// With post processing the scene goes into an sRGB image no matter what the swapchain is, and
+    /// the conversion happens in the effect's pass instead
+    fn scene_shader_config(&self) -> ShaderConfig {
+        match self.post_process {
+            Some(_) => ShaderConfig {
+                srgb_conversion: false,
+                ..self.shader_config
+            },
+            None => self.shader_config,
+        }
+    }
+
+    /// Clear colors skip the fragment shader, so they need the sRGB conversion done here
+    fn encode_clear_color(shader_config: ShaderConfig, color: [f32; 4]) -> [f32; 4] {
+        if shader_config.srgb_conversion {
+            let [r, g, b, a] = color;
+            [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a]
+        } else {
+            color
+        }
+    }
+
+    pub fn shader_config(&self) -> ShaderConfig {
+        self.shader_config
+    }
+
+    /// Takes effect on the next frame, pipelines for configs that haven't been used yet get created then
+    pub fn set_shader_config(&mut self, shader_config: ShaderConfig) {
+        self.shader_config = shader_config;
+    }
+
+    /// When enabled, frames that are exactly the same as the last one reuse the command buffers that
+    /// were already recorded for them instead of uploading and recording everything again. Costs a
+    /// hash of the whole frame, so it's only worth it when the scene is mostly static
+    pub fn set_reuse_command_buffers(&mut self, reuse: bool) {
+        self.reuse_command_buffers = reuse;
+        self.invalidate_command_buffers();
+    }
+
+    pub fn reuse_command_buffers(&self) -> bool {
+        self.reuse_command_buffers
+    }
+
+    /// Needed whenever something a recorded command buffer points to gets replaced
+    fn invalidate_command_buffers(&mut self) {
+        for recorded in &mut self.recorded_frames {
+            *recorded = None;
+        }
+    }
+
+    /// Everything that goes into recording a frame besides the frame itself
+    fn frame_hash(&self, frame: &Frame, shake: &ShakeOffset) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        frame.hash_contents(&mut hasher);
+        frame::hash_floats(&[shake.offset.x, shake.offset.y, shake.zoom], &mut hasher);
+        shake.rotation.to_bits().hash(&mut hasher);
+        frame::hash_floats(
+            &[
+                self.camera.position.x,
+                self.camera.position.y,
+                self.camera.zoom,
+            ],
+            &mut hasher,
+        );
+        self.camera.rotation.to_bits().hash(&mut hasher);
+        self.culling.hash(&mut hasher);
+        self.debug_draw.wireframe.hash(&mut hasher);
+        self.debug_draw.overdraw.hash(&mut hasher);
+        self.shader_config.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// What `EventHandler::on_resize` should call when there's no `Context` doing it. Fits the
+    /// swapchain to the window again and gives back the size it actually ended up with
+    pub fn handle_resize(&mut self, window: &winit::Window) -> Result<DrawableSize, &'static str> {
+        self.recreate_swapchain(window)?;
+        Ok(self.swapchain_info().drawable_size())
+    }
+
+    /// Makes a new swapchain to fit the window, call this when it gets resized or its hidpi factor
+    /// changes. Fails while the window is minimized, just keep calling it until it works again.
+    /// If this fails for any other reason the HalState can't draw anymore and should be recreated
+    pub fn recreate_swapchain(&mut self, window: &winit::Window) -> Result<(), &'static str> {
+        let window_size = window
+            .get_inner_size()
+            .ok_or("Window doesn't exist!")?
+            .to_physical(window.get_hidpi_factor());
+        // minimized windows can report 0x0, which is never a valid extent
+        if window_size.width < 1.0 || window_size.height < 1.0 {
+            return Err("The window has no area to draw to!");
+        }
+
+        let _ = self.device.wait_idle();
+        if let Some(capture) = self.capture.as_mut() {
+            unsafe { capture.flush(&self.device)? };
+        }
+        let (swapchain, extent, backbuffer, format, _, present_mode) = create_swapchain(
+            &self._adapter,
+            &self.device,
+            &mut self.surface,
+            window,
+            &self.preferred_vsync,
+            Some(self.format),
+            &mut self.swapchain,
+            &self.logger,
+        )?;
+        self.swapchain = Some(swapchain);
+        unsafe {
+            for framebuffer in self.framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer);
+            }
+            for image_view in self.image_views.drain(..) {
+                self.device.destroy_image_view(image_view);
+            }
+            let (swapchain_images, image_views, framebuffers) =
+                create_framebuffers(&self.device, &self.render_pass, backbuffer, format, extent)?;
+            self.swapchain_images = swapchain_images;
+            self.drawn_images = vec![false; self.swapchain_images.len()];
+            self.image_views = image_views;
+            self.framebuffers = framebuffers;
+            if let Some(post_process) = self.post_process.as_mut() {
+                post_process.resize(&self._adapter, &self.device, extent, &self.image_views)?;
+            }
+            if let Some(mut capture) = self.capture.take() {
+                // a capture with half its staging buffers would break every frame after this
+                match capture.resize(&self._adapter, &self.device, extent) {
+                    Ok(()) => self.capture = Some(capture),
+                    Err(e) => {
+                        capture.destroy(&self.device);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        self.render_area = extent.to_extent().rect();
+        if let Some(render_targets) = self.render_targets.as_ref() {
+            for index in render_targets.indices() {
+                self.resize_render_target(index)?;
+            }
+        }
+        self.present_mode = present_mode;
+        self.hidpi_factor = window.get_hidpi_factor();
+        self.invalidate_command_buffers();
+        info!(self.logger, "recreated the swapchain"; "width" => extent.width, "height" => extent.height,
+              "hidpi_factor" => self.hidpi_factor);
+        Ok(())
+    }
+
+    /// Size of what's being drawn to in physical pixels
+    pub fn drawable_size(&self) -> PhysicalSize {
+        PhysicalSize::new(f64::from(self.render_area.w), f64::from(self.render_area.h))
+    }
+
+    /// The hidpi factor the swapchain was last made with
+    pub fn hidpi_factor(&self) -> f64 {
+        self.hidpi_factor
+    }
+
+    pub fn to_physical(&self, position: LogicalPosition) -> PhysicalPosition {
+        position.to_physical(self.hidpi_factor)
+    }
+
+    pub fn to_logical(&self, position: PhysicalPosition) -> LogicalPosition {
+        position.to_logical(self.hidpi_factor)
+    }
+
+    pub fn swapchain_format(&self) -> Format {
+        self.format
+    }
+
+    /// False when the surface didn't offer an sRGB format. The fragment shader and clear colors then
+    /// do the conversion so colors look the same, but custom pipelines have to do it themselves (and
+    /// can check `ShaderConfig::srgb_conversion` for it)
+    pub fn srgb_surface(&self) -> bool {
+        is_srgb(self.format)
+    }
+
+    /// The present mode that actually got picked out of the preferred ones, for warning about vsync
+    /// being forced on when Mailbox and Immediate aren't supported. It's Fifo when none of them were
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    pub fn device_info(&self) -> DeviceInfo {
+        let info = &self._adapter.info;
+        DeviceInfo {
+            backend: BACKEND_NAME,
+            adapter_name: info.name.clone(),
+            vendor: info.vendor,
+            device: info.device,
+            device_type: format!("{:?}", info.device_type),
+            swapchain: self.swapchain_info(),
+            max_textures: self.max_textures(),
+        }
+    }
+
+    pub fn swapchain_info(&self) -> SwapchainInfo {
+        SwapchainInfo {
+            width: self.render_area.w as u32,
+            height: self.render_area.h as u32,
+            format: self.format,
+            image_count: self.swapchain_images.len(),
+            present_mode: self.present_mode,
+            frames_in_flight: self.frames_in_flight,
+            hidpi_factor: self.hidpi_factor,
+        }
+    }
+
+    /// Asks the driver and the window surface what they support. It's not cached, so get it once
+    /// for a settings menu instead of every frame
+    pub fn capabilities(&self) -> Capabilities {
+        let physical_device = &self._adapter.physical_device;
+        let limits = physical_device.limits();
+        let (caps, surface_formats, present_modes, _) = self.surface.compatibility(physical_device);
+        let names = |formats: Vec<Format>| {
+            formats
+                .iter()
+                .map(|format| format!("{:?}", format))
+                .collect::<Vec<_>>()
+        };
+        let texture_formats = REPORTED_FORMATS
+            .iter()
+            .cloned()
+            .filter(|&format| {
+                physical_device
+                    .format_properties(Some(format))
+                    .optimal_tiling
+                    .contains(ImageFeature::SAMPLED)
+            })
+            .collect();
+        Capabilities {
+            backend: BACKEND_NAME,
+            adapter_name: self._adapter.info.name.clone(),
+            device_type: format!("{:?}", self._adapter.info.device_type),
+            max_texture_size: limits.max_texture_size,
+            max_texture_array_size: self.max_textures(),
+            max_viewports: limits.max_viewports,
+            max_color_attachments: limits.max_color_attachments,
+            texture_formats: names(texture_formats),
+            surface_formats: surface_formats.map(names),
+            present_modes: present_modes
+                .iter()
+                .map(|mode| format!("{:?}", mode))
+                .collect(),
+            swapchain_images: (caps.image_count.start, caps.image_count.end),
+            memory_heaps: physical_device.memory_properties().memory_heaps,
+        }
+    }
+
+    /// Guesses the monitor's refresh rate in Hz from how fast frames get presented. winit 0.18 can't
+    /// ask the monitor, so this only works with vsync'd present modes, once a few frames have been
+    /// drawn, and only if rendering keeps up with the display
+    pub fn estimated_refresh_rate(&self) -> Option<f64> {
+        match self.present_mode {
+            PresentMode::Fifo | PresentMode::Relaxed => {
+                let interval = self.frame_timer.frame_interval();
+                let secs = interval.as_secs_f64();
+                if secs > 0.0 {
+                    Some(1.0 / secs)
+                } else {
+                    None
+                }
+            }
+            PresentMode::Mailbox | PresentMode::Immediate => None,
+        }
+    }
+
+    /// Stats for the last frame that got presented
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// How many quads got culled in the last call to `draw_quad_frame`
+    pub fn culled_quads(&self) -> usize {
+        self.culled_quads
+    }
+
+    pub fn extend_quad_alloc(&mut self, new_max: usize) -> Result<(), &'static str> {
+        if new_max > self.num_quads {
+            info!(&self.logger, "extending quad vertex/index buffer size"; "new_size" => new_max);
+
+            unsafe {
+                let new_vertices = StreamBuffer::new(
+                    &self._adapter,
+                    self.device.deref(),
+                    QUAD_SIZE * new_max,
+                    self.frames_in_flight,
                     BufferUsage::VERTEX,
                 )?;
-                const U16_QUAD_INDICES: usize = mem::size_of::<u16>() * 2 * 3;
+                let index_type = quad_index_type(new_max);
+                if index_type != self.index_type {
+                    info!(&self.logger, "promoting quad index buffer"; "index_type" => debug_repr!(index_type));
+                }
                 let new_indexes = {
                     let res = BufferBundle::new(
                         &self._adapter,
                         self.device.deref(),
-                        U16_QUAD_INDICES * new_max,
+                        quad_index_size(index_type) * new_max,
                         BufferUsage::INDEX,
                     );
                     if res.is_err() {
@@ -709,79 +2142,78 @@ impl HalState {
                     }
                     res?
                 };
-                let mut data_target = {
-                    let res = self
-                        .device
-                        .acquire_mapping_writer(
-                            &new_indexes.memory,
-                            0..new_indexes.requirements.size,
-                        )
-                        .map_err(|_| "Failed to require an index buffer mapping writer!");
-                    if res.is_err() {
-                        new_vertices.manually_drop(&self.device);
-                        new_indexes.manually_drop(&self.device);
-                    }
-                    res?
-                };
-                const INDEX_DATA: &[u16] = &[0, 1, 2, 2, 3, 0];
-                for i in 0..new_max {
-                    let stride: usize = 6;
-                    let vertex_stride = 4;
-                    let index_data: &[u16] = &[
-                        i as u16 * vertex_stride + INDEX_DATA[0],
-                        i as u16 * vertex_stride + INDEX_DATA[1],
-                        i as u16 * vertex_stride + INDEX_DATA[2],
-                        i as u16 * vertex_stride + INDEX_DATA[3],
-                        i as u16 * vertex_stride + INDEX_DATA[4],
-                        i as u16 * vertex_stride + INDEX_DATA[5],
-                    ];
-                    data_target[stride * i..stride * (i + 1)].copy_from_slice(&index_data);
-                }
-                if let Err(_) = self.device.release_mapping_writer(data_target) {
+                if let Err(e) = write_quad_indices(&self.device, &new_indexes, new_max, index_type)
+                {
                     new_vertices.manually_drop(&self.device);
                     new_indexes.manually_drop(&self.device);
-                    return Err("Couldn't release the index buffer mapping writer!");
+                    return Err(e);
                 }
-                let old_vertex_buffer = mem::replace(&mut self.vertices, new_vertices);
-                let old_index_buffer = mem::replace(&mut self.indexes, new_indexes);
-                old_vertex_buffer.manually_drop(&self.device);
-                old_index_buffer.manually_drop(&self.device);
+                // the old buffers might still be in use by frames in flight
+                let _ = self.device.wait_idle();
+                mem::replace(&mut self.vertices, new_vertices).manually_drop(&self.device);
+                mem::replace(&mut self.indexes, new_indexes).manually_drop(&self.device);
+                self.index_type = index_type;
                 self.num_quads = new_max;
             }
+            self.invalidate_command_buffers();
         }
         Ok(())
     }
 
-    pub fn draw_clear_frame(&mut self, color: [f32; 4]) -> Result<(), &'static str> {
-        // FRAME SETUP
-        let image_available = &self.image_available_semaphores[self.current_frame];
-        let render_finished = &self.render_finished_semaphores[self.current_frame];
+    fn extend_mesh_index_alloc(&mut self, new_max: usize) -> Result<(), &'static str> {
+        info!(&self.logger, "extending mesh index buffer size"; "new_size" => new_max);
+        let new_mesh_indexes = StreamBuffer::new(
+            &self._adapter,
+            self.device.deref(),
+            mem::size_of::<u16>() * new_max,
+            self.frames_in_flight,
+            BufferUsage::INDEX,
+        )?;
+        unsafe {
+            // the old buffers might still be in use by frames in flight
+            let _ = self.device.wait_idle();
+            mem::replace(&mut self.mesh_indexes, new_mesh_indexes).manually_drop(&self.device);
+        }
+        self.mesh_index_capacity = new_max;
+        self.invalidate_command_buffers();
+        Ok(())
+    }
 
+    pub fn draw_clear_frame(&mut self, color: [f32; 4]) -> Result<FrameStats, &'static str> {
+        self.finish_texture_loads();
+        let frame_start = Instant::now();
         // advance the frame before early returns can happen
+        let frame_index = self.current_frame;
         self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
 
+        // FRAME SETUP
+        let wait_start = Instant::now();
+        {
+            let _wait = profile::scope("gpu_wait");
+            self.wait_for_frame(frame_index)?;
+        }
+        let gpu_wait = wait_start.elapsed();
+        self.collect_capture(frame_index)?;
+        let image_available = &self.image_available_semaphores[frame_index];
+        let render_finished = &self.render_finished_semaphores[frame_index];
+        let flight_fence = &self.in_flight_fences[frame_index];
         let (i_u32, i_usize) = unsafe {
             let image_index = self
                 .swapchain
+                .as_mut()
+                .ok_or("There's no swapchain!")?
                 .acquire_image(core::u64::MAX, FrameSync::Semaphore(image_available))
                 .map_err(|_| "Couldn't acquire an image from the swapchain!")?;
             (image_index, image_index as usize)
         };
-
-        let flight_fence = &self.in_flight_fences[i_usize];
-        unsafe {
-            self.device
-                .wait_for_fence(flight_fence, core::u64::MAX)
-                .map_err(|_| "Failed to wait on the fence!")?;
-            self.device
-                .reset_fence(flight_fence)
-                .map_err(|_| "Couldn't reset fence!")?;
-        }
+        let acquired = Instant::now();
 
         // Record commands
         unsafe {
-            let buffer = &mut self.command_buffers[i_usize];
-            let clear_values = [ClearValue::Color(ClearColor::Float(color))];
+            let clear_values = [ClearValue::Color(ClearColor::Float(
+                Self::encode_clear_color(self.shader_config, color),
+            ))];
+            let buffer = &mut self.command_buffers[frame_index];
             buffer.begin(false);
             buffer.begin_render_pass_inline(
                 &self.render_pass,
@@ -789,11 +2221,23 @@ impl HalState {
                 self.render_area,
                 clear_values.iter(),
             );
+            self.drawn_images[i_usize] = true;
+            if let Some(capture) = self.capture.as_ref() {
+                capture.record(buffer, frame_index, &self.swapchain_images[i_usize]);
+            }
             buffer.finish();
         }
+        self.recorded_frames[frame_index] = None;
+
+        let cpu_record_time = acquired.elapsed();
 
         // Submission
-        let command_buffers = &self.command_buffers[i_usize..=i_usize];
+        unsafe {
+            self.device
+                .reset_fence(flight_fence)
+                .map_err(|_| "Couldn't reset fence!")?;
+        }
+        let command_buffers = &self.command_buffers[frame_index..=frame_index];
         let wait_semaphores: ArrayVec<[_; 1]> =
             [(image_available, PipelineStage::COLOR_ATTACHMENT_OUTPUT)].into();
         let signal_semaphores: ArrayVec<[_; 1]> = [render_finished].into();
@@ -804,111 +2248,640 @@ impl HalState {
             wait_semaphores,
             signal_semaphores,
         };
+        let stats = FrameStats {
+            gpu_wait,
+            cpu_record_time,
+            ..FrameStats::default()
+        };
+        if let Some(capture) = self.capture.as_mut() {
+            capture.submitted(frame_index);
+        }
         let the_command_queue = &mut self.queue_group.queues[0];
-        unsafe {
-            the_command_queue.submit(submission, Some(flight_fence));
-            self.swapchain
+        let swapchain = self.swapchain.as_ref().ok_or("There's no swapchain!")?;
+        let result = unsafe {
+            {
+                let _submit = profile::scope("submit");
+                the_command_queue.submit(submission, Some(flight_fence));
+            }
+            let _present = profile::scope("present");
+            swapchain
                 .present(the_command_queue, i_u32, present_wait_semaphores)
                 .map_err(|_| "Failed to present into the swapchain!")
+        };
+        self.frame_stats = self.frame_timer.presented(
+            stats,
+            frame_start,
+            acquired,
+            self.present_mode,
+            self.frames_in_flight,
+        );
+        result.map(|()| self.frame_stats)
+    }
+
+    /// The scene either goes straight to the swapchain image or through the post processing target,
+    /// and captures copy out of the swapchain image once everything else is done with it. The
+    /// render passes and `FrameCapture` still do their own barriers
+    fn frame_passes(&self) -> Result<Vec<EnginePass>, &'static str> {
+        let mut graph = FrameGraph::new();
+        let swapchain_image = graph.import_target();
+        let scene = match self.post_process {
+            Some(_) => graph.create_target(TargetDesc {
+                width: self.render_area.w as u32,
+                height: self.render_area.h as u32,
+                format: OFFSCREEN_FORMAT,
+            }),
+            None => swapchain_image,
+        };
+        graph.add_pass(EnginePass::Scene, &[(scene, Access::ColorWrite)]);
+        if self.post_process.is_some() {
+            graph.add_pass(
+                EnginePass::PostProcess,
+                &[
+                    (scene, Access::ShaderRead),
+                    (swapchain_image, Access::ColorWrite),
+                ],
+            );
+        }
+        if self.capture.is_some() {
+            let readback = graph.import_target();
+            graph.add_pass(
+                EnginePass::Capture,
+                &[
+                    (swapchain_image, Access::TransferRead),
+                    (readback, Access::TransferWrite),
+                ],
+            );
+        }
+        Ok(graph.compile()?.into_passes())
+    }
+
+    /// Waits until the last submission using this frame's resources is done with them. The fence only
+    /// gets reset right before submitting, so an early return can't leave it unsignaled forever
+    fn wait_for_frame(&self, frame_index: usize) -> Result<(), &'static str> {
+        unsafe {
+            self.device
+                .wait_for_fence(&self.in_flight_fences[frame_index], core::u64::MAX)
+                .map_err(|_| "Failed to wait on the fence!")
         }
     }
 
-    pub fn draw_quad_frame(&mut self, textured_quads: &[TexturedQuad]) -> Result<(), &'static str> {
+    /// Hands whatever this frame captured last time around to the capture callback, only call this
+    /// after `wait_for_frame`
+    fn collect_capture(&mut self, frame_index: usize) -> Result<(), &'static str> {
+        match self.capture.as_mut() {
+            Some(capture) => unsafe { capture.collect(&self.device, frame_index) },
+            None => Ok(()),
+        }
+    }
+
+    pub fn draw_quad_frame(
+        &mut self,
+        textured_quads: &[TexturedQuad],
+    ) -> Result<FrameStats, &'static str> {
+        let mut frame = Frame::new();
+        frame.draw_quads(textured_quads);
+        self.draw_frame(&frame)
+    }
+
+    /// Draws a frame containing just the one mesh, see `Frame::draw_mesh`
+    pub fn draw_mesh(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u16],
+        texture: TextureId,
+    ) -> Result<FrameStats, &'static str> {
+        let mut frame = Frame::new();
+        frame.draw_mesh(vertices, indices, texture);
+        self.draw_frame(&frame)
+    }
+
+    pub fn draw_frame(&mut self, frame: &Frame) -> Result<FrameStats, &'static str> {
+        self.finish_texture_loads();
+        let frame_start = Instant::now();
+        if let Some(last_update) = self.last_shake_update {
+            self.shake.advance(frame_start - last_update);
+        }
+        for &params in &frame.shakes {
+            self.shake.add(params);
+        }
+        self.last_shake_update = if self.shake.is_shaking() {
+            Some(frame_start)
+        } else {
+            None
+        };
+        let shake = self.shake.offset();
         // advance the frame before early returns can happen
+        let frame_index = self.current_frame;
         self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
 
-        if self.num_quads <= textured_quads.len() {
-            self.extend_quad_alloc(textured_quads.len())?;
+        // mesh vertices live in the same buffer as the quads, custom vertices go after both
+        let custom_base = custom_vertex_base(frame);
+        let required_quads =
+            (custom_base + frame.custom_vertices.len() + QUAD_SIZE - 1) / QUAD_SIZE;
+        if self.num_quads < required_quads {
+            self.extend_quad_alloc(required_quads.next_power_of_two())?;
+        }
+        if self.mesh_index_capacity < frame.indices.len() {
+            self.extend_mesh_index_alloc(frame.indices.len().next_power_of_two())?;
+        }
+        let wireframe = self.debug_draw.wireframe;
+        let overdraw = self.debug_draw.overdraw;
+        let shader_config = self.scene_shader_config();
+        let offscreen = self.post_process.is_some();
+        // render targets are sRGB images, so they get the same treatment as post processing
+        let target_config = ShaderConfig {
+            srgb_conversion: false,
+            ..self.shader_config
+        };
+        // in the order they get drawn, the screen always goes last
+        let mut pass_targets = Vec::new();
+        for batch in &frame.batches {
+            let custom = match batch.kind {
+                BatchKind::Custom { pipeline, .. } => Some(pipeline),
+                BatchKind::Indirect { pipeline, mesh } => {
+                    if mesh.0 >= self.indirect_meshes.len() {
+                        return Err("That indirect mesh doesn't exist!");
+                    }
+                    Some(pipeline)
+                }
+                _ => None,
+            };
+            if self.atlas.is_some() && batch.state.material.texture().is_some() {
+                return Err("Materials don't work with the texture atlas!");
+            }
+            let target = batch.state.target;
+            if let Some(texture) = target {
+                let is_target = self
+                    .render_targets
+                    .as_ref()
+                    .map_or(false, |render_targets| {
+                        render_targets
+                            .framebuffer(texture.index() as usize)
+                            .is_some()
+                    });
+                if !is_target {
+                    return Err("That texture isn't a render target!");
+                }
+            }
+            if !pass_targets.contains(&target) {
+                pass_targets.push(target);
+            }
+            let (shader_config, offscreen) = match target {
+                Some(_) => (target_config, true),
+                None => (shader_config, offscreen),
+            };
+            self.ensure_pipeline(PipelineKey {
+                blend_mode: batch.state.blend_mode,
+                wireframe,
+                overdraw,
+                shader_config,
+                material: material_mode(batch.state.material, custom),
+                custom,
+                offscreen,
+            })?;
+        }
+        // the screen gets its pass even when there's nothing to draw, for the clear
+        pass_targets.retain(Option::is_some);
+        pass_targets.push(None);
+        if self.debug_draw.log_batches {
+            self.log_batches(frame);
         }
 
         // FRAME SETUP
-        let image_available = &self.image_available_semaphores[self.current_frame];
-        let render_finished = &self.render_finished_semaphores[self.current_frame];
-
+        let wait_start = Instant::now();
+        {
+            let _wait = profile::scope("gpu_wait");
+            self.wait_for_frame(frame_index)?;
+        }
+        let gpu_wait = wait_start.elapsed();
+        self.collect_capture(frame_index)?;
+        let image_available = &self.image_available_semaphores[frame_index];
+        let render_finished = &self.render_finished_semaphores[frame_index];
+        let flight_fence = &self.in_flight_fences[frame_index];
         let (i_u32, i_usize) = unsafe {
             let image_index = self
                 .swapchain
+                .as_mut()
+                .ok_or("There's no swapchain!")?
                 .acquire_image(core::u64::MAX, FrameSync::Semaphore(image_available))
                 .map_err(|_| "Couldn't acquire an image from the swapchain!")?;
             (image_index, image_index as usize)
         };
+        let acquired = Instant::now();
 
-        let flight_fence = &self.in_flight_fences[i_usize];
-        unsafe {
-            self.device
-                .wait_for_fence(flight_fence, core::u64::MAX)
-                .map_err(|_| "Failed to wait on the fence!")?;
-            self.device
-                .reset_fence(flight_fence)
-                .map_err(|_| "Couldn't reset fence!")?;
-        }
+        let hash = if self.reuse_command_buffers {
+            Some(self.frame_hash(frame, &shake))
+        } else {
+            None
+        };
+        // the framebuffer is baked into the command buffer, so it has to be the same image too
+        let recorded = hash.map(|hash| (hash, i_usize));
+        let reuse = recorded.is_some() && recorded == self.recorded_frames[frame_index];
+        let mut upload_time = Duration::from_secs(0);
+        if !reuse {
+            let camera = self.camera;
+            let culling = self.culling;
+            let mut culled_quads = 0;
+            let mut num_draws = 0;
+            let mut num_visible = 0;
+            let mut draw_calls = Vec::with_capacity(frame.batches.len());
+
+            let upload_start = Instant::now();
+            unsafe {
+                let _upload = profile::scope("upload");
+                let data_target = self.vertices.region_mut::<Vertex>(frame_index);
+                // mesh vertices go first since their count is known up front, the quads that survive
+                // culling come after them
+                let atlas = self.atlas.as_ref();
+                let resolve = |vertex: Vertex| match atlas {
+                    Some(atlas) => atlas.resolve(vertex),
+                    None => vertex,
+                };
+                let quad_base = frame.vertices.len();
+                for batch in &frame.batches {
+                    let view = batch.state.view_matrix(&camera, &shake);
+                    let kind = match &batch.kind {
+                        BatchKind::Quads(range) => {
+                            let first_quad = num_visible;
+                            for textured_quad in &frame.quads[range.clone()] {
+                                let textured_quad = TexturedQuad {
+                                    quad: textured_quad.quad.transform(view),
+                                    ..*textured_quad
+                                };
+                                if culling
+                                    && !textured_quad.quad.intersects_rect(&Camera2D::CLIP_RECT)
+                                {
+                                    culled_quads += 1;
+                                    continue;
+                                }
+                                let stride = 4;
+                                let offset = quad_base + stride * num_visible;
+                                let mut vertices = textured_quad.to_vertices();
+                                if atlas.is_some() {
+                                    for vertex in vertices.iter_mut() {
+                                        *vertex = resolve(*vertex);
+                                    }
+                                }
+                                data_target[offset..offset + stride].copy_from_slice(&vertices);
+                                num_visible += 1;
+                            }
+                            DrawKind::Quads {
+                                first_vertex: quad_base + first_quad * 4,
+                                num_quads: num_visible - first_quad,
+                            }
+                        }
+                        BatchKind::Mesh { vertices, indices } => {
+                            for i in vertices.clone() {
+                                data_target[i] = resolve(frame.vertices[i].transform(view));
+                            }
+                            DrawKind::Mesh {
+                                indices: indices.start as u32..indices.end as u32,
+                                base_vertex: vertices.start as i32,
+                            }
+                        }
+                        BatchKind::Custom {
+                            pipeline,
+                            vertices,
+                            indices,
+                        } => DrawKind::Custom {
+                            pipeline: *pipeline,
+                            vertex_offset: (custom_base + vertices.start) as u64,
+                            indices: indices.start as u32..indices.end as u32,
+                            view: view_push_constants(view),
+                        },
+                        BatchKind::Indirect { pipeline, mesh } => DrawKind::Indirect {
+                            pipeline: *pipeline,
+                            mesh: *mesh,
+                            view: view_push_constants(view),
+                        },
+                    };
+                    draw_calls.push(DrawCall {
+                        state: batch.state,
+                        kind,
+                    });
+                }
+                let vertex_end = mem::size_of::<Vertex>() * (quad_base + 4 * num_visible);
+                self.vertices
+                    .flush(&self.device, frame_index, 0..vertex_end)?;
+
+                if !frame.custom_vertices.is_empty() {
+                    let custom_range = custom_base..custom_base + frame.custom_vertices.len();
+                    self.vertices.region_mut::<u8>(frame_index)[custom_range.clone()]
+                        .copy_from_slice(&frame.custom_vertices);
+                    self.vertices
+                        .flush(&self.device, frame_index, custom_range)?;
+                }
 
-        unsafe {
-            let mut data_target = self
-                .device
-                .acquire_mapping_writer(
-                    self.vertices.memory.deref(),
-                    0..self.vertices.requirements.size,
-                )
-                .map_err(|_| "Failed to acquire a memory writer!")?;
-            for i in 0..textured_quads.len().min(MAX_QUADS) {
-                let stride = 4;
-                data_target[4 * i..stride * (i + 1)]
-                    .copy_from_slice(&textured_quads[i].to_vertices());
+                if !frame.indices.is_empty() {
+                    self.mesh_indexes.region_mut::<u16>(frame_index)[..frame.indices.len()]
+                        .copy_from_slice(&frame.indices);
+                    self.mesh_indexes.flush(
+                        &self.device,
+                        frame_index,
+                        0..mem::size_of::<u16>() * frame.indices.len(),
+                    )?;
+                }
             }
-            self.device
-                .release_mapping_writer(data_target)
-                .map_err(|_| "Couldn't release the mapping writer")?;
-        }
-
-        let uv_rect = textured_quads[0].uv_rect;
-        // record commands
-        unsafe {
-            let buffer = &mut self.command_buffers[i_usize];
-            const TRIANGLE_CLEAR: [ClearValue; 1] =
-                [ClearValue::Color(ClearColor::Float([0.1, 0.2, 0.3, 1.0]))];
-            buffer.begin(false);
-            {
-                let mut encoder = buffer.begin_render_pass_inline(
-                    &self.render_pass,
-                    &self.framebuffers[i_usize],
-                    self.render_area,
-                    TRIANGLE_CLEAR.iter(),
-                );
-                encoder.bind_graphics_pipeline(&self.graphics_pipeline);
-                // force deref impl of ManuallyDrop to do stuff
-                let buffer_ref: &<back::Backend as Backend>::Buffer = &self.vertices.buffer;
-                let buffers: ArrayVec<[_; 1]> = [(buffer_ref, 0)].into();
-                encoder.bind_vertex_buffers(0, buffers);
-                encoder.bind_index_buffer(IndexBufferView {
-                    buffer: &self.indexes.buffer,
-                    offset: 0,
-                    index_type: IndexType::U16,
-                });
-                encoder.bind_graphics_descriptor_sets(
-                    &self.pipeline_layout,
-                    0,
-                    Some(&self.texture_pool.descriptor_sets[0]),
-                    &[],
-                );
-                encoder.push_graphics_constants(
-                    &self.pipeline_layout,
-                    ShaderStageFlags::VERTEX,
-                    0,
-                    &[
-                        uv_rect[0].to_bits(),
-                        uv_rect[1].to_bits(),
-                        uv_rect[2].to_bits(),
-                        uv_rect[3].to_bits(),
-                    ],
-                );
-                encoder.draw_indexed(0..6 * textured_quads.len() as u32, 0, 0..1);
+            upload_time = upload_start.elapsed();
+            self.culled_quads = culled_quads;
+
+            // record commands
+            let passes = self.frame_passes()?;
+            // loading an image that's never been drawn to gives garbage, so those get cleared
+            let fresh = match self.post_process.as_ref() {
+                Some(post_process) => !post_process.target_drawn(),
+                None => !self.drawn_images[i_usize],
+            };
+            // everything else reads what the scene writes, so it always comes first
+            debug_assert_eq!(passes.first(), Some(&EnginePass::Scene));
+            unsafe {
+                let _record = profile::scope("record");
+                // overdraw counts up from black, and skipping the clear would count old frames too
+                let clear_color = if overdraw {
+                    Some(OVERDRAW_CLEAR)
+                } else if fresh {
+                    Some(frame.clear_color().unwrap_or([0.0, 0.0, 0.0, 1.0]))
+                } else {
+                    frame.clear_color()
+                };
+                // a load op of Load just ignores the clear values
+                let clear_values = [ClearValue::Color(ClearColor::Float(
+                    Self::encode_clear_color(shader_config, clear_color.unwrap_or([0.0; 4])),
+                ))];
+                // render targets always get cleared, they aren't kept between frames
+                let target_clear = if overdraw {
+                    OVERDRAW_CLEAR
+                } else {
+                    frame.clear_color().unwrap_or([0.0; 4])
+                };
+                let target_clear_values = [ClearValue::Color(ClearColor::Float(
+                    Self::encode_clear_color(target_config, target_clear),
+                ))];
+                let buffer = &mut self.command_buffers[frame_index];
+                buffer.begin(false);
+                for &target in &pass_targets {
+                    // with post processing the scene goes into an offscreen image first
+                    let (render_pass, framebuffer) = match (target, self.post_process.as_ref()) {
+                        (Some(texture), _) => {
+                            let render_targets = self
+                                .render_targets
+                                .as_ref()
+                                .ok_or("There are no render targets!")?;
+                            (
+                                render_targets.render_pass(),
+                                render_targets
+                                    .framebuffer(texture.index() as usize)
+                                    .ok_or("That texture isn't a render target!")?,
+                            )
+                        }
+                        (None, Some(post_process)) => (
+                            post_process.render_pass(clear_color.is_some()),
+                            post_process
+                                .framebuffer()
+                                .ok_or("The post processing target is missing!")?,
+                        ),
+                        (None, None) if clear_color.is_some() => {
+                            (&*self.render_pass, &self.framebuffers[i_usize])
+                        }
+                        (None, None) => (&*self.load_render_pass, &self.framebuffers[i_usize]),
+                    };
+                    let (clear_values, shader_config, offscreen) = match target {
+                        Some(_) => (&target_clear_values, target_config, true),
+                        None => (&clear_values, shader_config, offscreen),
+                    };
+                    let mut encoder = buffer.begin_render_pass_inline(
+                        render_pass,
+                        framebuffer,
+                        self.render_area,
+                        clear_values.iter(),
+                    );
+                    // force deref impl of ManuallyDrop to do stuff
+                    let buffer_ref: &<back::Backend as Backend>::Buffer = self.vertices.buffer();
+                    let region_offset = self.vertices.region_offset(frame_index);
+                    let buffers: ArrayVec<[_; 1]> = [(buffer_ref, region_offset)].into();
+                    encoder.bind_vertex_buffers(0, buffers);
+                    // all the pipelines share a layout, so this stays bound when switching between them
+                    if let Some(descriptor_set) = self.texture_pool.descriptor_sets.first() {
+                        encoder.bind_graphics_descriptor_sets(
+                            &self.pipeline_layout,
+                            0,
+                            Some(descriptor_set),
+                            &[],
+                        );
+                    }
+                    let mut bound_pipeline = None;
+                    let mut bound_viewport = None;
+                    let mut bound_material = None;
+                    // None means an indirect mesh's vertices are bound
+                    let mut bound_vertex_offset = Some(0);
+                    // None means nothing of ours is bound, Some(true) means the quad indices are bound
+                    let mut quad_indices_bound = None;
+                    let pass_calls = draw_calls
+                        .iter()
+                        .filter(|draw_call| draw_call.state.target == target);
+                    for draw_call in pass_calls {
+                        let (custom, vertex_offset) = match draw_call.kind {
+                            DrawKind::Custom {
+                                pipeline,
+                                vertex_offset,
+                                ..
+                            } => (Some(pipeline), Some(vertex_offset)),
+                            DrawKind::Indirect { pipeline, .. } => (Some(pipeline), None),
+                            _ => (None, Some(0)),
+                        };
+                        let key = PipelineKey {
+                            blend_mode: draw_call.state.blend_mode,
+                            wireframe,
+                            overdraw,
+                            shader_config,
+                            material: material_mode(draw_call.state.material, custom),
+                            custom,
+                            offscreen,
+                        };
+                        // custom vertices have their own stride, so they get bound at their own offset
+                        if let Some(offset) = vertex_offset {
+                            if bound_vertex_offset != vertex_offset {
+                                let buffers: ArrayVec<[_; 1]> =
+                                    [(buffer_ref, region_offset + offset)].into();
+                                encoder.bind_vertex_buffers(0, buffers);
+                                bound_vertex_offset = vertex_offset;
+                            }
+                        }
+                        if bound_pipeline != Some(key) {
+                            encoder.bind_graphics_pipeline(&self.pipelines[&key]);
+                            bound_pipeline = Some(key);
+                        }
+                        if let Some(constant) = draw_call.state.material.push_constant() {
+                            if custom.is_none() && bound_material != Some(constant) {
+                                encoder.push_graphics_constants(
+                                    &self.pipeline_layout,
+                                    ShaderStageFlags::FRAGMENT,
+                                    MATERIAL_PUSH_CONSTANT,
+                                    &[constant],
+                                );
+                                bound_material = Some(constant);
+                            }
+                        }
+                        let viewport = match draw_call.state.viewport {
+                            Some(viewport) => viewport_rect(self.render_area, viewport),
+                            None => self.render_area,
+                        };
+                        if bound_viewport != Some(viewport) {
+                            encoder.set_viewports(
+                                0,
+                                &[Viewport {
+                                    rect: viewport,
+                                    depth: (0.0..1.0),
+                                }],
+                            );
+                            encoder.set_scissors(0, &[viewport]);
+                            bound_viewport = Some(viewport);
+                        }
+                        match draw_call.kind {
+                            DrawKind::Quads {
+                                first_vertex,
+                                num_quads,
+                            } => {
+                                if quad_indices_bound != Some(true) {
+                                    encoder.bind_index_buffer(IndexBufferView {
+                                        buffer: &self.indexes.buffer,
+                                        offset: 0,
+                                        index_type: self.index_type,
+                                    });
+                                    quad_indices_bound = Some(true);
+                                }
+                                // u16 indices only cover MAX_U16_QUADS quads, so bigger batches get drawn in
+                                // chunks that reuse the index buffer with a different base vertex
+                                let quads_per_draw = match self.index_type {
+                                    IndexType::U16 => MAX_U16_QUADS,
+                                    IndexType::U32 => self.num_quads.max(1),
+                                };
+                                for chunk_start in (0..num_quads).step_by(quads_per_draw) {
+                                    let chunk_len = (num_quads - chunk_start).min(quads_per_draw);
+                                    encoder.draw_indexed(
+                                        0..6 * chunk_len as u32,
+                                        (first_vertex + chunk_start * 4) as i32,
+                                        0..1,
+                                    );
+                                    num_draws += 1;
+                                }
+                            }
+                            DrawKind::Mesh {
+                                ref indices,
+                                base_vertex,
+                            } => {
+                                if quad_indices_bound != Some(false) {
+                                    encoder.bind_index_buffer(IndexBufferView {
+                                        buffer: self.mesh_indexes.buffer(),
+                                        offset: self.mesh_indexes.region_offset(frame_index),
+                                        index_type: IndexType::U16,
+                                    });
+                                    quad_indices_bound = Some(false);
+                                }
+                                encoder.draw_indexed(indices.clone(), base_vertex, 0..1);
+                                num_draws += 1;
+                            }
+                            DrawKind::Custom {
+                                ref indices,
+                                ref view,
+                                ..
+                            } => {
+                                if quad_indices_bound != Some(false) {
+                                    encoder.bind_index_buffer(IndexBufferView {
+                                        buffer: self.mesh_indexes.buffer(),
+                                        offset: self.mesh_indexes.region_offset(frame_index),
+                                        index_type: IndexType::U16,
+                                    });
+                                    quad_indices_bound = Some(false);
+                                }
+                                let constants: ArrayVec<[u32; 12]> =
+                                    view.iter().map(|f| f.to_bits()).collect();
+                                encoder.push_graphics_constants(
+                                    &self.pipeline_layout,
+                                    ShaderStageFlags::VERTEX,
+                                    0,
+                                    &constants,
+                                );
+                                encoder.draw_indexed(indices.clone(), 0, 0..1);
+                                num_draws += 1;
+                            }
+                            DrawKind::Indirect { mesh, ref view, .. } => {
+                                let mesh = &self.indirect_meshes[mesh.0];
+                                let vertices: &<back::Backend as Backend>::Buffer =
+                                    &mesh.vertices.buffer;
+                                let buffers: ArrayVec<[_; 1]> = [(vertices, 0)].into();
+                                encoder.bind_vertex_buffers(0, buffers);
+                                bound_vertex_offset = None;
+                                encoder.bind_index_buffer(IndexBufferView {
+                                    buffer: &mesh.indices.buffer,
+                                    offset: 0,
+                                    index_type: IndexType::U16,
+                                });
+                                quad_indices_bound = None;
+                                let constants: ArrayVec<[u32; 12]> =
+                                    view.iter().map(|f| f.to_bits()).collect();
+                                encoder.push_graphics_constants(
+                                    &self.pipeline_layout,
+                                    ShaderStageFlags::VERTEX,
+                                    0,
+                                    &constants,
+                                );
+                                // more than one draw per call needs the multiDrawIndirect feature,
+                                // which gfx-hal 0.1 has no way to turn on
+                                let stride = mem::size_of::<IndirectDraw>() as u32;
+                                for i in 0..mesh.num_commands {
+                                    encoder.draw_indexed_indirect(
+                                        &mesh.commands.buffer,
+                                        u64::from(i * stride),
+                                        1,
+                                        stride,
+                                    );
+                                    num_draws += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                for pass in passes.iter().skip(1) {
+                    match pass {
+                        EnginePass::PostProcess => {
+                            if let (Some(post_process), Some(effect)) =
+                                (self.post_process.as_ref(), self.crt_effect)
+                            {
+                                post_process.record(buffer, i_usize, effect);
+                            }
+                        }
+                        EnginePass::Capture => {
+                            if let Some(capture) = self.capture.as_ref() {
+                                capture.record(
+                                    buffer,
+                                    frame_index,
+                                    &self.swapchain_images[i_usize],
+                                );
+                            }
+                        }
+                        EnginePass::Scene => (),
+                    }
+                }
+                buffer.finish()
             }
-            buffer.finish()
+            // the forced clear is only for the first time, so that buffer can't be reused
+            self.recorded_frames[frame_index] = if fresh { None } else { recorded };
+            self.drawn_images[i_usize] = true;
+            if let Some(post_process) = self.post_process.as_mut() {
+                post_process.mark_drawn();
+            }
+            self.recorded_counts[frame_index] = (num_draws, num_visible);
         }
+        let cpu_record_time = acquired.elapsed();
 
         // Submission
-        let command_buffers = &self.command_buffers[i_usize..=i_usize];
+        unsafe {
+            self.device
+                .reset_fence(flight_fence)
+                .map_err(|_| "Couldn't reset fence!")?;
+        }
+        let command_buffers = &self.command_buffers[frame_index..=frame_index];
         let wait_semaphores: ArrayVec<[_; 1]> =
             [(image_available, PipelineStage::COLOR_ATTACHMENT_OUTPUT)].into();
         let signal_semaphores: ArrayVec<[_; 1]> = [render_finished].into();
@@ -919,81 +2892,169 @@ impl HalState {
             wait_semaphores,
             signal_semaphores,
         };
-        let the_command_queue = &mut self.queue_group.queues[0];
-        unsafe {
-            the_command_queue.submit(submission, Some(flight_fence));
-            self.swapchain
-                .present(the_command_queue, i_u32, present_wait_semaphores)
-                .map_err(|_| "Failed to present into the swapchain!")
-        }
+        let (draw_calls, quads) = self.recorded_counts[frame_index];
+        let stats = FrameStats {
+            draw_calls,
+            quads,
+            // only the first descriptor set ever gets bound
+            textures_bound: self
+                .texture_pool
+                .textures
+                .len()
+                .min(self.texture_pool.descriptor_size),
+            gpu_wait,
+            cpu_record_time,
+            upload_time,
+            ..FrameStats::default()
+        };
+        if let Some(capture) = self.capture.as_mut() {
+            capture.submitted(frame_index);
+        }
+        let the_command_queue = &mut self.queue_group.queues[0];
+        let swapchain = self.swapchain.as_ref().ok_or("There's no swapchain!")?;
+        let result = unsafe {
+            {
+                let _submit = profile::scope("submit");
+                the_command_queue.submit(submission, Some(flight_fence));
+            }
+            let _present = profile::scope("present");
+            swapchain
+                .present(the_command_queue, i_u32, present_wait_semaphores)
+                .map_err(|_| "Failed to present into the swapchain!")
+        };
+        self.frame_stats = self.frame_timer.presented(
+            stats,
+            frame_start,
+            acquired,
+            self.present_mode,
+            self.frames_in_flight,
+        );
+        result.map(|()| self.frame_stats)
+    }
+
+    fn compile_shaders(
+        device: &back::Device,
+        logger: &Logger,
+    ) -> Result<
+        (
+            <back::Backend as Backend>::ShaderModule,
+            <back::Backend as Backend>::ShaderModule,
+        ),
+        &'static str,
+    > {
+        let (vertex_spirv, fragment_spirv) = builtin_spirv(logger)?;
+        let vertex_shader_module = unsafe {
+            device
+                .create_shader_module(&vertex_spirv)
+                .map_err(|_| "Couldn't make the vertex module!")?
+        };
+        let fragment_shader_module = unsafe {
+            match device.create_shader_module(&fragment_spirv) {
+                Ok(module) => module,
+                Err(_) => {
+                    device.destroy_shader_module(vertex_shader_module);
+                    return Err("Couldn't make the fragment module!");
+                }
+            }
+        };
+        Ok((vertex_shader_module, fragment_shader_module))
     }
 
-    fn create_pipeline(
-        device: &mut back::Device,
-        extent: Extent2D,
-        render_pass: &<back::Backend as Backend>::RenderPass,
+    fn create_pipeline_layout(
+        device: &back::Device,
         texture_count: usize,
-        samples: Option<u8>,
-        logger: &Logger,
     ) -> Result<
         (
             Vec<<back::Backend as Backend>::DescriptorSetLayout>,
             <back::Backend as Backend>::PipelineLayout,
-            <back::Backend as Backend>::GraphicsPipeline,
         ),
         &'static str,
     > {
-        let mut compiler = shaderc::Compiler::new().ok_or("shaderc not found!")?;
-        let vertex_compile_artifact = compiler
-            .compile_into_spirv(
-                VERTEX_SOURCE,
-                shaderc::ShaderKind::Vertex,
-                "vertex.vert",
-                "halstate",
-                None,
-            )
-            .map_err(|e| {
-                error!(logger, "failed to compile vertex shader"; "err" => %e);
-                "Couldn't compile vertex shader!"
-            })?;
-        let fragment_compile_artifact = compiler
-            .compile_into_spirv(
-                FRAGMENT_SOURCE,
-                shaderc::ShaderKind::Fragment,
-                "fragment.frag",
-                "halstate",
-                None,
-            )
-            .map_err(|e| {
-                error!(logger, "failed to compile fragment shader"; "err" => %e);
-                "Couldn't compile fragment shader!"
-            })?;
-        let vertex_shader_module = unsafe {
-            device
-                .create_shader_module(vertex_compile_artifact.as_binary_u8())
-                .map_err(|_| "Couldn't make the vertex module!")?
-        };
-        let fragment_shader_module = unsafe {
+        // Apparently these variables are unused, but yeah, gonna keep them as comments here just in case
+        // let bindings = Vec::<DescriptorSetLayoutBinding>::new();
+        // let immutable_samplers = Vec::<<back::Backend as Backend>::Sampler>::new();
+
+        // 1. you make a DescriptorSetLayout which is the layout of one descriptor
+        //    set
+        let descriptor_set_layouts: Vec<<back::Backend as Backend>::DescriptorSetLayout> =
+            vec![unsafe {
+                device
+                    .create_descriptor_set_layout(
+                        &[
+                            DescriptorSetLayoutBinding {
+                                binding: 0,
+                                ty: gfx_hal::pso::DescriptorType::SampledImage,
+                                count: texture_count,
+                                stage_flags: ShaderStageFlags::FRAGMENT | ShaderStageFlags::VERTEX,
+                                immutable_samplers: false,
+                            },
+                            DescriptorSetLayoutBinding {
+                                binding: 1,
+                                ty: gfx_hal::pso::DescriptorType::Sampler,
+                                count: MAX_SAMPLERS,
+                                stage_flags: ShaderStageFlags::FRAGMENT | ShaderStageFlags::VERTEX,
+                                immutable_samplers: false,
+                            },
+                            DescriptorSetLayoutBinding {
+                                binding: 2,
+                                ty: gfx_hal::pso::DescriptorType::UniformBuffer,
+                                count: 1,
+                                stage_flags: ShaderStageFlags::FRAGMENT,
+                                immutable_samplers: false,
+                            },
+                        ],
+                        &[],
+                    )
+                    .map_err(|_| "Couldn't make a DescriptorSetLayout")?
+            }];
+
+        // room for a mat3 view matrix, which custom pipelines get, and the material texture
+        let push_constants = vec![
+            (ShaderStageFlags::VERTEX, 0..12),
+            (
+                ShaderStageFlags::FRAGMENT,
+                MATERIAL_PUSH_CONSTANT..MATERIAL_PUSH_CONSTANT + 1,
+            ),
+        ];
+        let layout = unsafe {
             device
-                .create_shader_module(fragment_compile_artifact.as_binary_u8())
-                .map_err(|_| "Couldn't make the fragment module!")?
+                .create_pipeline_layout(&descriptor_set_layouts, push_constants)
+                .map_err(|_| "Couldn't create pipeline layout!")?
         };
+        Ok((descriptor_set_layouts, layout))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_pipeline(
+        device: &back::Device,
+        render_pass: &<back::Backend as Backend>::RenderPass,
+        layout: &<back::Backend as Backend>::PipelineLayout,
+        vertex_shader_module: &<back::Backend as Backend>::ShaderModule,
+        fragment_shader_module: &<back::Backend as Backend>::ShaderModule,
+        vertex_format: &VertexFormat,
+        samples: Option<u8>,
+        texture_count: u32,
+        key: PipelineKey,
+    ) -> Result<<back::Backend as Backend>::GraphicsPipeline, &'static str> {
+        let specialization_data =
+            key.shader_config
+                .specialization_data(texture_count, key.overdraw, key.material);
         let shaders = {
             let (vs_entry, fs_entry) = (
                 EntryPoint {
                     entry: "main",
-                    module: &vertex_shader_module,
+                    module: vertex_shader_module,
                     specialization: Specialization {
-                        constants: &[],
-                        data: &[],
+                        constants: &SPECIALIZATION_CONSTANTS,
+                        data: &specialization_data,
                     },
                 },
                 EntryPoint {
                     entry: "main",
-                    module: &fragment_shader_module,
+                    module: fragment_shader_module,
                     specialization: Specialization {
-                        constants: &[],
-                        data: &[],
+                        constants: &SPECIALIZATION_CONSTANTS,
+                        data: &specialization_data,
                     },
                 },
             );
@@ -1005,17 +3066,17 @@ impl HalState {
                 fragment: Some(fs_entry),
             }
         };
-        let vertex_buffers: Vec<VertexBufferDesc> = vec![VertexBufferDesc {
-            binding: 0,
-            stride: mem::size_of::<Vertex>() as ElemStride,
-            rate: 0,
-        }];
+        let vertex_buffers: Vec<VertexBufferDesc> = vec![vertex_format.buffer_desc(0)];
 
-        let attributes: Vec<AttributeDesc> = Vertex::attributes();
+        let attributes: Vec<AttributeDesc> = vertex_format.attribute_descs();
 
         let rasterizer = Rasterizer {
             depth_clamping: false,
-            polygon_mode: PolygonMode::Fill,
+            polygon_mode: if key.wireframe {
+                PolygonMode::Line(1.0)
+            } else {
+                PolygonMode::Fill
+            },
             cull_face: Face::NONE,
             front_face: FrontFace::Clockwise,
             depth_bias: None,
@@ -1026,70 +3087,26 @@ impl HalState {
             depth_bounds: false,
             stencil: StencilTest::Off,
         };
-        let blender = {
-            // stuff that we were using before but yeah
-            /* let blend_state = BlendState::On {
-                color: BlendOp::Add {
-                src: Factor::One,
-                dst: Factor::Zero,
-            },
-                alpha: BlendOp::Add {
-                src: Factor::One,
-                dst: Factor::Zero,
-            },
-            };*/
-            BlendDesc {
-                logic_op: Some(LogicOp::Copy),
-                targets: vec![ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA)],
-            }
+        let blender = BlendDesc {
+            // a logic op turns blending off entirely, so this has to stay None for the blend modes to work
+            logic_op: None,
+            targets: vec![ColorBlendDesc(
+                ColorMask::ALL,
+                if key.overdraw {
+                    BlendState::ADD
+                } else {
+                    key.blend_mode.into()
+                },
+            )],
         };
+        // viewport and scissor are dynamic, they change with split screen and swapchain recreation
         let baked_states = BakedStates {
-            viewport: Some(Viewport {
-                rect: extent.to_extent().rect(),
-                depth: (0.0..1.0),
-            }),
-            scissor: Some(extent.to_extent().rect()),
+            viewport: None,
+            scissor: None,
             blend_color: None,
             depth_bounds: None,
         };
         let input_assembler = InputAssemblerDesc::new(Primitive::TriangleList);
-        // Apparently these variables are unused, but yeah, gonna keep them as comments here just in case
-        // let bindings = Vec::<DescriptorSetLayoutBinding>::new();
-        // let immutable_samplers = Vec::<<back::Backend as Backend>::Sampler>::new();
-
-        // 1. you make a DescriptorSetLayout which is the layout of one descriptor
-        //    set
-        let descriptor_set_layouts: Vec<<back::Backend as Backend>::DescriptorSetLayout> =
-            vec![unsafe {
-                device
-                    .create_descriptor_set_layout(
-                        &[
-                            DescriptorSetLayoutBinding {
-                                binding: 0,
-                                ty: gfx_hal::pso::DescriptorType::SampledImage,
-                                count: texture_count,
-                                stage_flags: ShaderStageFlags::FRAGMENT | ShaderStageFlags::VERTEX,
-                                immutable_samplers: false,
-                            },
-                            DescriptorSetLayoutBinding {
-                                binding: 1,
-                                ty: gfx_hal::pso::DescriptorType::Sampler,
-                                count: 1,
-                                stage_flags: ShaderStageFlags::FRAGMENT | ShaderStageFlags::VERTEX,
-                                immutable_samplers: false,
-                            },
-                        ],
-                        &[],
-                    )
-                    .map_err(|_| "Couldn't make a DescriptorSetLayout")?
-            }];
-
-        let push_constants = vec![(ShaderStageFlags::VERTEX, 0..5)];
-        let layout = unsafe {
-            device
-                .create_pipeline_layout(&descriptor_set_layouts, push_constants)
-                .map_err(|_| "Couldn't create pipeline layout!")?
-        };
 
         let multisampling = if let Some(samples) = samples {
             Some(Multisampling {
@@ -1103,33 +3120,599 @@ impl HalState {
             None
         };
 
-        let gfx_pipeline = {
-            let desc = GraphicsPipelineDesc {
-                shaders,
-                rasterizer,
-                vertex_buffers,
-                attributes,
-                input_assembler,
-                blender,
-                depth_stencil,
-                layout: &layout,
-                multisampling,
-                baked_states,
-                subpass: Subpass {
-                    index: 0,
-                    main_pass: render_pass,
+        let desc = GraphicsPipelineDesc {
+            shaders,
+            rasterizer,
+            vertex_buffers,
+            attributes,
+            input_assembler,
+            blender,
+            depth_stencil,
+            layout,
+            multisampling,
+            baked_states,
+            subpass: Subpass {
+                index: 0,
+                main_pass: render_pass,
+            },
+            flags: PipelineCreationFlags::empty(),
+            parent: BasePipeline::None,
+        };
+
+        unsafe {
+            device
+                .create_graphics_pipeline(&desc, None)
+                .map_err(|_| "Couldn't create graphics pipeline!")
+        }
+    }
+
+    /// Pipelines other than the default one get created the first time they're needed
+    fn ensure_pipeline(&mut self, key: PipelineKey) -> Result<(), &'static str> {
+        if !self.pipelines.contains_key(&key) {
+            info!(self.logger, "creating pipeline"; "key" => debug_repr!(key));
+            let builtin_format;
+            let (vertex_shader_module, fragment_shader_module, vertex_format) = match key.custom {
+                Some(CustomPipelineId(id)) => {
+                    let custom = self
+                        .custom_pipelines
+                        .get(id)
+                        .ok_or("That custom pipeline doesn't exist!")?;
+                    (
+                        &custom.vertex_shader_module,
+                        &custom.fragment_shader_module,
+                        &custom.vertex_format,
+                    )
+                }
+                None => {
+                    builtin_format = Vertex::format();
+                    (
+                        &*self.vertex_shader_module,
+                        &*self.fragment_shader_module,
+                        &builtin_format,
+                    )
+                }
+            };
+            let render_pass = match (key.offscreen, self.post_process.as_ref()) {
+                (false, _) => &*self.render_pass,
+                // compatible with the load pass too, only the formats and samples have to match
+                (true, Some(post_process)) => post_process.render_pass(true),
+                // and render targets have the same format as the post processing target
+                (true, None) => match self.render_targets.as_ref() {
+                    Some(render_targets) => render_targets.render_pass(),
+                    None => return Err("There's nothing offscreen to draw into!"),
                 },
-                flags: PipelineCreationFlags::empty(),
-                parent: BasePipeline::None,
             };
+            let pipeline = Self::create_pipeline(
+                &self.device,
+                render_pass,
+                &self.pipeline_layout,
+                vertex_shader_module,
+                fragment_shader_module,
+                vertex_format,
+                self.sampling_config.multisampling,
+                self.texture_pool.descriptor_size as u32,
+                key,
+            )?;
+            self.pipelines.insert(key, pipeline);
+        }
+        Ok(())
+    }
 
-            unsafe {
+    fn log_batches(&self, frame: &Frame) {
+        for (i, batch) in frame.batches.iter().enumerate() {
+            match &batch.kind {
+                BatchKind::Quads(range) => {
+                    let mut textures: Vec<u32> = frame.quads[range.clone()]
+                        .iter()
+                        .map(|q| q.tex_num)
+                        .collect();
+                    textures.sort();
+                    textures.dedup();
+                    debug!(self.logger, "quad batch"; "batch" => i, "quads" => debug_repr!(range),
+                           "textures" => debug_repr!(textures), "state" => debug_repr!(batch.state));
+                }
+                BatchKind::Mesh { vertices, indices } => {
+                    let texture = frame.vertices.get(vertices.start).map(|v| v.tex_num);
+                    debug!(self.logger, "mesh batch"; "batch" => i, "vertices" => debug_repr!(vertices),
+                           "indices" => debug_repr!(indices), "texture" => debug_repr!(texture),
+                           "state" => debug_repr!(batch.state));
+                }
+                BatchKind::Custom {
+                    pipeline,
+                    vertices,
+                    indices,
+                } => {
+                    debug!(self.logger, "custom batch"; "batch" => i, "pipeline" => debug_repr!(pipeline),
+                           "bytes" => debug_repr!(vertices), "indices" => debug_repr!(indices),
+                           "state" => debug_repr!(batch.state));
+                }
+                BatchKind::Indirect { pipeline, mesh } => {
+                    debug!(self.logger, "indirect batch"; "batch" => i, "pipeline" => debug_repr!(pipeline),
+                           "mesh" => debug_repr!(mesh), "state" => debug_repr!(batch.state));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "precompiled-shaders")]
+fn builtin_spirv(_logger: &Logger) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+    // include_bytes! doesn't promise any alignment and the backends read SPIR-V as u32s, so the
+    // copy is on purpose
+    Ok((VERTEX_SPIRV.to_vec(), FRAGMENT_SPIRV.to_vec()))
+}
+
+#[cfg(not(feature = "precompiled-shaders"))]
+fn builtin_spirv(logger: &Logger) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+    Ok((
+        compile_glsl(
+            VERTEX_SOURCE,
+            shaderc::ShaderKind::Vertex,
+            "vertex.vert",
+            logger,
+        )
+        .map_err(|_| "Couldn't compile vertex shader!")?,
+        compile_glsl(
+            FRAGMENT_SOURCE,
+            shaderc::ShaderKind::Fragment,
+            "fragment.frag",
+            logger,
+        )
+        .map_err(|_| "Couldn't compile fragment shader!")?,
+    ))
+}
+
+#[cfg(feature = "precompiled-shaders")]
+fn crt_spirv(_logger: &Logger) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+    Ok((CRT_VERTEX_SPIRV.to_vec(), CRT_FRAGMENT_SPIRV.to_vec()))
+}
+
+#[cfg(not(feature = "precompiled-shaders"))]
+fn crt_spirv(logger: &Logger) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+    Ok((
+        compile_glsl(
+            CRT_VERTEX_SOURCE,
+            shaderc::ShaderKind::Vertex,
+            "crt_vertex.vert",
+            logger,
+        )
+        .map_err(|_| "Couldn't compile the CRT vertex shader!")?,
+        compile_glsl(
+            CRT_FRAGMENT_SOURCE,
+            shaderc::ShaderKind::Fragment,
+            "crt_fragment.frag",
+            logger,
+        )
+        .map_err(|_| "Couldn't compile the CRT fragment shader!")?,
+    ))
+}
+
+#[cfg(not(feature = "precompiled-shaders"))]
+fn compile_glsl(
+    source: &str,
+    kind: shaderc::ShaderKind,
+    name: &str,
+    logger: &Logger,
+) -> Result<Vec<u8>, ()> {
+    let mut compiler =
+        shaderc::Compiler::new().ok_or_else(|| error!(logger, "shaderc not found!"))?;
+    let artifact = compiler
+        .compile_into_spirv(source, kind, name, "halstate", None)
+        .map_err(|e| {
+            error!(logger, "failed to compile shader"; "name" => name, "err" => %e);
+        })?;
+    Ok(artifact.as_binary_u8().to_vec())
+}
+
+/// Turns a viewport given as a fraction of the render area into pixels
+fn viewport_rect(render_area: Rect, viewport: geometry::Rect<f32, f32>) -> Rect {
+    let (w, h) = (f32::from(render_area.w), f32::from(render_area.h));
+    let x = (viewport.x * w).round() as i16;
+    let y = (viewport.y * h).round() as i16;
+    Rect {
+        x: render_area.x + x,
+        y: render_area.y + y,
+        w: ((viewport.x + viewport.w) * w).round() as i16 - x,
+        h: ((viewport.y + viewport.h) * h).round() as i16 - y,
+    }
+}
+
+/// Custom vertices go after the mesh vertices and every quad the frame could have, in bytes
+fn custom_vertex_base(frame: &Frame) -> usize {
+    let vertices = frame.vertices.len() + frame.quads.len() * 4;
+    (vertices * mem::size_of::<Vertex>() + 15) & !15
+}
+
+/// A mat3 as push constants, every column is padded out to a vec4
+fn view_push_constants(view: Mat3<f32>) -> [f32; 12] {
+    let mut constants = [0.0; 12];
+    for (i, col) in [view.cols.x, view.cols.y, view.cols.z].iter().enumerate() {
+        constants[i * 4..i * 4 + 3].copy_from_slice(&[col.x, col.y, col.z]);
+    }
+    constants
+}
+
+/// A palette is one row of pixels, one per color
+fn palette_image(colors: &[[u8; 4]]) -> Result<image::RgbaImage, &'static str> {
+    if colors.is_empty() || colors.len() > 256 {
+        return Err("Palettes need between 1 and 256 colors!");
+    }
+    Ok(image::RgbaImage::from_fn(colors.len() as u32, 1, |x, _| {
+        image::Rgba {
+            data: colors[x as usize],
+        }
+    }))
+}
+
+fn is_srgb(format: Format) -> bool {
+    format.base_format().1 == ChannelType::Srgb
+}
+
+/// Same as linear_to_srgb in fragment.glsl
+fn linear_to_srgb(c: f32) -> f32 {
+    if c < 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Makes a swapchain that fits the window, and retires `old_swapchain` if there is one. The render
+/// pass is made for the swapchain's format, so when recreating it `keep_format` has to stay the
+/// same. If the surface doesn't offer it anymore this fails before touching `old_swapchain`
+fn create_swapchain(
+    adapter: &Adapter<back::Backend>,
+    device: &back::Device,
+    surface: &mut <back::Backend as Backend>::Surface,
+    window: &winit::Window,
+    preferred_vsync: &[PresentMode; 4],
+    keep_format: Option<Format>,
+    old_swapchain: &mut Option<<back::Backend as Backend>::Swapchain>,
+    logger: &Logger,
+) -> Result<
+    (
+        <back::Backend as Backend>::Swapchain,
+        Extent2D,
+        Backbuffer<back::Backend>,
+        Format,
+        u32,
+        PresentMode,
+    ),
+    &'static str,
+> {
+    let (caps, preferred_formats, present_modes, composite_alphas) =
+        surface.compatibility(&adapter.physical_device);
+    info!(logger, "surface compatibility";
+          kv!("caps" => debug_repr!(caps),
+              "preferred_formats" => debug_repr!(preferred_formats),
+              "present_modes" => debug_repr!(present_modes),
+              "composite_alphas" => debug_repr!(composite_alphas)));
+    //
+    let present_mode = preferred_vsync
+        .iter()
+        .cloned()
+        .find(|pm| present_modes.contains(pm))
+        .unwrap_or_else(|| {
+            // the only one Vulkan guarantees
+            warn!(logger, "none of the preferred present modes are supported, falling back to Fifo";
+                  "preferred" => debug_repr!(preferred_vsync));
+            PresentMode::Fifo
+        });
+    let composite_alpha = {
+        use gfx_hal::window::CompositeAlpha::*;
+        [Opaque, Inherit, PreMultiplied, PostMultiplied]
+            .iter()
+            .cloned()
+            .find(|ca| composite_alphas.contains(ca))
+            .ok_or("No CompositeAlpha values specified!")?
+    };
+    let format = match (preferred_formats, keep_format) {
+        (None, keep_format) => keep_format.unwrap_or(Format::Rgba8Srgb),
+        (Some(formats), Some(keep_format)) => {
+            if !formats.contains(&keep_format) {
+                error!(logger, "the surface doesn't support the swapchain's format anymore";
+                       "format" => debug_repr!(keep_format), "formats" => debug_repr!(formats));
+                return Err("The swapchain format changed!");
+            }
+            keep_format
+        }
+        (Some(formats), None) => match formats.iter().cloned().find(|&format| is_srgb(format)) {
+            Some(srgb_format) => srgb_format,
+            None => {
+                // 8 bit UNORM is what the shader's sRGB conversion is made for
+                let format = formats
+                    .iter()
+                    .cloned()
+                    .find(|&format| format == Format::Bgra8Unorm || format == Format::Rgba8Unorm)
+                    .or_else(|| formats.get(0).cloned())
+                    .ok_or("Preferred format list was empty!")?;
+                warn!(logger, "the surface has no sRGB formats, converting in the shader instead";
+                      "format" => debug_repr!(format));
+                format
+            }
+        },
+    };
+    // This really just grabs the extent as reported, but does some extra math since metal might report 4096x4096 because reasons
+    let extent = {
+        let window_client_area = window
+            .get_inner_size()
+            .ok_or("Window doesn't exist!")?
+            .to_physical(window.get_hidpi_factor());
+        Extent2D {
+            width: caps.extents.end.width.min(window_client_area.width as u32),
+            height: caps
+                .extents
+                .end
+                .height
+                .min(window_client_area.height as u32),
+        }
+    };
+    let image_count = if present_mode == PresentMode::Mailbox {
+        (caps.image_count.end - 1).min(3)
+    } else {
+        (caps.image_count.end - 1).min(2)
+    };
+    let image_layers = 1;
+    let image_usage = if caps.usage.contains(Usage::COLOR_ATTACHMENT) {
+        Usage::COLOR_ATTACHMENT
+    } else {
+        Err("The surface isn't capable of supporting color!")?
+    };
+    // lets frames get copied out for capturing, see `HalState::start_capture`
+    let image_usage = image_usage | (caps.usage & Usage::TRANSFER_SRC);
+    let swapchain_config = SwapchainConfig {
+        present_mode,
+        composite_alpha,
+        format,
+        extent,
+        image_count,
+        image_layers,
+        image_usage,
+    };
+    info!(logger, "created a swapchain config"; "swapchain_config" => format!("{:#?}", swapchain_config));
+    let (swapchain, backbuffer) = unsafe {
+        device
+            .create_swapchain(surface, swapchain_config, old_swapchain.take())
+            .map_err(|_| "Failed to create the swapchain!")?
+    };
+    Ok((
+        swapchain,
+        extent,
+        backbuffer,
+        format,
+        image_count,
+        present_mode,
+    ))
+}
+
+/// The layout a color attachment starts in, only a load has to know what was there before
+pub(crate) fn initial_layout(load: AttachmentLoadOp, previous: Layout) -> Layout {
+    match load {
+        AttachmentLoadOp::Load => previous,
+        _ => Layout::Undefined,
+    }
+}
+
+/// The main render pass, `load` is the only thing that differs between the ones `HalState` keeps
+unsafe fn create_render_pass(
+    device: &back::Device,
+    format: Format,
+    samples: u8,
+    load: AttachmentLoadOp,
+) -> Result<<back::Backend as Backend>::RenderPass, &'static str> {
+    let color_attachment = Attachment {
+        format: Some(format),
+        samples,
+        ops: AttachmentOps {
+            load,
+            store: AttachmentStoreOp::Store,
+        },
+        stencil_ops: AttachmentOps::DONT_CARE,
+        // loading has to start from the layout the last frame left the image in, or what's in it
+        // is undefined. `HalState::draw_frame` clears images that haven't been presented yet
+        layouts: initial_layout(load, Layout::Present)..Layout::Present,
+    };
+    let subpass = SubpassDesc {
+        colors: &[(0, Layout::ColorAttachmentOptimal)],
+        depth_stencil: None,
+        inputs: &[],
+        resolves: &[],
+        preserves: &[],
+    };
+    device
+        .create_render_pass(&[color_attachment], &[subpass], &[])
+        .map_err(|_| "Couldn't create a render pass!")
+}
+
+unsafe fn create_framebuffers(
+    device: &back::Device,
+    render_pass: &<back::Backend as Backend>::RenderPass,
+    backbuffer: Backbuffer<back::Backend>,
+    format: Format,
+    extent: Extent2D,
+) -> Result<
+    (
+        Vec<<back::Backend as Backend>::Image>,
+        Vec<<back::Backend as Backend>::ImageView>,
+        Vec<<back::Backend as Backend>::Framebuffer>,
+    ),
+    &'static str,
+> {
+    let images = match backbuffer {
+        Backbuffer::Images(images) => images,
+        Backbuffer::Framebuffer(_) => {
+            return Err("The backend gave a framebuffer instead of images!")
+        }
+    };
+    let image_views = images
+        .iter()
+        .map(|image| {
+            device
+                .create_image_view(
+                    image,
+                    ViewKind::D2,
+                    format,
+                    Swizzle::NO,
+                    SubresourceRange {
+                        aspects: Aspects::COLOR,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                )
+                .map_err(|_| "Couldn't create the image view for the image!")
+        })
+        .collect::<Result<Vec<_>, &str>>()?;
+    let framebuffers: Vec<<back::Backend as Backend>::Framebuffer> = {
+        image_views
+            .iter()
+            .map(|image_view| {
                 device
-                    .create_graphics_pipeline(&desc, None)
-                    .map_err(|_| "Couldn't create graphics pipeline!")?
+                    .create_framebuffer(
+                        &render_pass,
+                        vec![image_view],
+                        Extent {
+                            width: extent.width as u32,
+                            height: extent.height as u32,
+                            depth: 1,
+                        },
+                    )
+                    .map_err(|_| "Failed to create a framebuffer!")
+            })
+            .collect::<Result<Vec<_>, &str>>()?
+    };
+    Ok((images, image_views, framebuffers))
+}
+
+/// Each texture set has a whole texture array, every sampler and a sampler table
+unsafe fn create_descriptor_pool(
+    device: &back::Device,
+    sets: usize,
+    images_per_set: usize,
+) -> Result<<back::Backend as Backend>::DescriptorPool, &'static str> {
+    device
+        .create_descriptor_pool(
+            sets,
+            &[
+                gfx_hal::pso::DescriptorRangeDesc {
+                    ty: gfx_hal::pso::DescriptorType::SampledImage,
+                    count: sets * images_per_set,
+                },
+                gfx_hal::pso::DescriptorRangeDesc {
+                    ty: gfx_hal::pso::DescriptorType::Sampler,
+                    count: sets * MAX_SAMPLERS,
+                },
+                gfx_hal::pso::DescriptorRangeDesc {
+                    ty: gfx_hal::pso::DescriptorType::UniformBuffer,
+                    count: sets,
+                },
+            ],
+        )
+        .map_err(|_| "Couldn't create a descriptor pool!")
+}
+
+/// How many textures fit in the texture array the shaders index into. gfx-hal 0.1 doesn't report the
+/// per stage descriptor limits, so these are the limits each API is known to have instead of a query:
+/// Vulkan only guarantees 16 (which is what mobile GPUs give you) but desktop drivers go way past 128,
+/// Metal's argument table has 128 textures on macOS and 31 on iOS, and D3D12 resource binding tier 1
+/// has 128 SRVs per stage. `wanted` gets clamped to that, asking for more would fail creating the
+/// descriptor set layout
+fn max_textures_per_set(
+    adapter: &Adapter<back::Backend>,
+    wanted: Option<usize>,
+    logger: &Logger,
+) -> usize {
+    let device_max = if cfg!(any(target_os = "android", target_os = "ios")) {
+        if cfg!(feature = "metal") {
+            31
+        } else {
+            16
+        }
+    } else {
+        128
+    };
+    // the sampler table has a byte per texture
+    let device_max = device_max.min(SAMPLER_TABLE_SIZE);
+    let max_textures = match wanted {
+        Some(wanted) if wanted > device_max => {
+            warn!(logger, "asked for more textures than the device can bind, clamping";
+                  "wanted" => wanted, "max_textures" => device_max);
+            device_max
+        }
+        Some(wanted) => wanted.max(1),
+        None => device_max,
+    };
+    info!(logger, "picked texture array size"; "max_textures" => max_textures,
+          "device_limits" => debug_repr!(adapter.physical_device.limits()));
+    max_textures
+}
+
+fn material_mode(material: Material, custom: Option<CustomPipelineId>) -> u32 {
+    if custom.is_some() {
+        0
+    } else {
+        material.mode()
+    }
+}
+
+fn quad_index_type(num_quads: usize) -> IndexType {
+    if num_quads > MAX_U16_QUADS {
+        IndexType::U32
+    } else {
+        IndexType::U16
+    }
+}
+
+/// Size in bytes of the 6 indices making up one quad
+fn quad_index_size(index_type: IndexType) -> usize {
+    let index_size = match index_type {
+        IndexType::U16 => mem::size_of::<u16>(),
+        IndexType::U32 => mem::size_of::<u32>(),
+    };
+    index_size * 2 * 3
+}
+
+/// Fills an index buffer with two triangles per quad, for `num_quads` quads
+unsafe fn write_quad_indices(
+    device: &back::Device,
+    indexes: &BufferBundle<back::Backend, back::Device>,
+    num_quads: usize,
+    index_type: IndexType,
+) -> Result<(), &'static str> {
+    const INDEX_DATA: &[u32] = &[0, 1, 2, 2, 3, 0];
+    let stride: usize = 6;
+    let vertex_stride = 4;
+    let quad_indices = |i: usize| {
+        INDEX_DATA
+            .iter()
+            .map(move |index| i as u32 * vertex_stride + index)
+    };
+    match index_type {
+        IndexType::U16 => {
+            let mut data_target = device
+                .acquire_mapping_writer::<u16>(&indexes.memory, 0..indexes.requirements.size)
+                .map_err(|_| "Failed to require an index buffer mapping writer!")?;
+            for i in 0..num_quads {
+                for (j, index) in quad_indices(i).enumerate() {
+                    data_target[stride * i + j] = index as u16;
+                }
             }
-        };
-        Ok((descriptor_set_layouts, layout, gfx_pipeline))
+            device
+                .release_mapping_writer(data_target)
+                .map_err(|_| "Couldn't release the index buffer mapping writer!")
+        }
+        IndexType::U32 => {
+            let mut data_target = device
+                .acquire_mapping_writer::<u32>(&indexes.memory, 0..indexes.requirements.size)
+                .map_err(|_| "Failed to require an index buffer mapping writer!")?;
+            for i in 0..num_quads {
+                for (j, index) in quad_indices(i).enumerate() {
+                    data_target[stride * i + j] = index;
+                }
+            }
+            device
+                .release_mapping_writer(data_target)
+                .map_err(|_| "Couldn't release the index buffer mapping writer!")
+        }
     }
 }
 
@@ -1156,12 +3739,18 @@ impl core::ops::Drop for HalState {
 
             self.vertices.manually_drop(self.device.deref());
             self.indexes.manually_drop(self.device.deref());
+            self.mesh_indexes.manually_drop(self.device.deref());
+            // the framebuffers have to go before the textures they point at
+            if let Some(render_targets) = self.render_targets.take() {
+                render_targets.destroy(&self.device);
+            }
             {
                 let &mut TexturePool {
-                    ref mut descriptor_pool,
+                    ref mut descriptor_pools,
                     ref mut textures,
                     ref mut descriptor_set_layouts,
                     ref mut samplers,
+                    ref mut sampler_tables,
                     ..
                 } = &mut self.texture_pool;
 
@@ -1169,14 +3758,19 @@ impl core::ops::Drop for HalState {
                     texture.manually_drop(self.device.deref());
                 }
 
-                for sampler in samplers.drain(..) {
+                for (_, sampler) in samplers.drain(..) {
                     self.device
                         .destroy_sampler(ManuallyDrop::into_inner(sampler))
                 }
 
-                // this implicitly frees all the descript sets
-                self.device
-                    .destroy_descriptor_pool(ManuallyDrop::into_inner(read(descriptor_pool)));
+                for sampler_table in sampler_tables.drain(..) {
+                    sampler_table.manually_drop(self.device.deref());
+                }
+
+                // this implicitly frees all the descriptor sets
+                for descriptor_pool in descriptor_pools.drain(..) {
+                    self.device.destroy_descriptor_pool(descriptor_pool);
+                }
 
                 for descriptor_set_layout in descriptor_set_layouts.drain(..) {
                     self.device
@@ -1185,17 +3779,45 @@ impl core::ops::Drop for HalState {
             }
             self.device
                 .destroy_pipeline_layout(ManuallyDrop::into_inner(read(&self.pipeline_layout)));
+            for (_, pipeline) in self.pipelines.drain() {
+                self.device.destroy_graphics_pipeline(pipeline);
+            }
             self.device
-                .destroy_graphics_pipeline(ManuallyDrop::into_inner(read(
-                    &mut self.graphics_pipeline,
+                .destroy_shader_module(ManuallyDrop::into_inner(read(&self.vertex_shader_module)));
+            self.device
+                .destroy_shader_module(ManuallyDrop::into_inner(read(
+                    &self.fragment_shader_module,
                 )));
+            for custom in self.custom_pipelines.drain(..) {
+                self.device
+                    .destroy_shader_module(custom.vertex_shader_module);
+                self.device
+                    .destroy_shader_module(custom.fragment_shader_module);
+            }
+            for mesh in self.indirect_meshes.drain(..) {
+                mesh.destroy(&self.device);
+            }
             self.device.destroy_command_pool(
                 ManuallyDrop::into_inner(read(&self.command_pool)).into_raw(),
             );
+            if let Some(transfer_queue) = &self.transfer_queue {
+                self.device.destroy_command_pool(
+                    ManuallyDrop::into_inner(read(&transfer_queue.command_pool)).into_raw(),
+                );
+            }
+            if let Some(post_process) = self.post_process.take() {
+                post_process.destroy(&self.device);
+            }
+            if let Some(capture) = self.capture.take() {
+                capture.destroy(&self.device);
+            }
             self.device
                 .destroy_render_pass(ManuallyDrop::into_inner(read(&mut self.render_pass)));
             self.device
-                .destroy_swapchain(ManuallyDrop::into_inner(read(&mut self.swapchain)));
+                .destroy_render_pass(ManuallyDrop::into_inner(read(&mut self.load_render_pass)));
+            if let Some(swapchain) = self.swapchain.take() {
+                self.device.destroy_swapchain(swapchain);
+            }
             ManuallyDrop::drop(&mut self.device);
             ManuallyDrop::drop(&mut self._instance);
         }
@@ -0,0 +1,495 @@
+use crate::graphics::{initial_layout, is_srgb};
+use gfx_hal::{
+    adapter::{Adapter, MemoryTypeId, PhysicalDevice},
+    command::{CommandBuffer, MultiShot, Primary},
+    device::Device,
+    format::{Aspects, Format, Swizzle},
+    image::{
+        Access, Extent, Filter, Kind, Layout, SamplerInfo, SubresourceRange, Tiling, Usage,
+        ViewCapabilities, ViewKind, WrapMode,
+    },
+    memory::Properties,
+    pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, Subpass, SubpassDependency,
+        SubpassDesc, SubpassRef,
+    },
+    pso::{
+        BakedStates, BasePipeline, BlendDesc, BlendState, ColorBlendDesc, ColorMask,
+        DepthStencilDesc, DepthTest, Descriptor, DescriptorRangeDesc, DescriptorSetLayoutBinding,
+        DescriptorSetWrite, DescriptorType, EntryPoint, Face, FrontFace, GraphicsPipelineDesc,
+        GraphicsShaderSet, InputAssemblerDesc, PipelineCreationFlags, PipelineStage, PolygonMode,
+        Rasterizer, Rect, ShaderStageFlags, Specialization, StencilTest, Viewport,
+    },
+    window::Extent2D,
+    Backend, DescriptorPool, Graphics, Primitive,
+};
+use std::{iter, marker::PhantomData};
+
+/// Retro CRT look, applied to the whole screen after everything else is drawn. All the settings go
+/// from 0 (off) to 1 (a lot), see `HalState::set_crt_effect`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrtEffect {
+    /// How much the screen bulges out
+    pub curvature: f32,
+    /// How dark the gaps between scanlines get
+    pub scanlines: f32,
+    /// How far red and blue drift apart towards the edges
+    pub chromatic_aberration: f32,
+}
+
+impl Default for CrtEffect {
+    fn default() -> Self {
+        CrtEffect {
+            curvature: 0.3,
+            scanlines: 0.4,
+            chromatic_aberration: 0.3,
+        }
+    }
+}
+
+impl CrtEffect {
+    fn push_constants(self, height: u32, srgb_conversion: bool) -> [u32; 5] {
+        [
+            self.curvature.to_bits(),
+            self.scanlines.to_bits(),
+            self.chromatic_aberration.to_bits(),
+            (height as f32).to_bits(),
+            srgb_conversion as u32,
+        ]
+    }
+}
+
+/// What the scene gets drawn into with post processing on, whatever the swapchain's format is. The
+/// effect samples it as linear colors, and only the present pass encodes them for the surface
+pub(crate) const OFFSCREEN_FORMAT: Format = Format::Rgba8Srgb;
+
+/// The scene gets drawn into an offscreen image instead of the swapchain image, then a fullscreen
+/// triangle draws that image into the swapchain with the effect applied
+pub(crate) struct PostProcess<B: Backend, D: Device<B>> {
+    /// Pipelines for the scene get made against this one instead of the main render pass, since it
+    /// uses `OFFSCREEN_FORMAT`. It leaves the image ready to be sampled instead of presented
+    render_pass: B::RenderPass,
+    /// The same thing without the clear, for frames that draw over everything
+    load_render_pass: B::RenderPass,
+    present_pass: B::RenderPass,
+    sampler: B::Sampler,
+    descriptor_set_layout: B::DescriptorSetLayout,
+    descriptor_pool: B::DescriptorPool,
+    descriptor_set: B::DescriptorSet,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    /// The swapchain isn't sRGB, so the effect's shader does the encoding
+    srgb_conversion: bool,
+    target: Option<Target<B>>,
+    phantom: PhantomData<D>,
+}
+
+/// Everything that depends on the size of the swapchain
+struct Target<B: Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    image_view: B::ImageView,
+    framebuffer: B::Framebuffer,
+    present_framebuffers: Vec<B::Framebuffer>,
+    extent: Extent2D,
+    /// The load pass can only be used once the scene has been drawn into it
+    drawn: bool,
+}
+
+impl<B: Backend, D: Device<B>> PostProcess<B, D> {
+    /// `resize` has to be called before it can be used, `format` is the swapchain's
+    pub unsafe fn new(
+        device: &D,
+        format: Format,
+        vertex_spirv: &[u8],
+        fragment_spirv: &[u8],
+    ) -> Result<Self, &'static str> {
+        let color_attachment = |format, load, layouts| Attachment {
+            format: Some(format),
+            samples: 1,
+            ops: AttachmentOps {
+                load,
+                store: AttachmentStoreOp::Store,
+            },
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts,
+        };
+        let subpass = || SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+        let dependencies = [
+            // the last frame's present pass has to be done reading before this writes over it
+            SubpassDependency {
+                passes: SubpassRef::External..SubpassRef::Pass(0),
+                stages: PipelineStage::FRAGMENT_SHADER..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                accesses: Access::SHADER_READ..Access::COLOR_ATTACHMENT_WRITE,
+            },
+            // and the present pass has to wait for this to be done writing
+            SubpassDependency {
+                passes: SubpassRef::Pass(0)..SubpassRef::External,
+                stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+                accesses: Access::COLOR_ATTACHMENT_WRITE..Access::SHADER_READ,
+            },
+        ];
+        let offscreen_pass = |load| {
+            device
+                .create_render_pass(
+                    &[color_attachment(
+                        OFFSCREEN_FORMAT,
+                        load,
+                        initial_layout(load, Layout::ShaderReadOnlyOptimal)
+                            ..Layout::ShaderReadOnlyOptimal,
+                    )],
+                    &[subpass()],
+                    &dependencies,
+                )
+                .map_err(|_| "Couldn't create the offscreen render pass!")
+        };
+        let render_pass = offscreen_pass(AttachmentLoadOp::Clear)?;
+        let load_render_pass = offscreen_pass(AttachmentLoadOp::Load)?;
+        let present_pass = device
+            .create_render_pass(
+                &[Attachment {
+                    // every pixel gets drawn over anyway
+                    ops: AttachmentOps {
+                        load: AttachmentLoadOp::DontCare,
+                        store: AttachmentStoreOp::Store,
+                    },
+                    ..color_attachment(
+                        format,
+                        AttachmentLoadOp::DontCare,
+                        Layout::Undefined..Layout::Present,
+                    )
+                }],
+                &[subpass()],
+                &[],
+            )
+            .map_err(|_| "Couldn't create the post processing render pass!")?;
+
+        let sampler = device
+            .create_sampler(SamplerInfo::new(Filter::Linear, WrapMode::Clamp))
+            .map_err(|_| "Couldn't create the post processing sampler!")?;
+        let descriptor_set_layout = device
+            .create_descriptor_set_layout(
+                &[
+                    DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: DescriptorType::SampledImage,
+                        count: 1,
+                        stage_flags: ShaderStageFlags::FRAGMENT,
+                        immutable_samplers: false,
+                    },
+                    DescriptorSetLayoutBinding {
+                        binding: 1,
+                        ty: DescriptorType::Sampler,
+                        count: 1,
+                        stage_flags: ShaderStageFlags::FRAGMENT,
+                        immutable_samplers: false,
+                    },
+                ],
+                &[],
+            )
+            .map_err(|_| "Couldn't make the post processing DescriptorSetLayout")?;
+        let mut descriptor_pool = device
+            .create_descriptor_pool(
+                1,
+                &[
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::SampledImage,
+                        count: 1,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Sampler,
+                        count: 1,
+                    },
+                ],
+            )
+            .map_err(|_| "Couldn't create the post processing descriptor pool!")?;
+        let descriptor_set = descriptor_pool
+            .allocate_set(&descriptor_set_layout)
+            .map_err(|_| "Couldn't allocate the post processing descriptor set!")?;
+        device.write_descriptor_sets(Some(DescriptorSetWrite {
+            set: &descriptor_set,
+            binding: 1,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Sampler(&sampler)),
+        }));
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                iter::once(&descriptor_set_layout),
+                &[(ShaderStageFlags::FRAGMENT, 0..5)],
+            )
+            .map_err(|_| "Couldn't create the post processing pipeline layout!")?;
+
+        let vertex_module = device
+            .create_shader_module(vertex_spirv)
+            .map_err(|_| "Couldn't make the CRT vertex module!")?;
+        let fragment_module = device
+            .create_shader_module(fragment_spirv)
+            .map_err(|_| "Couldn't make the CRT fragment module!")?;
+        let entry_point = |module| EntryPoint {
+            entry: "main",
+            module,
+            specialization: Specialization {
+                constants: &[],
+                data: &[],
+            },
+        };
+        let desc = GraphicsPipelineDesc {
+            shaders: GraphicsShaderSet {
+                vertex: entry_point(&vertex_module),
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(entry_point(&fragment_module)),
+            },
+            rasterizer: Rasterizer {
+                depth_clamping: false,
+                polygon_mode: PolygonMode::Fill,
+                cull_face: Face::NONE,
+                front_face: FrontFace::Clockwise,
+                depth_bias: None,
+                conservative: false,
+            },
+            vertex_buffers: vec![],
+            attributes: vec![],
+            input_assembler: InputAssemblerDesc::new(Primitive::TriangleList),
+            blender: BlendDesc {
+                logic_op: None,
+                targets: vec![ColorBlendDesc(ColorMask::ALL, BlendState::Off)],
+            },
+            depth_stencil: DepthStencilDesc {
+                depth: DepthTest::Off,
+                depth_bounds: false,
+                stencil: StencilTest::Off,
+            },
+            layout: &pipeline_layout,
+            multisampling: None,
+            baked_states: BakedStates {
+                viewport: None,
+                scissor: None,
+                blend_color: None,
+                depth_bounds: None,
+            },
+            subpass: Subpass {
+                index: 0,
+                main_pass: &present_pass,
+            },
+            flags: PipelineCreationFlags::empty(),
+            parent: BasePipeline::None,
+        };
+        let pipeline = device.create_graphics_pipeline(&desc, None);
+        // the pipeline keeps what it needs from the modules
+        device.destroy_shader_module(vertex_module);
+        device.destroy_shader_module(fragment_module);
+        let pipeline = pipeline.map_err(|_| "Couldn't create the CRT pipeline!")?;
+
+        Ok(PostProcess {
+            render_pass,
+            load_render_pass,
+            present_pass,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            srgb_conversion: !is_srgb(format),
+            target: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Makes the offscreen image match the swapchain, the GPU can't be using the old one anymore
+    pub unsafe fn resize(
+        &mut self,
+        adapter: &Adapter<B>,
+        device: &D,
+        extent: Extent2D,
+        swapchain_views: &[B::ImageView],
+    ) -> Result<(), &'static str> {
+        if let Some(target) = self.target.take() {
+            target.destroy(device);
+        }
+        let mut image = device
+            .create_image(
+                Kind::D2(extent.width, extent.height, 1, 1),
+                1,
+                OFFSCREEN_FORMAT,
+                Tiling::Optimal,
+                Usage::COLOR_ATTACHMENT | Usage::SAMPLED,
+                ViewCapabilities::empty(),
+            )
+            .map_err(|_| "Couldn't create the offscreen image!")?;
+        let requirements = device.get_image_requirements(&image);
+        let memory_type_id = adapter
+            .physical_device
+            .memory_properties()
+            .memory_types
+            .iter()
+            .enumerate()
+            .find(|&(id, memory_type)| {
+                requirements.type_mask & (1 << id) != 0
+                    && memory_type.properties.contains(Properties::DEVICE_LOCAL)
+            })
+            .map(|(id, _)| MemoryTypeId(id))
+            .ok_or("Couldn't find memory type to support the offscreen image!")?;
+        let memory = device
+            .allocate_memory(memory_type_id, requirements.size)
+            .map_err(|_| "Couldn't allocate offscreen image memory!")?;
+        device
+            .bind_image_memory(&memory, 0, &mut image)
+            .map_err(|_| "Couldn't bind the offscreen image memory!")?;
+        let image_view = device
+            .create_image_view(
+                &image,
+                ViewKind::D2,
+                OFFSCREEN_FORMAT,
+                Swizzle::NO,
+                SubresourceRange {
+                    aspects: Aspects::COLOR,
+                    levels: 0..1,
+                    layers: 0..1,
+                },
+            )
+            .map_err(|_| "Couldn't create the offscreen image view!")?;
+        let framebuffer_extent = Extent {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let framebuffer = device
+            .create_framebuffer(&self.render_pass, Some(&image_view), framebuffer_extent)
+            .map_err(|_| "Failed to create the offscreen framebuffer!")?;
+        let present_framebuffers = swapchain_views
+            .iter()
+            .map(|view| {
+                device
+                    .create_framebuffer(&self.present_pass, Some(view), framebuffer_extent)
+                    .map_err(|_| "Failed to create a post processing framebuffer!")
+            })
+            .collect::<Result<Vec<_>, &str>>()?;
+        device.write_descriptor_sets(Some(DescriptorSetWrite {
+            set: &self.descriptor_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Image(
+                &image_view,
+                Layout::ShaderReadOnlyOptimal,
+            )),
+        }));
+        self.target = Some(Target {
+            image,
+            memory,
+            image_view,
+            framebuffer,
+            present_framebuffers,
+            extent,
+            drawn: false,
+        });
+        Ok(())
+    }
+
+    /// False until a frame got drawn into the offscreen image, before that it has to be cleared
+    pub fn target_drawn(&self) -> bool {
+        self.target.as_ref().map_or(false, |target| target.drawn)
+    }
+
+    pub fn mark_drawn(&mut self) {
+        if let Some(target) = self.target.as_mut() {
+            target.drawn = true;
+        }
+    }
+
+    /// The pass the scene gets drawn with, `clear` picks between clearing and keeping what's there
+    pub fn render_pass(&self, clear: bool) -> &B::RenderPass {
+        if clear {
+            &self.render_pass
+        } else {
+            &self.load_render_pass
+        }
+    }
+
+    /// Where the scene goes instead of the swapchain framebuffer
+    pub fn framebuffer(&self) -> Option<&B::Framebuffer> {
+        self.target.as_ref().map(|target| &target.framebuffer)
+    }
+
+    /// Draws the offscreen image into the swapchain image, after the scene's render pass has ended
+    pub unsafe fn record(
+        &self,
+        buffer: &mut CommandBuffer<B, Graphics, MultiShot, Primary>,
+        image_index: usize,
+        effect: CrtEffect,
+    ) {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return,
+        };
+        let area = Rect {
+            x: 0,
+            y: 0,
+            w: target.extent.width as i16,
+            h: target.extent.height as i16,
+        };
+        let mut encoder = buffer.begin_render_pass_inline(
+            &self.present_pass,
+            &target.present_framebuffers[image_index],
+            area,
+            iter::empty::<gfx_hal::command::ClearValue>(),
+        );
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.set_viewports(
+            0,
+            &[Viewport {
+                rect: area,
+                depth: (0.0..1.0),
+            }],
+        );
+        encoder.set_scissors(0, &[area]);
+        encoder.bind_graphics_descriptor_sets(
+            &self.pipeline_layout,
+            0,
+            Some(&self.descriptor_set),
+            &[],
+        );
+        encoder.push_graphics_constants(
+            &self.pipeline_layout,
+            ShaderStageFlags::FRAGMENT,
+            0,
+            &effect.push_constants(target.extent.height, self.srgb_conversion),
+        );
+        encoder.draw(0..3, 0..1);
+    }
+
+    pub unsafe fn destroy(self, device: &D) {
+        if let Some(target) = self.target {
+            target.destroy(device);
+        }
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        // this frees the set too
+        device.destroy_descriptor_pool(self.descriptor_pool);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout);
+        device.destroy_sampler(self.sampler);
+        device.destroy_render_pass(self.present_pass);
+        device.destroy_render_pass(self.render_pass);
+        device.destroy_render_pass(self.load_render_pass);
+    }
+}
+
+impl<B: Backend> Target<B> {
+    unsafe fn destroy<D: Device<B>>(self, device: &D) {
+        for framebuffer in self.present_framebuffers {
+            device.destroy_framebuffer(framebuffer);
+        }
+        device.destroy_framebuffer(self.framebuffer);
+        device.destroy_image_view(self.image_view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::graphics::FrameStats;
+use std::time::Duration;
+
+/// What a `QualityGovernor` just did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityChange {
+    /// Frames were over budget for a while, `frame_interval` is what they were taking
+    Lowered {
+        level: u32,
+        frame_interval: Duration,
+    },
+    /// Frames have been comfortably under budget for a while
+    Raised { level: u32 },
+}
+
+/// Steps quality down when frames take too long and back up once there's room again. Level 0 is
+/// the best quality, what each level turns off (MSAA on the next restart, the CRT pass, particle
+/// counts) is up to the game, through `level` or the `on_change` callbacks.
+///
+/// It goes by the time between presents, and only counts frames where the GPU is what's slow,
+/// meaning the CPU spent at least a quarter of the frame waiting on it in `FrameStats::gpu_wait`,
+/// since turning down effects does nothing for a frame that's slow on the CPU. A frame only
+/// counts as over budget once it's past the tolerance, 10% by default, so a 59.94 Hz display under
+/// vsync doesn't look like it's missing a 60 fps budget every frame. Feed it every frame's stats:
+///
+/// ```ignore
+/// if let Some(change) = governor.update(&ctx.graphics.frame_stats()) { ... }
+/// ```
+pub struct QualityGovernor {
+    budget: Duration,
+    /// In percent of the budget
+    tolerance: u32,
+    lowest: u32,
+    level: u32,
+    /// Frames over budget in a row before stepping down
+    lower_after: u32,
+    /// Frames under 3/4 of the budget in a row before stepping up
+    raise_after: u32,
+    over: u32,
+    under: u32,
+    callbacks: Vec<Box<dyn FnMut(QualityChange)>>,
+}
+
+impl std::fmt::Debug for QualityGovernor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("QualityGovernor")
+            .field("budget", &self.budget)
+            .field("level", &self.level)
+            .field("lowest", &self.lowest)
+            .finish()
+    }
+}
+
+impl QualityGovernor {
+    /// `levels` counts level 0. Steps down after half a second over budget and back up after
+    /// ten seconds well under it, at 60 fps
+    pub fn new(budget: Duration, levels: u32) -> Self {
+        QualityGovernor {
+            budget,
+            tolerance: 10,
+            lowest: levels.max(1) - 1,
+            level: 0,
+            lower_after: 30,
+            raise_after: 600,
+            over: 0,
+            under: 0,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// In frames. Raising should take a lot longer than lowering, or it goes back and forth
+    pub fn with_hysteresis(self, lower_after: u32, raise_after: u32) -> Self {
+        QualityGovernor {
+            lower_after: lower_after.max(1),
+            raise_after: raise_after.max(1),
+            ..self
+        }
+    }
+
+    /// How far past the budget frames can go before they count as over it, in percent. Anything
+    /// past 1000% is as good as never lowering, so it gets clamped to that
+    pub fn with_tolerance(self, percent: u32) -> Self {
+        QualityGovernor {
+            tolerance: percent.min(1000),
+            ..self
+        }
+    }
+
+    /// Runs on every change, for showing a notice or applying the new level
+    pub fn on_change<F: FnMut(QualityChange) + 'static>(&mut self, callback: F) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// For a settings menu, doesn't run the callbacks
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level.min(self.lowest);
+        self.over = 0;
+        self.under = 0;
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    pub fn update(&mut self, stats: &FrameStats) -> Option<QualityChange> {
+        let interval = stats.frame_interval;
+        // nothing to go on until there have been two presents
+        if interval == Duration::from_secs(0) {
+            return None;
+        }
+        let gpu_bound = stats.gpu_wait * 4 >= interval;
+        // a budget too long to multiply is one nothing's ever going to go over
+        let over_budget = match self.budget.checked_mul(100 + self.tolerance) {
+            Some(allowed) => interval
+                .checked_mul(100)
+                .map_or(true, |frame| frame > allowed),
+            None => false,
+        };
+        if over_budget && gpu_bound {
+            self.over = self.over.saturating_add(1);
+            self.under = 0;
+        } else if interval * 4 / 3 < self.budget {
+            self.under = self.under.saturating_add(1);
+            self.over = 0;
+        } else {
+            self.over = 0;
+            self.under = 0;
+        }
+
+        let change = if self.over >= self.lower_after && self.level < self.lowest {
+            self.level += 1;
+            Some(QualityChange::Lowered {
+                level: self.level,
+                frame_interval: interval,
+            })
+        } else if self.under >= self.raise_after && self.level > 0 {
+            self.level -= 1;
+            Some(QualityChange::Raised { level: self.level })
+        } else {
+            None
+        };
+        if let Some(change) = change {
+            // the smoothed interval takes a while to catch up with the new level
+            self.over = 0;
+            self.under = 0;
+            for callback in &mut self.callbacks {
+                callback(change);
+            }
+        }
+        change
+    }
+}
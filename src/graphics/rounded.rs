@@ -0,0 +1,110 @@
+use crate::geometry::{Rect, Vec2};
+use crate::graphics::{loadedimage::TextureId, vertex::Vertex};
+use std::f32::consts::PI;
+
+/// A rectangle with rounded corners, for UI cards and buttons. It gets turned into a triangle fan,
+/// so the edges are only as smooth as `segments` and multisampling make them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundedQuad {
+    /// x and y are the bottom left corner, same as `Quad::from(Rect)`
+    pub rect: Rect<f32, f32>,
+    /// Top left, top right, bottom right, bottom left. Each one gets clamped to half the shorter
+    /// side, and 0 gives a sharp corner
+    pub radii: [f32; 4],
+    pub color: [f32; 4],
+    /// Stretched over the whole rect, corners and all
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    /// Triangles per corner
+    pub segments: u16,
+}
+
+impl RoundedQuad {
+    pub fn new(rect: Rect<f32, f32>, radius: f32, color: [f32; 4]) -> Self {
+        RoundedQuad {
+            rect,
+            radii: [radius; 4],
+            color,
+            texture: TextureId::NONE,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            segments: 8,
+        }
+    }
+
+    /// Top left, top right, bottom right, bottom left
+    pub fn with_radii(self, radii: [f32; 4]) -> Self {
+        RoundedQuad { radii, ..self }
+    }
+
+    pub fn with_texture(self, texture: TextureId, uv_rect: [f32; 4]) -> Self {
+        RoundedQuad {
+            texture,
+            uv_rect,
+            ..self
+        }
+    }
+
+    pub fn with_segments(self, segments: u16) -> Self {
+        RoundedQuad { segments, ..self }
+    }
+
+    /// Adds the fan to `vertices` and `indices`, with the indices starting from what's already in
+    /// `vertices`
+    pub fn build(&self, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>) {
+        let Rect { x, y, w, h } = self.rect;
+        let max_radius = w.min(h).max(0.0) / 2.0;
+        let radius = |i: usize| self.radii[i].max(0.0).min(max_radius);
+        // going counterclockwise from the top left, y points up like in `Quad::from(Rect)`
+        let corners = [
+            (
+                Vec2::new(x + radius(0), y + h - radius(0)),
+                radius(0),
+                PI * 0.5,
+            ),
+            (Vec2::new(x + radius(3), y + radius(3)), radius(3), PI),
+            (
+                Vec2::new(x + w - radius(2), y + radius(2)),
+                radius(2),
+                PI * 1.5,
+            ),
+            (
+                Vec2::new(x + w - radius(1), y + h - radius(1)),
+                radius(1),
+                0.0,
+            ),
+        ];
+
+        let first = vertices.len() as u16;
+        vertices.push(self.vertex(Vec2::new(x + w / 2.0, y + h / 2.0)));
+        for &(center, radius, start) in &corners {
+            let segments = if radius > 0.0 {
+                self.segments.max(1)
+            } else {
+                0
+            };
+            for i in 0..=segments {
+                let angle = start + PI / 2.0 * f32::from(i) / f32::from(segments.max(1));
+                vertices.push(self.vertex(center + Vec2::new(angle.cos(), angle.sin()) * radius));
+            }
+        }
+        let ring = vertices.len() as u16 - first - 1;
+        for i in 0..ring {
+            indices.extend_from_slice(&[first, first + 1 + i, first + 1 + (i + 1) % ring]);
+        }
+    }
+
+    fn vertex(&self, p: Vec2<f32>) -> Vertex {
+        let Rect { x, y, w, h } = self.rect;
+        Vertex {
+            xy: [p.x, p.y],
+            uv: [
+                (p.x - x) / w.max(core::f32::EPSILON),
+                (y + h - p.y) / h.max(core::f32::EPSILON),
+            ],
+            uv_rect: self.uv_rect,
+            tex_num: self.texture.index(),
+            color: self.color,
+        }
+    }
+}
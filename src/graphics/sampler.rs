@@ -0,0 +1,61 @@
+use gfx_hal::image::{Anisotropic, Filter, Lod, SamplerInfo, WrapMode};
+
+/// How a texture gets sampled. Identical descriptions share one sampler, and there can only be
+/// `MAX_SAMPLERS` different ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDesc {
+    pub filter: Filter,
+    pub wrap: WrapMode,
+    /// Samples taken at steep angles, None turns it off. Ignored when the device can't do it
+    pub anisotropy: Option<u8>,
+    /// Added to the mip level the GPU picks, negative values sharpen
+    pub mip_bias: f32,
+}
+
+impl SamplerDesc {
+    /// Crisp pixels and no bleeding in from the other side of the texture
+    pub const PIXEL_ART: SamplerDesc = SamplerDesc {
+        filter: Filter::Nearest,
+        wrap: WrapMode::Clamp,
+        anisotropy: None,
+        mip_bias: 0.0,
+    };
+
+    /// For anything that gets scaled or rotated a lot
+    pub const SMOOTH: SamplerDesc = SamplerDesc {
+        filter: Filter::Linear,
+        wrap: WrapMode::Clamp,
+        anisotropy: Some(8),
+        mip_bias: 0.0,
+    };
+
+    /// What happens outside of [0, 1]. `Clamp` stretches the edge pixels, `Tile` repeats the
+    /// texture and `Mirror` repeats it flipped every other time so the seams line up
+    pub fn with_wrap(self, wrap: WrapMode) -> Self {
+        SamplerDesc { wrap, ..self }
+    }
+
+    pub(crate) fn info(self, anisotropy_supported: bool) -> SamplerInfo {
+        let mut info = SamplerInfo::new(self.filter, self.wrap);
+        info.lod_bias = Lod::from(self.mip_bias);
+        info.anisotropic = match self.anisotropy {
+            Some(samples) if anisotropy_supported && samples > 1 => {
+                Anisotropic::On(samples.min(16))
+            }
+            _ => Anisotropic::Off,
+        };
+        info
+    }
+}
+
+/// What textures got before samplers were configurable
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc {
+            filter: Filter::Nearest,
+            wrap: WrapMode::Tile,
+            anisotropy: None,
+            mip_bias: 0.0,
+        }
+    }
+}
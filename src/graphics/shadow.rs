@@ -0,0 +1,46 @@
+use crate::geometry::{Quad, Vec2};
+use crate::graphics::TexturedQuad;
+
+/// A soft copy of what's being drawn in a single color, behind it and a bit off to the side. Used
+/// by `Frame::draw_quads_with_shadow` and friends, which draw the copies with `Material::Shadow`.
+/// Distance field text gets a blob instead of a shadow from that, draw `shadow_quad`s with
+/// `Material::DistanceField` for a sharp shadow there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DropShadow {
+    pub offset: Vec2<f32>,
+    /// In texels of the texture being drawn, not on screen
+    pub blur: u16,
+    pub color: [f32; 4],
+}
+
+impl DropShadow {
+    pub fn new(offset: Vec2<f32>, blur: u16, color: [f32; 4]) -> Self {
+        DropShadow {
+            offset,
+            blur,
+            color,
+        }
+    }
+
+    /// Moved by the offset and in the shadow's color, the alpha gets multiplied so fading the
+    /// quad out fades the shadow too
+    pub fn shadow_quad(&self, textured_quad: &TexturedQuad) -> TexturedQuad {
+        let Quad {
+            top_left,
+            bottom_left,
+            bottom_right,
+            top_right,
+        } = textured_quad.quad;
+        let [r, g, b, a] = self.color;
+        TexturedQuad {
+            quad: Quad {
+                top_left: top_left + self.offset,
+                bottom_left: bottom_left + self.offset,
+                bottom_right: bottom_right + self.offset,
+                top_right: top_right + self.offset,
+            },
+            color: [r, g, b, a * textured_quad.color[3]],
+            ..*textured_quad
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{anim::Easing, geometry::Vec2, graphics::camera::Camera2D};
+use std::time::Duration;
+
+/// One screen shake, see `HalState::apply_shake`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShakeParams {
+    /// Furthest the camera gets pushed, in world units
+    pub offset: Vec2<f32>,
+    /// Furthest the camera gets rotated, in degrees
+    pub rotation: f64,
+    /// Furthest the zoom gets pushed, 0.1 means anywhere from 0.9x to 1.1x
+    pub zoom: f32,
+    /// How many times per second the shake changes direction, roughly
+    pub frequency: f32,
+    pub duration: Duration,
+    /// How the intensity dies down over the duration, Linear goes from full to nothing evenly
+    pub falloff: Easing,
+}
+
+impl Default for ShakeParams {
+    fn default() -> Self {
+        ShakeParams {
+            offset: Vec2::new(0.02, 0.02),
+            rotation: 0.0,
+            zoom: 0.0,
+            frequency: 20.0,
+            duration: Duration::from_millis(300),
+            falloff: Easing::QuadOut,
+        }
+    }
+}
+
+/// What all the running shakes add up to right now
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ShakeOffset {
+    pub offset: Vec2<f32>,
+    pub rotation: f64,
+    pub zoom: f32,
+}
+
+impl ShakeOffset {
+    /// `camera` with the shake on top
+    pub fn apply(&self, camera: &Camera2D) -> Camera2D {
+        Camera2D {
+            position: camera.position + self.offset,
+            zoom: camera.zoom * (1.0 + self.zoom),
+            rotation: camera.rotation + self.rotation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveShake {
+    params: ShakeParams,
+    elapsed: Duration,
+    seed: u32,
+}
+
+/// Keeps track of running shakes, `HalState` has one of these that runs on wall clock time. Use
+/// your own with `Frame::set_camera` to drive shakes from game time instead
+#[derive(Debug, Clone, Default)]
+pub struct ScreenShake {
+    shakes: Vec<ActiveShake>,
+    next_seed: u32,
+}
+
+impl ScreenShake {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a shake, it adds on top of any that are already running
+    pub fn add(&mut self, params: ShakeParams) {
+        self.shakes.push(ActiveShake {
+            params,
+            elapsed: Duration::from_secs(0),
+            seed: self.next_seed,
+        });
+        // every shake gets its own noise so two at once don't just double up
+        self.next_seed = self.next_seed.wrapping_add(4);
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        for shake in &mut self.shakes {
+            shake.elapsed += dt;
+        }
+        self.shakes
+            .retain(|shake| shake.elapsed < shake.params.duration);
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        !self.shakes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.shakes.clear();
+    }
+
+    pub fn offset(&self) -> ShakeOffset {
+        let mut total = ShakeOffset::default();
+        for shake in &self.shakes {
+            let params = &shake.params;
+            let elapsed = shake.elapsed.as_secs_f32();
+            let progress = elapsed / params.duration.as_secs_f32().max(std::f32::EPSILON);
+            let intensity = 1.0 - params.falloff.apply(progress);
+            let t = elapsed * params.frequency;
+            total.offset.x += params.offset.x * intensity * noise(shake.seed, t);
+            total.offset.y += params.offset.y * intensity * noise(shake.seed + 1, t);
+            total.rotation += params.rotation * f64::from(intensity * noise(shake.seed + 2, t));
+            total.zoom += params.zoom * intensity * noise(shake.seed + 3, t);
+        }
+        total
+    }
+}
+
+/// Smooth 1D value noise from -1 to 1, `seed` picks the channel
+fn noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let frac = t - i;
+    let (a, b) = (lattice(seed, i as i32), lattice(seed, i as i32 + 1));
+    // smoothstep so the direction changes aren't sudden
+    let frac = frac * frac * (3.0 - 2.0 * frac);
+    a + (b - a) * frac
+}
+
+fn lattice(seed: u32, i: i32) -> f32 {
+    let mut x = (i as u32) ^ seed.wrapping_mul(0x9e37_79b9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x7feb_352d);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 23) as f32 - 1.0
+}
@@ -0,0 +1,473 @@
+use crate::geometry::Rect;
+use crate::graphics::{
+    camera::Camera2D,
+    frame::{BatchKind, Frame},
+    loadedimage::TextureId,
+    material::Material,
+    shake::ShakeOffset,
+    vertex::Vertex,
+    BlendMode, TexturedQuad,
+};
+use image::RgbaImage;
+
+/// Draws a `Frame` on the CPU, for checking geometry, batching and camera stuff in tests, or as a
+/// fallback on machines without a GPU. Everything goes through the same transforms and uv math as
+/// `HalState::draw_frame`, but sampling is always nearest and there's no multisampling, sRGB
+/// conversion or post processing, so compare against the GPU with some tolerance. Custom pipelines,
+/// indirect meshes and anything drawn into a render target get skipped
+#[derive(Debug, Clone, Default)]
+pub struct SoftwareRasterizer {
+    textures: Vec<RgbaImage>,
+    camera: Camera2D,
+}
+
+impl SoftwareRasterizer {
+    pub fn new() -> Self {
+        SoftwareRasterizer::default()
+    }
+
+    /// Ids count up from 0 like `HalState::load_image`, so loading the same images in the same
+    /// order into both gives the same ids
+    pub fn add_texture(&mut self, image: RgbaImage) -> TextureId {
+        self.textures.push(image);
+        TextureId(self.textures.len() as u32 - 1)
+    }
+
+    pub fn camera(&self) -> &Camera2D {
+        &self.camera
+    }
+
+    pub fn set_camera(&mut self, camera: Camera2D) {
+        self.camera = camera;
+    }
+
+    pub fn render(&self, frame: &Frame, width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        self.render_into(frame, &mut image);
+        image
+    }
+
+    /// Same as `render` but into an existing image, so rendering frame after frame doesn't allocate
+    /// a new one every time. Without a clear color the frame gets drawn over what's in `image`
+    pub fn render_into(&self, frame: &Frame, image: &mut RgbaImage) {
+        let (width, height) = image.dimensions();
+        let pixels = match frame.clear_color() {
+            Some(color) => vec![color; (width * height) as usize],
+            None => image
+                .pixels()
+                .map(|pixel| {
+                    let channel = |i: usize| f32::from(pixel[i]) / 255.0;
+                    [channel(0), channel(1), channel(2), channel(3)]
+                })
+                .collect(),
+        };
+        let mut target = Target {
+            width,
+            height,
+            pixels,
+        };
+        let shake = ShakeOffset::default();
+        for batch in &frame.batches {
+            if batch.state.target.is_some() {
+                continue;
+            }
+            let view = batch.state.view_matrix(&self.camera, &shake);
+            let viewport = match batch.state.viewport {
+                Some(viewport) => viewport,
+                None => Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    w: 1.0,
+                    h: 1.0,
+                },
+            };
+            // same rounding as viewport_rect
+            let (w, h) = (width as f32, height as f32);
+            let x0 = (viewport.x * w).round();
+            let y0 = (viewport.y * h).round();
+            let viewport = [
+                x0,
+                y0,
+                ((viewport.x + viewport.w) * w).round() - x0,
+                ((viewport.y + viewport.h) * h).round() - y0,
+            ];
+            let (blend_mode, material) = (batch.state.blend_mode, batch.state.material);
+            match &batch.kind {
+                BatchKind::Quads(range) => {
+                    for textured_quad in &frame.quads[range.clone()] {
+                        let vertices = TexturedQuad {
+                            quad: textured_quad.quad.transform(view),
+                            ..*textured_quad
+                        }
+                        .to_vertices();
+                        // same as the quad index buffer
+                        for triangle in &[[0, 1, 2], [2, 3, 0]] {
+                            let triangle = [
+                                vertices[triangle[0]],
+                                vertices[triangle[1]],
+                                vertices[triangle[2]],
+                            ];
+                            self.draw_triangle(
+                                &mut target,
+                                viewport,
+                                blend_mode,
+                                material,
+                                triangle,
+                            );
+                        }
+                    }
+                }
+                BatchKind::Mesh { vertices, indices } => {
+                    for triangle in frame.indices[indices.clone()].chunks_exact(3) {
+                        let vertex =
+                            |i: u16| frame.vertices[vertices.start + i as usize].transform(view);
+                        let triangle = [
+                            vertex(triangle[0]),
+                            vertex(triangle[1]),
+                            vertex(triangle[2]),
+                        ];
+                        self.draw_triangle(&mut target, viewport, blend_mode, material, triangle);
+                    }
+                }
+                BatchKind::Custom { .. } | BatchKind::Indirect { .. } => (),
+            }
+        }
+        target.write_to(image);
+    }
+
+    /// `viewport` is x, y, width, height in pixels
+    fn draw_triangle(
+        &self,
+        target: &mut Target,
+        viewport: [f32; 4],
+        blend_mode: BlendMode,
+        material: Material,
+        triangle: [Vertex; 3],
+    ) {
+        let [vx, vy, vw, vh] = viewport;
+        // clip space y points down, same as Vulkan
+        let point = |v: &Vertex| {
+            [
+                vx + (v.xy[0] + 1.0) * 0.5 * vw,
+                vy + (v.xy[1] + 1.0) * 0.5 * vh,
+            ]
+        };
+        let points = [
+            point(&triangle[0]),
+            point(&triangle[1]),
+            point(&triangle[2]),
+        ];
+        let area = edge(points[0], points[1], points[2]);
+        if area == 0.0 {
+            return;
+        }
+        // the viewport doubles as the scissor rect
+        let bounds = |axis: usize, low: f32, high: f32| {
+            let min = points.iter().map(|p| p[axis]).fold(std::f32::MAX, f32::min);
+            let max = points.iter().map(|p| p[axis]).fold(std::f32::MIN, f32::max);
+            (
+                min.max(low).max(0.0).floor() as u32,
+                max.min(high).max(0.0).ceil() as u32,
+            )
+        };
+        let edges = [
+            (points[1], points[2]),
+            (points[2], points[0]),
+            (points[0], points[1]),
+        ];
+        let (min_x, max_x) = bounds(0, vx, (vx + vw).min(target.width as f32));
+        let (min_y, max_y) = bounds(1, vy, (vy + vh).min(target.height as f32));
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                // barycentric weights, both windings get drawn since nothing gets culled
+                let weights = [
+                    edge(points[1], points[2], p) / area,
+                    edge(points[2], points[0], p) / area,
+                    edge(points[0], points[1], p) / area,
+                ];
+                // a pixel right on an edge only goes to one of the triangles sharing it, otherwise
+                // the diagonal of every quad would get blended twice
+                let outside = weights.iter().zip(&edges).any(|(&weight, &(a, b))| {
+                    weight < 0.0 || (weight == 0.0 && !owns_edge(a, b, area))
+                });
+                if outside {
+                    continue;
+                }
+                let interpolate = |attribute: fn(&Vertex) -> [f32; 4]| {
+                    let mut out = [0.0; 4];
+                    for (vertex, weight) in triangle.iter().zip(&weights) {
+                        for (out, value) in out.iter_mut().zip(&attribute(vertex)) {
+                            *out += weight * value;
+                        }
+                    }
+                    out
+                };
+                let uv = interpolate(|v| [v.uv[0], v.uv[1], 0.0, 0.0]);
+                let uv_rect = interpolate(|v| v.uv_rect);
+                let color = interpolate(|v| v.color);
+                // the same mix as the vertex shader
+                let u = uv_rect[0] + (uv_rect[2] - uv_rect[0]) * uv[0];
+                let v = uv_rect[1] + (uv_rect[3] - uv_rect[1]) * uv[1];
+                // tex_num is flat, so it comes from the first vertex like on the GPU
+                let texel = match self.textures.get(triangle[0].tex_num as usize) {
+                    Some(texture) => sample(texture, u, v),
+                    None => [1.0; 4],
+                };
+                // same as the MATERIAL branches in fragment.glsl
+                let second = material
+                    .texture()
+                    .and_then(|texture| self.textures.get(texture.index() as usize))
+                    .map_or([1.0; 4], |texture| sample(texture, u, v));
+                let palette = match material {
+                    Material::Palette(palette) => self
+                        .textures
+                        .get(palette.index() as usize)
+                        .map_or([1.0; 4], |palette| {
+                            let entry = (texel[0] * 255.0).round();
+                            sample(palette, (entry + 0.5) / palette.width() as f32, 0.5)
+                        }),
+                    _ => [1.0; 4],
+                };
+                let mut src = [0.0; 4];
+                for (i, out) in src.iter_mut().enumerate() {
+                    *out = match material {
+                        Material::Plain | Material::Mask(_) => texel[i] * color[i],
+                        Material::Multiply(_) => texel[i] * color[i] * second[i],
+                        Material::TintMask(_) => texel[i] * (1.0 + (color[i] - 1.0) * second[0]),
+                        Material::DistanceField => color[i],
+                        // no blur on the CPU
+                        Material::Shadow { .. } if i == 3 => texel[3] * color[3],
+                        Material::Shadow { .. } => color[i],
+                        Material::Palette(_) if i == 3 => palette[3] * texel[3] * color[3],
+                        Material::Palette(_) => palette[i] * color[i],
+                    };
+                }
+                if let Material::DistanceField = material {
+                    // no fwidth on the CPU, so the edge gets a fixed softness instead
+                    src[3] *= smoothstep(0.45, 0.55, texel[3]);
+                }
+                if let Material::Mask(_) = material {
+                    src[3] *= second[0];
+                }
+                let dst = &mut target.pixels[(y * target.width + x) as usize];
+                *dst = blend(blend_mode, src, *dst);
+            }
+        }
+    }
+}
+
+struct Target {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Target {
+    fn write_to(self, image: &mut RgbaImage) {
+        for (pixel, color) in image.pixels_mut().zip(self.pixels) {
+            for (channel, value) in pixel.data.iter_mut().zip(&color) {
+                *channel = (value.max(0.0).min(1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+/// Twice the signed area of the triangle (a, b, c)
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Whether pixels exactly on the edge from `a` to `b` belong to the triangle, `area` being its
+/// `edge`. The two triangles on either side of an edge go along it in opposite directions once
+/// their winding is evened out, so exactly one of them gets the pixel
+fn owns_edge(a: [f32; 2], b: [f32; 2], area: f32) -> bool {
+    let (dx, dy) = if area > 0.0 {
+        (b[0] - a[0], b[1] - a[1])
+    } else {
+        (a[0] - b[0], a[1] - b[1])
+    };
+    dy > 0.0 || (dy == 0.0 && dx < 0.0)
+}
+
+/// Nearest neighbour with the same tiling as the GPU samplers
+fn sample(texture: &RgbaImage, u: f32, v: f32) -> [f32; 4] {
+    let (w, h) = texture.dimensions();
+    if w == 0 || h == 0 {
+        return [0.0; 4];
+    }
+    let x = ((u - u.floor()) * w as f32) as u32;
+    let y = ((v - v.floor()) * h as f32) as u32;
+    let texel = texture.get_pixel(x.min(w - 1), y.min(h - 1));
+    [
+        f32::from(texel[0]) / 255.0,
+        f32::from(texel[1]) / 255.0,
+        f32::from(texel[2]) / 255.0,
+        f32::from(texel[3]) / 255.0,
+    ]
+}
+
+/// The blend states from `BlendMode`'s `Into<BlendState>`, clamped like a unorm target would be
+fn blend(blend_mode: BlendMode, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    let a = src[3];
+    let mut out = [0.0; 4];
+    for ((out, &s), &d) in out.iter_mut().zip(&src).zip(&dst).take(3) {
+        *out = match blend_mode {
+            BlendMode::Alpha => s * a + d * (1.0 - a),
+            BlendMode::Additive => s * a + d,
+            BlendMode::Multiply => s * d,
+            BlendMode::PremultipliedAlpha => s + d * (1.0 - a),
+            BlendMode::None => s,
+        };
+    }
+    out[3] = match blend_mode {
+        BlendMode::Alpha | BlendMode::PremultipliedAlpha => a + dst[3] * (1.0 - a),
+        BlendMode::Additive => a + dst[3],
+        BlendMode::Multiply => a * dst[3],
+        BlendMode::None => a,
+    };
+    for channel in &mut out {
+        *channel = channel.max(0.0).min(1.0);
+    }
+    out
+}
+
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = ((x - low) / (high - low)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geometry::Quad, graphics::Space};
+    use image::Rgba;
+
+    const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+    const TEXELS: [[u8; 4]; 4] = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 0, 255],
+    ];
+
+    /// In screen space (-1, -1) to (1, 1) is the whole image, with y = -1 at the top row
+    fn screen_quad(x: f32, y: f32, w: f32, h: f32, texture: TextureId) -> TexturedQuad {
+        TexturedQuad::new(Quad::from(Rect { x, y, w, h }), texture.index())
+    }
+
+    fn screen_frame(clear_color: [f32; 4]) -> Frame {
+        let mut frame = Frame::new();
+        frame
+            .set_clear_color(Some(clear_color))
+            .set_space(Space::Screen);
+        frame
+    }
+
+    /// Red, green, blue and yellow texels, left to right and top to bottom
+    fn checker(rasterizer: &mut SoftwareRasterizer) -> TextureId {
+        rasterizer.add_texture(RgbaImage::from_fn(2, 2, |x, y| Rgba {
+            data: TEXELS[(y * 2 + x) as usize],
+        }))
+    }
+
+    fn assert_pixels(image: &RgbaImage, expected: impl Fn(u32, u32) -> [u8; 4]) {
+        for (x, y, pixel) in image.enumerate_pixels() {
+            assert_eq!(pixel.data, expected(x, y), "pixel at {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn fills_only_covered_pixels() {
+        let mut frame = screen_frame(BLACK);
+        frame.draw_quads(&[screen_quad(-1.0, -1.0, 1.0, 2.0, TextureId::NONE)]);
+        let image = SoftwareRasterizer::new().render(&frame, 4, 4);
+        assert_pixels(&image, |x, _| if x < 2 { [255; 4] } else { [0, 0, 0, 255] });
+    }
+
+    #[test]
+    fn viewport_clips_fills() {
+        let mut frame = screen_frame(BLACK);
+        frame.set_viewport(Some(Rect {
+            x: 0.5,
+            y: 0.0,
+            w: 0.5,
+            h: 0.5,
+        }));
+        frame.draw_quads(&[screen_quad(-1.0, -1.0, 2.0, 2.0, TextureId::NONE)]);
+        let image = SoftwareRasterizer::new().render(&frame, 4, 4);
+        assert_pixels(&image, |x, y| {
+            if x >= 2 && y < 2 {
+                [255; 4]
+            } else {
+                [0, 0, 0, 255]
+            }
+        });
+    }
+
+    #[test]
+    fn maps_uvs_onto_texels() {
+        let mut rasterizer = SoftwareRasterizer::new();
+        let texture = checker(&mut rasterizer);
+        let mut frame = screen_frame(BLACK);
+        frame.draw_quads(&[screen_quad(-1.0, -1.0, 2.0, 2.0, texture)]);
+        let image = rasterizer.render(&frame, 4, 4);
+        assert_pixels(&image, |x, y| TEXELS[(y / 2 * 2 + x / 2) as usize]);
+    }
+
+    #[test]
+    fn uv_rect_picks_part_of_the_texture() {
+        let mut rasterizer = SoftwareRasterizer::new();
+        let texture = checker(&mut rasterizer);
+        let mut frame = screen_frame(BLACK);
+        frame.draw_quads(&[TexturedQuad {
+            uv_rect: [0.5, 0.0, 1.0, 1.0],
+            ..screen_quad(-1.0, -1.0, 2.0, 2.0, texture)
+        }]);
+        let image = rasterizer.render(&frame, 4, 4);
+        assert_pixels(&image, |_, y| TEXELS[(y / 2 * 2 + 1) as usize]);
+    }
+
+    #[test]
+    fn flipping_mirrors_the_uvs() {
+        let mut rasterizer = SoftwareRasterizer::new();
+        let texture = checker(&mut rasterizer);
+        let mut frame = screen_frame(BLACK);
+        frame.draw_quads(&[screen_quad(-1.0, -1.0, 2.0, 2.0, texture).flipped(true, false)]);
+        let image = rasterizer.render(&frame, 4, 4);
+        assert_pixels(&image, |x, y| TEXELS[(y / 2 * 2 + 1 - x / 2) as usize]);
+    }
+
+    #[test]
+    fn alpha_blends_every_pixel_once() {
+        let mut frame = screen_frame(BLACK);
+        frame.set_blend_mode(BlendMode::Alpha);
+        frame
+            .draw_quads(&[screen_quad(-1.0, -1.0, 2.0, 2.0, TextureId::NONE)
+                .with_color([1.0, 1.0, 1.0, 0.25])]);
+        let image = SoftwareRasterizer::new().render(&frame, 4, 4);
+        assert_pixels(&image, |_, _| [64, 64, 64, 255]);
+    }
+
+    #[test]
+    fn additive_blending_clamps() {
+        let mut frame = screen_frame([0.25, 0.5, 1.0, 1.0]);
+        frame.set_blend_mode(BlendMode::Additive);
+        frame.draw_quads(&[
+            screen_quad(-1.0, -1.0, 2.0, 2.0, TextureId::NONE).with_color([0.5, 0.5, 0.5, 1.0])
+        ]);
+        let image = SoftwareRasterizer::new().render(&frame, 4, 4);
+        assert_pixels(&image, |_, _| [191, 255, 255, 255]);
+    }
+
+    #[test]
+    fn multiply_blending() {
+        let mut frame = screen_frame([0.5, 0.4, 0.2, 1.0]);
+        frame.set_blend_mode(BlendMode::Multiply);
+        frame.draw_quads(&[
+            screen_quad(-1.0, -1.0, 2.0, 2.0, TextureId::NONE).with_color([0.5, 1.0, 0.0, 1.0])
+        ]);
+        let image = SoftwareRasterizer::new().render(&frame, 4, 4);
+        assert_pixels(&image, |_, _| [64, 102, 0, 255]);
+    }
+}
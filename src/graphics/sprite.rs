@@ -0,0 +1,129 @@
+use crate::{
+    geometry::{Quad, Vec2},
+    graphics::{loadedimage::TextureId, TexturedQuad, UvRotation},
+};
+
+/// Which point of a sprite sits on its position and everything rotates and scales around
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// A fraction of the size, (0, 0) is the bottom left corner and (1, 1) the top right
+    Custom(Vec2<f32>),
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Anchor::Center
+    }
+}
+
+impl Anchor {
+    /// Where the anchor is as a fraction of the size, (0, 0) being the bottom left corner
+    pub fn fraction(self) -> Vec2<f32> {
+        match self {
+            Anchor::Center => Vec2::new(0.5, 0.5),
+            Anchor::TopLeft => Vec2::new(0.0, 1.0),
+            Anchor::TopRight => Vec2::new(1.0, 1.0),
+            Anchor::BottomLeft => Vec2::new(0.0, 0.0),
+            Anchor::BottomRight => Vec2::new(1.0, 0.0),
+            Anchor::Custom(fraction) => fraction,
+        }
+    }
+}
+
+/// A textured rectangle placed by its pivot, so positioning and spinning things doesn't need any
+/// matrices. Draw it with `Frame::draw_sprites`, or turn it into a `TexturedQuad` yourself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    /// Size before scaling, in world units
+    pub size: Vec2<f32>,
+    pub pivot: Anchor,
+    /// Where the pivot ends up
+    pub position: Vec2<f32>,
+    /// In degrees counterclockwise around the pivot, same as `Quad::rotate_around_center_matrix`
+    pub rotation: f64,
+    pub scale: Vec2<f32>,
+    /// Multiplied with the texture color
+    pub tint: [f32; 4],
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Sprite {
+    /// The whole texture, centered on the origin, with no rotation, scaling or tint
+    pub fn new(texture: TextureId, size: Vec2<f32>) -> Self {
+        Sprite {
+            texture,
+            uv_rect: TexturedQuad::FULL_UV,
+            size,
+            pivot: Anchor::Center,
+            position: Vec2::zero(),
+            rotation: 0.0,
+            scale: Vec2::new(1.0, 1.0),
+            tint: [1.0; 4],
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+
+    pub fn with_position(self, position: Vec2<f32>) -> Self {
+        Sprite { position, ..self }
+    }
+
+    pub fn with_pivot(self, pivot: Anchor) -> Self {
+        Sprite { pivot, ..self }
+    }
+
+    pub fn with_rotation(self, rotation: f64) -> Self {
+        Sprite { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: Vec2<f32>) -> Self {
+        Sprite { scale, ..self }
+    }
+
+    pub fn with_tint(self, tint: [f32; 4]) -> Self {
+        Sprite { tint, ..self }
+    }
+
+    pub fn with_uv_rect(self, uv_rect: [f32; 4]) -> Self {
+        Sprite { uv_rect, ..self }
+    }
+
+    /// Where the corners end up in world space
+    pub fn quad(&self) -> Quad {
+        let size = self.size * self.scale;
+        let pivot = self.pivot.fraction() * size;
+        let radians = (self.rotation / 360.0 * (std::f64::consts::PI * 2.0)) as f32;
+        let (sin, cos) = radians.sin_cos();
+        let corner = |x: f32, y: f32| {
+            let local = Vec2::new(x, y) * size - pivot;
+            self.position + Vec2::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos)
+        };
+        Quad {
+            top_left: corner(0.0, 1.0),
+            bottom_left: corner(0.0, 0.0),
+            bottom_right: corner(1.0, 0.0),
+            top_right: corner(1.0, 1.0),
+        }
+    }
+
+    pub fn textured_quad(&self) -> TexturedQuad {
+        TexturedQuad {
+            quad: self.quad(),
+            uv_rect: self.uv_rect,
+            tex_num: self.texture.index(),
+            color: self.tint,
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+            rotation: UvRotation::None,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use gfx_hal::{format::Format, window::PresentMode};
+use std::time::{Duration, Instant};
+use winit::dpi::LogicalSize;
+
+/// What went into a frame, returned from the draw calls and also kept around in
+/// `HalState::frame_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// Includes the extra draws when a batch is too big for u16 indices
+    pub draw_calls: usize,
+    /// Quads that made it past culling
+    pub quads: usize,
+    pub textures_bound: usize,
+    /// Blocked waiting for the GPU to finish with this frame's resources
+    pub gpu_wait: Duration,
+    /// Writing vertices and recording the command buffer, close to zero when it got reused
+    pub cpu_record_time: Duration,
+    /// The vertex and index writing part of `cpu_record_time`, zero when the command buffer got
+    /// reused
+    pub upload_time: Duration,
+    /// From getting the swapchain image to handing it back to be presented
+    pub acquire_to_present: Duration,
+    /// Rough guess at how long it takes for input read right before the draw call to show up on
+    /// screen. gfx-hal doesn't expose any presentation timing extensions, so this is the time spent in
+    /// the draw call plus however many refreshes the present mode can keep frames queued for
+    pub estimated_latency: Duration,
+    /// Smoothed time between presents, zero for the first frame
+    pub frame_interval: Duration,
+}
+
+/// What the `HalState` ended up running on, for bug and crash reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// "vulkan", "dx12" or "metal"
+    pub backend: &'static str,
+    pub adapter_name: String,
+    /// PCI vendor and device ids
+    pub vendor: usize,
+    pub device: usize,
+    pub device_type: String,
+    pub swapchain: SwapchainInfo,
+    pub max_textures: usize,
+}
+
+/// What the swapchain actually got made with, from `HalState::swapchain_info`. It changes when the
+/// swapchain gets recreated, except for the format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapchainInfo {
+    /// In physical pixels, same as `HalState::drawable_size`
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    /// Can be more than was asked for, drivers are allowed to hand out extra images
+    pub image_count: usize,
+    pub present_mode: PresentMode,
+    /// Frames the CPU can get ahead of the GPU, at most `image_count`
+    pub frames_in_flight: usize,
+    pub hidpi_factor: f64,
+}
+
+impl SwapchainInfo {
+    pub fn drawable_size(&self) -> DrawableSize {
+        DrawableSize {
+            width: self.width,
+            height: self.height,
+            hidpi_factor: self.hidpi_factor,
+        }
+    }
+}
+
+/// What actually gets drawn to, in physical pixels. It can be smaller than the window when the
+/// surface caps the swapchain's size, see `ContextHandler::on_drawable_resize`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawableSize {
+    pub width: u32,
+    pub height: u32,
+    pub hidpi_factor: f64,
+}
+
+impl DrawableSize {
+    /// Width over height
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    /// For laying out UI
+    pub fn logical(&self) -> LogicalSize {
+        LogicalSize::new(
+            f64::from(self.width) / self.hidpi_factor,
+            f64::from(self.height) / self.hidpi_factor,
+        )
+    }
+}
+
+/// What the device and window surface support, from `HalState::capabilities`, for settings menus
+/// and bug reports. Formats and present modes are their gfx-hal names so it serializes without
+/// gfx-hal's own serde support
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Capabilities {
+    /// "vulkan", "dx12" or "metal"
+    pub backend: &'static str,
+    pub adapter_name: String,
+    pub device_type: String,
+    /// Width and height limit for a single texture
+    pub max_texture_size: usize,
+    /// How many textures a draw can use, see `HalState::max_textures`
+    pub max_texture_array_size: usize,
+    pub max_viewports: usize,
+    pub max_color_attachments: usize,
+    /// Out of a list of common ones, the texture formats that can be sampled from
+    pub texture_formats: Vec<String>,
+    /// None when the surface takes any format
+    pub surface_formats: Option<Vec<String>>,
+    pub present_modes: Vec<String>,
+    /// How many swapchain images the surface allows, the end is exclusive
+    pub swapchain_images: (u32, u32),
+    /// Size of each memory heap in bytes
+    pub memory_heaps: Vec<u64>,
+}
+
+/// Keeps track of the time between presents to turn queued frames into a duration
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FrameTimer {
+    last_present: Option<Instant>,
+    frame_interval: Duration,
+}
+
+impl FrameTimer {
+    /// Smoothed time between presents, zero until there have been two
+    pub fn frame_interval(&self) -> Duration {
+        self.frame_interval
+    }
+
+    /// Fills in the timing part of `stats`
+    pub fn presented(
+        &mut self,
+        stats: FrameStats,
+        frame_start: Instant,
+        acquired: Instant,
+        present_mode: PresentMode,
+        frames_in_flight: usize,
+    ) -> FrameStats {
+        let now = Instant::now();
+        if let Some(last_present) = self.last_present {
+            // smoothed so a single hitch doesn't throw the estimate off
+            self.frame_interval = self.frame_interval * 7 / 8 + (now - last_present) / 8;
+        }
+        self.last_present = Some(now);
+
+        let queued_frames = match present_mode {
+            PresentMode::Immediate => 0,
+            PresentMode::Mailbox => 1,
+            // vsync'd modes fill up the whole queue as soon as rendering is faster than the display
+            PresentMode::Fifo | PresentMode::Relaxed => frames_in_flight as u32,
+        };
+        FrameStats {
+            acquire_to_present: now - acquired,
+            estimated_latency: (now - frame_start) + self.frame_interval * queued_frames,
+            frame_interval: self.frame_interval,
+            ..stats
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use image::RgbaImage;
+
+/// Renders an SVG document to an image, `scale` is relative to the size the document says it is
+pub fn rasterize_svg(data: &[u8], scale: f32) -> Result<RgbaImage, &'static str> {
+    let tree =
+        usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|_| "invalid svg!")?;
+    let size = tree.svg_node().size;
+    let width = (size.width() as f32 * scale).ceil() as u32;
+    let height = (size.height() as f32 * scale).ceil() as u32;
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or("The svg has no area at that scale!")?;
+    resvg::render(&tree, usvg::FitTo::Zoom(scale), pixmap.as_mut())
+        .ok_or("Couldn't render the svg!")?;
+
+    // tiny-skia works in premultiplied alpha, everything else expects straight alpha
+    let mut pixels = pixmap.take();
+    for pixel in pixels.chunks_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        if alpha != 0 && alpha != 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((u16::from(*channel) * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+    RgbaImage::from_raw(width, height, pixels).ok_or("Couldn't make an image out of the svg!")
+}
@@ -0,0 +1,118 @@
+use crate::graphics::{loadedimage::LoadedImage, postprocess::OFFSCREEN_FORMAT};
+use gfx_hal::{
+    device::Device,
+    image::{Access, Extent, Layout},
+    pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDependency,
+        SubpassDesc, SubpassRef,
+    },
+    pso::PipelineStage,
+    Backend,
+};
+use std::{collections::HashMap, marker::PhantomData};
+
+/// Textures that frames can draw into with `Frame::set_target`. They live in the texture pool like
+/// any other texture, this only keeps the render pass and a framebuffer for each of them
+pub(crate) struct RenderTargets<B: Backend, D: Device<B>> {
+    /// Compatible with the post processing pass, so the scene's pipelines work for both
+    render_pass: B::RenderPass,
+    /// By texture index
+    framebuffers: HashMap<usize, B::Framebuffer>,
+    phantom: PhantomData<D>,
+}
+
+impl<B: Backend, D: Device<B>> RenderTargets<B, D> {
+    pub unsafe fn new(device: &D) -> Result<Self, &'static str> {
+        let render_pass = device
+            .create_render_pass(
+                &[Attachment {
+                    format: Some(OFFSCREEN_FORMAT),
+                    samples: 1,
+                    ops: AttachmentOps {
+                        load: AttachmentLoadOp::Clear,
+                        store: AttachmentStoreOp::Store,
+                    },
+                    stencil_ops: AttachmentOps::DONT_CARE,
+                    layouts: Layout::Undefined..Layout::ShaderReadOnlyOptimal,
+                }],
+                &[SubpassDesc {
+                    colors: &[(0, Layout::ColorAttachmentOptimal)],
+                    depth_stencil: None,
+                    inputs: &[],
+                    resolves: &[],
+                    preserves: &[],
+                }],
+                &[
+                    // the last frame might still be sampling it
+                    SubpassDependency {
+                        passes: SubpassRef::External..SubpassRef::Pass(0),
+                        stages: PipelineStage::FRAGMENT_SHADER
+                            ..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                        accesses: Access::SHADER_READ..Access::COLOR_ATTACHMENT_WRITE,
+                    },
+                    // and everything drawn after this samples it
+                    SubpassDependency {
+                        passes: SubpassRef::Pass(0)..SubpassRef::External,
+                        stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                            ..PipelineStage::FRAGMENT_SHADER,
+                        accesses: Access::COLOR_ATTACHMENT_WRITE..Access::SHADER_READ,
+                    },
+                ],
+            )
+            .map_err(|_| "Couldn't create the render target pass!")?;
+        Ok(RenderTargets {
+            render_pass,
+            framebuffers: HashMap::new(),
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn render_pass(&self) -> &B::RenderPass {
+        &self.render_pass
+    }
+
+    pub fn framebuffer(&self, index: usize) -> Option<&B::Framebuffer> {
+        self.framebuffers.get(&index)
+    }
+
+    pub fn indices(&self) -> Vec<usize> {
+        self.framebuffers.keys().cloned().collect()
+    }
+
+    /// Makes `texture` the target for `index`, it has to be `extent` big and made with
+    /// `LoadedImage::new_render_target`
+    pub unsafe fn insert(
+        &mut self,
+        device: &D,
+        index: usize,
+        texture: &LoadedImage<B, D>,
+        extent: Extent,
+    ) -> Result<(), &'static str> {
+        let framebuffer = device
+            .create_framebuffer(&self.render_pass, Some(&*texture.image_view), extent)
+            .map_err(|_| "Couldn't create the render target framebuffer!")?;
+        if let Some(old) = self.framebuffers.insert(index, framebuffer) {
+            device.destroy_framebuffer(old);
+        }
+        Ok(())
+    }
+
+    /// The GPU can't be using the framebuffer anymore, returns whether there was one
+    pub unsafe fn remove(&mut self, device: &D, index: usize) -> bool {
+        match self.framebuffers.remove(&index) {
+            Some(framebuffer) => {
+                device.destroy_framebuffer(framebuffer);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The textures themselves go with the texture pool
+    pub unsafe fn destroy(self, device: &D) {
+        for (_, framebuffer) in self.framebuffers {
+            device.destroy_framebuffer(framebuffer);
+        }
+        device.destroy_render_pass(self.render_pass);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::geometry::{Quad, Vec2};
+use crate::graphics::{frame::Frame, loadedimage::TextureId, shadow::DropShadow, TexturedQuad};
+use std::ops::Range;
+
+/// Where a character is in its font's texture and how it sits on the line, in ems so one set of
+/// metrics works at any size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    pub size: Vec2<f32>,
+    /// From the pen position on the baseline to the top left corner, y goes down
+    pub offset: Vec2<f32>,
+    /// How far the pen moves after this glyph
+    pub advance: f32,
+}
+
+/// Anything that can hand out glyphs for the layout to place
+pub trait Font {
+    fn glyph(&self, c: char) -> Option<Glyph>;
+    /// From the top of a line to its baseline, in ems
+    fn ascent(&self) -> f32;
+    /// From one baseline to the next, in ems
+    fn line_height(&self) -> f32 {
+        1.0
+    }
+    /// Added to the advance between two characters, in ems
+    fn kerning(&self, _left: char, _right: char) -> f32 {
+        0.0
+    }
+}
+
+/// A monospaced font in a grid of equally sized cells, row by row, starting at `first`. The
+/// classic 16x6 ASCII sheet starting at ' ' is `BitmapFont::new(texture, 16, 6, ' ')`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitmapFont {
+    pub texture: TextureId,
+    pub columns: u32,
+    pub rows: u32,
+    pub first: char,
+    /// Width of a cell divided by its height
+    pub cell_aspect: f32,
+    /// Where the baseline is, as a fraction of the cell height from the top
+    pub ascent: f32,
+}
+
+impl BitmapFont {
+    pub fn new(texture: TextureId, columns: u32, rows: u32, first: char) -> Self {
+        BitmapFont {
+            texture,
+            columns,
+            rows,
+            first,
+            cell_aspect: 1.0,
+            ascent: 0.8,
+        }
+    }
+
+    pub fn with_cell_aspect(self, cell_aspect: f32) -> Self {
+        BitmapFont {
+            cell_aspect,
+            ..self
+        }
+    }
+}
+
+impl Font for BitmapFont {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        let index = (c as u32).checked_sub(self.first as u32)?;
+        if index >= self.columns * self.rows {
+            return None;
+        }
+        let (column, row) = (index % self.columns, index / self.columns);
+        let (w, h) = (1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        Some(Glyph {
+            texture: self.texture,
+            uv_rect: [
+                column as f32 * w,
+                row as f32 * h,
+                (column + 1) as f32 * w,
+                (row + 1) as f32 * h,
+            ],
+            size: Vec2::new(self.cell_aspect, 1.0),
+            offset: Vec2::new(0.0, -self.ascent),
+            advance: self.cell_aspect,
+        })
+    }
+
+    fn ascent(&self) -> f32 {
+        self.ascent
+    }
+}
+
+/// A run of text that's all the same size and color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextSpan<'a> {
+    pub text: &'a str,
+    /// World units per em
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl<'a> TextSpan<'a> {
+    pub fn new(text: &'a str, size: f32) -> Self {
+        TextSpan {
+            text,
+            size,
+            color: [1.0; 4],
+        }
+    }
+
+    pub fn with_color(self, color: [f32; 4]) -> Self {
+        TextSpan { color, ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Where and how to lay out text. Coordinates go down like clip space, `position` is the top left
+/// corner of the box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextBox {
+    pub position: Vec2<f32>,
+    /// Lines wrap at spaces to stay narrower than this, words that don't fit on a line of their
+    /// own get broken up
+    pub max_width: Option<f32>,
+    /// Only used for vertical alignment, the text can still run out of the box
+    pub height: Option<f32>,
+    pub align: Align,
+    pub vertical_align: VerticalAlign,
+    /// Multiplies the font's line height
+    pub line_spacing: f32,
+}
+
+impl Default for TextBox {
+    fn default() -> Self {
+        TextBox {
+            position: Vec2::zero(),
+            max_width: None,
+            height: None,
+            align: Align::Left,
+            vertical_align: VerticalAlign::Top,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// One character after layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub c: char,
+    /// Byte offset into all of the spans' text put together
+    pub index: usize,
+    pub span: usize,
+    pub line: usize,
+    /// The pen position on the baseline
+    pub position: Vec2<f32>,
+    pub advance: f32,
+    /// None for whitespace and characters the font doesn't have
+    pub quad: Option<TexturedQuad>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMetrics {
+    /// Indices into `TextLayout::glyphs`, without the newline that ended it
+    pub glyphs: Range<usize>,
+    /// Where the line starts after alignment
+    pub x: f32,
+    pub top: f32,
+    pub baseline: f32,
+    pub height: f32,
+    /// Not counting whitespace at the end
+    pub width: f32,
+}
+
+/// What `TextBox::layout` comes up with
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub lines: Vec<LineMetrics>,
+    /// Of the text itself, which can be smaller or bigger than the box
+    pub size: Vec2<f32>,
+}
+
+impl TextLayout {
+    pub fn quads<'a>(&'a self) -> impl Iterator<Item = TexturedQuad> + 'a {
+        self.glyphs.iter().filter_map(|glyph| glyph.quad)
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let quads: Vec<TexturedQuad> = self.quads().collect();
+        frame.draw_quads(&quads);
+    }
+
+    /// See `DropShadow` for distance field fonts
+    pub fn draw_with_shadow(&self, frame: &mut Frame, shadow: &DropShadow) {
+        let quads: Vec<TexturedQuad> = self.quads().collect();
+        frame.draw_quads_with_shadow(&quads, shadow);
+    }
+}
+
+struct Char {
+    c: char,
+    index: usize,
+    span: usize,
+    glyph: Option<Glyph>,
+}
+
+impl TextBox {
+    pub fn new(position: Vec2<f32>) -> Self {
+        TextBox {
+            position,
+            ..TextBox::default()
+        }
+    }
+
+    pub fn with_max_width(self, max_width: f32) -> Self {
+        TextBox {
+            max_width: Some(max_width),
+            ..self
+        }
+    }
+
+    pub fn with_height(self, height: f32) -> Self {
+        TextBox {
+            height: Some(height),
+            ..self
+        }
+    }
+
+    pub fn with_align(self, align: Align, vertical_align: VerticalAlign) -> Self {
+        TextBox {
+            align,
+            vertical_align,
+            ..self
+        }
+    }
+
+    pub fn with_line_spacing(self, line_spacing: f32) -> Self {
+        TextBox {
+            line_spacing,
+            ..self
+        }
+    }
+
+    pub fn layout(&self, font: &dyn Font, spans: &[TextSpan]) -> TextLayout {
+        let mut chars = Vec::new();
+        let mut offset = 0;
+        for (span, text) in spans.iter().enumerate() {
+            for (i, c) in text.text.char_indices() {
+                let glyph = font.glyph(c).or_else(|| font.glyph('?'));
+                chars.push(Char {
+                    c,
+                    index: offset + i,
+                    span,
+                    glyph,
+                });
+            }
+            offset += text.text.len();
+        }
+        let size_of = |ch: &Char| spans[ch.span].size;
+        let advance = |chars: &[Char], i: usize, line_start: usize| {
+            let ch = &chars[i];
+            let mut advance = ch.glyph.map_or(0.0, |g| g.advance);
+            if i > line_start {
+                advance += font.kerning(chars[i - 1].c, ch.c);
+            }
+            advance * size_of(ch)
+        };
+
+        // split into lines at newlines and wherever it gets too wide
+        let mut ranges = Vec::new();
+        let (mut start, mut x, mut last_break) = (0, 0.0, None);
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i].c == '\n' {
+                ranges.push(start..i);
+                start = i + 1;
+                x = 0.0;
+                last_break = None;
+                i += 1;
+                continue;
+            }
+            let width = advance(&chars, i, start);
+            let too_wide = self.max_width.map_or(false, |max| x + width > max);
+            if too_wide && i > start && !chars[i].c.is_whitespace() {
+                let end = last_break.filter(|&b| b > start).unwrap_or(i);
+                ranges.push(start..end);
+                start = end;
+                x = 0.0;
+                last_break = None;
+                i = end;
+                continue;
+            }
+            x += width;
+            if chars[i].c.is_whitespace() {
+                last_break = Some(i + 1);
+            }
+            i += 1;
+        }
+        ranges.push(start..chars.len());
+
+        let default_size = spans.first().map_or(0.0, |span| span.size);
+        let mut layout = TextLayout::default();
+        let mut top = 0.0;
+        let mut size = default_size;
+        for (line, range) in ranges.into_iter().enumerate() {
+            let line_chars = &chars[range.clone()];
+            // empty lines keep the size of whatever came before them
+            if let Some(ch) = line_chars.first() {
+                size = size_of(ch);
+            }
+            let biggest = line_chars.iter().map(size_of).fold(size, f32::max);
+            let ascent = font.ascent() * biggest;
+            let height = font.line_height() * biggest * self.line_spacing;
+            let baseline = top + ascent;
+
+            let first = layout.glyphs.len();
+            let (mut x, mut width) = (0.0, 0.0);
+            for (i, ch) in line_chars.iter().enumerate() {
+                let advance = advance(&chars, range.start + i, range.start);
+                let span = &spans[ch.span];
+                let quad = match ch.glyph {
+                    Some(glyph) if !ch.c.is_whitespace() => {
+                        let top_left = Vec2::new(x, baseline) + glyph.offset * span.size;
+                        let bottom_right = top_left + glyph.size * span.size;
+                        let quad = Quad {
+                            top_left,
+                            bottom_left: Vec2::new(top_left.x, bottom_right.y),
+                            bottom_right,
+                            top_right: Vec2::new(bottom_right.x, top_left.y),
+                        };
+                        Some(
+                            TexturedQuad {
+                                uv_rect: glyph.uv_rect,
+                                ..TexturedQuad::new(quad, glyph.texture.index())
+                            }
+                            .with_color(span.color),
+                        )
+                    }
+                    _ => None,
+                };
+                layout.glyphs.push(PositionedGlyph {
+                    c: ch.c,
+                    index: ch.index,
+                    span: ch.span,
+                    line,
+                    position: Vec2::new(x, baseline),
+                    advance,
+                    quad,
+                });
+                x += advance;
+                if !ch.c.is_whitespace() {
+                    width = x;
+                }
+            }
+            layout.lines.push(LineMetrics {
+                glyphs: first..layout.glyphs.len(),
+                x: 0.0,
+                top,
+                baseline,
+                height,
+                width,
+            });
+            top += height;
+        }
+
+        let text_width = layout.lines.iter().map(|l| l.width).fold(0.0, f32::max);
+        layout.size = Vec2::new(text_width, top);
+        let box_width = self.max_width.unwrap_or(text_width);
+        let box_height = self.height.unwrap_or(top);
+        let dy = match self.vertical_align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Middle => (box_height - top) / 2.0,
+            VerticalAlign::Bottom => box_height - top,
+        };
+        for line in &mut layout.lines {
+            let dx = match self.align {
+                Align::Left => 0.0,
+                Align::Center => (box_width - line.width) / 2.0,
+                Align::Right => box_width - line.width,
+            };
+            let shift = self.position + Vec2::new(dx, dy);
+            line.x = shift.x;
+            line.top += shift.y;
+            line.baseline += shift.y;
+            for glyph in &mut layout.glyphs[line.glyphs.clone()] {
+                glyph.position += shift;
+                if let Some(quad) = &mut glyph.quad {
+                    quad.quad.top_left += shift;
+                    quad.quad.bottom_left += shift;
+                    quad.quad.bottom_right += shift;
+                    quad.quad.top_right += shift;
+                }
+            }
+        }
+        layout
+    }
+}
@@ -0,0 +1,222 @@
+use crate::geometry::Vec2;
+use crate::graphics::{frame::Frame, loadedimage::TextureId, vertex::Vertex};
+
+/// How the corners between two segments of a trail get filled in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    /// Sharp corners, miters longer than `limit` times the half width get clipped to that length
+    Miter { limit: f32 },
+    /// Rounded corners, each one made out of `segments` triangles
+    Round { segments: u16 },
+}
+
+/// Turns a list of points into a mesh of constant width, for hold note bodies, mouse trails, slider
+/// paths and so on. The texture gets stretched along the whole trail, with u going across and v along it
+#[derive(Debug, Clone)]
+pub struct TrailRenderer {
+    pub width: f32,
+    pub join: LineJoin,
+    pub texture: TextureId,
+    /// Normalized like `TexturedQuad::uv_rect`
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+}
+
+impl TrailRenderer {
+    pub fn textured(width: f32, texture: TextureId, uv_rect: [f32; 4]) -> Self {
+        TrailRenderer {
+            width,
+            join: LineJoin::Miter { limit: 4.0 },
+            texture,
+            uv_rect,
+            color: [1.0; 4],
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn solid(width: f32, color: [f32; 4]) -> Self {
+        TrailRenderer {
+            color,
+            ..TrailRenderer::textured(width, TextureId::NONE, [0.0; 4])
+        }
+    }
+
+    pub fn with_join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    /// Builds the mesh for `points`, the returned slices are only valid until the next call
+    pub fn build(&mut self, points: &[Vec2<f32>]) -> (&[Vertex], &[u16]) {
+        self.vertices.clear();
+        self.indices.clear();
+
+        // repeated points don't have a direction, so they'd just produce NaNs
+        let mut deduped: Vec<Vec2<f32>> = Vec::with_capacity(points.len());
+        for &p in points {
+            match deduped.last() {
+                Some(&last) if length(p - last) < std::f32::EPSILON => {}
+                _ => deduped.push(p),
+            }
+        }
+        let points = deduped;
+        if points.len() < 2 {
+            return (&self.vertices, &self.indices);
+        }
+
+        let half_width = self.width / 2.0;
+        let directions: Vec<Vec2<f32>> = points
+            .windows(2)
+            .map(|w| {
+                let d = w[1] - w[0];
+                d / length(d)
+            })
+            .collect();
+        let normals: Vec<Vec2<f32>> = directions.iter().map(|d| Vec2::new(-d.y, d.x)).collect();
+        let total_length: f32 = points.windows(2).map(|w| length(w[1] - w[0])).sum();
+        let mut v_coords = Vec::with_capacity(points.len());
+        let mut travelled = 0.0;
+        v_coords.push(0.0);
+        for w in points.windows(2) {
+            travelled += length(w[1] - w[0]);
+            v_coords.push(travelled / total_length);
+        }
+
+        let last = points.len() - 1;
+        match self.join {
+            LineJoin::Miter { limit } => {
+                for i in 0..points.len() {
+                    let offset = if i == 0 {
+                        normals[0] * half_width
+                    } else if i == last {
+                        normals[last - 1] * half_width
+                    } else {
+                        let miter = normals[i - 1] + normals[i];
+                        let miter_length = length(miter);
+                        if miter_length < std::f32::EPSILON {
+                            // the trail folds back on itself, there's no sensible miter
+                            normals[i] * half_width
+                        } else {
+                            let miter = miter / miter_length;
+                            miter * (half_width / miter.dot(normals[i])).min(limit * half_width)
+                        }
+                    };
+                    self.push_pair(points[i], offset, v_coords[i]);
+                    if i > 0 {
+                        let base = (2 * (i - 1)) as u16;
+                        self.indices.extend_from_slice(&[
+                            base,
+                            base + 1,
+                            base + 2,
+                            base + 2,
+                            base + 1,
+                            base + 3,
+                        ]);
+                    }
+                }
+            }
+            LineJoin::Round { segments } => {
+                for i in 0..last {
+                    let base = self.vertices.len() as u16;
+                    self.push_pair(points[i], normals[i] * half_width, v_coords[i]);
+                    self.push_pair(points[i + 1], normals[i] * half_width, v_coords[i + 1]);
+                    self.indices.extend_from_slice(&[
+                        base,
+                        base + 1,
+                        base + 2,
+                        base + 2,
+                        base + 1,
+                        base + 3,
+                    ]);
+                    if i + 1 < last {
+                        let turn = directions[i].x * directions[i + 1].y
+                            - directions[i].y * directions[i + 1].x;
+                        // the fan goes on the outside of the turn, the inside is already covered
+                        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+                        // left of the trail is u = 0, right is u = 1, same as push_pair
+                        let u = if side > 0.0 { 0.0 } else { 1.0 };
+                        self.push_round_join(
+                            points[i + 1],
+                            normals[i] * side * half_width,
+                            normals[i + 1] * side * half_width,
+                            [u, v_coords[i + 1]],
+                            segments.max(1),
+                        );
+                    }
+                }
+            }
+        }
+        debug_assert!(
+            self.vertices.len() <= core::u16::MAX as usize + 1,
+            "trail has too many vertices for u16 indices"
+        );
+        (&self.vertices, &self.indices)
+    }
+
+    /// Builds the mesh for `points` and adds it to the frame
+    pub fn draw(&mut self, frame: &mut Frame, points: &[Vec2<f32>]) {
+        let texture = self.texture;
+        let (vertices, indices) = self.build(points);
+        if !indices.is_empty() {
+            frame.draw_mesh(vertices, indices, texture);
+        }
+    }
+
+    fn vertex(&self, p: Vec2<f32>, uv: [f32; 2]) -> Vertex {
+        Vertex {
+            xy: [p.x, p.y],
+            uv,
+            uv_rect: self.uv_rect,
+            tex_num: self.texture.index(),
+            color: self.color,
+        }
+    }
+
+    fn push_pair(&mut self, p: Vec2<f32>, offset: Vec2<f32>, v: f32) {
+        let left = self.vertex(p + offset, [0.0, v]);
+        let right = self.vertex(p - offset, [1.0, v]);
+        self.vertices.push(left);
+        self.vertices.push(right);
+    }
+
+    /// `u` is the edge of the texture the outside of the turn is on
+    fn push_round_join(
+        &mut self,
+        center: Vec2<f32>,
+        from: Vec2<f32>,
+        to: Vec2<f32>,
+        [u, v]: [f32; 2],
+        segments: u16,
+    ) {
+        use std::f32::consts::PI;
+        let radius = length(from);
+        let start = from.y.atan2(from.x);
+        let mut sweep = to.y.atan2(to.x) - start;
+        if sweep > PI {
+            sweep -= 2.0 * PI;
+        } else if sweep < -PI {
+            sweep += 2.0 * PI;
+        }
+        let center_index = self.vertices.len() as u16;
+        let center_vertex = self.vertex(center, [0.5, v]);
+        self.vertices.push(center_vertex);
+        for k in 0..=segments {
+            let angle = start + sweep * f32::from(k) / f32::from(segments);
+            let p = center + Vec2::new(angle.cos(), angle.sin()) * radius;
+            let arc_vertex = self.vertex(p, [u, v]);
+            self.vertices.push(arc_vertex);
+            if k > 0 {
+                let arc_index = center_index + k;
+                self.indices
+                    .extend_from_slice(&[center_index, arc_index, arc_index + 1]);
+            }
+        }
+    }
+}
+
+fn length(v: Vec2<f32>) -> f32 {
+    v.dot(v).sqrt()
+}
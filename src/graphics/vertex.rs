@@ -1,6 +1,7 @@
+use crate::geometry::{Mat3, Vec3};
 use gfx_hal::{
     format::Format,
-    pso::{AttributeDesc, ElemOffset, Element},
+    pso::{AttributeDesc, ElemOffset, ElemStride, Element, VertexBufferDesc},
 };
 use std::mem;
 
@@ -9,64 +10,144 @@ use std::mem;
 pub struct Vertex {
     pub xy: [f32; 2],
     pub uv: [f32; 2],
+    /// Normalized like `TexturedQuad::uv_rect`, `uv` picks the spot inside it
     pub uv_rect: [f32; 4],
     pub tex_num: u32,
+    /// Multiplied with the texture color, or used as is for `TextureId::NONE`
+    pub color: [f32; 4],
+}
+
+unsafe impl VertexLayout for Vertex {
+    fn format() -> VertexFormat {
+        VertexFormat::new()
+            .attribute(Format::Rg32Float)
+            .attribute(Format::Rg32Float)
+            .attribute(Format::Rgba32Float)
+            .attribute(Format::R32Uint)
+            .attribute(Format::Rgba32Float)
+    }
+}
+
+/// Vertex types that can be handed to `Frame::draw_custom`. This is unsafe because the vertices get
+/// copied into the vertex buffer byte for byte, so the type has to be `#[repr(C)]` without any
+/// padding, and `format` has to describe it exactly
+pub unsafe trait VertexLayout: Copy {
+    fn format() -> VertexFormat;
+}
+
+/// A custom pipeline's vertex with four floats of shader parameters after it, for per-quad effects
+/// like hue shifting single notes without splitting the draw call. Make the pipeline with this
+/// type's `format`, the parameters come in at the location after `V`'s last attribute.
+/// `Frame::draw_custom_with_params` fills them in from one block per quad (or however many
+/// vertices make up one thing)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct WithParams<V> {
+    pub vertex: V,
+    pub params: [f32; 4],
+}
+
+// the f32s only line up right after `V` when its size is a multiple of 4, otherwise repr(C) pads
+// in between and the assert catches it before a pipeline gets made with the wrong format
+unsafe impl<V: VertexLayout> VertexLayout for WithParams<V> {
+    fn format() -> VertexFormat {
+        let format = V::format().attribute(Format::Rgba32Float);
+        assert_eq!(
+            mem::size_of::<Self>(),
+            format.stride() as usize,
+            "WithParams needs a vertex that's a multiple of 4 bytes"
+        );
+        format
+    }
+}
+
+/// One attribute in a `VertexFormat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VertexAttribute {
+    /// Matches `layout (location = ...)` in the vertex shader
+    pub location: u32,
+    pub format: Format,
+    /// In bytes from the start of the vertex
+    pub offset: u32,
+}
+
+/// How the vertices of a custom pipeline are laid out, see `HalState::create_custom_pipeline`.
+/// Attributes get packed one after the other in the order they're added, at locations counting up
+/// from 0, which is the same as a `#[repr(C)]` struct of f32 and u32 arrays
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct VertexFormat {
+    attributes: Vec<VertexAttribute>,
+    stride: u32,
+}
+
+impl VertexFormat {
+    pub fn new() -> Self {
+        VertexFormat::default()
+    }
+
+    /// Adds an attribute at the next location, right after the last one
+    pub fn attribute(mut self, format: Format) -> Self {
+        self.attributes.push(VertexAttribute {
+            location: self.attributes.len() as u32,
+            format,
+            offset: self.stride,
+        });
+        self.stride += u32::from(format.surface_desc().bits) / 8;
+        self
+    }
+
+    /// Skips some bytes the shader doesn't read
+    pub fn padding(mut self, bytes: u32) -> Self {
+        self.stride += bytes;
+        self
+    }
+
+    /// Size of one whole vertex in bytes
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn buffer_desc(&self, binding: u32) -> VertexBufferDesc {
+        VertexBufferDesc {
+            binding,
+            stride: self.stride as ElemStride,
+            rate: 0,
+        }
+    }
+
+    pub fn attribute_descs(&self) -> Vec<AttributeDesc> {
+        self.attributes
+            .iter()
+            .map(|attribute| AttributeDesc {
+                location: attribute.location,
+                binding: 0,
+                element: Element {
+                    format: attribute.format,
+                    offset: attribute.offset as ElemOffset,
+                },
+            })
+            .collect()
+    }
 }
 impl Vertex {
     pub fn attributes() -> Vec<AttributeDesc> {
-        const POSITION_ATTR_SIZE: usize = mem::size_of::<f32>() * 2;
-        //const COLOR_ATTR_SIZE: usize = mem::size_of::<f32>() * 3;
-        const UV_ATTR_SIZE: usize = mem::size_of::<f32>() * 2;
-        const UV_RECT_ATTR_SIZE: usize = mem::size_of::<f32>() * 4;
-
-        let position_attribute = AttributeDesc {
-            location: 0,
-            binding: 0,
-            element: Element {
-                format: Format::Rg32Float,
-                offset: 0,
-            },
-        };
-        /*let color_attribute = AttributeDesc {
-            location: 1,
-            binding: 0,
-            element: Element {
-            format: Format::Rgb32Float,
-            offset: POSITION_ATTR_SIZE as ElemOffset,
-        },
-        };*/
-        let uv_attribute = AttributeDesc {
-            location: 1,
-            binding: 0,
-            element: Element {
-                format: Format::Rg32Float,
-                offset: POSITION_ATTR_SIZE as ElemOffset,
-            },
-        };
-        let uv_rect_attribute = AttributeDesc {
-            location: 2,
-            binding: 0,
-            element: Element {
-                format: Format::Rgba32Float,
-                offset: (POSITION_ATTR_SIZE + UV_ATTR_SIZE) as ElemOffset,
-            },
-        };
-        let tex_num_attribute = AttributeDesc {
-            location: 3,
-            binding: 0,
-            element: Element {
-                format: Format::R32Uint,
-                offset: (POSITION_ATTR_SIZE + UV_ATTR_SIZE + UV_RECT_ATTR_SIZE) as ElemOffset,
-            },
-        };
-
-        vec![
-            position_attribute,
-            uv_attribute,
-            uv_rect_attribute,
-            tex_num_attribute,
-        ]
+        Self::format().attribute_descs()
     }
+
+    /// Applies a 2D homogeneous transform to the position, leaving everything else alone
+    pub fn transform(self, rhs: Mat3<f32>) -> Vertex {
+        let [x, y] = self.xy;
+        let p = rhs * Vec3::new(x, y, 1.0);
+        Vertex {
+            xy: [p.x / p.z, p.y / p.z],
+            ..self
+        }
+    }
+
     #[deprecated]
     pub fn to_array(self) -> [f32; 2 + 2 + 4] {
         let [x, y] = self.xy;
@@ -0,0 +1,204 @@
+use crate::{context::Context, run::RedrawHandle};
+use slog::Logger;
+use std::{
+    cell::Cell,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Where a job's result comes out
+#[derive(Debug)]
+pub struct JobHandle<T> {
+    result: Receiver<T>,
+    /// The channel closes once the result's been received too, this tells that apart from a panic
+    taken: Cell<bool>,
+}
+
+impl<T> JobHandle<T> {
+    /// None while it's still running. The result only comes out once, polling after that fails
+    pub fn poll(&self) -> Result<Option<T>, &'static str> {
+        if self.taken.get() {
+            return Err("The job's result was already taken!");
+        }
+        match self.result.try_recv() {
+            Ok(result) => {
+                self.taken.set(true);
+                Ok(Some(result))
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("The job panicked!"),
+        }
+    }
+
+    /// Blocks until it's done, for loading screens that can't go on without it
+    pub fn wait(self) -> Result<T, &'static str> {
+        if self.taken.get() {
+            return Err("The job's result was already taken!");
+        }
+        self.result.recv().map_err(|_| "The job panicked!")
+    }
+}
+
+/// A job started with `spawn_then`, gives back the callback once the result is in
+type Pending = Box<dyn FnMut() -> Option<Box<dyn FnOnce(&mut Context)>>>;
+
+/// A few worker threads for decoding, chart parsing, pathfinding and anything else that would
+/// drop frames on the render thread. Jobs run in the order they were spawned, as many at once as
+/// there are threads. A job that panics takes only itself down, the panic gets logged
+pub struct JobPool {
+    queue: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    pending: Vec<Pending>,
+    /// Wakes up a `Redraw::OnDemand` loop when a job's done, so its result doesn't sit there until
+    /// the next input
+    redraw: Option<RedrawHandle>,
+}
+
+impl fmt::Debug for JobPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobPool")
+            .field("threads", &self.workers.len())
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl JobPool {
+    pub fn new(threads: usize, log: Logger) -> Result<Self, &'static str> {
+        let (queue, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let jobs = Arc::clone(&jobs);
+                let log = log.new(o!("thread" => format!("job{}", i)));
+                thread::Builder::new()
+                    .name(format!("rmge-job{}", i))
+                    .spawn(move || worker(&jobs, &log))
+                    .map_err(|_| "Couldn't spawn a job thread!")
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(JobPool {
+            queue: Some(queue),
+            workers,
+            pending: Vec::new(),
+            redraw: None,
+        })
+    }
+
+    pub(crate) fn set_redraw(&mut self, redraw: RedrawHandle) {
+        self.redraw = Some(redraw);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn spawn<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (result_tx, result) = mpsc::sync_channel(1);
+        let redraw = self.redraw.clone();
+        let job: Job = Box::new(move || {
+            // nobody's waiting on it anymore if the handle's gone, that's fine
+            let _ = result_tx.send(job());
+            if let Some(redraw) = redraw {
+                redraw.request_redraw();
+            }
+        });
+        if let Some(queue) = &self.queue {
+            // the workers only stop when the queue's dropped, so this can't fail
+            let _ = queue.send(job);
+        }
+        JobHandle {
+            result,
+            taken: Cell::new(false),
+        }
+    }
+
+    /// Runs `job` on a worker, then `then` with its result on the render thread at the start of
+    /// the first update after it's done, where it can touch the `Context`. Nothing runs `then` if
+    /// the job panicked
+    pub fn spawn_then<T, F, C>(&mut self, job: F, then: C)
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+        C: FnOnce(&mut Context, T) + 'static,
+    {
+        let handle = self.spawn(job);
+        let mut then = Some(then);
+        self.pending.push(Box::new(move || match handle.poll() {
+            Ok(None) => None,
+            Ok(Some(result)) => then.take().map(|then| {
+                Box::new(move |ctx: &mut Context| then(ctx, result))
+                    as Box<dyn FnOnce(&mut Context)>
+            }),
+            // dropping it without a callback takes it off the pending list
+            Err(_) => Some(Box::new(|_: &mut Context| ())),
+        }));
+    }
+
+    /// `spawn_then` jobs that haven't had their callback run yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Takes the callbacks of every finished `spawn_then` job, in the order they were spawned
+    fn take_finished(&mut self) -> Vec<Box<dyn FnOnce(&mut Context)>> {
+        let mut finished = Vec::new();
+        let mut i = 0;
+        while i < self.pending.len() {
+            match (self.pending[i])() {
+                Some(callback) => {
+                    finished.push(callback);
+                    self.pending.remove(i);
+                }
+                None => i += 1,
+            }
+        }
+        finished
+    }
+}
+
+/// Runs the callbacks of finished `spawn_then` jobs, the run loop does this before every update
+pub(crate) fn run_finished(ctx: &mut Context) {
+    for callback in ctx.jobs.take_finished() {
+        callback(ctx);
+    }
+}
+
+impl Drop for JobPool {
+    /// Waits for the jobs that are already queued
+    fn drop(&mut self) {
+        self.queue = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(jobs: &Mutex<Receiver<Job>>, log: &Logger) {
+    loop {
+        // the lock only gets held while waiting for a job, not while running it
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let job = match job {
+            Ok(job) => job,
+            // the pool's gone
+            Err(_) => return,
+        };
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            error!(log, "a job panicked");
+        }
+    }
+}
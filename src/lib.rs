@@ -3,8 +3,32 @@
 #[macro_use]
 extern crate slog;
 
+pub mod anim;
+pub mod assets;
+pub mod audio;
+pub mod context;
+pub mod crash;
+#[cfg(feature = "discord")]
+pub mod discord;
 pub mod event;
 pub mod geometry;
 pub mod graphics;
+pub mod jobs;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod profile;
+pub mod random;
+pub mod run;
+#[cfg(feature = "settings")]
+pub mod settings;
+#[cfg(feature = "skin")]
+pub mod skin;
+#[cfg(feature = "steam")]
+pub mod steam;
+pub mod text_input;
+pub mod timer;
+pub mod timing;
+pub mod transition;
 
+pub use crate::context::{Context, ContextBuilder, ContextHandler};
 pub use crate::graphics::HalState;
@@ -0,0 +1,120 @@
+use crate::{
+    jobs::{JobHandle, JobPool},
+    net::NetError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io::Read, time::Duration};
+
+/// Requests block, so either `send` them from a job or use `send_on` to get a handle back that
+/// the game loop can poll. Submitting a score is
+///
+/// ```ignore
+/// let submit = HttpRequest::post("https://scores.example.com/submit")
+///     .with_json(&score)?
+///     .send_on(&ctx.jobs);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    method: &'static str,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+}
+
+impl HttpRequest {
+    /// Times out after 10 seconds
+    pub fn new(method: &'static str, url: &str) -> Self {
+        HttpRequest {
+            method,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn get(url: &str) -> Self {
+        HttpRequest::new("GET", url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        HttpRequest::new("POST", url)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// For auth tokens
+    pub fn with_bearer(self, token: &str) -> Self {
+        self.with_header("Authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Sets the content type too
+    pub fn with_json<T: Serialize>(self, body: &T) -> Result<Self, NetError> {
+        Ok(self
+            .with_header("Content-Type", "application/json")
+            .with_body(serde_json::to_vec(body)?))
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        HttpRequest { timeout, ..self }
+    }
+
+    /// Blocks until the whole response is in. http and https both work
+    pub fn send(self) -> Result<HttpResponse, NetError> {
+        let mut request = ureq::request(self.method, &self.url).timeout(self.timeout);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        let result = match &self.body {
+            Some(body) => request.send_bytes(body),
+            None => request.call(),
+        };
+        match result {
+            Ok(response) => {
+                let status = response.status();
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut body)
+                    .map_err(|e| NetError::Transport(e.to_string()))?;
+                Ok(HttpResponse { status, body })
+            }
+            Err(ureq::Error::Status(status, response)) => Err(NetError::Status(
+                status,
+                response.into_string().unwrap_or_default(),
+            )),
+            Err(e) => Err(NetError::Transport(e.to_string())),
+        }
+    }
+
+    /// Sends it from one of the pool's threads
+    pub fn send_on(self, jobs: &JobPool) -> JobHandle<Result<HttpResponse, NetError>> {
+        jobs.spawn(move || self.send())
+    }
+}
+
+/// A 2xx response, the others come back as `NetError::Status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn text(&self) -> Result<&str, NetError> {
+        std::str::from_utf8(&self.body).map_err(|e| NetError::Transport(e.to_string()))
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, NetError> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
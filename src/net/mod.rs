@@ -0,0 +1,39 @@
+mod http;
+mod room;
+
+pub use http::{HttpRequest, HttpResponse};
+pub use room::{Player, PlayerId, RoomClient, RoomEvent};
+
+use std::fmt;
+
+/// Why a request or a room connection failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetError {
+    /// Couldn't connect, or the connection dropped partway through
+    Transport(String),
+    /// The server answered with something other than 2xx, the body is in case it says why
+    Status(u16, String),
+    /// What was sent or received isn't the JSON it should be
+    Json(String),
+    /// The room connection is gone
+    Closed,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::Transport(e) => write!(f, "connection failed: {}", e),
+            NetError::Status(status, body) => write!(f, "server returned {}: {}", status, body),
+            NetError::Json(e) => write!(f, "invalid JSON: {}", e),
+            NetError::Closed => write!(f, "the connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<serde_json::Error> for NetError {
+    fn from(e: serde_json::Error) -> Self {
+        NetError::Json(e.to_string())
+    }
+}
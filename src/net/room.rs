@@ -0,0 +1,290 @@
+use crate::net::NetError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use slog::Logger;
+use std::{
+    collections::HashMap,
+    io,
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+/// How often the connection thread pings the server to measure latency
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a read waits before the thread goes back to check for outgoing messages
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub type PlayerId = u32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Player {
+    pub id: PlayerId,
+    pub name: String,
+}
+
+/// What the client sends, as JSON text messages tagged by "type"
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Join { room: String, name: String },
+    State { data: Value },
+    Ping { id: u32 },
+    Leave,
+}
+
+/// What the server sends back
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Joined {
+        room: String,
+        you: PlayerId,
+        players: Vec<Player>,
+    },
+    PlayerJoined {
+        player: Player,
+    },
+    PlayerLeft {
+        id: PlayerId,
+    },
+    State {
+        from: PlayerId,
+        data: Value,
+    },
+    Pong {
+        id: u32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Something that happened in the room, from `RoomClient::poll`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomEvent {
+    /// `players` includes us
+    Joined {
+        room: String,
+        you: PlayerId,
+        players: Vec<Player>,
+    },
+    PlayerJoined(Player),
+    PlayerLeft(PlayerId),
+    /// Whatever another player passed to `send_state`
+    State {
+        from: PlayerId,
+        data: Value,
+    },
+    /// The server didn't like something, the connection stays up
+    Error(String),
+    /// The connection is gone, nothing else comes after this
+    Disconnected(Option<String>),
+}
+
+/// From the connection thread to the game
+enum Incoming {
+    Event(RoomEvent),
+    RoundTrip(Duration),
+}
+
+/// A connection to a multiplayer room over a WebSocket. The socket lives on its own thread and
+/// everything goes through channels, so `poll` and `send_state` never block the game loop.
+///
+/// The protocol is JSON text messages with a "type" field. The client sends `join` (room, name),
+/// `state` (data), `ping` (id) and `leave`, the server answers with `joined` (room, you, players),
+/// `player_joined` (player), `player_left` (id), `state` (from, data), `pong` (id) and `error`
+/// (message). State messages get relayed to everyone else in the room as they are, what's in them
+/// is up to the game. Dropping it leaves the room
+pub struct RoomClient {
+    outgoing: Sender<ClientMessage>,
+    incoming: Receiver<Incoming>,
+    latency: Option<Duration>,
+    connected: bool,
+}
+
+impl std::fmt::Debug for RoomClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RoomClient")
+            .field("latency", &self.latency)
+            .field("connected", &self.connected)
+            .finish()
+    }
+}
+
+impl RoomClient {
+    /// Starts connecting to `url` (ws:// or wss://) and joins `room` once it's up. Returns right
+    /// away, a failed connection shows up as `RoomEvent::Disconnected`
+    pub fn connect(url: &str, room: &str, name: &str, log: Logger) -> Result<Self, NetError> {
+        let (outgoing, outgoing_rx) = mpsc::channel();
+        let (incoming_tx, incoming) = mpsc::channel();
+        let join = ClientMessage::Join {
+            room: room.to_string(),
+            name: name.to_string(),
+        };
+        let url = url.to_string();
+        thread::Builder::new()
+            .name("rmge-room".to_string())
+            .spawn(move || {
+                let reason = match connection(&url, join, &outgoing_rx, &incoming_tx) {
+                    Ok(()) => None,
+                    Err(e) => {
+                        error!(log, "room connection failed"; "url" => &url, "net_error" => %e);
+                        Some(e.to_string())
+                    }
+                };
+                let _ = incoming_tx.send(Incoming::Event(RoomEvent::Disconnected(reason)));
+            })
+            .map_err(|e| NetError::Transport(e.to_string()))?;
+        Ok(RoomClient {
+            outgoing,
+            incoming,
+            latency: None,
+            connected: true,
+        })
+    }
+
+    /// The next thing that happened, None when there's nothing new. Call it every update until
+    /// it's None
+    pub fn poll(&mut self) -> Option<RoomEvent> {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(Incoming::Event(event)) => {
+                    if let RoomEvent::Disconnected(_) = event {
+                        self.connected = false;
+                    }
+                    return Some(event);
+                }
+                Ok(Incoming::RoundTrip(round_trip)) => {
+                    let one_way = round_trip / 2;
+                    // smoothed so one slow ping doesn't throw things off
+                    self.latency = Some(match self.latency {
+                        Some(latency) => latency * 7 / 8 + one_way / 8,
+                        None => one_way,
+                    });
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Relayed to everyone else in the room
+    pub fn send_state<T: Serialize>(&self, state: &T) -> Result<(), NetError> {
+        let data = serde_json::to_value(state)?;
+        self.outgoing
+            .send(ClientMessage::State { data })
+            .map_err(|_| NetError::Closed)
+    }
+
+    /// Half the smoothed round trip to the server, None until the first pong. Add it to
+    /// timestamps from other players to line their state up with ours
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// False once `RoomEvent::Disconnected` has been polled
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+fn connection(
+    url: &str,
+    join: ClientMessage,
+    outgoing: &Receiver<ClientMessage>,
+    incoming: &Sender<Incoming>,
+) -> Result<(), NetError> {
+    let (mut socket, _) =
+        tungstenite::connect(url).map_err(|e| NetError::Transport(e.to_string()))?;
+    // reads only wait a little, so outgoing messages don't sit around until something comes in
+    if let Some(stream) = tcp_stream(&socket) {
+        stream
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|e| NetError::Transport(e.to_string()))?;
+    }
+    send(&mut socket, &join)?;
+
+    let mut pings = HashMap::new();
+    let mut next_ping = 0u32;
+    let mut last_ping: Option<Instant> = None;
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                // the RoomClient getting dropped counts as leaving
+                Ok(ClientMessage::Leave) | Err(TryRecvError::Disconnected) => {
+                    let _ = send(&mut socket, &ClientMessage::Leave);
+                    let _ = socket.close(None);
+                    return Ok(());
+                }
+                Ok(message) => send(&mut socket, &message)?,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if last_ping.map_or(true, |last| last.elapsed() >= PING_INTERVAL) {
+            let now = Instant::now();
+            last_ping = Some(now);
+            pings.insert(next_ping, now);
+            send(&mut socket, &ClientMessage::Ping { id: next_ping })?;
+            next_ping = next_ping.wrapping_add(1);
+            // pongs that never came back
+            pings.retain(|_, sent: &mut Instant| sent.elapsed() < PING_INTERVAL * 10);
+        }
+
+        let text = match socket.read_message() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            // tungstenite answers pings on its own
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(ref e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(NetError::Transport(e.to_string())),
+        };
+        let message = match serde_json::from_str(&text)? {
+            ServerMessage::Pong { id } => match pings.remove(&id) {
+                Some(sent) => Incoming::RoundTrip(sent.elapsed()),
+                None => continue,
+            },
+            ServerMessage::Joined { room, you, players } => {
+                Incoming::Event(RoomEvent::Joined { room, you, players })
+            }
+            ServerMessage::PlayerJoined { player } => {
+                Incoming::Event(RoomEvent::PlayerJoined(player))
+            }
+            ServerMessage::PlayerLeft { id } => Incoming::Event(RoomEvent::PlayerLeft(id)),
+            ServerMessage::State { from, data } => Incoming::Event(RoomEvent::State { from, data }),
+            ServerMessage::Error { message } => Incoming::Event(RoomEvent::Error(message)),
+        };
+        if incoming.send(message).is_err() {
+            // the RoomClient's gone without getting to say so
+            return Ok(());
+        }
+    }
+}
+
+fn send(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    message: &ClientMessage,
+) -> Result<(), NetError> {
+    let text = serde_json::to_string(message)?;
+    socket
+        .write_message(Message::Text(text))
+        .map_err(|e| NetError::Transport(e.to_string()))
+}
+
+fn tcp_stream(socket: &WebSocket<MaybeTlsStream<TcpStream>>) -> Option<&TcpStream> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => Some(stream),
+        MaybeTlsStream::Rustls(stream) => Some(&stream.sock),
+        _ => None,
+    }
+}
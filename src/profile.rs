@@ -0,0 +1,269 @@
+use crate::{
+    geometry::{Quad, Vec2},
+    graphics::{Font, Frame, TextBox, TextSpan, TextureId, TexturedQuad},
+};
+use std::{
+    cell::RefCell,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// One timed scope from a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeRecord {
+    pub name: &'static str,
+    /// How many scopes it's nested in, 0 for the run loop's own phases
+    pub depth: usize,
+    /// From the start of the frame
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Every scope from one pass through the run loop, parents come before their children
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameProfile {
+    pub scopes: Vec<ScopeRecord>,
+    pub duration: Duration,
+}
+
+impl FrameProfile {
+    /// Added up over every scope called `name`
+    pub fn total(&self, name: &str) -> Duration {
+        self.scopes
+            .iter()
+            .filter(|scope| scope.name == name)
+            .map(|scope| scope.duration)
+            .fold(Duration::from_secs(0), |a, b| a + b)
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    enabled: bool,
+    frame_start: Option<Instant>,
+    depth: usize,
+    scopes: Vec<ScopeRecord>,
+    last: FrameProfile,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Recorder> = RefCell::new(Recorder::default());
+}
+
+/// Starts or stops recording scopes on this thread, it's off to begin with. The run loop and
+/// `HalState::draw_frame` are on the same thread, so turning it on there covers every phase of a
+/// frame: poll, update, draw, and inside the draw upload, record, submit and present
+pub fn set_enabled(enabled: bool) {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.enabled = enabled;
+        recorder.frame_start = None;
+        recorder.depth = 0;
+        recorder.scopes.clear();
+    });
+}
+
+pub fn is_enabled() -> bool {
+    RECORDER.with(|recorder| recorder.borrow().enabled)
+}
+
+/// Times everything until the returned guard is dropped. Also opens a tracing span with the
+/// `tracing` feature and a puffin scope with the `puffin` feature, those happen even while the
+/// built-in recording is off
+pub fn scope(name: &'static str) -> Scope {
+    let index = RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if !recorder.enabled {
+            return None;
+        }
+        let now = Instant::now();
+        let frame_start = *recorder.frame_start.get_or_insert(now);
+        let record = ScopeRecord {
+            name,
+            depth: recorder.depth,
+            start: now - frame_start,
+            duration: Duration::from_secs(0),
+        };
+        recorder.scopes.push(record);
+        recorder.depth += 1;
+        Some((recorder.scopes.len() - 1, now))
+    });
+    Scope {
+        index,
+        #[cfg(feature = "tracing")]
+        _span: tracing::trace_span!("rmge", phase = name).entered(),
+        #[cfg(feature = "puffin")]
+        _puffin: if puffin::are_scopes_on() {
+            Some(puffin::ProfilerScope::new(name, file!(), ""))
+        } else {
+            None
+        },
+    }
+}
+
+/// Ends the frame, what it recorded shows up in `last_frame`. The run loop calls this after every
+/// draw
+pub fn finish_frame() {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if let Some(frame_start) = recorder.frame_start.take() {
+            let scopes = std::mem::replace(&mut recorder.scopes, Vec::new());
+            recorder.last = FrameProfile {
+                scopes,
+                duration: frame_start.elapsed(),
+            };
+            recorder.depth = 0;
+        }
+    });
+    #[cfg(feature = "puffin")]
+    puffin::GlobalProfiler::lock().new_frame();
+}
+
+/// The last finished frame on this thread, empty while recording is off
+pub fn last_frame() -> FrameProfile {
+    RECORDER.with(|recorder| recorder.borrow().last.clone())
+}
+
+/// See `scope`
+pub struct Scope {
+    index: Option<(usize, Instant)>,
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+    #[cfg(feature = "puffin")]
+    _puffin: Option<puffin::ProfilerScope>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Some((index, start)) = self.index {
+            RECORDER.with(|recorder| {
+                let mut recorder = recorder.borrow_mut();
+                // recording got turned off or the frame ended while this was open
+                if let Some(record) = recorder.scopes.get_mut(index) {
+                    record.duration = start.elapsed();
+                }
+                recorder.depth = recorder.depth.saturating_sub(1);
+            });
+        }
+    }
+}
+
+/// Draws a `FrameProfile` as a flame graph, one row per nesting level with time going right. The
+/// full width is `budget`, so a frame that runs over sticks out past the line at the end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlameGraph {
+    /// Top left corner, in whatever space the frame is drawing in
+    pub position: Vec2<f32>,
+    pub width: f32,
+    pub row_height: f32,
+    pub budget: Duration,
+    pub background: [f32; 4],
+    pub text_color: [f32; 4],
+}
+
+impl FlameGraph {
+    /// A 60 FPS budget
+    pub fn new(position: Vec2<f32>, width: f32, row_height: f32) -> Self {
+        FlameGraph {
+            position,
+            width,
+            row_height,
+            budget: Duration::from_micros(16_667),
+            background: [0.0, 0.0, 0.0, 0.6],
+            text_color: [1.0; 4],
+        }
+    }
+
+    pub fn with_budget(self, budget: Duration) -> Self {
+        FlameGraph { budget, ..self }
+    }
+
+    /// With a font the bars that are wide enough get their names written on them
+    pub fn draw(&self, frame: &mut Frame, profile: &FrameProfile, font: Option<&dyn Font>) {
+        let rows = profile
+            .scopes
+            .iter()
+            .map(|scope| scope.depth + 1)
+            .max()
+            .unwrap_or(1);
+        let budget = self.budget.as_secs_f32().max(std::f32::EPSILON);
+        let x = |time: Duration| self.position.x + time.as_secs_f32() / budget * self.width;
+
+        let mut quads = vec![rect(
+            self.position,
+            Vec2::new(self.width, rows as f32 * self.row_height),
+            self.background,
+        )];
+        for scope in &profile.scopes {
+            let left = x(scope.start);
+            // at least a pixel after the gap, so short scopes don't vanish
+            let width = (x(scope.start + scope.duration) - left).max(2.0);
+            let top = self.position.y + scope.depth as f32 * self.row_height;
+            // a gap between bars so neighbours with similar colors stay apart
+            quads.push(rect(
+                Vec2::new(left, top + 1.0),
+                Vec2::new(width - 1.0, self.row_height - 2.0),
+                color_for(scope.name),
+            ));
+        }
+        quads.push(rect(
+            Vec2::new(self.position.x + self.width - 1.0, self.position.y),
+            Vec2::new(2.0, rows as f32 * self.row_height),
+            [1.0, 0.2, 0.2, 1.0],
+        ));
+        frame.draw_quads(&quads);
+
+        if let Some(font) = font {
+            let size = self.row_height * 0.7;
+            for scope in &profile.scopes {
+                let left = x(scope.start);
+                let width = x(scope.start + scope.duration) - left;
+                if width < size * 3.0 {
+                    continue;
+                }
+                let label = format!(
+                    "{} {:.2}ms",
+                    scope.name,
+                    scope.duration.as_secs_f32() * 1000.0
+                );
+                let top = self.position.y + scope.depth as f32 * self.row_height;
+                TextBox::new(Vec2::new(left + 2.0, top + (self.row_height - size) / 2.0))
+                    .with_max_width(width - 4.0)
+                    .layout(
+                        font,
+                        &[TextSpan::new(&label, size).with_color(self.text_color)],
+                    )
+                    .draw(frame);
+            }
+        }
+    }
+}
+
+fn rect(top_left: Vec2<f32>, size: Vec2<f32>, color: [f32; 4]) -> TexturedQuad {
+    let quad = Quad {
+        top_left,
+        bottom_left: top_left + Vec2::new(0.0, size.y),
+        bottom_right: top_left + size,
+        top_right: top_left + Vec2::new(size.x, 0.0),
+    };
+    TexturedQuad::new(quad, TextureId::NONE.index()).with_color(color)
+}
+
+/// Same name, same color every frame, so phases are easy to follow
+fn color_for(name: &str) -> [f32; 4] {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let hue = (hasher.finish() % 360) as f32 / 60.0;
+    // muted so white labels stay readable on any of them
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [0.3 + r * 0.5, 0.3 + g * 0.5, 0.3 + b * 0.5, 1.0]
+}
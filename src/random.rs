@@ -0,0 +1,130 @@
+use std::{
+    ops::Range,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+/// PCG32, small and fast with the same output on every platform for the same seed, which is what
+/// replays need. Not for anything that has to be unpredictable to players, like loot that's worth
+/// money
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    seed: u64,
+    stream: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng::with_stream(seed, 0)
+    }
+
+    /// A different sequence for the same seed for every `stream`
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng {
+            seed,
+            stream,
+            state: 0,
+        };
+        rng.reseed(seed);
+        rng
+    }
+
+    /// Seeded from the clock, for a session that isn't a replay
+    pub fn from_time() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() ^ (u64::from(since.subsec_nanos()) << 32))
+            .unwrap_or(0);
+        Rng::new(now)
+    }
+
+    /// What it got seeded with, save this with a replay
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts the sequence over from `seed`, before playing a replay back
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.state = 0;
+        self.next_u32();
+        self.state = self.state.wrapping_add(seed);
+        self.next_u32();
+    }
+
+    /// Its own sequence from the same seed, picked by `name`. Drawing from it doesn't move this one
+    /// along, so particles can use as many numbers as they like without changing what gameplay
+    /// gets, and the other way around
+    pub fn derive(&self, name: &str) -> Rng {
+        // FNV-1a, since std's hasher isn't guaranteed to stay the same between Rust versions
+        let stream = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Rng::with_stream(self.seed, stream)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        // the increment has to be odd
+        self.state = old
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add((self.stream << 1) | 1);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        (u64::from(self.next_u32()) << 32) | u64::from(self.next_u32())
+    }
+
+    /// From 0 up to but not including 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// From 0 up to but not including 1
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Evenly spread over `range`, which can't be empty
+    pub fn range(&mut self, range: Range<u32>) -> u32 {
+        assert!(range.start < range.end, "empty range");
+        let span = range.end - range.start;
+        // rejecting the top few values keeps the small ones from coming up more often
+        let zone = std::u32::MAX - std::u32::MAX % span;
+        loop {
+            let x = self.next_u32();
+            if x < zone {
+                return range.start + x % span;
+            }
+        }
+    }
+
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// True with a probability of `p`
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.next_f32() < p
+    }
+
+    /// None for an empty slice
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            items.get(self.range(0..items.len() as u32) as usize)
+        }
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.range(0..i as u32 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
@@ -0,0 +1,650 @@
+use crate::{
+    event::{DeviceButton, EventHandler, Key, KeyModifiers, MouseMove},
+    profile,
+};
+use gfx_hal::window::PresentMode;
+use slog::Logger;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::LogicalSize, ControlFlow, DeviceEvent, ElementState, Event, EventsLoop, EventsLoopProxy,
+    KeyboardInput, Window, WindowBuilder, WindowEvent,
+};
+
+/// A winit event stamped with when the event thread got it, which is a lot closer to when it
+/// actually happened than whenever the render loop gets around to looking at it
+#[derive(Debug, Clone)]
+pub struct TimedEvent {
+    pub time: Instant,
+    pub event: Event,
+}
+
+/// What the window gets created with, it has to be plain data because the window is built on the
+/// event thread
+#[derive(Debug, Clone)]
+pub struct WindowSettings {
+    pub title: String,
+    pub size: LogicalSize,
+    /// Which display server to talk to on Linux and the BSDs, ignored everywhere else. None lets
+    /// winit decide, which is Wayland when it's there
+    pub unix_backend: Option<UnixBackend>,
+    /// Fullscreen on the primary monitor, for latency critical play. Create the `HalState` with
+    /// `HalStateBuilder::low_latency` to go with it. winit only does borderless fullscreen, but
+    /// compositors skip compositing fullscreen windows so that ends up about as fast as exclusive
+    pub performance_mode: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: "rmge".to_string(),
+            size: LogicalSize::new(1024.0, 768.0),
+            unix_backend: None,
+            performance_mode: false,
+        }
+    }
+}
+
+/// How the render loop paces `update` and `draw`, for `run_with`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LoopSettings {
+    pub timestep: Timestep,
+    pub redraw: Redraw,
+}
+
+/// Whether the loop draws all the time or only when something changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redraw {
+    /// Frame after frame as fast as presenting allows, for games
+    Continuous,
+    /// Sleeps until a window event, a `RedrawHandle::request_redraw` or the time
+    /// `EventHandler::next_redraw` asked for, then does one update and one draw. For tools and
+    /// editors that would otherwise keep a core busy redrawing the same thing. Device events like
+    /// raw mouse motion don't count, and `Clock::delta` after a long sleep covers all of it
+    OnDemand,
+}
+
+impl Default for Redraw {
+    fn default() -> Self {
+        Redraw::Continuous
+    }
+}
+
+/// Asks a `Redraw::OnDemand` loop for another frame, from any thread. A burst of requests still
+/// only gets one frame, and with `Redraw::Continuous` there's always another frame anyway
+#[derive(Clone)]
+pub struct RedrawHandle {
+    requested: Arc<AtomicBool>,
+    proxy: EventsLoopProxy,
+}
+
+impl fmt::Debug for RedrawHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedrawHandle")
+            .field("requested", &self.requested.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl RedrawHandle {
+    pub fn request_redraw(&self) {
+        if !self.requested.swap(true, Ordering::SeqCst) {
+            // the render loop might be asleep waiting for an event, this gets it one
+            let _ = self.proxy.wakeup();
+        }
+    }
+}
+
+/// Whether `update` gets called once per frame or in fixed steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timestep {
+    /// One `update` per frame, however long the frame took
+    Variable,
+    /// `fixed_update`s of exactly `step` each, as many as the time since the last frame covers
+    Fixed(FixedTimestep),
+}
+
+impl Default for Timestep {
+    fn default() -> Self {
+        Timestep::Variable
+    }
+}
+
+/// How far a fixed timestep loop goes to catch up when frames take longer than a step. Without a
+/// limit a slow machine spirals, every frame needs more updates which make the frame slower still
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedTimestep {
+    pub step: Duration,
+    /// The most updates one frame gets, whatever's left over carries on to the next frames
+    pub max_updates: u32,
+    /// Once the loop is this far behind it gives up on the time it missed and tells the handler
+    /// through `EventHandler::fell_behind`
+    pub max_lag: Duration,
+}
+
+impl FixedTimestep {
+    /// Up to 5 updates a frame, and gives up when a quarter of a second behind. Panics if `step`
+    /// is zero
+    pub fn new(step: Duration) -> Self {
+        assert!(step > Duration::from_secs(0), "the step can't be zero");
+        FixedTimestep {
+            step,
+            max_updates: 5,
+            max_lag: Duration::from_millis(250),
+        }
+    }
+
+    /// Steps of a `hz`th of a second. Steps are whole nanoseconds, so `hz` goes from 1 up to a
+    /// billion
+    pub fn per_second(hz: u32) -> Result<Self, &'static str> {
+        if hz == 0 || hz > 1_000_000_000 {
+            return Err("Fixed timesteps need between 1 and a billion steps a second!");
+        }
+        Ok(FixedTimestep::new(Duration::from_secs(1) / hz))
+    }
+
+    pub fn with_max_updates(self, max_updates: u32) -> Self {
+        FixedTimestep {
+            max_updates: max_updates.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_max_lag(self, max_lag: Duration) -> Self {
+        FixedTimestep { max_lag, ..self }
+    }
+}
+
+/// The compositors handle presenting differently enough that the best present mode depends on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnixBackend {
+    X11,
+    Wayland,
+}
+
+impl UnixBackend {
+    /// Which one the window ended up on, None on other platforms
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    pub fn of(window: &Window) -> Option<UnixBackend> {
+        use winit::os::unix::WindowExt;
+        if window.get_wayland_display().is_some() {
+            Some(UnixBackend::Wayland)
+        } else if window.get_xlib_display().is_some() {
+            Some(UnixBackend::X11)
+        } else {
+            None
+        }
+    }
+
+    /// Which one the window ended up on, None on other platforms
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )))]
+    pub fn of(_window: &Window) -> Option<UnixBackend> {
+        None
+    }
+
+    /// Lowest latency first, for `HalStateBuilder::vsync`
+    pub fn present_modes(self) -> [PresentMode; 4] {
+        use PresentMode::*;
+        match self {
+            // Fifo goes through the compositor and picks up an extra frame of latency there, Mesa's
+            // Mailbox does too. Immediate tears but is the only thing that skips it
+            UnixBackend::X11 => [Immediate, Mailbox, Relaxed, Fifo],
+            // Wayland never tears, Immediate is usually just Mailbox anyway. Fifo blocks on frame
+            // callbacks, which stop coming while the window is hidden
+            UnixBackend::Wayland => [Mailbox, Immediate, Fifo, Relaxed],
+        }
+    }
+}
+
+/// Sent back from the event thread once the window exists
+struct Ready {
+    window: Window,
+    proxy: EventsLoopProxy,
+}
+
+/// Opens a window and runs `handler` until it quits. The `EventsLoop` lives on its own thread and
+/// does nothing but timestamp events and forward them, so input timing doesn't depend on how long
+/// frames take. Rendering, `update` and all the `EventHandler` callbacks happen on the calling
+/// thread.
+///
+/// Losing focus suspends everything if `window_focused` says so, the render thread then just sleeps
+/// until the next event. `update` gets called once per frame, `run_with` can make that a fixed
+/// timestep instead.
+///
+/// `make_handler` gets the window once it's up, so this is where the `HalState` gets created. The
+/// handler can keep a clone of it for recreating the swapchain, the window sticks around until the
+/// events loop is gone either way. Doesn't work on macOS, where the events loop has to stay on the
+/// main thread
+pub fn run<H, F>(settings: WindowSettings, log: Logger, make_handler: F) -> Result<(), &'static str>
+where
+    H: EventHandler,
+    F: FnOnce(&Arc<Window>) -> Result<H, &'static str>,
+{
+    run_with(settings, LoopSettings::default(), log, |window, _| {
+        make_handler(window)
+    })
+}
+
+/// `run` with control over how the loop paces itself. `make_handler` also gets a `RedrawHandle`,
+/// which is how a `Redraw::OnDemand` handler asks for frames
+pub fn run_with<H, F>(
+    settings: WindowSettings,
+    loop_settings: LoopSettings,
+    log: Logger,
+    make_handler: F,
+) -> Result<(), &'static str>
+where
+    H: EventHandler,
+    F: FnOnce(&Arc<Window>, RedrawHandle) -> Result<H, &'static str>,
+{
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (start_tx, start_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+    let running = Arc::new(AtomicBool::new(true));
+
+    let thread_running = Arc::clone(&running);
+    let thread_log = log.new(o!("thread" => "events"));
+    let event_thread = thread::Builder::new()
+        .name("rmge-events".to_string())
+        .spawn(move || {
+            event_thread(
+                settings,
+                thread_log,
+                ready_tx,
+                start_rx,
+                event_tx,
+                thread_running,
+            )
+        })
+        .map_err(|_| "Couldn't spawn the event thread!")?;
+
+    let Ready { window, proxy } = match ready_rx.recv() {
+        Ok(ready) => ready?,
+        Err(_) => return Err("The event thread died before the window was created!"),
+    };
+    let window = Arc::new(window);
+    let redraw = RedrawHandle {
+        requested: Arc::new(AtomicBool::new(false)),
+        proxy: proxy.clone(),
+    };
+
+    let result = match make_handler(&window, redraw.clone()) {
+        Ok(mut handler) => {
+            // events only start flowing once there's something to hand them to
+            let result = if start_tx.send(()).is_ok() {
+                info!(&log, "event thread started");
+                render_loop(
+                    &mut handler,
+                    &window,
+                    &event_rx,
+                    loop_settings,
+                    &redraw.requested,
+                )
+            } else {
+                Err("The event thread died before it was started!")
+            };
+            // the handler usually holds a HalState with a surface for the window, so it goes first
+            drop(handler);
+            result
+        }
+        // dropping start_tx without sending tells the event thread to give up
+        Err(e) => Err(e),
+    };
+
+    running.store(false, Ordering::SeqCst);
+    if proxy.wakeup().is_err() {
+        debug!(&log, "the events loop was already gone");
+    }
+    drop(start_tx);
+    // the window belongs to the events loop, so that has to be gone before the window is
+    if event_thread.join().is_err() {
+        error!(&log, "the event thread panicked");
+    }
+    drop(window);
+    result
+}
+
+fn event_thread(
+    settings: WindowSettings,
+    log: Logger,
+    ready: Sender<Result<Ready, &'static str>>,
+    start: Receiver<()>,
+    events: Sender<TimedEvent>,
+    running: Arc<AtomicBool>,
+) {
+    let mut events_loop = match create_events_loop(settings.unix_backend) {
+        Ok(events_loop) => events_loop,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let fullscreen = if settings.performance_mode {
+        Some(events_loop.get_primary_monitor())
+    } else {
+        None
+    };
+    let window = WindowBuilder::new()
+        .with_title(settings.title)
+        .with_dimensions(settings.size)
+        .with_fullscreen(fullscreen)
+        .build(&events_loop)
+        .map_err(|_| "Couldn't create the window!");
+    let window = match window {
+        Ok(window) => window,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let proxy = events_loop.create_proxy();
+    if ready.send(Ok(Ready { window, proxy })).is_err() || start.recv().is_err() {
+        debug!(
+            &log,
+            "render thread gave up before starting the events loop"
+        );
+        return;
+    }
+    events_loop.run_forever(|event| {
+        if !running.load(Ordering::SeqCst) {
+            return ControlFlow::Break;
+        }
+        let time = Instant::now();
+        match events.send(TimedEvent { time, event }) {
+            Ok(()) => ControlFlow::Continue,
+            Err(_) => ControlFlow::Break,
+        }
+    });
+    debug!(&log, "events loop stopped");
+}
+
+/// What the render loop should do after an event
+enum Control {
+    Continue,
+    Resize(Instant),
+    Suspend(Instant),
+    Resume(Instant),
+    Quit,
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn create_events_loop(backend: Option<UnixBackend>) -> Result<EventsLoop, &'static str> {
+    use winit::os::unix::EventsLoopExt;
+    match backend {
+        Some(UnixBackend::X11) => {
+            EventsLoop::new_x11().map_err(|_| "Couldn't connect to the X server!")
+        }
+        // this one panics if there's no Wayland compositor, which ends up as the event thread dying
+        Some(UnixBackend::Wayland) => Ok(EventsLoop::new_wayland()),
+        None => Ok(EventsLoop::new()),
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn create_events_loop(_backend: Option<UnixBackend>) -> Result<EventsLoop, &'static str> {
+    Ok(EventsLoop::new())
+}
+
+/// Loop state that events can change
+#[derive(Default)]
+struct LoopState {
+    suspended: bool,
+    /// When the window last changed size, resizes come in bursts while dragging so they only get
+    /// passed on once per frame
+    resized: Option<Instant>,
+    /// A window event came in since the last frame, for `Redraw::OnDemand`
+    redraw: bool,
+}
+
+fn render_loop<H: EventHandler>(
+    handler: &mut H,
+    window: &Window,
+    events: &Receiver<TimedEvent>,
+    settings: LoopSettings,
+    requested: &AtomicBool,
+) -> Result<(), &'static str> {
+    let mut state = LoopState {
+        // the first frame always gets drawn
+        redraw: true,
+        ..LoopState::default()
+    };
+    // for the fixed timestep, time that hasn't been updated for yet
+    let mut lag = Duration::from_secs(0);
+    let mut last_frame: Option<Instant> = None;
+    // for `Redraw::OnDemand`, from `EventHandler::next_redraw`
+    let mut next_redraw: Option<Instant> = None;
+    loop {
+        let poll = profile::scope("poll");
+        let idle = settings.redraw == Redraw::OnDemand
+            && !state.redraw
+            && !requested.load(Ordering::SeqCst);
+        if state.suspended || idle {
+            // nothing gets drawn, so sleep until the next event instead of spinning
+            let until = if state.suspended { None } else { next_redraw };
+            if let Some(event) = wait(events, until)? {
+                if !handle(handler, event, &mut state) {
+                    return Ok(());
+                }
+            }
+        }
+        for event in events.try_iter() {
+            if !handle(handler, event, &mut state) {
+                return Ok(());
+            }
+        }
+        if let Some(time) = state.resized.take() {
+            let size = window
+                .get_inner_size()
+                .map(|size| size.to_physical(window.get_hidpi_factor()));
+            // minimized windows have no size, there'll be another resize when they come back
+            match size {
+                Some(size) if size.width >= 1.0 && size.height >= 1.0 => {
+                    handler.on_resize(time, size.width as u32, size.height as u32)
+                }
+                _ => (),
+            }
+        }
+        drop(poll);
+        let draw = !state.suspended
+            && match settings.redraw {
+                Redraw::Continuous => true,
+                Redraw::OnDemand => {
+                    // cleared before the update, so a request made during it gets another frame
+                    let due = next_redraw.map_or(false, |at| Instant::now() >= at);
+                    let event = state.redraw;
+                    state.redraw = false;
+                    requested.swap(false, Ordering::SeqCst) || event || due
+                }
+            };
+        if !draw {
+            // the time spent suspended or idle doesn't need catching up on
+            last_frame = None;
+        } else {
+            {
+                let _update = profile::scope("update");
+                match settings.timestep {
+                    Timestep::Variable => handler.update(),
+                    Timestep::Fixed(fixed) => {
+                        let now = Instant::now();
+                        // the first frame gets one update so there's something to draw
+                        lag += last_frame.map_or(fixed.step, |last| now - last);
+                        last_frame = Some(now);
+                        catch_up(handler, fixed, &mut lag, now);
+                    }
+                }
+            }
+            {
+                let _draw = profile::scope("draw");
+                handler.draw();
+            }
+            if settings.redraw == Redraw::OnDemand {
+                next_redraw = handler.next_redraw();
+            }
+        }
+        profile::finish_frame();
+    }
+}
+
+/// Blocks until the next event or `until`, None means it timed out
+fn wait(
+    events: &Receiver<TimedEvent>,
+    until: Option<Instant>,
+) -> Result<Option<TimedEvent>, &'static str> {
+    let stopped = "The event thread stopped unexpectedly!";
+    let until = match until {
+        Some(until) => until,
+        None => return events.recv().map(Some).map_err(|_| stopped),
+    };
+    let now = Instant::now();
+    if until <= now {
+        return Ok(None);
+    }
+    match events.recv_timeout(until - now) {
+        Ok(event) => Ok(Some(event)),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err(stopped),
+    }
+}
+
+/// Runs the fixed updates `lag` covers, up to the limit, and drops the time the loop can't make up
+fn catch_up<H: EventHandler>(
+    handler: &mut H,
+    fixed: FixedTimestep,
+    lag: &mut Duration,
+    now: Instant,
+) {
+    let mut updates = 0;
+    while *lag >= fixed.step && updates < fixed.max_updates {
+        handler.fixed_update(fixed.step);
+        *lag -= fixed.step;
+        updates += 1;
+    }
+    if *lag > fixed.max_lag {
+        handler.fell_behind(now, *lag);
+        *lag = Duration::from_secs(0);
+    }
+}
+
+/// Dispatches the event and does the suspending, returns false once the handler wants to quit
+fn handle<H: EventHandler>(handler: &mut H, event: TimedEvent, state: &mut LoopState) -> bool {
+    match event.event {
+        // anything the window gets might change what's on it
+        Event::WindowEvent { .. } | Event::Suspended(_) => state.redraw = true,
+        _ => (),
+    }
+    match dispatch(handler, event.time, event.event) {
+        Control::Continue => (),
+        Control::Resize(time) => state.resized = Some(time),
+        Control::Suspend(time) if !state.suspended => {
+            state.suspended = true;
+            handler.on_suspend(time);
+        }
+        Control::Resume(time) if state.suspended => {
+            state.suspended = false;
+            handler.on_resume(time);
+        }
+        Control::Suspend(_) | Control::Resume(_) => (),
+        Control::Quit => return false,
+    }
+    true
+}
+
+fn dispatch<H: EventHandler>(handler: &mut H, time: Instant, event: Event) -> Control {
+    match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested if handler.quit() => return Control::Quit,
+            WindowEvent::KeyboardInput { device_id, input } => {
+                let KeyboardInput {
+                    scancode,
+                    state,
+                    virtual_keycode,
+                    modifiers,
+                } = input;
+                let key = Key {
+                    device: device_id,
+                    scancode,
+                    virtual_keycode,
+                    modifiers: KeyModifiers {
+                        shift: modifiers.shift,
+                        ctrl: modifiers.ctrl,
+                        alt: modifiers.alt,
+                        logo: modifiers.logo,
+                    },
+                };
+                match state {
+                    ElementState::Pressed => handler.key_down(time, key),
+                    ElementState::Released => handler.key_up(time, key),
+                }
+            }
+            WindowEvent::ReceivedCharacter(c) => handler.received_character(time, c),
+            WindowEvent::CursorMoved { position, .. } => {
+                handler.mouse_move(time, MouseMove { position })
+            }
+            WindowEvent::MouseWheel { delta, .. } => handler.mouse_wheel(time, delta),
+            WindowEvent::Touch(touch) => handler.touch(time, touch),
+            WindowEvent::Resized(_) => return Control::Resize(time),
+            WindowEvent::HiDpiFactorChanged(factor) => {
+                handler.hidpi_factor_changed(time, factor);
+                return Control::Resize(time);
+            }
+            WindowEvent::Focused(true) => {
+                handler.window_focused(time, true);
+                return Control::Resume(time);
+            }
+            WindowEvent::Focused(false) if handler.window_focused(time, false) => {
+                return Control::Suspend(time);
+            }
+            _ => (),
+        },
+        // the app went to the background (or came back) on mobile, there's no asking the handler
+        Event::Suspended(true) => return Control::Suspend(time),
+        Event::Suspended(false) => return Control::Resume(time),
+        Event::DeviceEvent {
+            device_id,
+            event: DeviceEvent::Button { button, state },
+        } => {
+            let button = DeviceButton {
+                device: device_id,
+                button,
+            };
+            match state {
+                ElementState::Pressed => handler.device_button_down(time, button),
+                ElementState::Released => handler.device_button_up(time, button),
+            }
+        }
+        _ => (),
+    }
+    Control::Continue
+}
@@ -0,0 +1,110 @@
+use crate::{context::KeyBindings, graphics::Vsync, timing::SongClock};
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Why settings couldn't be loaded or saved
+#[derive(Debug)]
+pub enum SettingsError {
+    /// There's no config directory for this platform, or the user's home couldn't be found
+    NoConfigDir,
+    Io(PathBuf, io::Error),
+    /// The file isn't valid TOML or has the wrong shape
+    Parse(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::NoConfigDir => write!(f, "no config directory on this platform"),
+            SettingsError::Io(path, e) => write!(f, "couldn't access {}: {}", path.display(), e),
+            SettingsError::Parse(e) => write!(f, "invalid settings: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// What a player changed in the options menu, kept in settings.toml in the platform's config
+/// directory (~/.config/<app> on Linux, %APPDATA%\<app> on Windows, ~/Library/Application
+/// Support/<app> on macOS). `ContextBuilder::settings` applies them on startup. Anything that's
+/// None or missing from the file keeps whatever the game set up
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Logical window size
+    pub resolution: Option<(f64, f64)>,
+    /// Goes to `WindowSettings::performance_mode`
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<Vsync>,
+    /// Seconds between the mixer playing something and it coming out of the speakers, positive
+    /// for output that's late like over bluetooth
+    pub audio_offset: f64,
+    /// Seconds the player hits late, what `Calibration::offset` comes up with
+    pub input_offset: f64,
+    /// Merged with the game's own bindings, so actions added in an update still have keys. Last
+    /// since TOML wants tables after plain values
+    pub key_bindings: KeyBindings,
+}
+
+impl Settings {
+    /// Where `app`'s settings live, None when the platform doesn't have a config directory
+    pub fn path(app: &str) -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(app).join("settings.toml"))
+    }
+
+    /// Defaults when there's no file yet, which is the case on the first run
+    pub fn load(app: &str) -> Result<Settings, SettingsError> {
+        Settings::load_from(&Settings::path(app).ok_or(SettingsError::NoConfigDir)?)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Settings, SettingsError> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(SettingsError::Io(path.into(), e)),
+        };
+        toml::from_str(&data).map_err(|e| SettingsError::Parse(e.to_string()))
+    }
+
+    /// Same as `load`, but logs what went wrong and falls back to the defaults, so a broken file
+    /// doesn't keep the game from starting
+    pub fn load_or_default(app: &str, log: &Logger) -> Settings {
+        Settings::load(app).unwrap_or_else(|e| {
+            error!(log, "couldn't load settings, using the defaults"; "settings_error" => %e);
+            Settings::default()
+        })
+    }
+
+    /// Creates the directory if it isn't there yet
+    pub fn save(&self, app: &str) -> Result<(), SettingsError> {
+        self.save_to(&Settings::path(app).ok_or(SettingsError::NoConfigDir)?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), SettingsError> {
+        let data = toml::to_string_pretty(self).map_err(|e| SettingsError::Parse(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SettingsError::Io(dir.into(), e))?;
+        }
+        // written next to it and moved over, so a crash halfway through doesn't eat the old file
+        let temp = path.with_extension("toml.tmp");
+        fs::write(&temp, data).map_err(|e| SettingsError::Io(temp.clone(), e))?;
+        fs::rename(&temp, path).map_err(|e| SettingsError::Io(path.into(), e))
+    }
+
+    /// A clock for a song whose first sample the mixer plays at `start`, with both offsets
+    /// applied
+    pub fn song_clock(&self, start: Instant) -> SongClock {
+        let delay = Duration::from_nanos((self.audio_offset.abs() * 1_000_000_000.0) as u64);
+        let heard = if self.audio_offset >= 0.0 {
+            start.checked_add(delay)
+        } else {
+            start.checked_sub(delay)
+        };
+        SongClock::new(heard.unwrap_or(start)).with_offset(self.input_offset)
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    assets::{AssetSource, DirectorySource},
+    context::Context,
+    graphics::{
+        decode_image, HalState, HighwaySkin, NoteHighway, SamplerDesc, ScrollSpeed, TextureError,
+        TextureId,
+    },
+};
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Why a skin directory couldn't be loaded
+#[derive(Debug)]
+pub enum SkinError {
+    Io(PathBuf, io::Error),
+    /// The manifest isn't valid TOML or JSON, or has the wrong shape
+    Manifest(String),
+    Texture(PathBuf, TextureError),
+}
+
+impl fmt::Display for SkinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkinError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+            SkinError::Manifest(e) => write!(f, "invalid skin manifest: {}", e),
+            SkinError::Texture(path, e) => write!(f, "couldn't load {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for SkinError {}
+
+/// Either "#rrggbb", "#rrggbbaa" or an [r, g, b, a] array of floats
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Hex(String),
+    Rgba([f32; 4]),
+}
+
+impl ColorValue {
+    fn parse(&self) -> Result<[f32; 4], String> {
+        let hex = match self {
+            ColorValue::Rgba(rgba) => return Ok(*rgba),
+            ColorValue::Hex(hex) => hex,
+        };
+        let digits = hex.trim_start_matches('#');
+        if digits.len() != 6 && digits.len() != 8 {
+            return Err(format!("{} isn't a color", hex));
+        }
+        let mut color = [1.0; 4];
+        for (i, channel) in color.iter_mut().enumerate().take(digits.len() / 2) {
+            let byte = digits
+                .get(i * 2..i * 2 + 2)
+                .and_then(|d| u8::from_str_radix(d, 16).ok())
+                .ok_or_else(|| format!("{} isn't a color", hex))?;
+            *channel = f32::from(byte) / 255.0;
+        }
+        Ok(color)
+    }
+}
+
+/// Either just the path or a table with sampling options as well
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum TextureEntry {
+    Path(PathBuf),
+    Detailed {
+        path: PathBuf,
+        /// `SamplerDesc::SMOOTH` instead of the default sampler
+        #[serde(default)]
+        smooth: bool,
+    },
+}
+
+/// skin.toml or skin.json, every path is relative to the skin's directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    name: Option<String>,
+    textures: HashMap<String, TextureEntry>,
+    fonts: HashMap<String, PathBuf>,
+    colors: HashMap<String, ColorValue>,
+    metrics: HashMap<String, f32>,
+    lane_colors: Vec<ColorValue>,
+}
+
+/// A set of textures, fonts, colors and layout numbers looked up by name, so the look of a game
+/// can be swapped out without touching the code. A skin directory has a skin.toml (or skin.json)
+/// like
+///
+/// ```toml
+/// name = "Arrows"
+/// lane_colors = ["#ff4060", "#40a0ff", [0.4, 1.0, 0.4, 1.0], "#ffd040"]
+///
+/// [textures]
+/// note = "note.png"
+/// receptor = { path = "receptor.png", smooth = true }
+///
+/// [fonts]
+/// title = "fonts/title.otf"
+///
+/// [colors]
+/// background = "#101018"
+///
+/// [metrics]
+/// lane_width = 96
+/// ```
+///
+/// Anything it leaves out comes from the built-in skin, see `Skin::builtin` for what that has
+#[derive(Debug, Clone)]
+pub struct Skin {
+    pub name: String,
+    textures: HashMap<String, TextureId>,
+    fonts: HashMap<String, Vec<u8>>,
+    colors: HashMap<String, [f32; 4]>,
+    metrics: HashMap<String, f32>,
+    lane_colors: Vec<[f32; 4]>,
+}
+
+impl Skin {
+    /// Plain white shapes for the "receptor", "note" and "hold_body" textures that get tinted by
+    /// the lane colors, metrics for `note_highway` ("lane_width", "note_length", "visible_length"
+    /// and "scroll_speed") and no fonts
+    pub fn builtin(hal: &mut HalState) -> Result<Skin, &'static str> {
+        let mut skin = Skin::without_textures();
+        let sampler = hal.default_sampler();
+        for (name, image) in builtin_images() {
+            match hal.load_image_with_sampler(image, sampler) {
+                Ok(texture) => {
+                    skin.textures.insert(name.to_string(), texture);
+                }
+                Err(e) => {
+                    skin.unload(hal);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(skin)
+    }
+
+    /// Everything in the built-in skin but the textures
+    fn without_textures() -> Skin {
+        let mut metrics = HashMap::new();
+        metrics.insert("lane_width".to_string(), 64.0);
+        metrics.insert("note_length".to_string(), 64.0);
+        metrics.insert("visible_length".to_string(), 640.0);
+        metrics.insert("scroll_speed".to_string(), 800.0);
+
+        let mut colors = HashMap::new();
+        colors.insert("background".to_string(), [0.0, 0.0, 0.0, 1.0]);
+
+        Skin {
+            name: "Default".to_string(),
+            textures: HashMap::new(),
+            fonts: HashMap::new(),
+            colors,
+            metrics,
+            lane_colors: vec![
+                [1.0, 0.3, 0.4, 1.0],
+                [0.3, 0.6, 1.0, 1.0],
+                [0.4, 1.0, 0.4, 1.0],
+                [1.0, 0.8, 0.3, 1.0],
+            ],
+        }
+    }
+
+    /// Loads the skin in `dir` on top of the built-in one. Any file the manifest names that can't
+    /// be read is an error, leaving an entry out is fine. Paths in errors are relative to `dir`
+    pub fn load(hal: &mut HalState, dir: &Path) -> Result<Skin, SkinError> {
+        Skin::load_from(hal, &DirectorySource(dir.to_path_buf()), "")
+    }
+
+    /// Same as `load` for a skin in a `Vfs`, `Bundle` or any other source, `dir` is where in the
+    /// source it is
+    pub fn load_from(
+        hal: &mut HalState,
+        source: &dyn AssetSource,
+        dir: &str,
+    ) -> Result<Skin, SkinError> {
+        Skin::load_with(hal, Path::new(dir), &|path| {
+            source.read(&path.to_string_lossy())
+        })
+    }
+
+    fn load_with(
+        hal: &mut HalState,
+        dir: &Path,
+        read: &dyn Fn(&Path) -> io::Result<Vec<u8>>,
+    ) -> Result<Skin, SkinError> {
+        let manifest = read_manifest(dir, read)?;
+        let mut skin = Skin::without_textures();
+        if let Some(name) = manifest.name {
+            skin.name = name;
+        }
+
+        // everything gets read and decoded before any of it goes to the GPU, so a broken skin
+        // doesn't leave half its textures loaded
+        let mut images = Vec::new();
+        for (name, entry) in manifest.textures {
+            let (path, sampler) = match entry {
+                TextureEntry::Path(path) => (path, hal.default_sampler()),
+                TextureEntry::Detailed { path, smooth: true } => (path, SamplerDesc::SMOOTH),
+                TextureEntry::Detailed { path, .. } => (path, hal.default_sampler()),
+            };
+            let path = dir.join(path);
+            let data = read(&path).map_err(|e| SkinError::Io(path.clone(), e))?;
+            let image =
+                decode_image(&data).map_err(|e| SkinError::Texture(path.clone(), e.into()))?;
+            images.push((name, path, image, sampler));
+        }
+        for (name, path) in manifest.fonts {
+            let path = dir.join(path);
+            let data = read(&path).map_err(|e| SkinError::Io(path, e))?;
+            skin.fonts.insert(name, data);
+        }
+        for (name, color) in manifest.colors {
+            skin.colors
+                .insert(name, color.parse().map_err(SkinError::Manifest)?);
+        }
+        skin.metrics.extend(manifest.metrics);
+        if !manifest.lane_colors.is_empty() {
+            skin.lane_colors = manifest
+                .lane_colors
+                .iter()
+                .map(ColorValue::parse)
+                .collect::<Result<_, _>>()
+                .map_err(SkinError::Manifest)?;
+        }
+
+        // built-in textures only get loaded if the skin doesn't have its own
+        for (name, image) in builtin_images() {
+            if images.iter().all(|(existing, ..)| existing != name) {
+                let sampler = hal.default_sampler();
+                images.push((name.to_string(), dir.into(), image, sampler));
+            }
+        }
+        for (name, path, image, sampler) in images {
+            match hal.load_image_with_sampler(image, sampler) {
+                Ok(texture) => {
+                    skin.textures.insert(name, texture);
+                }
+                Err(e) => {
+                    skin.unload(hal);
+                    return Err(SkinError::Texture(path, e.into()));
+                }
+            }
+        }
+        Ok(skin)
+    }
+
+    /// Frees the skin's textures, for switching to another skin. With the texture atlas on they
+    /// stay loaded since the atlas can't give space back
+    pub fn unload(self, hal: &mut HalState) {
+        for texture in self.textures.values() {
+            // only fails for textures in the atlas, which stay loaded either way
+            let _ = hal.unload_texture(*texture);
+        }
+    }
+
+    /// Loads the skin at `dir` in `ctx.assets`, but logs what went wrong and falls back to the
+    /// built-in skin instead of failing, for skins the player picked
+    pub fn load_or_builtin(ctx: &mut Context, dir: &str) -> Result<Skin, &'static str> {
+        match Skin::load_from(&mut ctx.graphics, &ctx.assets, dir) {
+            Ok(skin) => {
+                info!(ctx.logger(), "loaded skin"; "name" => &skin.name, "dir" => dir);
+                Ok(skin)
+            }
+            Err(e) => {
+                error!(ctx.logger(), "couldn't load skin, using the built-in one";
+                       "dir" => dir, "skin_error" => %e);
+                Skin::builtin(&mut ctx.graphics)
+            }
+        }
+    }
+
+    pub fn texture(&self, name: &str) -> Option<TextureId> {
+        self.textures.get(name).cloned()
+    }
+
+    /// The raw font file, for whatever ends up rendering text
+    pub fn font(&self, name: &str) -> Option<&[u8]> {
+        self.fonts.get(name).map(Vec::as_slice)
+    }
+
+    pub fn color(&self, name: &str) -> Option<[f32; 4]> {
+        self.colors.get(name).cloned()
+    }
+
+    pub fn metric(&self, name: &str) -> Option<f32> {
+        self.metrics.get(name).cloned()
+    }
+
+    pub fn lane_colors(&self) -> &[[f32; 4]] {
+        &self.lane_colors
+    }
+
+    /// The "receptor", "note" and "hold_body" textures
+    pub fn highway_skin(&self) -> HighwaySkin {
+        let texture = |name| self.texture(name).unwrap_or(TextureId::NONE);
+        HighwaySkin::new(texture("receptor"), texture("note"), texture("hold_body"))
+    }
+
+    /// A highway laid out by the skin's metrics and colored by its lane colors
+    pub fn note_highway(&self, lanes: u32) -> NoteHighway {
+        let lane_width = self.metric("lane_width").unwrap_or(64.0);
+        let mut highway = NoteHighway::new(lanes, lane_width, self.highway_skin())
+            .with_lane_colors(self.lane_colors.clone());
+        if let Some(note_length) = self.metric("note_length") {
+            highway.note_length = note_length;
+        }
+        if let Some(visible_length) = self.metric("visible_length") {
+            highway.visible_length = visible_length;
+        }
+        if let Some(speed) = self.metric("scroll_speed") {
+            highway.speed = ScrollSpeed::Constant(speed);
+        }
+        highway
+    }
+}
+
+fn read_manifest(
+    dir: &Path,
+    read: &dyn Fn(&Path) -> io::Result<Vec<u8>>,
+) -> Result<Manifest, SkinError> {
+    let utf8 = |data| String::from_utf8(data).map_err(|e| SkinError::Manifest(e.to_string()));
+    let toml_path = dir.join("skin.toml");
+    match read(&toml_path) {
+        Ok(data) => {
+            return toml::from_str(&utf8(data)?).map_err(|e| SkinError::Manifest(e.to_string()))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(SkinError::Io(toml_path, e)),
+    }
+    let json_path = dir.join("skin.json");
+    let data = read(&json_path).map_err(|e| SkinError::Io(json_path, e))?;
+    serde_json::from_str(&utf8(data)?).map_err(|e| SkinError::Manifest(e.to_string()))
+}
+
+/// A white circle `size` pixels across with a `thickness` pixel outline, antialiased by one pixel
+fn ring(size: u32, thickness: u32) -> RgbaImage {
+    let radius = size as f32 / 2.0;
+    RgbaImage::from_fn(size, size, |x, y| {
+        let dx = x as f32 + 0.5 - radius;
+        let dy = y as f32 + 0.5 - radius;
+        let distance = (dx * dx + dy * dy).sqrt();
+        let outer = (radius - distance).max(0.0).min(1.0);
+        let inner = (distance - (radius - thickness as f32)).max(0.0).min(1.0);
+        Rgba {
+            data: [255, 255, 255, (outer * inner * 255.0) as u8],
+        }
+    })
+}
+
+/// The built-in skin's textures, before they go to the GPU
+fn builtin_images() -> Vec<(&'static str, RgbaImage)> {
+    let hold = RgbaImage::from_pixel(
+        4,
+        4,
+        Rgba {
+            data: [255, 255, 255, 160],
+        },
+    );
+    vec![
+        ("receptor", ring(32, 4)),
+        ("note", ring(32, 16)),
+        ("hold_body", hold),
+    ]
+}
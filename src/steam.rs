@@ -0,0 +1,135 @@
+use crate::context::InputState;
+use slog::Logger;
+use steamworks::{Client, InputActionSetHandle_t, InputDigitalActionHandle_t, SingleClient};
+
+/// Steam Input actions and the handles Steam gave out for them
+struct SteamInput {
+    action_set: InputActionSetHandle_t,
+    actions: Vec<(String, InputDigitalActionHandle_t)>,
+}
+
+/// The Steamworks API for a game that ships on Steam. `ContextBuilder::steam` starts it before
+/// the window opens, which the overlay needs, and `Context` runs its callbacks and forwards Steam
+/// Input actions into `InputState::set_action` before every update
+pub struct Steam {
+    client: Client,
+    single: SingleClient,
+    input: Option<SteamInput>,
+    log: Logger,
+}
+
+impl std::fmt::Debug for Steam {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Steam")
+            .field("input", &self.input.is_some())
+            .finish()
+    }
+}
+
+impl Steam {
+    /// Fails when Steam isn't running or the game wasn't started through it. Run
+    /// `restart_through_steam` first for builds started from outside Steam
+    pub fn init(log: Logger) -> Result<Self, &'static str> {
+        let (client, single) = Client::init().map_err(|e| {
+            error!(log, "couldn't start Steam"; "steam_error" => %e);
+            "Couldn't connect to Steam!"
+        })?;
+        info!(log, "connected to Steam"; "app_id" => client.utils().app_id().0);
+        Ok(Steam {
+            client,
+            single,
+            input: None,
+            log,
+        })
+    }
+
+    /// True means Steam is starting the game again itself and this process should quit right away
+    pub fn restart_through_steam(app_id: u32) -> bool {
+        steamworks::restart_app_if_necessary(steamworks::AppId(app_id))
+    }
+
+    /// For everything this doesn't wrap
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Shows the popup and sends it to Steam's servers right away
+    pub fn unlock_achievement(&self, name: &str) -> Result<(), &'static str> {
+        let stats = self.client.user_stats();
+        stats
+            .achievement(name)
+            .set()
+            .map_err(|()| "That achievement doesn't exist!")?;
+        stats
+            .store_stats()
+            .map_err(|()| "Couldn't store the stats!")
+    }
+
+    /// For testing, players can't lose achievements otherwise
+    pub fn clear_achievement(&self, name: &str) -> Result<(), &'static str> {
+        let stats = self.client.user_stats();
+        stats
+            .achievement(name)
+            .clear()
+            .map_err(|()| "That achievement doesn't exist!")?;
+        stats
+            .store_stats()
+            .map_err(|()| "Couldn't store the stats!")
+    }
+
+    /// None when it doesn't exist or the stats haven't come in from Steam yet
+    pub fn is_achieved(&self, name: &str) -> Option<bool> {
+        self.client.user_stats().achievement(name).get().ok()
+    }
+
+    /// Turns on Steam Input with `action_set` from the game's action manifest. The digital
+    /// `actions` in it get forwarded to `InputState::set_action` under the same names, so
+    /// controller buttons and bound keys work through the same `is_action_down`
+    pub fn enable_input(&mut self, action_set: &str, actions: &[&str]) -> Result<(), &'static str> {
+        let input = self.client.input();
+        if !input.init(true) {
+            return Err("Couldn't start Steam Input!");
+        }
+        let action_set = input.get_action_set_handle(action_set);
+        if action_set == 0 {
+            return Err("That action set isn't in the action manifest!");
+        }
+        let actions = actions
+            .iter()
+            .map(|&name| (name.to_string(), input.get_digital_action_handle(name)))
+            .collect::<Vec<_>>();
+        for (name, handle) in &actions {
+            if *handle == 0 {
+                error!(self.log, "Steam Input action isn't in the action manifest"; "action" => name);
+            }
+        }
+        self.input = Some(SteamInput {
+            action_set,
+            actions,
+        });
+        Ok(())
+    }
+
+    /// Runs Steam's callbacks and forwards the controllers' actions, `Context` calls this before
+    /// every update
+    pub fn update(&mut self, input_state: &mut InputState) {
+        self.single.run_callbacks();
+        let steam_input = match &self.input {
+            Some(steam_input) => steam_input,
+            None => return,
+        };
+        let input = self.client.input();
+        input.run_frame();
+        let controllers = input.get_connected_controllers();
+        for &controller in &controllers {
+            input.activate_action_set_handle(controller, steam_input.action_set);
+        }
+        for (name, action) in &steam_input.actions {
+            // any controller pressing it counts, for local multiplayer read the client directly
+            let down = controllers
+                .iter()
+                .any(|&controller| input.get_digital_action_data(controller, *action).bState);
+            input_state.set_action(name, down);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::{
+    context::Context,
+    geometry::{Quad, Vec2},
+    graphics::{Font, Frame, TextBox, TextLayout, TextSpan, TextureId, TexturedQuad},
+};
+use std::{ops::Range, time::Duration};
+use winit::VirtualKeyCode;
+
+/// Where cut and copied text goes and pasted text comes from
+pub trait Clipboard {
+    fn get(&mut self) -> Option<String>;
+    fn set(&mut self, text: String);
+}
+
+/// A clipboard that only exists inside the game, for when there's no system one to get at
+#[derive(Debug, Clone, Default)]
+pub struct LocalClipboard(Option<String>);
+
+impl Clipboard for LocalClipboard {
+    fn get(&mut self) -> Option<String> {
+        self.0.clone()
+    }
+
+    fn set(&mut self, text: String) {
+        self.0 = Some(text);
+    }
+}
+
+/// The OS clipboard
+#[cfg(feature = "clipboard")]
+pub struct SystemClipboard(clipboard::ClipboardContext);
+
+#[cfg(feature = "clipboard")]
+impl SystemClipboard {
+    pub fn new() -> Result<Self, &'static str> {
+        use clipboard::ClipboardProvider;
+        clipboard::ClipboardContext::new()
+            .map(SystemClipboard)
+            .map_err(|_| "Couldn't open the clipboard!")
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl Clipboard for SystemClipboard {
+    fn get(&mut self) -> Option<String> {
+        use clipboard::ClipboardProvider;
+        self.0.get_contents().ok()
+    }
+
+    fn set(&mut self, text: String) {
+        use clipboard::ClipboardProvider;
+        let _ = self.0.set_contents(text);
+    }
+}
+
+/// How long the caret stays on and then off, in milliseconds
+const BLINK: u64 = 530;
+
+/// A single line text field for search boxes, profile names and such. It edits with the usual
+/// keys (arrows and Home/End, with Shift to select and Ctrl to go by word, Ctrl+A/C/X/V) and
+/// takes what's typed from `InputState::text`, so IMEs work as far as the window gets their text
+#[derive(Debug, Clone)]
+pub struct TextInput {
+    /// Where the text goes, wrapping with `max_width` makes it wrap instead of scroll
+    pub text_box: TextBox,
+    pub size: f32,
+    pub color: [f32; 4],
+    pub selection_color: [f32; 4],
+    pub caret_color: [f32; 4],
+    /// In world units
+    pub caret_width: f32,
+    /// In chars, not bytes
+    pub max_length: Option<usize>,
+    /// Only focused inputs take input and show a caret
+    pub focused: bool,
+    text: String,
+    /// Byte offsets, the selection goes from the anchor to the caret
+    caret: usize,
+    anchor: usize,
+    blink: Duration,
+    layout: TextLayout,
+}
+
+impl TextInput {
+    pub fn new(text_box: TextBox, size: f32) -> Self {
+        TextInput {
+            text_box,
+            size,
+            color: [1.0; 4],
+            selection_color: [0.2, 0.4, 0.9, 0.5],
+            caret_color: [1.0; 4],
+            caret_width: size * 0.08,
+            max_length: None,
+            focused: true,
+            text: String::new(),
+            caret: 0,
+            anchor: 0,
+            blink: Duration::from_secs(0),
+            layout: TextLayout::default(),
+        }
+    }
+
+    pub fn with_max_length(self, max_length: usize) -> Self {
+        TextInput {
+            max_length: Some(max_length),
+            ..self
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text and puts the caret at the end
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.caret = 0;
+        self.anchor = 0;
+        self.insert(text);
+    }
+
+    /// Byte offset into the text
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Byte range, empty when nothing's selected
+    pub fn selection(&self) -> Range<usize> {
+        self.caret.min(self.anchor)..self.caret.max(self.anchor)
+    }
+
+    pub fn selected_text(&self) -> &str {
+        &self.text[self.selection()]
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = 0;
+        self.caret = self.text.len();
+    }
+
+    /// Replaces the selection with `text`, cut short to fit in `max_length`. Newlines and other
+    /// control characters are left out
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        let room = match self.max_length {
+            Some(max) => max.saturating_sub(self.text.chars().count()),
+            None => std::usize::MAX,
+        };
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(room)
+            .collect();
+        self.text.insert_str(self.caret, &text);
+        self.caret += text.len();
+        self.anchor = self.caret;
+        self.blink = Duration::from_secs(0);
+    }
+
+    /// Returns whether there was anything to delete
+    pub fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        if selection.start == selection.end {
+            return false;
+        }
+        self.text.replace_range(selection.clone(), "");
+        self.caret = selection.start;
+        self.anchor = selection.start;
+        true
+    }
+
+    pub fn copy(&self, clipboard: &mut dyn Clipboard) {
+        if !self.selected_text().is_empty() {
+            clipboard.set(self.selected_text().to_string());
+        }
+    }
+
+    pub fn cut(&mut self, clipboard: &mut dyn Clipboard) {
+        self.copy(clipboard);
+        self.delete_selection();
+    }
+
+    pub fn paste(&mut self, clipboard: &mut dyn Clipboard) {
+        if let Some(text) = clipboard.get() {
+            self.insert(&text);
+        }
+    }
+
+    /// Moves the caret to `to`, in bytes. A `to` in the middle of a char snaps back to the start
+    /// of it. With `extend` the selection grows to it, otherwise it gets dropped
+    pub fn move_caret(&mut self, to: usize, extend: bool) {
+        let mut to = to.min(self.text.len());
+        while !self.text.is_char_boundary(to) {
+            to -= 1;
+        }
+        self.caret = to;
+        if !extend {
+            self.anchor = self.caret;
+        }
+        self.blink = Duration::from_secs(0);
+    }
+
+    /// Handles this update's typing and editing keys, returns whether Enter was pressed
+    pub fn update(&mut self, ctx: &Context, clipboard: &mut dyn Clipboard) -> bool {
+        self.blink += ctx.clock.delta();
+        if !self.focused {
+            return false;
+        }
+        let mut submitted = false;
+        for key in ctx.input.key_presses() {
+            let (shift, ctrl) = (key.modifiers.shift, key.modifiers.ctrl);
+            let code = match key.virtual_keycode {
+                Some(code) => code,
+                None => continue,
+            };
+            match code {
+                VirtualKeyCode::Left => {
+                    let to = if !shift && !ctrl && self.caret != self.anchor {
+                        self.selection().start
+                    } else if ctrl {
+                        word_left(&self.text, self.caret)
+                    } else {
+                        char_left(&self.text, self.caret)
+                    };
+                    self.move_caret(to, shift);
+                }
+                VirtualKeyCode::Right => {
+                    let to = if !shift && !ctrl && self.caret != self.anchor {
+                        self.selection().end
+                    } else if ctrl {
+                        word_right(&self.text, self.caret)
+                    } else {
+                        char_right(&self.text, self.caret)
+                    };
+                    self.move_caret(to, shift);
+                }
+                VirtualKeyCode::Home => self.move_caret(0, shift),
+                VirtualKeyCode::End => self.move_caret(self.text.len(), shift),
+                // with a selection the guard already deleted it
+                VirtualKeyCode::Back | VirtualKeyCode::Delete if !self.delete_selection() => {
+                    let to = match (code, ctrl) {
+                        (VirtualKeyCode::Back, true) => word_left(&self.text, self.caret),
+                        (VirtualKeyCode::Back, false) => char_left(&self.text, self.caret),
+                        (_, true) => word_right(&self.text, self.caret),
+                        (_, false) => char_right(&self.text, self.caret),
+                    };
+                    self.anchor = to;
+                    self.delete_selection();
+                    self.blink = Duration::from_secs(0);
+                }
+                VirtualKeyCode::A if ctrl => self.select_all(),
+                VirtualKeyCode::C if ctrl => self.copy(clipboard),
+                VirtualKeyCode::X if ctrl => self.cut(clipboard),
+                VirtualKeyCode::V if ctrl => self.paste(clipboard),
+                VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => submitted = true,
+                _ => (),
+            }
+        }
+        if !ctx.input.text().is_empty() {
+            self.insert(ctx.input.text());
+        }
+        submitted
+    }
+
+    /// Where a click at `point` puts the caret, going by the layout from the last `draw`
+    pub fn caret_at(&self, point: Vec2<f32>) -> usize {
+        let line = self
+            .layout
+            .lines
+            .iter()
+            .position(|line| point.y < line.top + line.height)
+            .unwrap_or_else(|| self.layout.lines.len().saturating_sub(1));
+        let glyphs = match self.layout.lines.get(line) {
+            Some(line) => &self.layout.glyphs[line.glyphs.clone()],
+            None => return 0,
+        };
+        glyphs
+            .iter()
+            .find(|glyph| point.x < glyph.position.x + glyph.advance / 2.0)
+            .map(|glyph| glyph.index)
+            .or_else(|| glyphs.last().map(|glyph| glyph.index + glyph.c.len_utf8()))
+            .unwrap_or(0)
+    }
+
+    /// Puts the caret where `point` is, dragging to select is clicking with `extend`
+    pub fn click(&mut self, point: Vec2<f32>, extend: bool) {
+        let to = self.caret_at(point);
+        self.move_caret(to, extend);
+    }
+
+    /// Lays the text out with `font` and draws it with the selection behind it and the caret on
+    /// top
+    pub fn draw(&mut self, frame: &mut Frame, font: &dyn Font) {
+        let span = TextSpan::new(&self.text, self.size).with_color(self.color);
+        self.layout = self.text_box.layout(font, &[span]);
+
+        let mut quads = Vec::new();
+        let selection = self.selection();
+        if self.focused && selection.start != selection.end {
+            for glyph in &self.layout.glyphs {
+                if selection.contains(&glyph.index) {
+                    let line = &self.layout.lines[glyph.line];
+                    quads.push(rect(
+                        Vec2::new(glyph.position.x, line.top),
+                        Vec2::new(glyph.advance, line.height),
+                        self.selection_color,
+                    ));
+                }
+            }
+        }
+        quads.extend(self.layout.quads());
+        let millis = self.blink.as_secs() * 1000 + u64::from(self.blink.subsec_millis());
+        let blink_on = millis % (BLINK * 2) < BLINK;
+        if self.focused && blink_on {
+            let (x, top, height) = self.caret_position();
+            quads.push(rect(
+                Vec2::new(x - self.caret_width / 2.0, top),
+                Vec2::new(self.caret_width, height),
+                self.caret_color,
+            ));
+        }
+        frame.draw_quads(&quads);
+    }
+
+    /// x, top and height of the caret in the current layout
+    fn caret_position(&self) -> (f32, f32, f32) {
+        let layout = &self.layout;
+        let glyph = layout.glyphs.iter().find(|glyph| glyph.index >= self.caret);
+        let (x, line) = match (glyph, layout.glyphs.last()) {
+            (Some(glyph), _) => (glyph.position.x, glyph.line),
+            (None, Some(last)) => (last.position.x + last.advance, last.line),
+            (None, None) => match layout.lines.first() {
+                Some(line) => (line.x, 0),
+                None => {
+                    return (
+                        self.text_box.position.x,
+                        self.text_box.position.y,
+                        self.size,
+                    )
+                }
+            },
+        };
+        let line = &layout.lines[line];
+        (x, line.top, line.height)
+    }
+}
+
+fn rect(top_left: Vec2<f32>, size: Vec2<f32>, color: [f32; 4]) -> TexturedQuad {
+    let quad = Quad {
+        top_left,
+        bottom_left: top_left + Vec2::new(0.0, size.y),
+        bottom_right: top_left + size,
+        top_right: top_left + Vec2::new(size.x, 0.0),
+    };
+    TexturedQuad::new(quad, TextureId::NONE.index()).with_color(color)
+}
+
+fn char_left(text: &str, i: usize) -> usize {
+    text[..i].char_indices().next_back().map_or(0, |(j, _)| j)
+}
+
+fn char_right(text: &str, i: usize) -> usize {
+    text[i..].chars().next().map_or(i, |c| i + c.len_utf8())
+}
+
+/// The start of the word before `i`
+fn word_left(text: &str, i: usize) -> usize {
+    let mut i = i;
+    while i > 0 && text[..i].ends_with(char::is_whitespace) {
+        i = char_left(text, i);
+    }
+    while i > 0 && !text[..i].ends_with(char::is_whitespace) {
+        i = char_left(text, i);
+    }
+    i
+}
+
+/// The start of the word after `i`
+fn word_right(text: &str, i: usize) -> usize {
+    let mut i = i;
+    while i < text.len() && !text[i..].starts_with(char::is_whitespace) {
+        i = char_right(text, i);
+    }
+    while i < text.len() && text[i..].starts_with(char::is_whitespace) {
+        i = char_right(text, i);
+    }
+    i
+}
@@ -0,0 +1,213 @@
+use crate::context::Context;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    time::{Duration, Instant},
+};
+
+/// Handle for cancelling a scheduled callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer<C> {
+    callback: Box<dyn FnMut(&mut C)>,
+    interval: Option<Duration>,
+}
+
+/// Runs callbacks at offsets from the current engine time. It keeps its own clock that only moves
+/// when `advance` is called, so hand it the same timestep the rest of the update gets. Repeating
+/// timers are scheduled off their last due time instead of when they actually ran, so they don't
+/// drift, and fire more than once if a single step covers several intervals.
+///
+/// Callbacks get `&mut C`, whatever state the game wants to poke at when they fire
+pub struct Scheduler<C = ()> {
+    now: Duration,
+    next_id: u64,
+    timers: HashMap<u64, Timer<C>>,
+    /// (due time, id), cancelled timers stay in here until they'd have fired
+    queue: BinaryHeap<Reverse<(Duration, u64)>>,
+}
+
+impl<C> Default for Scheduler<C> {
+    fn default() -> Self {
+        Scheduler {
+            now: Duration::from_secs(0),
+            next_id: 0,
+            timers: HashMap::new(),
+            queue: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<C> std::fmt::Debug for Scheduler<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("now", &self.now)
+            .field("timers", &self.timers.len())
+            .finish()
+    }
+}
+
+impl<C> Scheduler<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total time advanced so far
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Runs `callback` once, `delay` from now
+    pub fn after<F: FnMut(&mut C) + 'static>(&mut self, delay: Duration, callback: F) -> TimerId {
+        self.schedule(delay, None, Box::new(callback))
+    }
+
+    /// Runs `callback` every `interval`, starting one interval from now. Panics if `interval` is
+    /// zero
+    pub fn every<F: FnMut(&mut C) + 'static>(
+        &mut self,
+        interval: Duration,
+        callback: F,
+    ) -> TimerId {
+        assert!(
+            interval > Duration::from_secs(0),
+            "Repeating timers can't have a zero interval!"
+        );
+        self.schedule(interval, Some(interval), Box::new(callback))
+    }
+
+    fn schedule(
+        &mut self,
+        delay: Duration,
+        interval: Option<Duration>,
+        callback: Box<dyn FnMut(&mut C)>,
+    ) -> TimerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.timers.insert(id, Timer { callback, interval });
+        self.queue.push(Reverse((self.now + delay, id)));
+        TimerId(id)
+    }
+
+    /// Returns whether the timer was still scheduled
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        self.timers.remove(&id.0).is_some()
+    }
+
+    pub fn is_scheduled(&self, id: TimerId) -> bool {
+        self.timers.contains_key(&id.0)
+    }
+
+    /// How many timers are still scheduled
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Moves the clock forward by `dt` and runs everything that came due, in order of due time
+    pub fn advance(&mut self, dt: Duration, context: &mut C) {
+        self.now += dt;
+        while let Some((due, id, mut timer)) = self.pop_due() {
+            (timer.callback)(context);
+            self.requeue(due, id, timer);
+        }
+    }
+
+    /// Takes out the next timer that's come due, the caller runs it and hands it to `requeue`
+    fn pop_due(&mut self) -> Option<(Duration, u64, Timer<C>)> {
+        while let Some(&Reverse((due, id))) = self.queue.peek() {
+            if due > self.now {
+                return None;
+            }
+            self.queue.pop();
+            // cancelled ones aren't in timers anymore
+            if let Some(timer) = self.timers.remove(&id) {
+                return Some((due, id, timer));
+            }
+        }
+        None
+    }
+
+    fn requeue(&mut self, due: Duration, id: u64, timer: Timer<C>) {
+        if let Some(interval) = timer.interval {
+            self.queue.push(Reverse((due + interval, id)));
+            self.timers.insert(id, timer);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+        self.queue.clear();
+    }
+}
+
+/// `Scheduler::advance` for `ctx.scheduler`, which can't hand out the `Context` it's part of. Each
+/// timer gets taken out while it runs, so callbacks can schedule and cancel through `ctx` as usual
+pub(crate) fn advance_context(ctx: &mut Context, dt: Duration) {
+    ctx.scheduler.now += dt;
+    while let Some((due, id, mut timer)) = ctx.scheduler.pop_due() {
+        (timer.callback)(ctx);
+        ctx.scheduler.requeue(due, id, timer);
+    }
+}
+
+/// Frame timing for the run loop, `tick` once per update
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    start: Instant,
+    last_tick: Instant,
+    delta: Duration,
+    /// Time spent suspended, so `elapsed` doesn't count it
+    paused: Duration,
+    ticks: u64,
+}
+
+impl Clock {
+    pub fn new(now: Instant) -> Self {
+        Clock {
+            start: now,
+            last_tick: now,
+            delta: Duration::from_secs(0),
+            paused: Duration::from_secs(0),
+            ticks: 0,
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        self.delta = now - self.last_tick;
+        self.last_tick = now;
+        self.ticks += 1;
+    }
+
+    /// `tick` for fixed timesteps, the delta is exactly `step` however long it actually was
+    pub fn step(&mut self, step: Duration) {
+        self.delta = step;
+        self.last_tick += step;
+        self.ticks += 1;
+    }
+
+    /// Skips over the time since the last tick, for coming back from a suspend without one giant
+    /// frame
+    pub fn resume(&mut self, now: Instant) {
+        self.paused += now - self.last_tick;
+        self.last_tick = now;
+    }
+
+    /// How long the last update was after the one before it
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Time since the clock started, minus the time spent suspended
+    pub fn elapsed(&self) -> Duration {
+        (self.last_tick - self.start) - self.paused
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
@@ -0,0 +1,594 @@
+use crate::timer::{Scheduler, TimerId};
+use std::time::{Duration, Instant};
+
+fn from_seconds(seconds: f64) -> Duration {
+    Duration::from_nanos((seconds.max(0.0) * 1_000_000_000.0) as u64)
+}
+
+/// Timing windows from tightest to loosest, each one is how far off (early or late) a hit can be
+/// and still get that judgment. `J` is whatever the game calls its judgments
+#[derive(Debug, Clone, PartialEq)]
+pub struct JudgmentWindows<J> {
+    windows: Vec<(J, f64)>,
+}
+
+impl<J: Copy> JudgmentWindows<J> {
+    /// The order doesn't matter, they get sorted by size
+    pub fn new(windows: &[(J, Duration)]) -> Self {
+        let mut windows: Vec<(J, f64)> = windows
+            .iter()
+            .map(|&(judgment, window)| (judgment, window.as_secs_f64()))
+            .collect();
+        windows.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        JudgmentWindows { windows }
+    }
+
+    /// `error` is in seconds, negative for early. None is outside every window, which is usually a
+    /// miss or a hit that shouldn't count at all
+    pub fn judge(&self, error: f64) -> Option<J> {
+        self.windows
+            .iter()
+            .find(|&&(_, window)| error.abs() <= window)
+            .map(|&(judgment, _)| judgment)
+    }
+
+    /// Past this a note can't be hit anymore
+    pub fn widest(&self) -> f64 {
+        self.windows.last().map_or(0.0, |&(_, window)| window)
+    }
+
+    /// Scales every window, for difficulty settings like judge 4 vs judge 5
+    pub fn scaled(&self, factor: f64) -> Self {
+        JudgmentWindows {
+            windows: self
+                .windows
+                .iter()
+                .map(|&(judgment, window)| (judgment, window * factor))
+                .collect(),
+        }
+    }
+}
+
+/// Collects hit errors over a song, for an average offset and how consistent the player was
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HitErrors {
+    errors: Vec<f64>,
+}
+
+impl HitErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// In seconds, negative for early
+    pub fn push(&mut self, error: f64) {
+        self.errors.push(error);
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+
+    pub fn errors(&self) -> &[f64] {
+        &self.errors
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Positive means the player tends to be late
+    pub fn mean(&self) -> Option<f64> {
+        if self.errors.is_empty() {
+            return None;
+        }
+        Some(self.errors.iter().sum::<f64>() / self.errors.len() as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance = self
+            .errors
+            .iter()
+            .map(|error| (error - mean) * (error - mean))
+            .sum::<f64>()
+            / self.errors.len() as f64;
+        Some(variance.sqrt())
+    }
+
+    /// osu!'s consistency number, ten times the standard deviation in milliseconds
+    pub fn unstable_rate(&self) -> Option<f64> {
+        self.std_dev().map(|std_dev| std_dev * 10_000.0)
+    }
+
+    /// How many hits were early and how many were late, dead on counts as neither
+    pub fn early_late(&self) -> (usize, usize) {
+        let early = self.errors.iter().filter(|&&error| error < 0.0).count();
+        let late = self.errors.iter().filter(|&&error| error > 0.0).count();
+        (early, late)
+    }
+}
+
+/// Turns input timestamps into song time. Input events come in with an `Instant`, the song plays
+/// on the audio device's clock, so this keeps track of which instant the song started at and gets
+/// nudged back in line with `resync` whenever the audio library reports where it is. The offset is
+/// what `Calibration` comes up with, it gets taken off every input so a player who's consistently
+/// 20ms late hits dead on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SongClock {
+    start: Instant,
+    offset: f64,
+}
+
+impl SongClock {
+    /// `start` is the instant song time 0 was (or will be) heard
+    pub fn new(start: Instant) -> Self {
+        SongClock { start, offset: 0.0 }
+    }
+
+    pub fn with_offset(self, offset: f64) -> Self {
+        SongClock { offset, ..self }
+    }
+
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: f64) {
+        self.offset = offset;
+    }
+
+    /// Song time at `at` without the offset, for drawing and scheduling
+    pub fn song_time(&self, at: Instant) -> f64 {
+        if at >= self.start {
+            (at - self.start).as_secs_f64()
+        } else {
+            -(self.start - at).as_secs_f64()
+        }
+    }
+
+    /// Song time an input at `at` counts as, for judging it
+    pub fn input_time(&self, at: Instant) -> f64 {
+        self.song_time(at) - self.offset
+    }
+
+    /// The audio was at `song_time` at `at`, moves the start so the two agree again. Audio clocks
+    /// tend to report in chunks of a buffer, so smooth what goes in here if it jitters
+    pub fn resync(&mut self, at: Instant, song_time: f64) {
+        let since_start = Duration::from_nanos((song_time.abs() * 1_000_000_000.0) as u64);
+        let start = if song_time >= 0.0 {
+            at.checked_sub(since_start)
+        } else {
+            at.checked_add(since_start)
+        };
+        if let Some(start) = start {
+            self.start = start;
+        }
+    }
+}
+
+/// Works out a player's input offset from taps along to a steady beat. Each tap gets compared to
+/// the beat closest to it, and the offset is the median of those errors so a couple of stray taps
+/// don't throw it off
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    first_beat: f64,
+    interval: f64,
+    errors: Vec<f64>,
+}
+
+impl Calibration {
+    /// Fewer taps than this and `offset` doesn't trust the result
+    pub const MIN_TAPS: usize = 8;
+
+    /// Beats are at `first_beat` and every `interval` after it, in song time
+    pub fn new(first_beat: f64, interval: Duration) -> Self {
+        Calibration {
+            first_beat,
+            interval: interval.as_secs_f64(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// `time` is the song time of the tap, `SongClock::song_time` without any offset
+    pub fn tap(&mut self, time: f64) {
+        if self.interval <= 0.0 {
+            return;
+        }
+        let beat = ((time - self.first_beat) / self.interval).round();
+        // taps before the first beat don't have a beat to go with
+        if beat < 0.0 {
+            return;
+        }
+        self.errors
+            .push(time - (self.first_beat + beat * self.interval));
+    }
+
+    pub fn taps(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+
+    /// What to give `SongClock::set_offset`, None until there are `MIN_TAPS` taps
+    pub fn offset(&self) -> Option<f64> {
+        if self.errors.len() < Self::MIN_TAPS {
+            return None;
+        }
+        let mut errors = self.errors.clone();
+        errors.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let middle = errors.len() / 2;
+        Some(if errors.len() % 2 == 0 {
+            (errors[middle - 1] + errors[middle]) / 2.0
+        } else {
+            errors[middle]
+        })
+    }
+}
+
+/// From `time` on, the beat goes up from `beat` at `bpm`. Stops are a segment with a bpm of 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    beat: f64,
+    time: f64,
+    bpm: f64,
+}
+
+/// A beat the song reaches, see `BeatClock::events_between`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatEvent {
+    pub beat: i64,
+    pub measure: i64,
+    /// Counting from 0, so 0 is the downbeat
+    pub beat_in_measure: u32,
+    /// Song time the beat lands on
+    pub time: f64,
+}
+
+impl BeatEvent {
+    pub fn is_downbeat(&self) -> bool {
+        self.beat_in_measure == 0
+    }
+}
+
+/// Maps song time in seconds to beats and back for songs with BPM changes, stops and time
+/// signature changes. Beats are counted from 0 at `offset` seconds into the song, everything before
+/// that is negative beats at the starting BPM
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatClock {
+    offset: f64,
+    /// (beat, bpm), sorted by beat and starting with the one at beat 0
+    bpm_changes: Vec<(f64, f64)>,
+    /// (beat, seconds), sorted by beat
+    stops: Vec<(f64, f64)>,
+    /// (beat, beats per measure), sorted by beat and starting with the one at beat 0
+    signatures: Vec<(i64, u32)>,
+    segments: Vec<Segment>,
+}
+
+impl BeatClock {
+    /// 4/4 at a constant `bpm`, with beat 0 `offset` seconds into the song. `bpm` has to be
+    /// positive, use a stop to stand still
+    pub fn new(bpm: f64, offset: f64) -> Result<Self, &'static str> {
+        check_bpm(bpm)?;
+        if !offset.is_finite() {
+            return Err("The offset has to be a finite number of seconds!");
+        }
+        let mut clock = BeatClock {
+            offset,
+            bpm_changes: vec![(0.0, bpm)],
+            stops: Vec::new(),
+            signatures: vec![(0, 4)],
+            segments: Vec::new(),
+        };
+        clock.rebuild();
+        Ok(clock)
+    }
+
+    /// The BPM is `bpm` from `beat` on, a change at beat 0 replaces the starting BPM. `bpm` has to
+    /// be positive like in `new`
+    pub fn with_bpm_change(mut self, beat: f64, bpm: f64) -> Result<Self, &'static str> {
+        check_bpm(bpm)?;
+        if !beat.is_finite() {
+            return Err("BPM changes have to be on a finite beat!");
+        }
+        self.bpm_changes
+            .retain(|&(existing, _)| existing != beat.max(0.0));
+        self.bpm_changes.push((beat.max(0.0), bpm));
+        self.bpm_changes
+            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        self.rebuild();
+        Ok(self)
+    }
+
+    /// Time keeps going but the beat stays at `beat` for `duration`
+    pub fn with_stop(mut self, beat: f64, duration: Duration) -> Self {
+        self.stops.push((beat.max(0.0), duration.as_secs_f64()));
+        self.stops
+            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        self.rebuild();
+        self
+    }
+
+    /// Measures have `beats_per_measure` beats from `beat` on. Only the top of the time signature
+    /// matters here, a beat is always a quarter of whatever the BPM says
+    pub fn with_time_signature(mut self, beat: i64, beats_per_measure: u32) -> Self {
+        let beat = beat.max(0);
+        self.signatures.retain(|&(existing, _)| existing != beat);
+        self.signatures.push((beat, beats_per_measure.max(1)));
+        self.signatures.sort_by_key(|&(beat, _)| beat);
+        self
+    }
+
+    fn rebuild(&mut self) {
+        self.segments.clear();
+        let (mut beat, mut bpm) = self.bpm_changes[0];
+        let mut time = self.offset;
+        self.segments.push(Segment { beat, time, bpm });
+        let mut changes = self.bpm_changes[1..].iter().peekable();
+        let mut stops = self.stops.iter().peekable();
+        loop {
+            // stops come before BPM changes on the same beat, they stop at the old tempo's time
+            let stop_next = match (stops.peek(), changes.peek()) {
+                (Some(stop), Some(change)) => stop.0 <= change.0,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let next_beat = if stop_next {
+                stops.peek().unwrap().0
+            } else {
+                changes.peek().unwrap().0
+            };
+            time += (next_beat - beat) * 60.0 / bpm;
+            beat = next_beat;
+            if stop_next {
+                let (_, duration) = *stops.next().unwrap();
+                self.segments.push(Segment {
+                    beat,
+                    time,
+                    bpm: 0.0,
+                });
+                time += duration;
+            } else {
+                bpm = changes.next().unwrap().1;
+            }
+            self.segments.push(Segment { beat, time, bpm });
+        }
+    }
+
+    /// Beats since beat 0 at `time` seconds into the song, the fraction is how far into the beat
+    /// it is for things that pulse along
+    pub fn beat_at(&self, time: f64) -> f64 {
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.time <= time)
+            .unwrap_or(&self.segments[0]);
+        segment.beat + (time - segment.time) * segment.bpm / 60.0
+    }
+
+    /// When the song first reaches `beat`, stops on it come after
+    pub fn time_at(&self, beat: f64) -> f64 {
+        let last = self
+            .segments
+            .iter()
+            .rposition(|segment| segment.beat <= beat)
+            .unwrap_or(0);
+        let first_on_beat = self.segments[..=last]
+            .iter()
+            .position(|segment| segment.beat == self.segments[last].beat)
+            .unwrap_or(last);
+        let segment = if self.segments[last].beat == beat {
+            &self.segments[first_on_beat]
+        } else {
+            &self.segments[last]
+        };
+        if segment.bpm > 0.0 {
+            segment.time + (beat - segment.beat) * 60.0 / segment.bpm
+        } else {
+            segment.time
+        }
+    }
+
+    pub fn bpm_at(&self, time: f64) -> f64 {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.time <= time)
+            .unwrap_or(&self.segments[0])
+            .bpm
+    }
+
+    /// Which measure `beat` is in and which beat of the measure it is, from 0
+    pub fn measure_of(&self, beat: i64) -> (i64, u32) {
+        let (first_beat, first_length) = self.signatures[0];
+        if beat < first_beat {
+            let length = i64::from(first_length);
+            let before = first_beat - beat;
+            let measures = (before + length - 1) / length;
+            return (-measures, (measures * length - before) as u32);
+        }
+        let mut measure = 0;
+        for (i, &(start, length)) in self.signatures.iter().enumerate() {
+            let length = i64::from(length);
+            let end = self.signatures.get(i + 1).map(|&(next, _)| next);
+            match end {
+                Some(end) if beat >= end => {
+                    // a signature change in the middle of a measure starts a new one
+                    measure += (end - start + length - 1) / length;
+                }
+                _ => {
+                    let into = beat - start;
+                    return (measure + into / length, (into % length) as u32);
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    /// Every beat that lands in `from..to` seconds, in order. Both ends have to be finite, there'd
+    /// be no end to the beats otherwise
+    pub fn events_between(&self, from: f64, to: f64) -> Result<Vec<BeatEvent>, &'static str> {
+        if !from.is_finite() || !to.is_finite() {
+            return Err("Beat events need a finite time range!");
+        }
+        let mut events = Vec::new();
+        let mut beat = self.beat_at(from).ceil() as i64;
+        loop {
+            let time = self.time_at(beat as f64);
+            if time >= to {
+                return Ok(events);
+            }
+            if time >= from {
+                let (measure, beat_in_measure) = self.measure_of(beat);
+                events.push(BeatEvent {
+                    beat,
+                    measure,
+                    beat_in_measure,
+                    time,
+                });
+            }
+            beat += 1;
+        }
+    }
+
+    /// Schedules `callback` for every beat from `song_time` until `until`, both in song time.
+    /// `scheduler` has to be advanced in step with the song from here on, and this needs calling
+    /// again before `until` comes around to keep the events going. Fails on the same non-finite
+    /// times as `events_between`
+    pub fn schedule<C, F>(
+        &self,
+        scheduler: &mut Scheduler<C>,
+        song_time: f64,
+        until: f64,
+        callback: F,
+    ) -> Result<Vec<TimerId>, &'static str>
+    where
+        F: FnMut(&mut C, BeatEvent) + Clone + 'static,
+    {
+        Ok(self
+            .events_between(song_time, until)?
+            .into_iter()
+            .map(|event| {
+                let mut callback = callback.clone();
+                scheduler.after(from_seconds(event.time - song_time), move |context| {
+                    callback(context, event)
+                })
+            })
+            .collect())
+    }
+}
+
+fn check_bpm(bpm: f64) -> Result<(), &'static str> {
+    // NaN fails this too
+    if bpm > 0.0 && bpm.is_finite() {
+        Ok(())
+    } else {
+        Err("BPMs have to be positive and finite!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(got: f64, want: f64) {
+        assert!((got - want).abs() < 1e-9, "{} isn't {}", got, want);
+    }
+
+    #[test]
+    fn beats_and_times_across_a_bpm_change() {
+        // 0.5s a beat until beat 8 at 5s, then 1s a beat
+        let clock = BeatClock::new(120.0, 1.0)
+            .unwrap()
+            .with_bpm_change(8.0, 60.0)
+            .unwrap();
+        for &(time, beat) in &[(0.0, -2.0), (1.0, 0.0), (3.0, 4.0), (5.0, 8.0), (7.5, 10.5)] {
+            assert_close(clock.beat_at(time), beat);
+            assert_close(clock.time_at(beat), time);
+        }
+        assert_close(clock.bpm_at(4.9), 120.0);
+        assert_close(clock.bpm_at(5.0), 60.0);
+    }
+
+    #[test]
+    fn beats_stand_still_during_a_stop() {
+        let clock = BeatClock::new(60.0, 0.0)
+            .unwrap()
+            .with_stop(2.0, Duration::from_millis(1500));
+        assert_close(clock.beat_at(1.0), 1.0);
+        assert_close(clock.beat_at(2.0), 2.0);
+        assert_close(clock.beat_at(3.0), 2.0);
+        assert_close(clock.beat_at(4.5), 3.0);
+        assert_close(clock.bpm_at(3.0), 0.0);
+        // the song first reaches the stop's beat when the stop starts
+        assert_close(clock.time_at(2.0), 2.0);
+        assert_close(clock.time_at(3.0), 4.5);
+    }
+
+    #[test]
+    fn measures_across_a_signature_change() {
+        let clock = BeatClock::new(120.0, 0.0)
+            .unwrap()
+            .with_time_signature(8, 3);
+        assert_eq!(clock.measure_of(-1), (-1, 3));
+        assert_eq!(clock.measure_of(0), (0, 0));
+        assert_eq!(clock.measure_of(7), (1, 3));
+        assert_eq!(clock.measure_of(8), (2, 0));
+        assert_eq!(clock.measure_of(11), (3, 0));
+
+        // changing in the middle of a measure starts a new one
+        let clock = BeatClock::new(120.0, 0.0)
+            .unwrap()
+            .with_time_signature(6, 3);
+        assert_eq!(clock.measure_of(5), (1, 1));
+        assert_eq!(clock.measure_of(6), (2, 0));
+    }
+
+    #[test]
+    fn events_land_on_downbeats() {
+        let clock = BeatClock::new(120.0, 0.0)
+            .unwrap()
+            .with_time_signature(4, 3);
+        let events = clock.events_between(0.0, 5.0).unwrap();
+        assert_eq!(events.len(), 10);
+        for (event, beat) in events.iter().zip(0..) {
+            assert_eq!(event.beat, beat);
+            assert_close(event.time, beat as f64 * 0.5);
+        }
+        let downbeats: Vec<i64> = events
+            .iter()
+            .filter(|event| event.is_downbeat())
+            .map(|event| event.beat)
+            .collect();
+        assert_eq!(downbeats, [0, 4, 7]);
+    }
+
+    #[test]
+    fn bad_bpms_and_times_are_errors() {
+        for &bpm in &[0.0, -120.0, std::f64::NAN, std::f64::INFINITY] {
+            assert!(BeatClock::new(bpm, 0.0).is_err(), "{} bpm worked", bpm);
+            let clock = BeatClock::new(120.0, 0.0).unwrap();
+            assert!(
+                clock.with_bpm_change(4.0, bpm).is_err(),
+                "{} bpm worked",
+                bpm
+            );
+        }
+        assert!(BeatClock::new(120.0, std::f64::NAN).is_err());
+        assert!(BeatClock::new(120.0, std::f64::INFINITY).is_err());
+        let clock = BeatClock::new(120.0, 0.0).unwrap();
+        assert!(clock.clone().with_bpm_change(std::f64::NAN, 60.0).is_err());
+        assert!(clock.events_between(0.0, std::f64::INFINITY).is_err());
+        assert!(clock.events_between(std::f64::NAN, 1.0).is_err());
+        assert!(clock.events_between(std::f64::NEG_INFINITY, 1.0).is_err());
+    }
+}
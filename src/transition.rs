@@ -0,0 +1,362 @@
+use crate::{
+    context::{Context, ContextHandler},
+    geometry::{Quad, Vec2},
+    graphics::{BlendMode, DrawableSize, Frame, Space, TextureId, TexturedQuad},
+};
+use std::{mem, time::Duration};
+
+/// One screen of a game, like a menu or the gameplay. `SceneRunner` switches between them with
+/// `Context::transition_to`
+pub trait Scene {
+    fn update(&mut self, ctx: &mut Context);
+    /// Build up `frame`, the runner draws the transition on top and submits it. During a
+    /// crossfade `frame` draws into a render target, so leave `Frame::set_target` alone
+    fn draw(&mut self, ctx: &mut Context, frame: &mut Frame);
+    fn on_resize(&mut self, _ctx: &mut Context, _width: u32, _height: u32) {}
+    /// See `ContextHandler::on_drawable_resize`. A scene also gets one when `transition_to` hands
+    /// it to the runner, so it knows the size before its first update
+    fn on_drawable_resize(&mut self, _ctx: &mut Context, _size: DrawableSize) {}
+    fn quit(&mut self, _ctx: &mut Context) -> bool {
+        true
+    }
+}
+
+/// Which way a wipe moves across the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// How one scene changes into the next. The old scene gets covered during the first half of the
+/// duration, the switch happens in the middle and the new scene gets uncovered in the second half.
+/// Crossfades are the exception, see `Transition::Crossfade`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    /// Switches on the next update
+    Cut,
+    /// Fades out to `color` and back in
+    Fade { duration: Duration, color: [f32; 4] },
+    /// An edge of `color` sweeps across to cover the screen, then the new scene comes in behind
+    /// another edge going the same way
+    Wipe {
+        duration: Duration,
+        color: [f32; 4],
+        direction: WipeDirection,
+    },
+    /// Blends straight from the old scene to the new one. The switch happens right away, and the
+    /// old scene stops updating but keeps getting drawn until the end. Needs render targets, so
+    /// with multisampling or the texture atlas it fades through black instead
+    Crossfade { duration: Duration },
+}
+
+impl Transition {
+    /// Fades through black
+    pub fn fade(duration: Duration) -> Self {
+        Transition::Fade {
+            duration,
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    /// Wipes through black
+    pub fn wipe(duration: Duration, direction: WipeDirection) -> Self {
+        Transition::Wipe {
+            duration,
+            color: [0.0, 0.0, 0.0, 1.0],
+            direction,
+        }
+    }
+
+    pub fn crossfade(duration: Duration) -> Self {
+        Transition::Crossfade { duration }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match *self {
+            Transition::Cut => Duration::from_secs(0),
+            Transition::Fade { duration, .. }
+            | Transition::Wipe { duration, .. }
+            | Transition::Crossfade { duration } => duration,
+        }
+    }
+
+    /// How far in the new scene takes over
+    fn switch_point(&self) -> Duration {
+        match *self {
+            Transition::Crossfade { .. } => Duration::from_secs(0),
+            _ => self.duration() / 2,
+        }
+    }
+
+    /// What covers the screen `progress` of the way through, from 0 to 1. Wipes cover the part
+    /// from `start` to `end` along their direction
+    fn overlay(&self, progress: f32) -> Option<TexturedQuad> {
+        let progress = progress.max(0.0).min(1.0);
+        match *self {
+            // it's the scenes themselves that get blended
+            Transition::Cut | Transition::Crossfade { .. } => None,
+            Transition::Fade { color, .. } => {
+                let coverage = 1.0 - (progress * 2.0 - 1.0).abs();
+                let color = [color[0], color[1], color[2], color[3] * coverage];
+                Some(screen_quad(-1.0, -1.0, 1.0, 1.0, TextureId::NONE).with_color(color))
+            }
+            Transition::Wipe {
+                color, direction, ..
+            } => {
+                let (start, end) = if progress < 0.5 {
+                    (0.0, progress * 2.0)
+                } else {
+                    (progress * 2.0 - 1.0, 1.0)
+                };
+                // clip space goes from -1 to 1
+                let (start, end) = (start * 2.0 - 1.0, end * 2.0 - 1.0);
+                let quad = match direction {
+                    WipeDirection::Right => screen_quad(start, -1.0, end, 1.0, TextureId::NONE),
+                    WipeDirection::Left => screen_quad(-end, -1.0, -start, 1.0, TextureId::NONE),
+                    WipeDirection::Down => screen_quad(-1.0, start, 1.0, end, TextureId::NONE),
+                    WipeDirection::Up => screen_quad(-1.0, -end, 1.0, -start, TextureId::NONE),
+                };
+                Some(quad.with_color(color))
+            }
+        }
+    }
+}
+
+/// In clip space with y going down
+fn screen_quad(x0: f32, y0: f32, x1: f32, y1: f32, texture: TextureId) -> TexturedQuad {
+    let quad = Quad {
+        top_left: Vec2::new(x0, y0),
+        bottom_left: Vec2::new(x0, y1),
+        bottom_right: Vec2::new(x1, y1),
+        top_right: Vec2::new(x1, y0),
+    };
+    TexturedQuad::new(quad, texture.index())
+}
+
+/// Where `Context::transition_to` leaves the next scene for the `SceneRunner` to pick up
+#[derive(Default)]
+pub(crate) struct SceneChange(pub Option<(Box<dyn Scene>, Transition)>);
+
+impl std::fmt::Debug for SceneChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("SceneChange")
+            .field(&self.0.as_ref().map(|(_, transition)| transition))
+            .finish()
+    }
+}
+
+struct ActiveTransition {
+    transition: Transition,
+    elapsed: Duration,
+    /// None once the switch has happened
+    next: Option<Box<dyn Scene>>,
+    /// What a crossfade switched away from, it gets drawn until the crossfade is done
+    previous: Option<Box<dyn Scene>>,
+}
+
+impl ActiveTransition {
+    fn progress(&self) -> f32 {
+        let duration = self.transition.duration();
+        if duration == Duration::from_secs(0) {
+            1.0
+        } else {
+            (self.elapsed.as_secs_f64() / duration.as_secs_f64()) as f32
+        }
+    }
+}
+
+/// A `ContextHandler` that runs one `Scene` at a time and plays the transitions between them. Most
+/// transitions get drawn over the scene as a fullscreen quad, crossfades draw both scenes into
+/// render targets that get made for the first one and kept around for the next
+pub struct SceneRunner {
+    scene: Box<dyn Scene>,
+    transition: Option<ActiveTransition>,
+    frame: Frame,
+    /// The outgoing and incoming scene's
+    crossfade_targets: Option<(TextureId, TextureId)>,
+    /// Making them failed once already, so crossfades fade through black
+    no_render_targets: bool,
+}
+
+impl SceneRunner {
+    pub fn new(scene: Box<dyn Scene>) -> Self {
+        SceneRunner {
+            scene,
+            transition: None,
+            frame: Frame::new(),
+            crossfade_targets: None,
+            no_render_targets: false,
+        }
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// The scene a transition is switching to, until the switch happens
+    fn incoming(&mut self) -> Option<&mut Box<dyn Scene>> {
+        self.transition
+            .as_mut()
+            .and_then(|active| active.next.as_mut())
+    }
+
+    /// The scene a crossfade is switching away from
+    fn outgoing(&mut self) -> Option<&mut Box<dyn Scene>> {
+        self.transition
+            .as_mut()
+            .and_then(|active| active.previous.as_mut())
+    }
+
+    fn crossfade_targets(&mut self, ctx: &mut Context) -> Option<(TextureId, TextureId)> {
+        if self.crossfade_targets.is_none() && !self.no_render_targets {
+            let graphics = &mut ctx.graphics;
+            let targets = graphics.create_render_target().and_then(|outgoing| {
+                match graphics.create_render_target() {
+                    Ok(incoming) => Ok((outgoing, incoming)),
+                    Err(e) => {
+                        let _ = graphics.unload_texture(outgoing);
+                        Err(e)
+                    }
+                }
+            });
+            match targets {
+                Ok(targets) => self.crossfade_targets = Some(targets),
+                Err(e) => {
+                    warn!(ctx.logger(), "couldn't make the crossfade's render targets, fading instead";
+                          "error" => e);
+                    self.no_render_targets = true;
+                }
+            }
+        }
+        self.crossfade_targets
+    }
+
+    fn advance_transition(&mut self, delta: Duration) {
+        let done = match self.transition.as_mut() {
+            Some(active) => {
+                active.elapsed += delta;
+                if active.elapsed >= active.transition.switch_point() {
+                    if let Some(next) = active.next.take() {
+                        let previous = mem::replace(&mut self.scene, next);
+                        if let Transition::Crossfade { .. } = active.transition {
+                            active.previous = Some(previous);
+                        }
+                    }
+                }
+                active.elapsed >= active.transition.duration()
+            }
+            None => false,
+        };
+        if done {
+            self.transition = None;
+        }
+    }
+}
+
+impl ContextHandler for SceneRunner {
+    fn update(&mut self, ctx: &mut Context) {
+        self.advance_transition(ctx.clock.delta());
+        self.scene.update(ctx);
+        // a transition that's already running gets cut short by the new one
+        if let Some((mut next, transition)) = ctx.scene_change.0.take() {
+            let size = ctx.graphics.swapchain_info().drawable_size();
+            next.on_drawable_resize(ctx, size);
+            self.transition = Some(ActiveTransition {
+                transition,
+                elapsed: Duration::from_secs(0),
+                next: Some(next),
+                previous: None,
+            });
+            self.advance_transition(Duration::from_secs(0));
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) {
+        self.frame.clear();
+        let targets = match self.outgoing() {
+            Some(_) => self.crossfade_targets(ctx),
+            None => None,
+        };
+        let (transition, progress) = match &self.transition {
+            Some(active) => (Some(active.transition), active.progress()),
+            None => (None, 1.0),
+        };
+        let previous = self
+            .transition
+            .as_mut()
+            .and_then(|active| active.previous.as_mut());
+        let overlay = match (previous, targets) {
+            (Some(previous), Some((outgoing, incoming))) => {
+                self.frame.set_target(Some(outgoing));
+                previous.draw(ctx, &mut self.frame);
+                self.frame.set_target(Some(incoming));
+                self.scene.draw(ctx, &mut self.frame);
+                let alpha = progress.max(0.0).min(1.0);
+                self.frame
+                    .set_target(None)
+                    .set_space(Space::Screen)
+                    .set_blend_mode(BlendMode::Alpha)
+                    .draw_quads(&[
+                        screen_quad(-1.0, -1.0, 1.0, 1.0, outgoing),
+                        screen_quad(-1.0, -1.0, 1.0, 1.0, incoming)
+                            .with_color([1.0, 1.0, 1.0, alpha]),
+                    ]);
+                None
+            }
+            // no render targets, so it's a fade that switches in the middle after all
+            (Some(previous), None) => {
+                if progress < 0.5 {
+                    previous.draw(ctx, &mut self.frame);
+                } else {
+                    self.scene.draw(ctx, &mut self.frame);
+                }
+                transition.and_then(|transition| {
+                    Transition::fade(transition.duration()).overlay(progress)
+                })
+            }
+            (None, _) => {
+                self.scene.draw(ctx, &mut self.frame);
+                transition.and_then(|transition| transition.overlay(progress))
+            }
+        };
+        if let Some(overlay) = overlay {
+            self.frame
+                .set_target(None)
+                .set_space(Space::Screen)
+                .set_blend_mode(BlendMode::Alpha)
+                .draw_quads(&[overlay]);
+        }
+        if let Err(e) = ctx.graphics.draw_frame(&self.frame) {
+            error!(ctx.logger(), "render error"; "render_error" => e);
+        }
+    }
+
+    /// The scene that's coming in during a transition gets resizes too, it could be switched to
+    /// before the next one. So does the one a crossfade is still drawing
+    fn on_resize(&mut self, ctx: &mut Context, width: u32, height: u32) {
+        self.scene.on_resize(ctx, width, height);
+        if let Some(next) = self.incoming() {
+            next.on_resize(ctx, width, height);
+        }
+        if let Some(previous) = self.outgoing() {
+            previous.on_resize(ctx, width, height);
+        }
+    }
+
+    fn on_drawable_resize(&mut self, ctx: &mut Context, size: DrawableSize) {
+        self.scene.on_drawable_resize(ctx, size);
+        if let Some(next) = self.incoming() {
+            next.on_drawable_resize(ctx, size);
+        }
+        if let Some(previous) = self.outgoing() {
+            previous.on_drawable_resize(ctx, size);
+        }
+    }
+
+    fn quit(&mut self, ctx: &mut Context) -> bool {
+        self.scene.quit(ctx)
+    }
+}